@@ -1,10 +1,18 @@
+use std::collections::{BTreeSet, HashSet};
 use std::env;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use log::*;
 
 use rowhammer::*;
 use rowhammer::config::*;
 use rowhammer::utils::{*, serialize::*};
+use rowhammer::utils::row_wear::{self, RowWear};
+use rowhammer::utils::report::{self, Reporter, Reporters};
+use rowhammer::utils::victim_ledger::{self, VictimLedger};
+use rowhammer::utils::oracle::{PhysAddrOracle, PagemapOracle};
+use rowhammer::utils::tool_lock::ToolLock;
+use rowhammer::context::RhContext;
+use rowhammer::layout::PatternLayout;
 
 
 /*
@@ -37,8 +45,72 @@ use rowhammer::utils::{*, serialize::*};
  *
  * When the environment variable `RH_TEST` is defined, the loader component will
  * just return without any action
+ * When not all victims flipped, the loader hammers only the patterns of the
+ * failed victim bits again, up to the `attempts` of each bit (default:
+ * 1 + `rehammer_attempts`). When a bit that is not `tolerable` still did not
+ * flip, the `on_failure` policy decides what happens (see
+ * docs/example_attack_config.toml)
+ *
+ * When the environment variable `RH_FAKE_HAMMER` is defined, step 5 flips the
+ * victim bits through /dev/mem instead of hammering (for testing)
+ *
+ * Whether each victim bit flipped is recorded in the victim ledger (see
+ * rowhammer::utils::victim_ledger), the loader warns about victim bits that
+ * are worn or used by other binaries according to that ledger
+ * The activations of the hammered rows are added to the row wear accounting
+ * (see rowhammer::utils::row_wear)
+ * Victim bits given by a symbol and an offset in its code are resolved to file
+ * offsets with the symbol table of the running binary before step 1
+ *
+ * Victim frames can also hold a page table of the loader instead of binary
+ * content (`pte_victim_frames`), after step 4 the loader sprays page tables
+ * into them, hammers their patterns and checks the flips through the changed
+ * translations (see rowhammer::pte)
+ *
+ * With `rehearsal` in attack_config.toml, the loader rehearses the attack
+ * between step 1 and 2: it copies the target pages into the victim frames,
+ * hammers them like in step 5 (including the attempts of each bit) and exits
+ * with REHEARSAL_FAILURE_EXIT_CODE when a bit that is not `tolerable` did not
+ * flip, so the real target is never placed in frames that do not flip on this
+ * boot. The page-table victims are not rehearsed
+ *
+ * When the environment variable `RH_TIMELINE=<path>` is defined, a timeline of
+ * the run (phases, allocation progress, hammer durations, flip outcomes) is
+ * written to that path as JSON (see rowhammer::utils::timeline)
+ * The outcome of every victim bit is reported to the sinks in `RH_REPORT` (see
+ * rowhammer::utils::report)
+ *
+ * When the environment variable `RH_STEALTH` is defined, the loader minimizes
+ * its observable footprint: no log output, no files (timeline, reports,
+ * ledger, row wear), randomized allocation pacing and the aggressor pages are
+ * released before returning to main (see rowhammer::utils::stealth)
+ *
+ * Steps 1-5 hold the tool lock (see rowhammer::utils::tool_lock), so no other
+ * rowhammer tool runs at the same time (in stealth mode the lock is taken
+ * anonymously). Define the environment variable `RH_STEAL_LOCK` to take it
+ * from a running tool
+ *
+ * Before step 1, the loader checks its external dependencies and reports all
+ * missing ones at once (see rowhammer::preflight)
  */
 
+//exit code of the target process when not all victims flipped and the
+//`on_failure` policy in attack_config.toml is `exit`
+pub static FLIP_FAILURE_EXIT_CODE: i32 = 86;
+//exit code of the target process when the victims did not flip in the
+//rehearsal (`rehearsal` in attack_config.toml)
+pub static REHEARSAL_FAILURE_EXIT_CODE: i32 = 87;
+
+//How the victim bits are flipped instead of hammering (for testing)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FakeHammer {
+  //through /dev/mem (`RH_FAKE_HAMMER`, see rowhammer::utils::devmem)
+  DevMem,
+  //through the mapped victim pages, without root privileges (e.g., with a
+  //simulated PhysAddrOracle)
+  Mapped
+}
+
 //entry for the protected program to invoke the loader
 #[no_mangle]
 pub extern "C" fn do_the_thing() {
@@ -51,52 +123,42 @@ pub extern "C" fn do_the_thing() {
   }
 
   // 0. Preparations
-  rowhammer::configure().init();
-  let attack_config =
-    files::parse_toml::<AttackConfig>(ATTACK_CONFIG_PATH).validate();
-  let dram_config = dram::create_config();
-
-  // 1. Allocate until a page landed in all victim frames
-  let mut start_time = SystemTime::now();
-  let mut frame2map = allocation::allocate_attack(
-    &dram_config, &attack_config);
-  let mut duration = SystemTime::now().duration_since(start_time).unwrap();
-  info!("Allocation took {}ms", duration.as_millis());
-
-  // 2. Mapping the PT_NULL segment and forging part of the address space
-  start_time = SystemTime::now();
+  let stealth = stealth::enable_if_requested();
+  let mut logger_builder = rowhammer::configure();
+  if stealth {
+    logger_builder.filter_level(LevelFilter::Off);
+  }
+  logger_builder.init();
+  preflight::check(&preflight::tool_features("loader"));
+  //hammer on the requested core type, the target runs on all its cores again
+  //afterwards
+  let affinity = sched::get_affinity();
+  host::restrict_to_core_type();
   let program_path = std::env::args().next().unwrap();
-  allocation::map_binary(&program_path, &attack_config, &mut frame2map);
-  duration = SystemTime::now().duration_since(start_time).unwrap();
-  info!("Mapping took {}ms", duration.as_millis());
-
-  let content_before_rh =
-    mem_init::read_victim_content(attack_config.victim_frames.iter(), &frame2map);
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
+  ctx.timeline.start_if_requested(&program_path, stealth);
+  let mut attack_config = files::parse_toml::<AttackConfig>(ATTACK_CONFIG_PATH)
+    .with_geometry(&ctx.geometry).resolve(&program_path).validate();
+  let fake_hammer = (env::var("RH_FAKE_HAMMER").is_ok()
+    || attack_config.hammer_backend == hammer::HammerBackend::Fake)
+    .then_some(FakeHammer::DevMem);
 
-  // 3. RowHammer all aggressors at once
-  start_time = SystemTime::now();
-  //bcs the attack config is validated, victims and aggressors do not overlap,
-  //thus all aggressors can be initialized at once
-  mem_init::initialize_rows(attack_config.iter_aggr_rows(), &frame2map);
-  duration = SystemTime::now().duration_since(start_time).unwrap();
-  info!("Aggr init took {}ms", duration.as_millis());
-
-  let sleep = Duration::new(2, 0);
-
-  start_time = SystemTime::now();
-  attack_config.iter_aggr_patterns_per_victim().for_each(|v| {
-    let virt_aggrs = v.aggr_rows_to_virt(&frame2map);
-    std::thread::sleep(sleep);
-    //hammer::hammer_rust(&virt_aggrs, attack_config.hammer_count);
-    //hammer::hammer_asm(&virt_aggrs, 35, attack_config.hammer_count);
-    hammer::hammer_jit(&virt_aggrs, 35, attack_config.hammer_count);
-  });
-  duration = SystemTime::now().duration_since(start_time).unwrap();
-  info!("Hammering took {}ms", duration.as_millis());
-
-  // 4. Check if target content changed
-  flip_checks::check_victim_flips(
-    attack_config.victim_frames.iter(), &frame2map, &content_before_rh);
+  // 1.-5. (see above)
+  //(anonymously in stealth mode, see utils/tool_lock.rs)
+  let steal_lock = env::var("RH_STEAL_LOCK").is_ok();
+  let lock = if stealth {
+    ToolLock::acquire_anonymous(steal_lock)
+  } else {
+    ToolLock::acquire("loader", steal_lock)
+  };
+  let (mut frame2map, _) = load(&ctx, &PagemapOracle,
+    &program_path, &mut attack_config, fake_hammer);
+  drop(lock);
+  sched::pin_to_cores(&affinity);
+  ctx.timeline.finish();
+  if stealth {
+    stealth::release(&mut frame2map, attack_config.iter_aggr_frames());
+  }
 
   info!("Returning to target binary");
 
@@ -125,3 +187,412 @@ pub extern "C" fn do_the_thing() {
   unsafe {assert!(libc::setuid(sudo_uid) == 0);}
 }
 
+//Steps 1-5 (see above) of the loader for the binary at arg:program_path
+//arg:ctx needs a DRAM config, arg:oracle translates virtual to physical
+//addresses
+//With arg:fake_hammer, the bit flips are faked instead of hammering (see
+//FakeHammer)
+//Missing aggressor rows are substituted in arg:attack_config according to its
+//`aggr_fallback` policy
+//Returns the MemoryMap objects of all mapped pages (including the forged
+//PT_NULL segment) and whether all victims flipped as expected
+pub fn load(
+  ctx: &RhContext,
+  oracle: &impl PhysAddrOracle,
+  program_path: &str,
+  attack_config: &mut AttackConfig,
+  fake_hammer: Option<FakeHammer>
+) -> (Frame2Map, bool) {
+  //faked flips say nothing about the cells, keep them out of the ledger
+  //(and the stealth mode writes no files)
+  let record = fake_hammer.is_none() && !ctx.settings.stealth;
+  let ledger_path = victim_ledger::ledger_path();
+  let mut ledger = record.then(|| VictimLedger::load(&ledger_path));
+  let wear_path = row_wear::wear_path();
+  let mut row_wear = record.then(|| RowWear::load(&wear_path));
+  if let Some(ledger) = &ledger {
+    for l in lint::lint_victims(ledger, Some(program_path),
+      iter_victim_bits(attack_config)) {
+      warn!("{}", l);
+    }
+  }
+
+  //patterns that disturb each other's victims (or need other init values for
+  //the same aggressor row, see hammer_patterns)
+  victim_conflicts::check(ctx, attack_config);
+
+  // 1. Allocate until a page landed in all victim frames
+  ctx.phase_start("allocation");
+  let mut start_time = SystemTime::now();
+  let mut frame2map = allocation::allocate_attack(
+    ctx, oracle, attack_config);
+  let mut duration = SystemTime::now().duration_since(start_time).unwrap();
+  info!("Allocation took {}ms", duration.as_millis());
+  ctx.phase_end("allocation");
+  let attack_config = &*attack_config;
+  //optionally verify the banks of the aggressors (also of substituted rows,
+  //see layout)
+  if let Some(layout) = PatternLayout::for_attack_if_requested(ctx) {
+    layout.warn_on_attack_mismatches(ctx, attack_config);
+  }
+
+  //optional rehearsal on a scratch copy of the target pages
+  if attack_config.rehearsal {
+    ctx.phase_start("rehearsal");
+    let failed = rehearse(ctx, program_path, attack_config, &frame2map,
+      &mut row_wear, fake_hammer);
+    ctx.phase_end("rehearsal");
+    if let Some(row_wear) = &row_wear {
+      row_wear.save(&wear_path);
+    }
+    if failed.iter().any(|(_, bit)| !bit.tolerable) {
+      for (frame_addr, bit) in &failed {
+        warn!("Victim bit {} at P0x{:x} did not flip in the rehearsal",
+          bit.bitflip, frame_addr + bit.offset);
+      }
+      error!("Rehearsal failed, exiting with code {} before placing the \
+        target", REHEARSAL_FAILURE_EXIT_CODE);
+      ctx.timeline.finish();
+      std::process::exit(REHEARSAL_FAILURE_EXIT_CODE);
+    }
+    info!("Rehearsal succeeded");
+  }
+
+  // 2. Mapping the PT_NULL segment and forging part of the address space
+  ctx.phase_start("mapping");
+  start_time = SystemTime::now();
+  allocation::map_binary(ctx, oracle, program_path, attack_config,
+    &mut frame2map);
+  duration = SystemTime::now().duration_since(start_time).unwrap();
+  info!("Mapping took {}ms", duration.as_millis());
+  ctx.phase_end("mapping");
+
+  //pre-flight check: the victim bytes should still match the binary
+  flip_checks::check_victim_content(program_path, attack_config, &frame2map);
+  let content_before_rh =
+    mem_init::read_victim_content(ctx, attack_config.victim_frames.iter(),
+      &frame2map);
+
+  //the binary is mapped now, do not hammer next to the loader's own code,
+  //stack or heap (the victim pages are excluded, see self_guard)
+  if fake_hammer.is_none() {
+    self_guard::check(ctx, attack_config.iter_aggr_frames(),
+      attack_config.iter_victim_frame_addrs(), &frame2map);
+  }
+
+  // 3. RowHammer all aggressors at once
+  let hammered = hammer_patterns(ctx,
+    attack_config.iter_aggr_patterns_per_victim(), attack_config, &frame2map,
+    fake_hammer);
+  add_row_wear(ctx, &mut row_wear, &hammered);
+
+  // 4. Check which victim bits flipped, hammer only the patterns of the
+  //failed bits again while their attempt budget allows it
+  let pending: Vec<(u64, &VictimBit)> = attack_config.victim_frames.iter()
+    .flat_map(|x| x.victim_bits.iter().map(move |b| (x.frame_addr, b)))
+    .collect();
+  let mut failed = rehammer_until_flipped(ctx, pending,
+    |frame_addr, bit| flip_checks::check_victim_bit(
+    frame_addr, bit, &frame2map, &content_before_rh),
+    attack_config, &frame2map, &mut row_wear, fake_hammer);
+
+  // 5. Page-table victims: spray page tables into their frames, then hammer
+  //and check them like above (see rowhammer::pte)
+  if !attack_config.pte_victim_frames.is_empty() {
+    ctx.phase_start("pte_spray");
+    let spray = pte::PteSpray::spray(oracle, attack_config, &mut frame2map);
+    ctx.phase_end("pte_spray");
+    let pending: Vec<(u64, &VictimBit)> = attack_config.pte_victim_frames
+      .iter()
+      .flat_map(|x| x.victim_bits.iter().map(move |b| (x.frame_addr, b)))
+      .collect();
+    if fake_hammer.is_some() {
+      spray.fake_flips(attack_config);
+    } else {
+      let hammered = hammer_patterns(ctx, pending.iter()
+        .map(|(_, bit)| attack_config.aggr_pattern(&bit.aggr_pattern_key)),
+        attack_config, &frame2map, None);
+      add_row_wear(ctx, &mut row_wear, &hammered);
+    }
+    failed.extend(rehammer_until_flipped(ctx, pending,
+      |frame_addr, bit| spray.check_flip(oracle, frame_addr, bit),
+      attack_config, &frame2map, &mut row_wear, fake_hammer));
+    //restores the page tables and unmaps the spray
+    std::mem::drop(spray);
+  }
+
+  //full report (including unexpected flips)
+  let flipped = flip_checks::check_victim_flips(
+    attack_config.victim_frames.iter(), &frame2map, &content_before_rh);
+
+  for (frame_addr, bit) in &failed {
+    warn!("Victim bit {} at P0x{:x} did not flip ({})", bit.bitflip,
+      frame_addr + bit.offset, if bit.tolerable {"tolerable"} else {"fatal"});
+  }
+
+  if let Some(ledger) = ledger.as_mut() {
+    let binary = victim_ledger::binary_name(program_path);
+    let failed: HashSet<(u64, &BitFlip)> = failed.iter()
+      .map(|(frame_addr, bit)| (frame_addr + bit.offset, &bit.bitflip))
+      .collect();
+    for victim in iter_victim_bits(attack_config) {
+      ledger.record(&binary, victim.0, victim.1, !failed.contains(&victim));
+    }
+    ledger.save(&ledger_path);
+  }
+  if let Some(row_wear) = &row_wear {
+    row_wear.save(&wear_path);
+  }
+  let result = report::ExperimentResult::attack(program_path, attack_config,
+    ctx.resources.summary(),
+    |frame_addr, bit| !failed.iter().any(|(f, b)| *f == frame_addr
+    && std::ptr::eq(*b, bit)));
+  let mut reporters = Reporters::from_env();
+  if let report::ExperimentResult::Attack {flipped, ..} = &result {
+    flipped.iter().for_each(|f| reporters.flip_discovered(f));
+  }
+  reporters.experiment_finished(&result);
+
+  if failed.iter().any(|(_, bit)| !bit.tolerable) {
+    match attack_config.on_failure {
+      FailurePolicy::Continue => {
+        warn!("Not all victims flipped, continuing anyway");
+      },
+      FailurePolicy::Restore => {
+        warn!("Not all victims flipped, continuing with the original content");
+        mem_init::restore_victim_content(attack_config.victim_frames.iter(),
+          &frame2map, &content_before_rh);
+      },
+      FailurePolicy::Exit => {
+        error!("Not all victims flipped, exiting with code {}",
+          FLIP_FAILURE_EXIT_CODE);
+        ctx.timeline.finish();
+        std::process::exit(FLIP_FAILURE_EXIT_CODE);
+      }
+    }
+  }
+
+  (frame2map, flipped)
+}
+
+//Copies the target pages into the victim frames (the copies are overwritten
+//in step 2), hammers them and checks the victim bits like steps 3 and 4
+//Returns the bits that did not flip
+fn rehearse<'a>(
+  ctx: &RhContext, program_path: &str, attack_config: &'a AttackConfig,
+  frame2map: &Frame2Map, row_wear: &mut Option<RowWear>,
+  fake_hammer: Option<FakeHammer>
+) -> Vec<(u64, &'a VictimBit)> {
+  info!("Rehearsing the attack on a copy of the target pages");
+  mem_init::copy_target_pages(ctx, program_path,
+    attack_config.victim_frames.iter(), frame2map);
+  let content_before_rh = mem_init::read_victim_content(ctx,
+    attack_config.victim_frames.iter(), frame2map);
+
+  let hammered = hammer_patterns(ctx,
+    attack_config.iter_aggr_patterns_per_victim(), attack_config, frame2map,
+    fake_hammer);
+  add_row_wear(ctx, row_wear, &hammered);
+  let pending: Vec<(u64, &VictimBit)> = attack_config.victim_frames.iter()
+    .flat_map(|x| x.victim_bits.iter().map(move |b| (x.frame_addr, b)))
+    .collect();
+  rehammer_until_flipped(ctx, pending,
+    |frame_addr, bit| flip_checks::check_victim_bit(
+    frame_addr, bit, frame2map, &content_before_rh),
+    attack_config, frame2map, row_wear, fake_hammer)
+}
+
+//(byte address, bitflip) of all victim bits in arg:attack_config (including
+//the page-table victims)
+fn iter_victim_bits(
+  attack_config: &AttackConfig
+) -> impl Iterator<Item = (u64, &BitFlip)> {
+  attack_config.victim_frames.iter().flat_map(|v| v.victim_bits.iter()
+    .map(move |b| (v.frame_addr + b.offset, &b.bitflip)))
+    .chain(attack_config.pte_victim_frames.iter().flat_map(|v| v.victim_bits
+    .iter().map(move |b| (v.frame_addr + b.offset, &b.bitflip))))
+}
+
+//Checks arg:pending victim bits (frame address, bit) with arg:flipped after
+//the first hammer attempt and hammers the patterns of the bits that did not
+//flip again while their attempt budget allows it
+//Returns the bits that did not flip
+fn rehammer_until_flipped<'a>(
+  ctx: &RhContext,
+  mut pending: Vec<(u64, &'a VictimBit)>,
+  flipped: impl Fn(u64, &VictimBit) -> bool,
+  attack_config: &'a AttackConfig, frame2map: &Frame2Map,
+  row_wear: &mut Option<RowWear>, fake_hammer: Option<FakeHammer>
+) -> Vec<(u64, &'a VictimBit)> {
+  let mut failed = Vec::new();
+  let mut attempt = 1;
+  loop {
+    pending.retain(|(frame_addr, bit)| {
+      let flipped = flipped(*frame_addr, bit);
+      if flipped {
+        record_flip(ctx, *frame_addr, bit, true, attempt);
+      }
+      !flipped
+    });
+    //the bits without attempts left failed for good
+    let (retry, out_of_budget): (Vec<_>, Vec<_>) = pending.into_iter()
+      .partition(|(_, bit)| attack_config.hammer_attempts(bit) > attempt);
+    for (frame_addr, bit) in &out_of_budget {
+      record_flip(ctx, *frame_addr, bit, false, attempt);
+    }
+    failed.extend(out_of_budget);
+    pending = retry;
+    if pending.is_empty() {
+      return failed;
+    }
+
+    attempt += 1;
+    warn!("{} victim bits did not flip, hammering their patterns again \
+      (attempt {})", pending.len(), attempt);
+    let keys: BTreeSet<&str> =
+      pending.iter().map(|(_, bit)| bit.aggr_pattern_key.as_str()).collect();
+    let hammered = hammer_patterns(ctx,
+      keys.into_iter().map(|k| attack_config.aggr_pattern(k)), attack_config,
+      frame2map, fake_hammer);
+    add_row_wear(ctx, row_wear, &hammered);
+  }
+}
+
+//Records the outcome of a victim bit after arg:attempts hammer attempts in the
+//timeline
+fn record_flip(
+  ctx: &RhContext, frame_addr: u64, bit: &VictimBit, flipped: bool,
+  attempts: u32
+) {
+  ctx.timeline.record(timeline::Event::Flip {
+    phys_addr: frame_addr + bit.offset,
+    bitflip: bit.bitflip.to_string(),
+    flipped,
+    attempts
+  });
+}
+
+//Accounts the activations of arg:hammered in arg:row_wear (if any), with the
+//achieved hammer iterations (more than the hammer_count with
+//`RH_HAMMER_DURATION`)
+fn add_row_wear(
+  ctx: &RhContext, row_wear: &mut Option<RowWear>,
+  hammered: &[(&AggressorPattern, hammer::HammerStats)]
+) {
+  if let Some(row_wear) = row_wear.as_mut() {
+    for (pattern, stats) in hammered {
+      row_wear.add_pattern(ctx.dram_config(), pattern, stats.hammer_count);
+    }
+  }
+}
+
+//Hammers each pattern in arg:patterns once (or fakes the flips of all victims
+//with arg:fake_hammer)
+//Returns the hammered patterns with the stats of their hammering (empty when
+//faked)
+fn hammer_patterns<'a>(
+  ctx: &RhContext,
+  patterns: impl Iterator<Item = &'a AggressorPattern>,
+  attack_config: &AttackConfig, frame2map: &Frame2Map,
+  fake_hammer: Option<FakeHammer>
+) -> Vec<(&'a AggressorPattern, hammer::HammerStats)> {
+  if let Some(fake_hammer) = fake_hammer {
+    match fake_hammer {
+      FakeHammer::DevMem => devmem::hammer_fake(attack_config, frame2map),
+      FakeHammer::Mapped => hammer_fake_mapped(attack_config, frame2map)
+    }
+    Vec::new()
+  } else {
+    let patterns: Vec<_> = patterns.collect();
+    let sleep = Duration::new(2, 0);
+    //optionally disable the L2 prefetchers for the duration of the hammering
+    let prefetchers = host::disable_prefetchers_if_requested();
+    //optionally switch to SCHED_FIFO and reduce the timer slack
+    let shield = host::shield_if_requested();
+
+    //patterns that use an aggressor row with different init values are in
+    //different groups (usually there is only one group)
+    let mut hammered = Vec::new();
+    for group in victim_conflicts::init_groups(&patterns) {
+      hammered.extend(hammer_init_group(ctx, &group, attack_config, frame2map,
+        sleep));
+    }
+    //restores the prefetchers and the scheduling settings (also dropped when
+    //a panic unwinds)
+    drop(shield);
+    drop(prefetchers);
+    hammered
+  }
+}
+
+//Fakes the flips of all victims through their pages in arg:frame2map (see
+//FakeHammer::Mapped)
+fn hammer_fake_mapped(attack_config: &AttackConfig, frame2map: &Frame2Map) {
+  info!("Performing fake RowHammer through the mapped victim pages");
+  for victim_frame in &attack_config.victim_frames {
+    let page = frame2map[&victim_frame.frame_addr].data();
+    for bit in &victim_frame.victim_bits {
+      let virt_victim = unsafe {page.add(bit.offset as usize)};
+      let mask = 1u8 << bit.bitflip.flip_index;
+      unsafe {
+        let value = std::ptr::read_volatile(virt_victim);
+        std::ptr::write_volatile(virt_victim, if bit.bitflip.flip_direction {
+          value | mask
+        } else {
+          value & !mask
+        });
+      }
+    }
+  }
+}
+
+//Initializes the aggressor rows of arg:patterns and hammers them
+//Returns every pattern with the stats of the round it was hammered in
+fn hammer_init_group<'a>(
+  ctx: &RhContext, patterns: &[&'a AggressorPattern],
+  attack_config: &AttackConfig, frame2map: &Frame2Map, sleep: Duration
+) -> Vec<(&'a AggressorPattern, hammer::HammerStats)> {
+  ctx.phase_start("aggr_init");
+  let mut start_time = SystemTime::now();
+  //bcs the attack config is validated, victims and aggressors do not
+  //overlap, thus all aggressors of the group can be initialized at once
+  mem_init::initialize_rows_batched(ctx,
+    patterns.iter().flat_map(|p| p.into_iter()), frame2map);
+  let mut duration = SystemTime::now().duration_since(start_time).unwrap();
+  info!("Aggr init took {}ms", duration.as_millis());
+  ctx.phase_end("aggr_init");
+
+  ctx.phase_start("hammer");
+  start_time = SystemTime::now();
+  let mut hammered = Vec::new();
+  for round in attack_config.hammer_rounds(patterns.iter().cloned()) {
+    std::thread::sleep(sleep);
+    let hammer_start = Instant::now();
+    let stats = match &round[..] {
+      [pattern] if !pattern.is_weighted() => {
+        let virt_aggrs = pattern.aggr_rows_to_virt(frame2map);
+        hammer::hammer_with_backend(ctx, attack_config.hammer_backend,
+          &virt_aggrs, 35, attack_config.hammer_count)
+      },
+      _ => {
+        assert_eq!(attack_config.hammer_backend, hammer::HammerBackend::Jit,
+          "Concurrently hammered patterns need the jit hammer backend");
+        let virt_aggrs: Vec<_> = round.iter()
+          .map(|p| p.aggr_rows_to_virt_weighted(frame2map)).collect();
+        hammer::hammer_jit_interleaved(ctx, &virt_aggrs, 35,
+          attack_config.hammer_count)
+      }
+    };
+    //the achieved iterations (more with `RH_HAMMER_DURATION`)
+    ctx.timeline.record(timeline::Event::Hammer {
+      aggressors: round.iter().map(|p| p.real_aggressors().count()).sum(),
+      hammer_count: stats.hammer_count,
+      duration_us: hammer_start.elapsed().as_micros() as u64
+    });
+    hammered.extend(round.iter().map(|p| (*p, stats)));
+  }
+  duration = SystemTime::now().duration_since(start_time).unwrap();
+  info!("Hammering took {}ms", duration.as_millis());
+  ctx.phase_end("hammer");
+  hammered
+}
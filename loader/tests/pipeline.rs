@@ -0,0 +1,253 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use mmap::{MemoryMap, MapOption};
+
+use rowhammer::config::*;
+use rowhammer::utils::{self, files, proc, serialize::*};
+use rowhammer::utils::oracle::{PagemapOracle, PhysAddrOracle};
+use rowhammer::context::RhContext;
+use loader::FakeHammer;
+
+//Integration tests of the full loader pipeline with a fake hammer:
+//  - `loader_pipeline_with_mapped_fake_hammer`: the frames come from an
+//    in-memory oracle and the flips are written through the mapped victim
+//    pages, runs without root privileges
+//  - `loader_pipeline_with_fake_hammer`: the real frames of free rows and the
+//    /dev/mem fake hammer, requires root and a kernel with
+//    CONFIG_STRICT_DEVMEM=n (or PTEditor), so it is ignored by default
+//    USAGE: sudo -E cargo test -- --ignored
+
+//file offset of the PT_NULL segment in the fixture binary
+static SEGMENT_OFFSET: u64 = 0x1000;
+//the segment spans 2 pages (filesz is not page aligned, like real binaries)
+static SEGMENT_SIZE: u64 = 0x1800;
+static SEGMENT_PAGES: u64 = 2;
+//number of pages to allocate when looking for free frames
+static POOL_SIZE: usize = 4096;
+//first frame of the in-memory oracle
+static FRAME_BASE: u64 = 0x1000_0000;
+
+//content of the fixture binary at file offset arg:offset
+fn fixture_byte(offset: u64) -> u8 {
+  (offset % 251) as u8
+}
+
+//Creates a tiny ELF file with only a PT_NULL segment
+fn create_elf_fixture(path: &PathBuf) {
+  let mut elf = vec![0u8; (SEGMENT_OFFSET + SEGMENT_PAGES * *PAGE_SIZE as u64)
+    as usize];
+  //ELF header: 64 bit, little endian, executable, x86-64
+  elf[0..8].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+  elf[16..18].copy_from_slice(&2u16.to_le_bytes()); //e_type
+  elf[18..20].copy_from_slice(&0x3eu16.to_le_bytes()); //e_machine
+  elf[20..24].copy_from_slice(&1u32.to_le_bytes()); //e_version
+  elf[32..40].copy_from_slice(&64u64.to_le_bytes()); //e_phoff
+  elf[52..54].copy_from_slice(&64u16.to_le_bytes()); //e_ehsize
+  elf[54..56].copy_from_slice(&56u16.to_le_bytes()); //e_phentsize
+  elf[56..58].copy_from_slice(&1u16.to_le_bytes()); //e_phnum
+  elf[58..60].copy_from_slice(&64u16.to_le_bytes()); //e_shentsize
+  //program header: PT_NULL (= 0) with r-x permissions
+  let phdr = &mut elf[64..120];
+  phdr[4..8].copy_from_slice(&5u32.to_le_bytes()); //p_flags
+  phdr[8..16].copy_from_slice(&SEGMENT_OFFSET.to_le_bytes()); //p_offset
+  phdr[32..40].copy_from_slice(&SEGMENT_SIZE.to_le_bytes()); //p_filesz
+  phdr[40..48].copy_from_slice(&SEGMENT_SIZE.to_le_bytes()); //p_memsz
+  phdr[48..56].copy_from_slice(&(*PAGE_SIZE as u64).to_le_bytes()); //p_align
+  //segment content
+  for offset in SEGMENT_OFFSET..elf.len() as u64 {
+    elf[offset as usize] = fixture_byte(offset);
+  }
+
+  fs::write(path, elf).unwrap();
+}
+
+//Allocates a pool of pages and returns the rows of which all frames were
+//allocated, the pages are released so the loader can allocate them again
+fn find_free_rows(count: usize) -> Vec<u64> {
+  let pages: Vec<MemoryMap> = (0..POOL_SIZE).map(|_| {
+    let page = MemoryMap::new(*PAGE_SIZE,
+      &[MapOption::MapReadable, MapOption::MapWritable]).unwrap();
+    unsafe {std::ptr::write_volatile(page.data(), 1);}
+    page
+  }).collect();
+  let frames: HashSet<u64> =
+    pages.iter().map(|p| proc::virt_to_phys(p.data() as u64)).collect();
+
+  let mut rows: Vec<u64> = frames.iter().cloned()
+    .filter(|f| f & *ROW_ALIGN_MASK == *f)
+    .filter(|f| utils::get_frames_in_row(*f).iter().all(|x| frames.contains(x)))
+    .collect();
+  rows.sort();
+  assert!(rows.len() >= count, "Not enough complete rows allocated");
+  rows.truncate(count);
+
+  rows
+}
+
+//Physical address oracle without root privileges: every new page gets the
+//next frame after FRAME_BASE, remapped pages keep their frame
+struct InMemoryOracle {
+  next_frame: Cell<u64>,
+  page2frame: RefCell<HashMap<u64, u64>>
+}
+
+impl InMemoryOracle {
+  fn new() -> InMemoryOracle {
+    InMemoryOracle {next_frame: Cell::new(FRAME_BASE),
+      page2frame: RefCell::new(HashMap::new())}
+  }
+}
+
+impl PhysAddrOracle for InMemoryOracle {
+  fn virt_to_phys(&self, virt_addr: u64) -> u64 {
+    let page = virt_addr & *PAGE_ALIGN_MASK;
+    let frame = *self.page2frame.borrow_mut().entry(page).or_insert_with(|| {
+      let frame = self.next_frame.get();
+      self.next_frame.set(frame + *PAGE_SIZE as u64);
+      frame
+    });
+    frame | (virt_addr & *PAGE_OFFSET_MASK)
+  }
+
+  fn remapped(&self, old_virt: u64, new_virt: u64) {
+    let mut page2frame = self.page2frame.borrow_mut();
+    if let Some(frame) = page2frame.remove(&(old_virt & *PAGE_ALIGN_MASK)) {
+      page2frame.insert(new_virt & *PAGE_ALIGN_MASK, frame);
+    }
+  }
+}
+
+//Returns a virtual address range of arg:len bytes that is unmapped for sure
+fn find_unmapped_range(len: usize) -> u64 {
+  let range = MemoryMap::new(len, &[MapOption::MapReadable]).unwrap();
+  range.data() as u64
+}
+
+//Creates an attack_config.toml that puts each segment page in the first frame
+//of a victim row and flips one bit per page
+//Returns the expected flips: (segment page index, page offset, BitFlip)
+fn create_attack_config(
+  path: &PathBuf, victim_rows: &[u64], aggr_rows: &[u64], segment_addr: u64
+) -> Vec<(u64, u64, BitFlip)> {
+  let mut config = format!("hammer_count = 1\nsegment_virt_addr = 0x{:x}\n",
+    segment_addr);
+  let mut flips = Vec::new();
+  for (page_index, victim_row) in victim_rows.iter().enumerate() {
+    let page_index = page_index as u64;
+    let page_offset = 0x10 + page_index * 0x111;
+    let file_offset = SEGMENT_OFFSET + page_index * *PAGE_SIZE as u64;
+    let flip_index = 3;
+    //flip the bit to the opposite of its current value
+    let flip_direction =
+      (fixture_byte(file_offset + page_offset) >> flip_index) & 1 == 0;
+    let flip = BitFlip {flip_index, flip_direction};
+    config += &format!("[[victim_frames]]\npage_file_offset = 0x{:x}\n\
+      frame_addr = 0x{:x}\nvictim_bits = [{{offset = 0x{:x}, \
+      bitflip = '{}', aggr_pattern_key = '0'}}]\n",
+      file_offset, victim_row, page_offset, flip);
+    flips.push((page_index, page_offset, flip));
+  }
+  config += &format!("[aggressor_patterns]\n0 = '{}'\n", aggr_rows.iter()
+    .map(|x| format!("0x{:x}(0x0)", x)).collect::<Vec<_>>().join(","));
+
+  fs::write(path, config).unwrap();
+  flips
+}
+
+//DDR3 mapping functions with rows of 2 pages
+fn dram_config() -> DRAMConfig {
+  DRAMConfig {
+    dram_id: "test".to_owned(),
+    row_fn: 0x7ffe0000,
+    column_fn: 0x1fff,
+    bank_fns: vec![0x22000, 0x44000, 0x88000, 0x110000],
+    dram_type: DRAMType::Ddr3,
+    bank_groups: None
+  }
+}
+
+//Loads the fixture binary with its pages in arg:victim_rows and hammers
+//arg:aggr_rows with arg:fake_hammer, then checks the placement (with
+//arg:oracle) and the content of the forged segment
+//arg:name keeps the files of the tests apart
+fn run_pipeline(
+  name: &str, ctx: &RhContext, oracle: &impl PhysAddrOracle,
+  victim_rows: &[u64], aggr_rows: &[u64], fake_hammer: FakeHammer
+) {
+  let dir = std::env::temp_dir();
+  let elf_path = dir.join(format!("gluezilla_loader_test_{}.elf", name));
+  let config_path =
+    dir.join(format!("gluezilla_loader_test_{}_attack_config.toml", name));
+
+  create_elf_fixture(&elf_path);
+  let segment_addr =
+    find_unmapped_range(SEGMENT_PAGES as usize * *PAGE_SIZE);
+  let flips =
+    create_attack_config(&config_path, victim_rows, aggr_rows, segment_addr);
+
+  let mut attack_config = files::parse_toml::<AttackConfig>(
+    config_path.to_str().unwrap()).validate();
+
+  let (frame2map, flipped) = loader::load(ctx, oracle,
+    elf_path.to_str().unwrap(), &mut attack_config, Some(fake_hammer));
+  //the loader returned, control flows back to the "target binary"
+  assert!(flipped, "Not all victim bits flipped as expected");
+
+  //the segment is forged at the fixed address and each page is in its
+  //victim frame
+  for page_index in 0..SEGMENT_PAGES {
+    let virt_page = segment_addr + page_index * *PAGE_SIZE as u64;
+    assert_eq!(oracle.virt_to_phys(virt_page),
+      victim_rows[page_index as usize],
+      "Segment page {} is not in its victim frame", page_index);
+  }
+
+  //the segment content equals the file content, except for the flipped bits
+  for offset in 0..SEGMENT_PAGES * *PAGE_SIZE as u64 {
+    let mut expected = fixture_byte(SEGMENT_OFFSET + offset);
+    for (page_index, page_offset, flip) in &flips {
+      if *page_index * *PAGE_SIZE as u64 + page_offset == offset {
+        let mask = 1u8 << flip.flip_index;
+        expected = if flip.flip_direction {expected | mask}
+          else {expected & !mask};
+      }
+    }
+    let content =
+      unsafe {std::ptr::read_volatile((segment_addr + offset) as *const u8)};
+    assert_eq!(content, expected, "Wrong content at segment offset 0x{:x}",
+      offset);
+  }
+
+  std::mem::drop(frame2map);
+  fs::remove_file(elf_path).unwrap();
+  fs::remove_file(config_path).unwrap();
+}
+
+#[test]
+fn loader_pipeline_with_mapped_fake_hammer() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  let mut ctx = RhContext::from_env().with_dram_config(dram_config());
+  //`su` would ask for a password
+  ctx.settings.keep_page_cache = true;
+  //the first frames the oracle hands out
+  let rows: Vec<u64> = (0..SEGMENT_PAGES + 2)
+    .map(|i| FRAME_BASE + i * *ROW_SIZE).collect();
+  let (victim_rows, aggr_rows) = rows.split_at(SEGMENT_PAGES as usize);
+
+  run_pipeline("mapped", &ctx, &InMemoryOracle::new(), victim_rows,
+    aggr_rows, FakeHammer::Mapped);
+}
+
+#[test]
+#[ignore]
+fn loader_pipeline_with_fake_hammer() {
+  rowhammer::configure().init();
+  let ctx = RhContext::from_env().with_dram_config(dram_config());
+  let rows = find_free_rows(SEGMENT_PAGES as usize + 2);
+  let (victim_rows, aggr_rows) = rows.split_at(SEGMENT_PAGES as usize);
+
+  run_pipeline("devmem", &ctx, &PagemapOracle, victim_rows, aggr_rows,
+    FakeHammer::DevMem);
+}
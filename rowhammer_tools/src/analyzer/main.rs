@@ -0,0 +1,143 @@
+use log::*;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use env_logger::Env;
+
+use rowhammer::analysis;
+use rowhammer::utils::{files, serialize::*};
+use rowhammer::context::RhContext;
+
+//Post-processing of templater output: computes the row distance between every
+//discovered flip and the aggressors of its pattern, and prints histograms of
+//the distance to the nearest aggressor (distance 1 = adjacent, distance 2 =
+//e.g. Half-Double), overall and per garbage count
+//The full report is written to <template>_distances.json
+//Does not hammer, so it does not need root (nor the machine of the template)
+//USAGE: ./analyzer path/to/templating<id>.json
+//
+//Anonymized export: writes <template>_anonymized.json, a copy of the template
+//that can be published without the host-identifying fields, the victims and
+//aggressors only have DRAM addresses (see analysis::anonymize)
+//USAGE: ./analyzer anonymize path/to/templating<id>.json
+//
+//Frame map: renders which wanted frames the allocation of a templater run
+//captured, per bank and row (see analysis::render_frame_map)
+//USAGE: ./analyzer frame-map path/to/frame_map<id>.json
+//
+//Attack estimate: the probability that an attack config succeeds and the
+//expected hammer attempts and rounds of the loader, from the reliability of
+//its victim bits in the template they were taken from (see
+//analysis::estimate_attack), written to <attack_config>_estimate.json
+//`garbage_count` is the garbage count of the reliabilities (default: the one
+//where each bit flipped most often), `binary` resolves bits given by a symbol
+//USAGE: ./analyzer estimate path/to/templating<id>.json attack_config.toml
+//  [garbage_count] [binary]
+
+pub fn main() {
+  env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+    .format_timestamp(None).init();
+  let mut args = std::env::args().skip(1);
+  let mut path = args.next().expect("Provide the path to a memory template");
+  let anonymize = path == "anonymize";
+  if anonymize {
+    path = args.next().expect("Provide the path to a memory template");
+  }
+  if path == "frame-map" {
+    let path = args.next().expect("Provide the path to a frame map");
+    let map = files::parse_json::<analysis::FrameMap>(&path);
+    print!("{}", analysis::render_frame_map(&map));
+    return;
+  }
+  if path == "estimate" {
+    let path = args.next().expect("Provide the path to a memory template");
+    let config_path = args.next()
+      .expect("Provide the path to an attack config");
+    let garbage_count = args.next().map(|x| x.parse()
+      .expect("The garbage count is NaN"));
+    run_estimate(&path, &config_path, garbage_count, args.next());
+    return;
+  }
+
+  let template = files::parse_json::<MemoryTemplate>(&path);
+  if anonymize {
+    let out_path = with_suffix(&path, "_anonymized.json");
+    let victims = template.victims.len();
+    let mut file = File::create(&out_path).unwrap();
+    write!(file, "{}", serde_json::to_string(&analysis::anonymize(template))
+      .unwrap()).unwrap();
+    info!("Anonymized template with {} victims written to {}", victims,
+      out_path);
+    return;
+  }
+  let report = analysis::distance_report(&template);
+
+  let other_bank =
+    report.victims.iter().filter(|v| v.min_distance.is_none()).count();
+  info!("{} victims, {} without aggressor in the same bank",
+    report.victims.len(), other_bank);
+  info!("Distance to the nearest aggressor:");
+  for (distance, count) in &report.histogram {
+    info!("  {}: {}", distance, count);
+  }
+  for (garbage_count, histogram) in &report.histogram_per_garbage_count {
+    info!("garbage_count {}: {}", garbage_count, histogram.iter()
+      .map(|(d, c)| format!("{}: {}", d, c)).collect::<Vec<_>>().join(", "));
+  }
+
+  let out_path = with_suffix(&path, "_distances.json");
+  let mut file = File::create(&out_path).unwrap();
+  write!(file, "{}", serde_json::to_string(&report).unwrap()).unwrap();
+  info!("Report written to {}", out_path);
+}
+
+fn run_estimate(
+  path: &str, config_path: &str, garbage_count: Option<u32>,
+  program_path: Option<String>
+) {
+  let template = files::parse_json::<MemoryTemplate>(path);
+  let ctx = RhContext::from_env()
+    .with_dram_config(template.dram_config.clone());
+  let template = template.with_geometry(&ctx.geometry);
+  let mut attack_config = files::parse_toml::<AttackConfig>(config_path)
+    .with_geometry(&ctx.geometry);
+  if let Some(program_path) = program_path {
+    for error in attack_config.resolve_symbols(&program_path) {
+      error!("{}", error);
+    }
+  }
+  let attack_config = attack_config.normalize();
+  let estimate = analysis::estimate_attack(&template, &attack_config,
+    garbage_count);
+  if estimate.template_hammer_count != estimate.attack_hammer_count {
+    warn!("The template hammered {} times per round, the attack hammers {} \
+      times, the reliabilities may not carry over",
+      estimate.template_hammer_count, estimate.attack_hammer_count);
+  }
+  for bit in &estimate.bits {
+    let source = match (bit.template_victim, bit.same_pattern) {
+      (None, _) => "not in the template".to_owned(),
+      (Some(i), same_pattern) => format!("victim {}{}, garbage count {}", i,
+        if same_pattern {""} else {" (another pattern)"},
+        bit.garbage_count.map_or("-".to_owned(), |g| g.to_string()))
+    };
+    info!("P0x{:x} {} (pattern {}{}): reliability {:.3} ({}), flips with \
+      {:.3} in {} attempts", bit.phys_addr, bit.bitflip, bit.aggr_pattern_key,
+      if bit.tolerable {", tolerable"} else {""}, bit.reliability, source,
+      bit.flip_probability, bit.attempts);
+  }
+  info!("Success probability: {:.3} (all bits flip: {:.3})",
+    estimate.success_probability, estimate.all_flip_probability);
+  info!("Expected hammer attempts: {:.2}, hammer rounds: {:.2}",
+    estimate.expected_attempts, estimate.expected_hammer_rounds);
+
+  let out_path = with_suffix(config_path, "_estimate.json");
+  let mut file = File::create(&out_path).unwrap();
+  write!(file, "{}", serde_json::to_string(&estimate).unwrap()).unwrap();
+  info!("Estimate written to {}", out_path);
+}
+
+//arg:path without its extension + arg:suffix
+fn with_suffix(path: &str, suffix: &str) -> String {
+  Path::new(path).with_extension("").to_string_lossy().into_owned() + suffix
+}
@@ -0,0 +1,290 @@
+use log::*;
+use std::fs;
+use std::time::Duration;
+use serde::Serialize;
+
+use rowhammer::{allocation, calibration, hammer, preflight};
+use rowhammer::context::RhContext;
+use rowhammer::schedule_export::ExportedSchedule;
+use rowhammer::hammer::{dynasmrt::{dynasm, DynasmApi, x64::Assembler},
+  FlushInstruction, HammerStats};
+use rowhammer::utils::{host, ram_swap, sched,
+  serialize::{CoreType, HostConfig}};
+use rowhammer::utils::garbage::garbage_add;
+use rowhammer::utils::oracle::PagemapOracle;
+use rowhammer::utils::tool_lock::{self, ToolLock};
+
+//Hammer throughput benchmark: measures the aggressor access rate every hammer
+//backend reaches on every core of this machine, with every flush strategy,
+//so the hammer_count and garbage counts of the experiment configs can be
+//chosen from measured instead of assumed throughput
+//The aggressors are BENCH_AGGRESSORS addresses one row apart in a buffer
+//(their banks and rows do not matter for the throughput, only that every
+//access misses the cache)
+//Backends:
+//  - `rust`: hammer::hammer_rust
+//  - `asm`: hammer::hammer_asm (garbage count >= 1)
+//  - `jit:<kernel>`: the built-in kernels and the kernels of the plugins in
+//    `RH_HAMMER_PLUGINS`
+//  - `jit:flush`: the aggressors are accessed, then flushed with the flush
+//    strategy (see `FlushKernel`), the strategies with an instruction the CPU
+//    does not support are skipped
+//The rust backend flushes with clflush, the other backends with the
+//instruction of `RH_FLUSH` (see hammer::FlushInstruction)
+//Every combination runs BENCH_RUNS times on every core in `RH_BENCH_CORES`
+//(comma separated, default: the assigned cores), the medians are written to
+//bench<id>.json with the host config
+//On CPUs with a hybrid core topology the assigned cores are restricted to the
+//type of `RH_CORE_TYPE` (see host::restrict_to_core_type), list E-cores in
+//`RH_BENCH_CORES` to compare both types
+//USAGE: sudo ./bench <arbitrary_id> [garbage_count]
+
+//"replay" mode: executes the n-th (default: the first) schedule of a file
+//written with `RH_EXPORT_SCHEDULE` (see schedule_export) with
+//hammer::ReplayKernel, the physical frames of the schedule are allocated
+//again, e.g. to compare the timing of the schedules of two backends on the
+//same addresses, the flushes use their recorded flush instruction (or
+//its fallback)
+//USAGE: sudo ./bench replay <schedules.jsonl> [n]
+
+//Both modes take the tool lock (see utils/tool_lock.rs), pass `--steal-lock`
+//to take it from a running tool, and check their external dependencies
+//first (see preflight.rs)
+
+static BENCH_AGGRESSORS: usize = 2;
+static BENCH_HAMMER_COUNT: u64 = 100_000;
+static BENCH_RUNS: usize = 5;
+static DEFAULT_GARBAGE_COUNT: u32 = 1;
+
+#[derive(Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Flush {
+  Clflush,
+  Clflushopt,
+  Clwb,
+  //clflush followed by an mfence after the flushes of every iteration
+  ClflushMfence
+}
+
+impl Flush {
+  fn name(&self) -> &'static str {
+    match self {
+      Flush::ClflushMfence => "clflush_mfence",
+      _ => self.instruction().name()
+    }
+  }
+
+  fn instruction(&self) -> FlushInstruction {
+    match self {
+      Flush::Clflushopt => FlushInstruction::Clflushopt,
+      Flush::Clwb => FlushInstruction::Clwb,
+      _ => FlushInstruction::Clflush
+    }
+  }
+}
+
+impl From<FlushInstruction> for Flush {
+  fn from(flush: FlushInstruction) -> Flush {
+    match flush {
+      FlushInstruction::Clflush => Flush::Clflush,
+      FlushInstruction::Clflushopt => Flush::Clflushopt,
+      FlushInstruction::Clwb => Flush::Clwb
+    }
+  }
+}
+
+static FLUSHES: [Flush; 4] = [Flush::Clflush, Flush::Clflushopt, Flush::Clwb,
+  Flush::ClflushMfence];
+
+//Accesses all aggressors, then flushes them with arg:flush, followed by the
+//garbage
+struct FlushKernel {
+  flush: Flush
+}
+
+impl hammer::HammerKernel for FlushKernel {
+  fn name(&self) -> &str {
+    "flush"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &hammer::KernelParams) {
+    let aggrs: Vec<u64> = params.aggrs.iter().map(|a| a[0]).collect();
+    for _ in 0..params.hammer_count {
+      for aggr in &aggrs {
+        dynasm!(ops
+          ; mov rax, QWORD *aggr as i64
+          ; mov rdx, [rax]
+        );
+      }
+      for aggr in &aggrs {
+        dynasm!(ops; mov rax, QWORD *aggr as i64);
+        self.flush.instruction().emit(ops, 0);
+      }
+      if self.flush == Flush::ClflushMfence {
+        dynasm!(ops; mfence);
+      }
+      garbage_add(ops, 1, params.garbage_count);
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct BenchResult {
+  core: u32,
+  //None without a hybrid core topology
+  core_type: Option<CoreType>,
+  backend: String,
+  flush: Flush,
+  garbage_count: u32,
+  aggressors: usize,
+  hammer_count: u64,
+  //median of BENCH_RUNS runs
+  duration_ns: u128,
+  accesses_per_s: f64,
+  activations_per_trefi: f64
+}
+
+#[derive(Serialize)]
+struct Bench {
+  host_config: HostConfig,
+  results: Vec<BenchResult>
+}
+
+//The median duration of BENCH_RUNS runs of arg:hammer
+fn median_duration(mut hammer: impl FnMut() -> HammerStats) -> Duration {
+  let mut durations: Vec<Duration> = (0..BENCH_RUNS)
+    .map(|_| hammer().duration).collect();
+  durations.sort();
+  durations[durations.len() / 2]
+}
+
+fn bench_cores() -> Vec<u32> {
+  let mut cores: Vec<u32> = match std::env::var("RH_BENCH_CORES") {
+    Ok(cores) => cores.split(',').map(|c| c.trim().parse()
+      .expect("RH_BENCH_CORES is not a list of cores")).collect(),
+    Err(_) => host::get_assigned_cpu_cores().into_iter().collect()
+  };
+  cores.sort();
+  cores
+}
+
+fn replay(ctx: &RhContext, path: &str, index: usize) {
+  let schedule = ExportedSchedule::load(path, index);
+  info!("Replaying schedule {} of {} ({}, {} operations, {} x {} iterations)",
+    index, path, schedule.backend, schedule.iteration.len(), schedule.bursts,
+    schedule.hammer_count);
+  let frame2map = allocation::allocate_pages(ctx, &PagemapOracle,
+    schedule.frames(ctx), 0.0);
+  let kernel = hammer::ReplayKernel::new(schedule.remap(ctx, &frame2map));
+  let no_aggrs = Vec::new();
+  //the flushes of the replay use the instructions of their operations
+  let params = hammer::KernelParams {aggrs: &no_aggrs, rates: &[],
+    hammer_count: schedule.hammer_count, garbage_count: 0,
+    flush: FlushInstruction::Clflush, unroll: ctx.settings.jit_unroll,
+    probes: &ctx.jit_probes};
+  let stats = hammer::hammer_kernel_bursts(ctx, &kernel, &params,
+    schedule.bursts, Duration::from_nanos(schedule.idle_ns));
+  info!("Replay took {:?} ({:.0} accesses/s)", stats.duration,
+    stats.accesses_per_s);
+}
+
+//Prints arg:question and returns the answer from the terminal (see
+//ram_swap::prompt)
+fn ask(question: &str) -> String {
+  println!("{}", question);
+  let mut answer = String::new();
+  std::io::stdin().read_line(&mut answer).unwrap();
+  answer
+}
+
+pub fn main() {
+  let mut logger_builder = rowhammer::configure();
+  logger_builder.init();
+  preflight::check(&preflight::tool_features("bench"));
+  let mut args: Vec<String> = std::env::args().skip(1).collect();
+  let _lock = ToolLock::acquire("bench", tool_lock::take_steal_flag(&mut args));
+  host::restrict_to_core_type();
+  let mut args = args.into_iter();
+  let id = args.next().expect("Provide an experiment ID");
+  if id == "replay" {
+    let path = args.next().expect("Provide the schedule file");
+    let index = args.next().map_or(0, |x| x.parse()
+      .expect("The schedule index is NaN"));
+    replay(&RhContext::from_env(), &path, index);
+    return;
+  }
+  let garbage_count: u32 = args.next().map_or(DEFAULT_GARBAGE_COUNT,
+    |x| x.parse().expect("The garbage count is NaN"));
+  //recorded in the host config
+  ram_swap::ensure_or_ask(ask);
+
+  let mut ctx = RhContext::from_env();
+  //the throughput is measured with BENCH_HAMMER_COUNT iterations
+  ctx.settings.hammer_duration = None;
+  ctx.kernels.load_plugins_from_env();
+  let row_size = ctx.geometry.row_size() as usize;
+  let buffer = vec![1u8; BENCH_AGGRESSORS * row_size];
+  let aggrs: Vec<u64> = (0..BENCH_AGGRESSORS)
+    .map(|i| buffer[i * row_size..].as_ptr() as u64).collect();
+  let aliases: Vec<Vec<u64>> = aggrs.iter().map(|a| vec![*a]).collect();
+  let flush = ctx.settings.flush;
+  let params = hammer::KernelParams {aggrs: &aliases, rates: &[],
+    hammer_count: BENCH_HAMMER_COUNT, garbage_count, flush,
+    unroll: ctx.settings.jit_unroll, probes: &ctx.jit_probes};
+
+  //(backend, flush, hammer function)
+  let mut backends: Vec<(String, Flush, Box<dyn Fn() -> HammerStats + '_>)> =
+    vec![
+    ("rust".to_owned(), Flush::Clflush,
+      Box::new(|| hammer::hammer_rust(&aggrs, BENCH_HAMMER_COUNT))),
+    ("asm".to_owned(), flush.into(), Box::new(|| hammer::hammer_asm(&aggrs,
+      garbage_count.max(1), BENCH_HAMMER_COUNT, flush)))
+  ];
+  for name in ctx.kernels.names() {
+    let kernel = ctx.kernels.get(&name);
+    let (ctx, params) = (&ctx, &params);
+    backends.push((format!("jit:{}", name), flush.into(),
+      Box::new(move || hammer::hammer_kernel(ctx, &*kernel, params))));
+  }
+  for flush in FLUSHES.iter().cloned()
+    .filter(|f| f.instruction().supported())
+  {
+    let kernel = FlushKernel {flush};
+    let (ctx, params) = (&ctx, &params);
+    backends.push(("jit:flush".to_owned(), flush,
+      Box::new(move || hammer::hammer_kernel(ctx, &kernel, params))));
+  }
+
+  let host_config = host::read_config();
+  let core_types = host::get_core_types();
+  let mut results = Vec::new();
+  for core in bench_cores() {
+    sched::pin_to_core(core);
+    for (backend, flush, hammer) in &backends {
+      let duration = median_duration(hammer);
+      let accesses = (BENCH_AGGRESSORS as u64 * BENCH_HAMMER_COUNT) as f64;
+      let result = BenchResult {
+        core,
+        core_type: core_types.as_ref().and_then(|t| t.get(&core).cloned()),
+        backend: backend.clone(),
+        flush: *flush,
+        garbage_count,
+        aggressors: BENCH_AGGRESSORS,
+        hammer_count: BENCH_HAMMER_COUNT,
+        duration_ns: duration.as_nanos(),
+        accesses_per_s: accesses / duration.as_secs_f64().max(1e-9),
+        activations_per_trefi: calibration::activation_rate(BENCH_AGGRESSORS,
+          BENCH_HAMMER_COUNT, duration)
+      };
+      info!("core {} {} ({}): {:.0} accesses/s, {:.2} activations/tREFI",
+        core, backend, flush.name(),
+        result.accesses_per_s, result.activations_per_trefi);
+      results.push(result);
+    }
+  }
+
+  let path = format!("bench{}.json", id);
+  fs::write(&path, serde_json::to_string_pretty(&Bench {host_config, results})
+    .unwrap()).unwrap_or_else(|_| panic!("Writing {} failed", path));
+  info!("Benchmark results written to {}", path);
+}
@@ -0,0 +1,449 @@
+use log::*;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use rowhammer::utils::kmsg;
+
+//Remote control daemon for the lab machines: a small HTTP API to start and
+//stop templater and prehammer jobs, follow their logs and fetch their result
+//files, so all machines can be driven from one place
+//Every request needs the header `Authorization: Bearer <token>` with the
+//token in the `RH_DAEMON_TOKEN` environment variable
+//Every job runs in its own directory jobs/<id> (relative to the working
+//directory of the daemon), which starts with a copy of the config files
+//(*.toml, victim_addresses.txt) in the working directory of the daemon, the
+//files in the request are written on top of them
+//The jobs are queued and run one after another (concurrent hammering would
+//disturb both), a thermal-sensitive job (`thermal`, default) only starts
+//when the last thermal-sensitive job exited at least the cooldown ago, so
+//the DIMMs are back at their idle temperature (`RH_DAEMON_COOLDOWN=<s>`,
+//default DEFAULT_COOLDOWN, `cooldown_s` of a job overrides it for the wait
+//before that job)
+//A snapshot of the machine state (load, memory pressure, temperatures,
+//kernel events) is written to machine_state_start.json and
+//machine_state_end.json in the job directory when the job starts and exits
+//API:
+//  POST /jobs                     queue a job, body:
+//    {"tool": "templater", "args": ["1"], "files": {"<name>": "<content>"},
+//     "thermal": true, "cooldown_s": 300}
+//  GET  /jobs                     all jobs and their state
+//  GET  /jobs/<id>                the state of a job
+//  POST /jobs/<id>/stop[?kill]    SIGINT (the templater exports the results
+//                                 so far), or SIGKILL with `kill`, a queued
+//                                 job is cancelled
+//  GET  /jobs/<id>/log            the stdout and stderr of the job, follows
+//                                 the log until the job exited
+//  GET  /jobs/<id>/files          the files in the job directory
+//  GET  /jobs/<id>/files/<name>   a file in the job directory
+//The API is plain HTTP, so the token can be read by anyone on the path to the
+//daemon: it listens on the loopback interface by default (reach it through
+//an SSH tunnel), an address on another interface has to be allowed with
+//`RH_DAEMON_EXPOSE=1`
+//Build with `--features daemon`
+//USAGE: sudo RH_DAEMON_TOKEN=<token> ./daemon [address]
+//  `address` defaults to DEFAULT_ADDRESS
+
+static DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+static JOBS_DIR: &str = "jobs";
+//binaries (next to the daemon) that can be started
+static TOOLS: [&str; 2] = ["templater", "prehammer"];
+//poll interval when following the log of a running job
+static LOG_POLL: Duration = Duration::from_millis(500);
+//poll interval of the queue
+static QUEUE_POLL: Duration = Duration::from_secs(1);
+static DEFAULT_COOLDOWN: Duration = Duration::from_secs(120);
+
+#[derive(Deserialize)]
+struct JobRequest {
+  tool: String,
+  #[serde(default)]
+  args: Vec<String>,
+  //file name -> content, written to the job directory before the start
+  #[serde(default)]
+  files: HashMap<String, String>,
+  //whether the job has to wait for the cooldown
+  #[serde(default = "default_thermal")]
+  thermal: bool,
+  cooldown_s: Option<u64>
+}
+
+fn default_thermal() -> bool {
+  true
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+enum JobState {
+  Queued,
+  Running {pid: u32},
+  Exited {code: Option<i32>},
+  Cancelled
+}
+
+#[derive(Serialize)]
+struct Job {
+  id: u64,
+  tool: String,
+  args: Vec<String>,
+  thermal: bool,
+  cooldown_s: Option<u64>,
+  state: Mutex<JobState>
+}
+
+impl Job {
+  fn dir(&self) -> PathBuf {
+    job_dir(self.id)
+  }
+
+  fn state(&self) -> JobState {
+    self.state.lock().unwrap().clone()
+  }
+
+  //queued or running
+  fn is_active(&self) -> bool {
+    matches!(self.state(), JobState::Queued | JobState::Running {..})
+  }
+}
+
+struct Daemon {
+  jobs: Mutex<Vec<Arc<Job>>>,
+  token: String,
+  cooldown: Duration,
+  //when the last thermal-sensitive job exited
+  last_thermal_end: Mutex<Option<Instant>>,
+  //kernel events since the last machine state snapshot
+  kmsg_reader: Mutex<Option<kmsg::KmsgReader>>
+}
+
+#[derive(Serialize)]
+struct MachineState {
+  time: String,
+  loadavg: String,
+  memory_pressure: String,
+  //sensor -> degrees Celsius
+  temperatures: Vec<(String, f64)>,
+  kernel_messages: Vec<String>
+}
+
+fn job_dir(id: u64) -> PathBuf {
+  Path::new(JOBS_DIR).join(id.to_string())
+}
+
+pub fn main() {
+  let mut logger_builder = rowhammer::configure();
+  logger_builder.init();
+
+  let token = std::env::var("RH_DAEMON_TOKEN")
+    .expect("Set the API token in RH_DAEMON_TOKEN");
+  assert!(!token.is_empty(), "The API token is empty");
+  let address = std::env::args().nth(1)
+    .unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+  let exposed = address.to_socket_addrs()
+    .unwrap_or_else(|e| panic!("Invalid address {}: {}", address, e))
+    .any(|a| !a.ip().is_loopback());
+  assert!(!exposed
+    || std::env::var("RH_DAEMON_EXPOSE").is_ok_and(|x| x == "1"),
+    "{} is reachable from other machines and the API is plain HTTP (the \
+    token can be sniffed), set RH_DAEMON_EXPOSE=1 to listen on it anyway",
+    address);
+  let cooldown = std::env::var("RH_DAEMON_COOLDOWN").ok()
+    .map_or(DEFAULT_COOLDOWN, |x| Duration::from_secs(x.parse()
+    .expect("RH_DAEMON_COOLDOWN is NaN")));
+  fs::create_dir_all(JOBS_DIR).expect("Could not create the jobs directory");
+
+  let daemon = Arc::new(Daemon {
+    jobs: Mutex::new(Vec::new()),
+    token,
+    cooldown,
+    last_thermal_end: Mutex::new(None),
+    kmsg_reader: Mutex::new(kmsg::KmsgReader::open().map_err(|e|
+      warn!("Could not open /dev/kmsg, kernel events are not recorded: {}", e))
+      .ok())
+  });
+  let queue = daemon.clone();
+  std::thread::spawn(move || run_queue(&queue));
+  let server = Server::http(&address)
+    .unwrap_or_else(|e| panic!("Could not listen on {}: {}", address, e));
+  info!("Listening on {}", address);
+  for request in server.incoming_requests() {
+    let daemon = daemon.clone();
+    //log requests can take until the job exited
+    std::thread::spawn(move || handle(&daemon, request));
+  }
+}
+
+fn handle(daemon: &Daemon, mut request: Request) {
+  let authorized = request.headers().iter()
+    .find(|h| h.field.equiv("Authorization"))
+    .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+    .is_some_and(|t| constant_time_eq(t.as_bytes(), daemon.token.as_bytes()));
+  if !authorized {
+    warn!("Unauthorized request from {:?}", request.remote_addr());
+    respond(request, error(401, "unauthorized"));
+    return;
+  }
+  info!("{} {}", request.method(), request.url());
+
+  let url = request.url().to_owned();
+  let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+  let segments: Vec<&str> =
+    path.split('/').filter(|s| !s.is_empty()).collect();
+  let method = request.method().clone();
+  let job = segments.get(1).and_then(|id| id.parse().ok())
+    .and_then(|id: u64| daemon.jobs.lock().unwrap().iter()
+    .find(|j| j.id == id).cloned());
+  let response = match (&method, &segments[..], job) {
+    (Method::Post, ["jobs"], _) => {
+      let mut body = String::new();
+      match request.as_reader().read_to_string(&mut body).ok()
+        .and_then(|_| serde_json::from_str(&body).ok())
+      {
+        Some(job_request) => queue_job(daemon, job_request),
+        None => error(400, "invalid job request")
+      }
+    },
+    (Method::Get, ["jobs"], _) =>
+      json(&daemon.jobs.lock().unwrap().iter().map(|j| j.as_ref())
+      .collect::<Vec<_>>()),
+    (_, ["jobs", _, ..], None) => error(404, "unknown job"),
+    (Method::Get, ["jobs", _], Some(job)) => json(job.as_ref()),
+    (Method::Post, ["jobs", _, "stop"], Some(job)) => stop_job(&job, query),
+    (Method::Get, ["jobs", _, "log"], Some(job)) => {
+      respond(request, follow_log(job));
+      return;
+    },
+    (Method::Get, ["jobs", _, "files"], Some(job)) => list_files(&job),
+    (Method::Get, ["jobs", _, "files", name], Some(job)) => {
+      //no paths out of the job directory, checked before opening
+      let file = Some(name).filter(|n| is_plain_name(n))
+        .and_then(|n| File::open(job.dir().join(n)).ok());
+      match file {
+        Some(file) => {
+          respond(request, Response::from_file(file));
+          return;
+        },
+        None => error(404, "unknown file")
+      }
+    },
+    _ => error(404, "unknown endpoint")
+  };
+  respond(request, response);
+}
+
+fn queue_job(daemon: &Daemon, job_request: JobRequest)
+  -> Response<std::io::Cursor<Vec<u8>>>
+{
+  if !TOOLS.contains(&job_request.tool.as_str()) {
+    return error(400, "unknown tool");
+  }
+  if job_request.files.keys().any(|name| !is_plain_name(name)) {
+    return error(400, "invalid file name");
+  }
+  let mut jobs = daemon.jobs.lock().unwrap();
+  //continue the numbering of a previous daemon
+  let id = (jobs.len() as u64..).find(|id| !job_dir(*id).exists()).unwrap();
+  let dir = job_dir(id);
+  fs::create_dir_all(&dir).expect("Could not create the job directory");
+  for entry in fs::read_dir(".").expect("Could not read the working dir")
+    .flatten()
+  {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    if entry.path().is_file()
+      && (name.ends_with(".toml") || name == "victim_addresses.txt")
+    {
+      fs::copy(entry.path(), dir.join(&name))
+        .unwrap_or_else(|_| panic!("Could not copy {}", name));
+    }
+  }
+  for (name, content) in &job_request.files {
+    fs::write(dir.join(name), content)
+      .unwrap_or_else(|_| panic!("Could not write {}", name));
+  }
+
+  //the log is followed from the start, even while the job is queued
+  File::create(dir.join("log")).expect("Could not create the log");
+
+  info!("Queued job {}: {} {}", id, job_request.tool,
+    job_request.args.join(" "));
+  jobs.push(Arc::new(Job {
+    id,
+    tool: job_request.tool,
+    args: job_request.args,
+    thermal: job_request.thermal,
+    cooldown_s: job_request.cooldown_s,
+    state: Mutex::new(JobState::Queued)
+  }));
+  json(&serde_json::json!({"id": id}))
+}
+
+//Starts the queued jobs one after another, see the comment at the top for
+//the cooldown
+fn run_queue(daemon: &Arc<Daemon>) {
+  loop {
+    std::thread::sleep(QUEUE_POLL);
+    let jobs = daemon.jobs.lock().unwrap();
+    if jobs.iter().any(|j| matches!(j.state(), JobState::Running {..})) {
+      continue;
+    }
+    let job = match jobs.iter().find(|j| matches!(j.state(), JobState::Queued))
+    {
+      Some(job) => job.clone(),
+      None => continue
+    };
+    let cooldown = job.cooldown_s.map_or(daemon.cooldown, Duration::from_secs);
+    let cooling = daemon.last_thermal_end.lock().unwrap()
+      .is_some_and(|end| end.elapsed() < cooldown);
+    if job.thermal && cooling {
+      continue;
+    }
+    start_job(daemon, job);
+  }
+}
+
+fn start_job(daemon: &Arc<Daemon>, job: Arc<Job>) {
+  write_machine_state(daemon, &job, "start");
+  let binary = std::env::current_exe().expect("Could not find the daemon")
+    .with_file_name(&job.tool);
+  let log = File::options().append(true).open(job.dir().join("log"))
+    .expect("Could not open the log");
+  let child = Command::new(&binary)
+    .args(&job.args)
+    .current_dir(job.dir())
+    .stdin(Stdio::null())
+    .stdout(log.try_clone().unwrap())
+    .stderr(log)
+    .spawn();
+  let mut child = match child {
+    Ok(child) => child,
+    Err(e) => {
+      warn!("Starting {:?} failed: {}", binary, e);
+      *job.state.lock().unwrap() = JobState::Exited {code: None};
+      return;
+    }
+  };
+  info!("Started job {}: {} {}", job.id, job.tool, job.args.join(" "));
+  *job.state.lock().unwrap() = JobState::Running {pid: child.id()};
+
+  let daemon = daemon.clone();
+  std::thread::spawn(move || {
+    let code = child.wait().ok().and_then(|s| s.code());
+    info!("Job {} exited with {:?}", job.id, code);
+    if job.thermal {
+      *daemon.last_thermal_end.lock().unwrap() = Some(Instant::now());
+    }
+    write_machine_state(&daemon, &job, "end");
+    *job.state.lock().unwrap() = JobState::Exited {code};
+  });
+}
+
+fn stop_job(job: &Job, query: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+  let mut state = job.state.lock().unwrap();
+  match *state {
+    JobState::Queued => {
+      info!("Cancelling job {}", job.id);
+      *state = JobState::Cancelled;
+    },
+    JobState::Running {pid} => {
+      let signal = if query == "kill" {libc::SIGKILL} else {libc::SIGINT};
+      info!("Sending signal {} to job {}", signal, job.id);
+      unsafe {libc::kill(pid as libc::pid_t, signal);}
+    },
+    _ => return error(409, "the job is not running")
+  }
+  std::mem::drop(state);
+  json(job)
+}
+
+//Writes a snapshot of the machine state to machine_state_<arg:when>.json in
+//the directory of arg:job
+fn write_machine_state(daemon: &Daemon, job: &Job, when: &str) {
+  let state = MachineState {
+    time: Local::now().to_rfc3339(),
+    loadavg: kmsg::read_loadavg(),
+    memory_pressure: kmsg::read_memory_pressure(),
+    temperatures: kmsg::read_temperatures(),
+    kernel_messages: daemon.kmsg_reader.lock().unwrap().as_mut()
+      .map_or(Vec::new(), |r| r.read_new_interesting())
+  };
+  let path = job.dir().join(format!("machine_state_{}.json", when));
+  if let Err(e) = fs::write(&path,
+    serde_json::to_string_pretty(&state).unwrap())
+  {
+    warn!("Writing {:?} failed: {}", path, e);
+  }
+}
+
+fn list_files(job: &Job) -> Response<std::io::Cursor<Vec<u8>>> {
+  let files: Vec<String> = fs::read_dir(job.dir())
+    .map_or(Vec::new(), |dir| dir.flatten()
+    .map(|e| e.file_name().to_string_lossy().into_owned()).collect());
+  json(&files)
+}
+
+//Reads the log of a job, waits for more output at the end of the file while
+//the job is queued or running
+struct LogFollower {
+  file: File,
+  job: Arc<Job>
+}
+
+impl Read for LogFollower {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+      //check before reading, so the output before the exit is not lost
+      let running = self.job.is_active();
+      let n = self.file.read(buf)?;
+      if n > 0 || !running {
+        return Ok(n);
+      }
+      std::thread::sleep(LOG_POLL);
+    }
+  }
+}
+
+fn follow_log(job: Arc<Job>) -> Response<Box<dyn Read + Send>> {
+  match File::open(job.dir().join("log")) {
+    Ok(file) => Response::new(200.into(),
+      vec![header("Content-Type", "text/plain")],
+      Box::new(LogFollower {file, job}) as Box<dyn Read + Send>, None, None),
+    Err(_) => error(404, "no log").boxed()
+  }
+}
+
+//whether arg:name is a file name without a directory
+fn is_plain_name(name: &str) -> bool {
+  !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y))
+    == 0
+}
+
+fn header(field: &str, value: &str) -> Header {
+  Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json(value: &impl Serialize) -> Response<std::io::Cursor<Vec<u8>>> {
+  Response::from_string(serde_json::to_string(value).unwrap())
+    .with_header(header("Content-Type", "application/json"))
+}
+
+fn error(code: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+  json(&serde_json::json!({"error": message})).with_status_code(code)
+}
+
+fn respond<R: Read>(request: Request, response: Response<R>) {
+  if let Err(e) = request.respond(response) {
+    warn!("Responding failed: {}", e);
+  }
+}
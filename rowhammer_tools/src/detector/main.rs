@@ -0,0 +1,235 @@
+use log::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::{Duration, Instant};
+
+use rowhammer::utils::perf::{self, Counter};
+
+//Defensive companion of the tools in this crate: monitors the host for
+//rowhammer-like activity and logs alerts, so the templater, prehammer and
+//loader (e.g. with and without `RH_STEALTH`) can be evaluated against a
+//detection baseline
+//Two detectors, both with performance counters:
+//  - per process: the LLC misses and LLC references of every thread are
+//    counted, a process with more than `RH_DETECT_MISS_RATE` misses per
+//    second is suspicious (like ANVIL, hammering needs a high rate of memory
+//    accesses that miss the cache), the alert notes a flush-like access
+//    pattern when nearly all references miss (`RH_DETECT_MISS_RATIO`), which
+//    is what clflush (or eviction sets) before every access looks like
+//  - system wide: the DRAM activations counted by the memory controller
+//    PMUs (uncore_imc*, Intel only), more than `RH_DETECT_ACT_RATE`
+//    activations per second raise an alert (without the variable the rate is
+//    only logged at debug level), `RH_DETECT_ACT_EVENT` is the config of the
+//    event (default DEFAULT_ACT_EVENT, ACT_COUNT.ALL on most Intel server and
+//    client parts)
+//The counters are read every `RH_DETECT_INTERVAL_MS` (default
+//DEFAULT_INTERVAL), new threads are picked up at every interval, so threads
+//that live shorter than an interval are missed
+//USAGE: sudo ./detector
+
+static DEFAULT_INTERVAL: Duration = Duration::from_millis(1000);
+static DEFAULT_MISS_RATE: f64 = 3_000_000.0;
+static DEFAULT_MISS_RATIO: f64 = 0.9;
+//event 0x01 (ACT_COUNT), umask 0x0b (RD | WR | BYP)
+static DEFAULT_ACT_EVENT: u64 = 0x0b01;
+
+struct Thresholds {
+  miss_rate: f64,
+  miss_ratio: f64,
+  act_rate: Option<f64>
+}
+
+//The counters of a thread and their last values
+struct TaskCounters {
+  pid: i32,
+  misses: Counter,
+  references: Counter,
+  last: (u64, u64)
+}
+
+impl TaskCounters {
+  fn open(pid: i32, tid: i32) -> Option<TaskCounters> {
+    let open = |config| Counter::open(perf::PERF_TYPE_HARDWARE, config, tid, -1)
+      .ok();
+    Some(TaskCounters {
+      pid,
+      misses: open(perf::PERF_COUNT_HW_CACHE_MISSES)?,
+      references: open(perf::PERF_COUNT_HW_CACHE_REFERENCES)?,
+      last: (0, 0)
+    })
+  }
+
+  //(misses, references) since the last call
+  fn delta(&mut self) -> (u64, u64) {
+    let now = (self.misses.read(), self.references.read());
+    let delta = (now.0.saturating_sub(self.last.0),
+      now.1.saturating_sub(self.last.1));
+    self.last = now;
+    delta
+  }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+  std::env::var(name).ok().map_or(default, |x| x.parse()
+    .unwrap_or_else(|_| panic!("{} is NaN", name)))
+}
+
+//(pid, tid) of all threads on the host
+fn list_tasks() -> Vec<(i32, i32)> {
+  let numbers = |dir: &str| fs::read_dir(dir).map(|entries| entries.flatten()
+    .filter_map(|e| e.file_name().to_str()?.parse().ok()).collect())
+    .unwrap_or_else(|_| Vec::new());
+  numbers("/proc").into_iter().flat_map(|pid: i32|
+    numbers(&format!("/proc/{}/task", pid)).into_iter()
+    .map(move |tid| (pid, tid))).collect()
+}
+
+fn process_name(pid: i32) -> String {
+  fs::read_to_string(format!("/proc/{}/comm", pid))
+    .map_or_else(|_| "?".to_owned(), |c| c.trim().to_owned())
+}
+
+//Many threads need many file descriptors (two per thread)
+fn raise_nofile_limit() {
+  let mut limit = libc::rlimit {rlim_cur: 0, rlim_max: 0};
+  unsafe {
+    if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) == 0 {
+      limit.rlim_cur = limit.rlim_max;
+      libc::setrlimit(libc::RLIMIT_NOFILE, &limit);
+    }
+  }
+}
+
+//Opens the counters of the threads that appeared since the last call and
+//returns the (misses, references) per process since the last call
+fn sample_tasks(
+  tasks: &mut HashMap<i32, TaskCounters>, unmonitored: &mut HashSet<i32>
+) -> HashMap<i32, (u64, u64)> {
+  let own_pid = std::process::id() as i32;
+  let alive: HashMap<i32, i32> = list_tasks().into_iter()
+    .map(|(pid, tid)| (tid, pid)).collect();
+  for (tid, pid) in &alive {
+    if *pid == own_pid || tasks.contains_key(tid) || unmonitored.contains(tid)
+    {
+      continue;
+    }
+    match TaskCounters::open(*pid, *tid) {
+      Some(counters) => {tasks.insert(*tid, counters);},
+      //kernel threads, or the thread exited in the meantime
+      None => {unmonitored.insert(*tid);}
+    }
+  }
+  unmonitored.retain(|tid| alive.contains_key(tid));
+
+  let mut per_process: HashMap<i32, (u64, u64)> = HashMap::new();
+  for counters in tasks.values_mut() {
+    //the counters of exited threads keep their final values
+    let (misses, references) = counters.delta();
+    let sum = per_process.entry(counters.pid).or_insert((0, 0));
+    sum.0 += misses;
+    sum.1 += references;
+  }
+  tasks.retain(|tid, _| alive.contains_key(tid));
+  per_process
+}
+
+fn check_processes(
+  per_process: &HashMap<i32, (u64, u64)>, elapsed: f64,
+  thresholds: &Thresholds
+) {
+  for (pid, (misses, references)) in per_process {
+    let miss_rate = *misses as f64 / elapsed;
+    if miss_rate <= thresholds.miss_rate {
+      continue;
+    }
+    let ratio = *misses as f64 / (*references).max(1) as f64;
+    let pattern = if ratio > thresholds.miss_ratio {
+      ", flush-like access pattern"
+    } else {
+      ""
+    };
+    warn!("ALERT: process {} ({}): {:.0} LLC misses/s, {:.0}% of the LLC \
+      references miss{}", pid, process_name(*pid), miss_rate, ratio * 100.0,
+      pattern);
+  }
+}
+
+fn check_activations(
+  imc_counters: &mut [(String, Counter, u64)], elapsed: f64,
+  thresholds: &Thresholds
+) {
+  if imc_counters.is_empty() {
+    return;
+  }
+  let mut activations = 0;
+  for (_, counter, last) in imc_counters.iter_mut() {
+    let now = counter.read();
+    activations += now.saturating_sub(*last);
+    *last = now;
+  }
+  let act_rate = activations as f64 / elapsed;
+  match thresholds.act_rate {
+    Some(limit) if act_rate > limit =>
+      warn!("ALERT: {:.0} DRAM activations/s", act_rate),
+    _ => debug!("{:.0} DRAM activations/s", act_rate)
+  }
+}
+
+//Counters of the DRAM activations on all memory controller PMUs
+fn open_imc_counters() -> Vec<(String, Counter, u64)> {
+  let config = std::env::var("RH_DETECT_ACT_EVENT").ok().map_or(
+    DEFAULT_ACT_EVENT, |x| u64::from_str_radix(x.trim_start_matches("0x"), 16)
+    .expect("RH_DETECT_ACT_EVENT is not a hex number"));
+  let counters: Vec<_> = perf::find_pmus("uncore_imc").into_iter()
+    .filter_map(|(name, type_, cpu)| match Counter::open(type_, config, -1,
+      cpu)
+    {
+      Ok(counter) => Some((name, counter, 0)),
+      Err(e) => {
+        warn!("Could not count the activations on {}: {}", name, e);
+        None
+      }
+    }).collect();
+  if counters.is_empty() {
+    warn!("No memory controller PMU, the DRAM activations are not monitored");
+  } else {
+    info!("Counting the DRAM activations on {:?}",
+      counters.iter().map(|c| &c.0).collect::<Vec<_>>());
+  }
+  counters
+}
+
+pub fn main() {
+  let mut logger_builder = rowhammer::configure();
+  logger_builder.init();
+
+  raise_nofile_limit();
+  let interval = Duration::from_millis(env_or("RH_DETECT_INTERVAL_MS",
+    DEFAULT_INTERVAL.as_millis() as u64));
+  let thresholds = Thresholds {
+    miss_rate: env_or("RH_DETECT_MISS_RATE", DEFAULT_MISS_RATE),
+    miss_ratio: env_or("RH_DETECT_MISS_RATIO", DEFAULT_MISS_RATIO),
+    act_rate: std::env::var("RH_DETECT_ACT_RATE").ok().map(|x| x.parse()
+      .expect("RH_DETECT_ACT_RATE is NaN"))
+  };
+
+  let mut imc_counters = open_imc_counters();
+  let mut tasks = HashMap::new();
+  let mut unmonitored = HashSet::new();
+  sample_tasks(&mut tasks, &mut unmonitored);
+  info!("Monitoring {} threads every {:?}", tasks.len(), interval);
+  if tasks.is_empty() {
+    warn!("No thread could be monitored, are the hardware cache events \
+      supported (perf_event_paranoid, virtual machine)?");
+  }
+
+  let mut last = Instant::now();
+  loop {
+    std::thread::sleep(interval);
+    let per_process = sample_tasks(&mut tasks, &mut unmonitored);
+    let elapsed = last.elapsed().as_secs_f64();
+    last = Instant::now();
+    check_processes(&per_process, elapsed, &thresholds);
+    check_activations(&mut imc_counters, elapsed, &thresholds);
+  }
+}
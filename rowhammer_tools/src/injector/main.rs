@@ -0,0 +1,105 @@
+use log::*;
+use std::fs::OpenOptions;
+
+use rowhammer::config::*;
+use rowhammer::utils::{devmem, files, proc, serialize::*};
+
+//Injects the bitflips of an attack config into the physical pages of a
+//running process with /dev/mem (see utils/devmem.rs for the requirements),
+//without hammering: fault-injection testing of the victim software, e.g.,
+//to check whether an attack config has the intended effect before templating
+//The victim page `page_file_offset` of the attacked binary is looked up in
+//the mappings of the process:
+//  - in a file-backed mapping of the binary, the flip then ends up in the page
+//    cache and affects every process that maps the page (and the file on disk
+//    if the page is written back, restore it afterwards)
+//  - otherwise in the PT_NULL segment mapped by the loader at
+//    `segment_virt_addr`
+//The `frame_addr` of the victim frames and the aggressor patterns are ignored
+//USAGE: sudo ./injector attack_config pid [binary]
+//  `binary` is the attacked binary, defaults to the executable of the process
+
+pub fn main() {
+  let args: Vec<_> = std::env::args().collect();
+  let mut logger_builder = rowhammer::configure();
+  logger_builder.init();
+
+  let pid: u32 = args[2].parse().expect("The pid is NaN");
+  let program_path = std::fs::canonicalize(args.get(3).cloned()
+    .unwrap_or_else(|| format!("/proc/{}/exe", pid)))
+    .expect("Could not find the attacked binary")
+    .to_string_lossy().into_owned();
+  let attack_config = files::parse_toml::<AttackConfig>(&args[1])
+    .resolve(&program_path).normalize();
+  info!("Injecting the flips of {} into process {} ({})", args[1], pid,
+    program_path);
+
+  let maps = proc::get_maps(pid);
+  let elf_file =
+    elf::File::open_path(&program_path).expect("Open ELF file failed");
+  let segment_file_offset = elf_file.phdrs.iter()
+    .find(|&&i| i.progtype == elf::types::PT_NULL).map(|s| s.offset);
+
+  let mut devmem = OpenOptions::new()
+    .read(true).write(true).open("/dev/mem").expect("Could not open /dev/mem");
+  let mut injected = 0;
+  for victim_frame in &attack_config.victim_frames {
+    let page = match victim_frame.page_file_offset {
+      Some(page) => page,
+      None => {
+        warn!("Victim frame P0x{:x} has no page_file_offset, skipped",
+          victim_frame.frame_addr);
+        continue;
+      }
+    };
+    let virt_page = match page_virt_addr(&maps, &program_path, page,
+      segment_file_offset, attack_config.segment_virt_addr)
+    {
+      Some(virt_page) => virt_page,
+      None => {
+        warn!("Page 0x{:x} of {} is not mapped in process {}, skipped", page,
+          program_path, pid);
+        continue;
+      }
+    };
+
+    for victim_bit in &victim_frame.victim_bits {
+      let virt_addr = virt_page + victim_bit.offset;
+      let phys_addr = proc::pid_virt_to_phys(pid, virt_addr)
+        .or_else(|| {
+          proc::pid_read_byte(pid, virt_addr);
+          proc::pid_virt_to_phys(pid, virt_addr)
+        })
+        .unwrap_or_else(|| panic!("V0x{:x} of process {} is not present",
+        virt_addr, pid));
+      let (original, flipped) =
+        devmem::apply_bitflip(&mut devmem, phys_addr, &victim_bit.bitflip);
+      info!("  - page 0x{:x} + 0x{:x} (V0x{:x}, P0x{:x}) {}: 0x{:02x} -> \
+        0x{:02x}", page, victim_bit.offset, virt_addr, phys_addr,
+        victim_bit.bitflip, original, flipped);
+      if original == flipped {
+        warn!("The bit already had the flipped value");
+      }
+      injected += 1;
+    }
+  }
+  info!("Injected {} flips", injected);
+}
+
+//Virtual address of the page at file offset arg:page of arg:program_path in
+//arg:maps, see the comment at the top for the lookup order
+fn page_virt_addr(
+  maps: &[proc::Mapping], program_path: &str, page: u64,
+  segment_file_offset: Option<u64>, segment_virt_addr: u64
+) -> Option<u64> {
+  let file_backed = maps.iter().find(|m| m.path == program_path
+    && m.file_offset <= page && page < m.file_offset + (m.end - m.start));
+  if let Some(m) = file_backed {
+    return Some(m.start + page - m.file_offset);
+  }
+  //the loader maps the segment page by page (see `map_binary`)
+  let page_index = page.checked_sub(segment_file_offset?)? >> *PAGE_SIZE_BITS;
+  let segment_page = segment_virt_addr + page_index * *PAGE_SIZE as u64;
+  maps.iter().any(|m| m.start <= segment_page && segment_page < m.end)
+    .then_some(segment_page)
+}
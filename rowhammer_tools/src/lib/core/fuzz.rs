@@ -0,0 +1,71 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+//Non-uniform aggressor access patterns (like Blacksmith, Jattke et al., S&P
+//2022): instead of accessing all aggressors equally often in a fixed order,
+//every aggressor is accessed with its own frequency, phase and amplitude
+//within a base period of access slots, which defeats TRR implementations
+//that only track the most frequent or the most regular aggressors
+//An aggressor with frequency f, phase p and amplitude a is accessed a times
+//in a row in the slots p, p + period / f, p + 2 * period / f, ... of every
+//base period
+//The `fuzz` mode of the templater samples the parameters (see
+//`FuzzedPattern::sample`), hammers them with hammer::FuzzKernel and records
+//them in the templater config of every template
+
+//base periods in access slots (powers of 2, so every frequency divides them)
+static BASE_PERIODS: [u32; 4] = [16, 32, 64, 128];
+//frequencies are 2^0 .. 2^MAX_FREQUENCY_EXP per base period
+static MAX_FREQUENCY_EXP: u32 = 4;
+static MAX_AMPLITUDE: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AggressorParams {
+  //accesses per base period
+  pub frequency: u32,
+  //first slot
+  pub phase: u32,
+  //consecutive accesses per slot
+  pub amplitude: u32
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FuzzedPattern {
+  pub base_period: u32,
+  //the parameters of every aggressor row of the pattern (in the order of the
+  //aggressor pattern, dummy rows included)
+  pub aggressors: Vec<AggressorParams>
+}
+
+impl FuzzedPattern {
+  //Samples the parameters of arg:aggressors aggressors
+  pub fn sample(rng: &mut impl Rng, aggressors: usize) -> FuzzedPattern {
+    let base_period = *BASE_PERIODS.choose(rng).unwrap();
+    FuzzedPattern {
+      base_period,
+      aggressors: (0..aggressors).map(|_| {
+        let frequency = (1 << rng.gen_range(0..=MAX_FREQUENCY_EXP))
+          .min(base_period);
+        AggressorParams {
+          frequency,
+          phase: rng.gen_range(0..base_period / frequency),
+          amplitude: rng.gen_range(1..=MAX_AMPLITUDE)
+        }
+      }).collect()
+    }
+  }
+
+  //Indices of the aggressors in the access order of one base period
+  pub fn access_order(&self) -> Vec<usize> {
+    let mut slots = vec![Vec::new(); self.base_period as usize];
+    for (i, aggr) in self.aggressors.iter().enumerate() {
+      let interval = self.base_period / aggr.frequency;
+      for k in 0..aggr.frequency {
+        let slot = (aggr.phase + k * interval) % self.base_period;
+        slots[slot as usize].extend(vec![i; aggr.amplitude as usize]);
+      }
+    }
+    slots.concat()
+  }
+}
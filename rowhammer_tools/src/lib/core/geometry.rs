@@ -0,0 +1,61 @@
+use crate::config::*;
+use crate::core::dram;
+use crate::core::serialize::DRAMConfig;
+
+//The page geometry of a session: the page size and the pages per row, the
+//context of a session holds one (see RhContext), the serialized configs are
+//parsed with the default one (see `RowAndInitValue::set_geometry`)
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+  //2log of the page size
+  pub page_size_bits: u32,
+  //number of pages in a row (needs to be power of 2)
+  pub pages_per_row: u64
+}
+
+impl Default for Geometry {
+  fn default() -> Geometry {
+    Geometry {page_size_bits: *PAGE_SIZE_BITS, pages_per_row: *PAGES_PER_ROW}
+  }
+}
+
+impl Geometry {
+  pub fn page_size(&self) -> usize {
+    1 << self.page_size_bits
+  }
+
+  pub fn page_offset_mask(&self) -> u64 {
+    self.page_size() as u64 - 1
+  }
+
+  pub fn page_align_mask(&self) -> u64 {
+    !self.page_offset_mask()
+  }
+
+  pub fn row_size(&self) -> u64 {
+    self.pages_per_row * self.page_size() as u64
+  }
+
+  pub fn row_align_mask(&self) -> u64 {
+    !(self.row_size() - 1)
+  }
+
+  //Frames of the row that contains arg:phys_addr
+  pub fn frames_in_row(&self, phys_addr: u64) -> Vec<u64> {
+    let row_start = phys_addr & self.row_align_mask();
+    (row_start..row_start + self.row_size()).step_by(self.page_size())
+      .collect()
+  }
+
+  //Same page size, with the rows of arg:dram_config: as many pages per row as
+  //fit in its physically contiguous bytes (see dram::row_size), at least one
+  pub fn for_dram_config(&self, dram_config: &DRAMConfig) -> Geometry {
+    let pages_per_row =
+      pages_per_row(dram::row_size(dram_config), self.page_size_bits);
+    assert!(pages_per_row.is_power_of_two(), "The row size 0x{:x} of the \
+      mapping functions is not a power of 2 pages",
+      dram::row_size(dram_config));
+    Geometry {pages_per_row, ..*self}
+  }
+}
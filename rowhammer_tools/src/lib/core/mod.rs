@@ -0,0 +1,23 @@
+//The core of the library: the pure logic of the tools, i.e., the DRAM
+//address translation, the configs and memory templates and their
+//(de)serialization, the pattern generation (fuzzed patterns, schedules), the
+//analysis of templates and the statistics in the results
+//The core only depends on serde and a few small crates and never touches the
+//system, so analysis scripts and notebooks (also on WASM) can read and
+//analyze templates without building the privileged system layer (the
+//allocation, /proc, /dev/mem, MSRs, hammering, ...) and its dependencies
+//(dynasm, capstone, nix, ...), which is behind the `system` feature (default):
+//depend on the library with `default-features = false` for the core only
+//The core modules are also reachable at their old paths
+//(rowhammer::utils::dram, rowhammer::fuzz, ...), the few functions of them
+//that need the system (e.g., the virtual addresses of the aggressors of a
+//pattern, or detecting the installed DIMMs) need the `system` feature too
+
+pub mod geometry;
+pub mod dram;
+pub mod files;
+pub mod serialize;
+pub mod stats;
+pub mod fuzz;
+pub mod schedule;
+pub mod analysis;
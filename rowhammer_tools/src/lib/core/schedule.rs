@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+//Hammer schedules: a small language to describe the accesses of one hammer
+//iteration in the config files, so published patterns (e.g. with dummy rows
+//to trick TRR) can be reproduced exactly without a new hammer kernel:
+//  "(A1 A2)x50 (D1)x5 pause 500ns"
+//  - `A<i>`: access (and flush) the i-th aggressor row of the pattern (the
+//    `A` rows, then the `B` rows of the templater pattern)
+//  - `D<i>`: access (and flush) the i-th dummy row, the dummy rows are the
+//    rows of the pattern after the highest aggressor in the schedule (the `D`
+//    rows of the templater pattern, e.g. "AVAD")
+//  - `( ... )`: a group of steps
+//  - `x<n>` after a row or group: repeats it n times (a loop in the hammer
+//    code, not unrolled, at most MAX_REPEAT_DEPTH nested loops)
+//  - `pause <n>ns` / `pause <n>us`: busy waits (on the TSC)
+//The steps are separated by whitespace, the hammer code runs the schedule
+//hammer_count times (in the unrolled hammer loop of the other kernels, see
+//hammer::ScheduleKernel)
+
+//maximal nesting of repeated steps (one register per loop level)
+pub static MAX_REPEAT_DEPTH: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+  //(dummy row, 0-based index)
+  Access {dummy: bool, idx: usize},
+  Pause(Duration),
+  Repeat(Vec<Step>, u64)
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+  pub steps: Vec<Step>,
+  //number of aggressor rows (highest `A<i>`), the dummy rows follow them
+  pub aggressors: usize,
+  //number of dummy rows (highest `D<i>`)
+  pub dummies: usize
+}
+
+impl Schedule {
+  pub fn parse(source: &str) -> Schedule {
+    let tokens = tokenize(source);
+    let mut pos = 0;
+    let (steps, _) = parse_steps(&tokens, &mut pos, false, source);
+    let (mut aggressors, mut dummies) = (0, 0);
+    count_rows(&steps, &mut aggressors, &mut dummies);
+    assert!(aggressors + dummies > 0,
+      "The hammer schedule `{}` accesses no rows", source);
+    assert!(repeat_depth(&steps) <= MAX_REPEAT_DEPTH,
+      "The hammer schedule `{}` has more than {} nested repetitions", source,
+      MAX_REPEAT_DEPTH);
+    Schedule {steps, aggressors, dummies}
+  }
+
+  //Index of aggressor (or dummy row with arg:dummy) arg:idx in the rows of
+  //the pattern
+  pub fn row_index(&self, dummy: bool, idx: usize) -> usize {
+    if dummy {self.aggressors + idx} else {idx}
+  }
+
+  //number of rows of the pattern the schedule accesses
+  pub fn rows(&self) -> usize {
+    self.aggressors + self.dummies
+  }
+}
+
+fn count_rows(steps: &[Step], aggressors: &mut usize, dummies: &mut usize) {
+  for step in steps {
+    match step {
+      Step::Access {dummy: false, idx} =>
+        *aggressors = (*aggressors).max(idx + 1),
+      Step::Access {dummy: true, idx} => *dummies = (*dummies).max(idx + 1),
+      Step::Pause(_) => {},
+      Step::Repeat(steps, _) => count_rows(steps, aggressors, dummies)
+    }
+  }
+}
+
+//number of nested loops (groups without repetition are not loops)
+pub fn repeat_depth(steps: &[Step]) -> usize {
+  steps.iter().map(|step| match step {
+    Step::Repeat(steps, 1) => repeat_depth(steps),
+    Step::Repeat(steps, _) => repeat_depth(steps) + 1,
+    _ => 0
+  }).max().unwrap_or(0)
+}
+
+fn tokenize(source: &str) -> Vec<String> {
+  let mut tokens = Vec::new();
+  let mut current = String::new();
+  for c in source.chars() {
+    if c.is_whitespace() || c == '(' || c == ')' {
+      if !current.is_empty() {
+        tokens.push(std::mem::take(&mut current));
+      }
+      if !c.is_whitespace() {
+        tokens.push(c.to_string());
+      }
+    } else {
+      current.push(c);
+    }
+  }
+  if !current.is_empty() {
+    tokens.push(current);
+  }
+  tokens
+}
+
+//`x<n>`
+fn parse_repetition(token: &str) -> Option<u64> {
+  token.strip_prefix('x').and_then(|n| n.parse().ok())
+}
+
+//Parses the steps up to the end or the `)` closing the group (arg:in_group),
+//returns the steps and whether the group was closed
+fn parse_steps(
+  tokens: &[String], pos: &mut usize, in_group: bool, source: &str
+) -> (Vec<Step>, bool) {
+  let invalid = |msg: String| -> ! {
+    panic!("Invalid hammer schedule `{}`: {}", source, msg)
+  };
+  let mut steps = Vec::new();
+  while *pos < tokens.len() {
+    let token = &tokens[*pos];
+    *pos += 1;
+    let step = match token.as_str() {
+      ")" => {
+        if !in_group {
+          invalid("unmatched `)`".to_owned());
+        }
+        return (steps, true);
+      },
+      "(" => {
+        let (group, closed) = parse_steps(tokens, pos, true, source);
+        if !closed {
+          invalid("unmatched `(`".to_owned());
+        }
+        Step::Repeat(group, 1)
+      },
+      "pause" => {
+        let duration = tokens.get(*pos)
+          .unwrap_or_else(|| invalid("`pause` without duration".to_owned()));
+        *pos += 1;
+        Step::Pause(parse_duration(duration)
+          .unwrap_or_else(|| invalid(format!("invalid duration `{}`",
+          duration))))
+      },
+      t if parse_repetition(t).is_some() => {
+        let n = parse_repetition(t).unwrap();
+        match steps.pop() {
+          Some(Step::Repeat(group, 1)) => Step::Repeat(group, n),
+          Some(step @ Step::Access {..}) => Step::Repeat(vec![step], n),
+          _ => invalid(format!("`{}` does not follow a row or group", t))
+        }
+      },
+      t => {
+        //a row, optionally with the repetition attached (`A1x3`)
+        let (row, n) = match t.split_once('x') {
+          Some((row, n)) => (row, n.parse().unwrap_or_else(|_|
+            invalid(format!("invalid repetition in `{}`", t)))),
+          None => (t, 1)
+        };
+        let dummy = match row.chars().next() {
+          Some('A') => false,
+          Some('D') => true,
+          _ => invalid(format!("unknown step `{}`", t))
+        };
+        let idx: usize = row[1..].parse().ok().filter(|i| *i > 0)
+          .unwrap_or_else(|| invalid(format!("invalid row `{}` (rows are \
+          numbered from 1)", row)));
+        let access = Step::Access {dummy, idx: idx - 1};
+        if n == 1 {access} else {Step::Repeat(vec![access], n)}
+      }
+    };
+    if let Step::Repeat(_, 0) = step {
+      invalid("repetition 0".to_owned());
+    }
+    steps.push(step);
+  }
+  (steps, false)
+}
+
+fn parse_duration(duration: &str) -> Option<Duration> {
+  if let Some(ns) = duration.strip_suffix("ns") {
+    ns.parse().ok().map(Duration::from_nanos)
+  } else if let Some(us) = duration.strip_suffix("us") {
+    us.parse().ok().map(Duration::from_micros)
+  } else {
+    None
+  }
+}
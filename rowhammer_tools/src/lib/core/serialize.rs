@@ -0,0 +1,1563 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use sscanf::sscanf;
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+use crate::utils;
+#[cfg(feature = "system")]
+use crate::utils::symbols::SymbolTable;
+use crate::core::{dram, dram::DRAMAddr};
+use crate::core::geometry::Geometry;
+use crate::core::stats::{CoRunnerStats, HammerStats, ResourceSummary};
+use crate::core::fuzz::FuzzedPattern;
+use crate::config::*;
+
+//The hammer implementation, `hammer_backend` in the attack and templater
+//configs
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HammerBackend {
+  Rust, //`hammer_rust`, ignores the garbage count and the flush setting
+  Asm, //`hammer_asm`
+  #[default]
+  Jit, //`hammer_jit` (or the hammer kernel of the config)
+  Refsync, //`hammer_jit_refresh`, bursts synchronized with the refreshes
+  Smt, //`hammer_smt`, the SMT siblings of the core hammer disjoint halves of
+       //the pattern at the same time
+  Fake //no hammering, the flips are faked with /dev/mem where supported
+       //(see devmem::hammer_fake), otherwise a dry run
+}
+
+// --- ATTACK CONFIG ---
+//main structure for attack_config.toml
+#[derive(Deserialize)]
+pub struct AttackConfig {
+  pub hammer_count: u64,
+  pub segment_virt_addr: u64,
+  pub victim_frames: Vec<VictimFrame>,
+  aggressor_patterns: HashMap<String, AggressorPattern>,
+  //how many times the loader hammers again when not all victims flipped
+  #[serde(default)]
+  pub rehammer_attempts: u32,
+  //what the loader does when the victims still did not flip as expected
+  #[serde(default)]
+  pub on_failure: FailurePolicy,
+  //what the loader does when an aggressor frame cannot be allocated
+  #[serde(default)]
+  pub aggr_fallback: AggrFallback,
+  //hammer all patterns of a hammer round concurrently (see `hammer_rounds`)
+  //instead of one after another
+  #[serde(default)]
+  pub interleave: bool,
+  //hammer a scratch copy of the victim content before the binary is mapped
+  //and abort the attack if the victims do not flip (see the loader)
+  #[serde(default)]
+  pub rehearsal: bool,
+  //victim frames that hold a page table instead of binary content (see
+  //rowhammer::pte)
+  #[serde(default)]
+  pub pte_victim_frames: Vec<PteVictimFrame>,
+  //hammer implementation (see hammer::HammerBackend), `fake` fakes the flips
+  //with /dev/mem like `RH_FAKE_HAMMER`, patterns that are hammered
+  //concurrently (`interleave`, weighted patterns) need `jit`
+  #[serde(default)]
+  pub hammer_backend: HammerBackend,
+  //what the tools do when hammering a pattern disturbs the victim row of
+  //another pattern (see rowhammer::victim_conflicts)
+  #[serde(default)]
+  pub on_conflict: ConflictPolicy
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AggrFallback {
+  #[default]
+  Exact, //keep allocating until all aggressor frames are captured
+  Row, //hammer the captured frames of the aggressor row
+  Mirror //as `row`, or use the row at the same distance on the other side of
+         //the victim if no frame of the aggressor row was captured
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+  #[default]
+  Continue, //run the target with the (partially) flipped content
+  Restore, //restore the original content of all victims and continue
+  Exit //terminate the process with a distinct exit code
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+  #[default]
+  Warn, //log the conflicts and hammer anyway
+  Refuse //abort before hammering
+}
+
+#[derive(Deserialize)]
+pub struct VictimFrame {
+  pub page_file_offset: Option<u64>,
+  pub frame_addr: u64,
+  #[serde(default)]
+  pub victim_bits: Vec<VictimBit>,
+  //victim words with their expected transition, their bits are added to
+  //`victim_bits` by `AttackConfig::normalize`
+  #[serde(default)]
+  pub victim_values: Vec<VictimValue>
+}
+
+impl VictimFrame {
+  //Victim frame with the single victim bit at physical address arg:phys_addr
+  //(e.g., a victim of a memory template)
+  pub fn single(phys_addr: u64, bitflip: &BitFlip, pattern_id: usize)
+    -> VictimFrame
+  {
+    VictimFrame {
+      page_file_offset: None,
+      frame_addr: phys_addr & *PAGE_ALIGN_MASK,
+      victim_bits: vec![VictimBit {
+        offset: phys_addr & *PAGE_OFFSET_MASK,
+        has_offset: true,
+        bitflip: bitflip.clone(),
+        aggr_pattern_key: pattern_id.to_string(),
+        attempts: None,
+        tolerable: false,
+        symbol: None,
+        insn_offset: 0,
+        size: 1,
+        value_offset: None
+      }],
+      victim_values: Vec::new()
+    }
+  }
+}
+
+//Victim frame for a page table of the loader (see rowhammer::pte), the
+//`offset` of its victim bits is the offset in the page table, the bits have
+//to be in the frame number of a PTE
+#[derive(Deserialize)]
+pub struct PteVictimFrame {
+  pub frame_addr: u64,
+  pub victim_bits: Vec<VictimBit>
+}
+
+//Position of arg:victim_bit in its PTE (bit index in the 64 bit entry)
+pub fn pte_bit(victim_bit: &VictimBit) -> u64 {
+  (victim_bit.offset % 8) * 8 + victim_bit.bitflip.flip_index as u64
+}
+
+//Whether arg:victim_bit is in the frame number of a PTE
+pub fn in_pte_frame(victim_bit: &VictimBit) -> bool {
+  PTE_FRAME_MASK & (1 << pte_bit(victim_bit)) != 0
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(from = "VictimBitConfig")]
+pub struct VictimBit {
+  //offset in page, derived from `symbol` and `insn_offset` if the bit is given
+  //by its symbol
+  pub offset: u64,
+  //whether `offset` is given (in the config or by the resolved `symbol`),
+  //otherwise it is 0 and `AttackConfig::check` rejects the bit
+  pub has_offset: bool,
+  pub bitflip: BitFlip,
+  pub aggr_pattern_key: String,
+  //number of times the loader may hammer for this bit, defaults to
+  //1 + `rehammer_attempts` of the attack config
+  pub attempts: Option<u32>,
+  //whether the target can run when this bit did not flip (the `on_failure`
+  //policy is only applied for bits that are not tolerable)
+  pub tolerable: bool,
+  //alternative to `offset`: the victim byte is `insn_offset` bytes after the
+  //start of the function (or object) `symbol` in the binary, resolved with
+  //`AttackConfig::resolve_symbols`
+  pub symbol: Option<String>,
+  pub insn_offset: u64,
+  //size in bytes of the victim value at `offset` (e.g., 8 for a pointer, or
+  //the size of a field of a struct), `bit` is the index of the bit in the
+  //whole value (little endian: bit i is bit i % 8 of byte i / 8)
+  pub size: u64,
+  //offset in page of the victim value, set by `AttackConfig::normalize`,
+  //which moves `offset` and `bit` to the byte of the bit
+  pub value_offset: Option<u64>
+}
+
+//`VictimBit` as written in the attack config
+#[derive(Deserialize)]
+struct VictimBitConfig {
+  offset: Option<u64>,
+  #[serde(alias = "bit")]
+  bitflip: BitFlip,
+  aggr_pattern_key: String,
+  #[serde(default)]
+  attempts: Option<u32>,
+  #[serde(default)]
+  tolerable: bool,
+  #[serde(default)]
+  symbol: Option<String>,
+  #[serde(default)]
+  insn_offset: u64,
+  #[serde(default = "default_victim_size")]
+  size: u64
+}
+
+impl From<VictimBitConfig> for VictimBit {
+  fn from(x: VictimBitConfig) -> VictimBit {
+    VictimBit {
+      offset: x.offset.unwrap_or(0),
+      has_offset: x.offset.is_some(),
+      bitflip: x.bitflip,
+      aggr_pattern_key: x.aggr_pattern_key,
+      attempts: x.attempts,
+      tolerable: x.tolerable,
+      symbol: x.symbol,
+      insn_offset: x.insn_offset,
+      size: x.size,
+      value_offset: None
+    }
+  }
+}
+
+fn default_victim_size() -> u64 {
+  1
+}
+
+impl VictimBit {
+  //(offset in page, size) of the victim value of the bit
+  pub fn value(&self) -> (u64, u64) {
+    (self.value_offset.unwrap_or(self.offset), self.size)
+  }
+
+  //Moves the bit of a multi-byte victim value to its byte (once)
+  fn move_to_byte(&mut self) {
+    if self.value_offset.is_some() {
+      return;
+    }
+    self.value_offset = Some(self.offset);
+    self.offset += self.bitflip.flip_index as u64 / 8;
+    self.bitflip.flip_index %= 8;
+  }
+}
+
+//A victim word (e.g., a pointer or an immediate operand that spans byte
+//boundaries) with its expected content before and after hammering (little
+//endian, at most 8 bytes), the bits that differ are its victim bits and the
+//flip checks verify the whole transition (see
+//flip_checks::check_victim_flips)
+#[derive(Deserialize, PartialEq)]
+pub struct VictimValue {
+  //offset in page
+  pub offset: u64,
+  //size in bytes
+  pub size: u64,
+  pub before: u64,
+  pub after: u64,
+  pub aggr_pattern_key: String,
+  //see `VictimBit`, for every bit of the value
+  #[serde(default)]
+  pub attempts: Option<u32>,
+  #[serde(default)]
+  pub tolerable: bool
+}
+
+impl VictimValue {
+  //The bits that flip from `before` to `after` (with the index in the whole
+  //value, see `VictimBit::size`)
+  pub fn victim_bits(&self) -> Vec<VictimBit> {
+    let flips = self.before ^ self.after;
+    (0..64u8).filter(|i| flips >> i & 1 == 1).map(|i| VictimBit {
+      offset: self.offset,
+      has_offset: true,
+      bitflip: BitFlip {flip_index: i,
+        flip_direction: self.after >> i & 1 == 1},
+      aggr_pattern_key: self.aggr_pattern_key.clone(),
+      attempts: self.attempts,
+      tolerable: self.tolerable,
+      symbol: None,
+      insn_offset: 0,
+      size: self.size,
+      value_offset: None
+    }).collect()
+  }
+
+  //whether arg:value fits in the size of the value
+  fn fits(&self, value: u64) -> bool {
+    self.size >= 8 || value >> (8 * self.size) == 0
+  }
+}
+
+#[derive(SerializeDisplay, DeserializeFromStr, PartialEq, Eq, Hash, Clone, Default)]
+pub struct BitFlip {
+  pub flip_index: u8, //offset in byte (in the value of a multi-byte victim)
+  pub flip_direction: bool, //true = 0->1 ; false = 1->0
+}
+
+impl fmt::Display for BitFlip {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}{}", self.flip_index, if self.flip_direction {"+"} else {"-"})
+  }
+}
+
+impl FromStr for BitFlip {
+  type Err = std::string::FromUtf8Error; //some random error, not used
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    assert!(s.len() >= 2 && s.is_char_boundary(s.len() - 1),
+      "Invalid bit flip {}", s);
+    let (index, direction) = s.split_at(s.len() - 1);
+    assert!(direction == "+" || direction == "-", "Invalid bit flip {}", s);
+    Ok(BitFlip {
+      flip_index: index.parse()
+        .unwrap_or_else(|_| panic!("Invalid bit flip {}", s)),
+      flip_direction: direction == "+"
+    })
+  }
+}
+
+#[derive(SerializeDisplay, DeserializeFromStr, PartialEq, Eq, Hash, Default, Clone)]
+//#[serde(transparent)]
+pub struct AggressorPattern {
+  pub pattern: Vec<RowAndInitValue>
+}
+
+impl AggressorPattern {
+  #[cfg(feature = "system")]
+  pub fn aggr_rows_to_virt(&self, frame2map: &Frame2Map) -> Vec<u64> {
+    self.pattern.iter()
+      .map(|x| frame2map[&x.frames[0]].data() as u64)
+      .collect::<Vec<_>>()
+  }
+
+  //Same as `aggr_rows_to_virt`, but with the access rate of each aggressor
+  //(see `hammer::create_hammer_jit_interleaved`)
+  #[cfg(feature = "system")]
+  pub fn aggr_rows_to_virt_weighted(&self, frame2map: &Frame2Map)
+    -> Vec<(u64, AccessRate)>
+  {
+    self.pattern.iter()
+      .map(|x| (frame2map[&x.frames[0]].data() as u64, x.rate()))
+      .collect()
+  }
+
+  //whether any aggressor is accessed more or less often than the others
+  pub fn is_weighted(&self) -> bool {
+    self.pattern.iter().any(|x| x.rate() != AccessRate::default())
+  }
+
+  //the access rate of every aggressor (see `hammer::create_hammer_jit`)
+  pub fn rates(&self) -> Vec<AccessRate> {
+    self.pattern.iter().map(|x| x.rate()).collect()
+  }
+
+  //the rows that are no decoys (see `RowAndInitValue::decoy`)
+  pub fn real_aggressors(&self) -> impl Iterator<Item=&RowAndInitValue> {
+    self.pattern.iter().filter(|x| !x.decoy)
+  }
+
+  //Derives the frames of all aggressor rows from arg:geometry (see
+  //`RowAndInitValue::set_geometry`)
+  pub fn set_geometry(&mut self, geometry: &Geometry) {
+    self.pattern.iter_mut().for_each(|r| r.set_geometry(geometry));
+  }
+
+  //Same as `aggr_rows_to_virt`, but returns all virtual addresses for each
+  //aggressor row: the mapping in arg:frame2map followed by its aliases
+  #[cfg(feature = "system")]
+  pub fn aggr_rows_to_virt_aliases(
+    &self, frame2map: &Frame2Map, frame2aliases: &Frame2Aliases
+  ) -> Vec<Vec<u64>> {
+    self.pattern.iter().map(|x| {
+      let frame = x.frames[0];
+      std::iter::once(frame2map[&frame].data() as u64)
+        .chain(frame2aliases.get(&frame).into_iter().flatten()
+          .map(|a| a.data() as u64))
+        .collect()
+    }).collect()
+  }
+}
+
+impl<'a> IntoIterator for &'a AggressorPattern {
+  type Item =&'a RowAndInitValue;
+  type IntoIter = std::slice::Iter<'a, RowAndInitValue>;
+
+  //iter over RowAndInitValues
+  fn into_iter(self) -> Self::IntoIter {
+    self.pattern.iter()
+  }
+}
+
+impl AggressorPattern {
+  //the format of `fmt` with the `P` prefix of physical addresses, for the log
+  //output (see utils::redact), the result files keep the format of `fmt`
+  pub fn fmt_phys(&self) -> String {
+    self.format("P")
+  }
+
+  fn format(&self, prefix: &str) -> String {
+    let mut s = String::new();
+    let mut sep = "";
+    for a in &self.pattern {
+      s += &format!("{}{}{}0x{:x}(0x{:x})", sep, if a.decoy {"~"} else {""},
+        prefix, a.frames[0], a.init);
+      if a.weight != 1 {
+        s += &format!("*{}", a.weight);
+      }
+      if a.interval != 1 {
+        s += &format!("/{}", a.interval);
+      }
+      sep = ",";
+    }
+    s
+  }
+}
+
+impl fmt::Display for AggressorPattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.format(""))
+  }
+}
+
+impl FromStr for AggressorPattern {
+  type Err = std::string::FromUtf8Error; //some random error, not used
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    //aggressor format: [~][P]0x<row>(0x<init>)[*<weight>][/<interval>], `~`
+    //marks a decoy row, `P` marks the physical address (as written by
+    //`fmt_phys` to the logs)
+    Ok(AggressorPattern {pattern: s.split(",").map(|x| {
+      let (x, decoy) = match x.trim_start().strip_prefix('~') {
+        Some(x) => (x, true),
+        None => (x, false)
+      };
+      let (x, interval) = match x.split_once('/') {
+        Some((x, interval)) => (x, interval.trim().parse().unwrap_or_else(|_|
+          panic!("Invalid aggressor interval {}", interval))),
+        None => (x, 1)
+      };
+      let (x, weight) = match x.split_once('*') {
+        Some((x, weight)) => (x, weight.trim().parse()
+          .unwrap_or_else(|_| panic!("Invalid aggressor weight {}", weight))),
+        None => (x, 1)
+      };
+      assert!(weight > 0, "Aggressor weight 0 in {}", s);
+      assert!(interval > 0, "Aggressor interval 0 in {}", s);
+      let x = x.trim_start();
+      let x = x.strip_prefix('P').unwrap_or(x);
+      let tmp = sscanf!(x, "0x{:x}(0x{:x})", u64, u8).unwrap();
+      RowAndInitValue {frames: parse_row(tmp.0), init: tmp.1, weight, interval,
+        decoy}
+    }).collect()})
+  }
+}
+
+// --- PATTERN STORE ---
+//Interned aggressor patterns: each unique pattern is stored once and
+//referenced by its id (= index in insertion order), ids never change
+//(De)serialized as a plain list of patterns
+#[derive(Default)]
+pub struct PatternStore {
+  patterns: Vec<AggressorPattern>,
+  ids: HashMap<AggressorPattern, usize>
+}
+
+impl PatternStore {
+  pub fn new() -> PatternStore {
+    Default::default()
+  }
+
+  //Returns the id of arg:pattern, the pattern is added if it is new
+  pub fn intern(&mut self, pattern: &AggressorPattern) -> usize {
+    if let Some(id) = self.ids.get(pattern) {
+      return *id;
+    }
+    let id = self.patterns.len();
+    self.patterns.push(pattern.clone());
+    self.ids.insert(pattern.clone(), id);
+    id
+  }
+
+  pub fn id(&self, pattern: &AggressorPattern) -> Option<usize> {
+    self.ids.get(pattern).cloned()
+  }
+
+  pub fn get(&self, id: usize) -> &AggressorPattern {
+    self.patterns.get(id)
+      .expect(&format!("Unknown aggressor pattern id {}", id))
+  }
+
+  pub fn len(&self) -> usize {
+    self.patterns.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.patterns.is_empty()
+  }
+
+  //iter over the patterns in id order
+  pub fn iter(&self) -> std::slice::Iter<'_, AggressorPattern> {
+    self.patterns.iter()
+  }
+
+  //Derives the frames of all aggressor rows from arg:geometry, keeps the ids
+  pub fn set_geometry(&mut self, geometry: &Geometry) {
+    self.patterns.iter_mut().for_each(|p| p.set_geometry(geometry));
+    self.ids.clear();
+    for (id, pattern) in self.patterns.iter().enumerate() {
+      self.ids.entry(pattern.clone()).or_insert(id);
+    }
+  }
+}
+
+impl Serialize for PatternStore {
+  fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+    self.patterns.serialize(s)
+  }
+}
+
+impl<'de> Deserialize<'de> for PatternStore {
+  fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+    //keep the ids of the file, even if it contains duplicate patterns
+    let patterns = Vec::<AggressorPattern>::deserialize(d)?;
+    let mut ids = HashMap::new();
+    for (id, pattern) in patterns.iter().enumerate() {
+      ids.entry(pattern.clone()).or_insert(id);
+    }
+    Ok(PatternStore {patterns, ids})
+  }
+}
+
+//How often an aggressor is accessed: arg:weight times in every
+//arg:interval-th hammer iteration, e.g., the far aggressors of Half-Double
+//are hammered in every iteration, the near ("dribble") rows only get a few
+//accesses (see `RowAndInitValue::weight` and `RowAndInitValue::interval`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccessRate {
+  pub weight: u32,
+  pub interval: u32
+}
+
+impl Default for AccessRate {
+  fn default() -> AccessRate {
+    AccessRate {weight: 1, interval: 1}
+  }
+}
+
+impl AccessRate {
+  //whether the aggressor is accessed in hammer iteration arg:itr
+  pub fn active(&self, itr: u64) -> bool {
+    itr.is_multiple_of(self.interval as u64)
+  }
+
+  //the accesses in arg:hammer_count hammer iterations
+  pub fn accesses(&self, hammer_count: u64) -> u64 {
+    self.weight as u64 * hammer_count.div_ceil(self.interval as u64)
+  }
+
+  //The steps of hammer iteration arg:itr of aggressors with arg:rates: step
+  //s accesses (and then flushes) the aggressors that are active in the
+  //iteration and have a weight > s, so the accesses of an aggressor are
+  //spread over the iteration
+  //Returns the indices (in arg:rates) of the aggressors of every step
+  pub fn steps(rates: &[AccessRate], itr: u64) -> Vec<Vec<usize>> {
+    let steps = rates.iter().filter(|r| r.active(itr)).map(|r| r.weight)
+      .max().unwrap_or(0);
+    (0..steps).map(|step| (0..rates.len())
+      .filter(|i| rates[*i].active(itr) && rates[*i].weight > step).collect())
+      .collect()
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct RowAndInitValue {
+  #[serde(rename = "phys_aggr")] //for attack_config.toml
+  #[serde(deserialize_with = "deserialize_row")]
+  pub frames: Vec<u64>, //all physical frames in the row
+  #[serde(rename = "aggr_init")] //for attack_config.toml
+  pub init: u8,
+  //relative number of accesses of this aggressor per hammer iteration
+  #[serde(default = "default_weight")]
+  #[serde(skip_serializing_if = "is_default_weight")]
+  pub weight: u32,
+  //the aggressor is only accessed in every interval-th hammer iteration (e.g.
+  //the "dribble" rows of Half-Double, see `hammer::AccessRate`)
+  #[serde(default = "default_weight")]
+  #[serde(skip_serializing_if = "is_default_weight")]
+  pub interval: u32,
+  //a decoy row is hammered like an aggressor to pollute the sampler of the
+  //TRR mitigation, but is no real aggressor of the victims (e.g. the analysis
+  //and the minimization of the patterns ignore it)
+  #[serde(default)]
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub decoy: bool
+}
+
+fn default_weight() -> u32 {
+  1
+}
+
+fn is_default_weight(weight: &u32) -> bool {
+  *weight == 1
+}
+
+fn deserialize_row<'de, D>(d: D) -> Result<Vec<u64>, D::Error>
+where D: Deserializer<'de> {
+  let aggr_addr = u64::deserialize(d)?;
+  Ok(parse_row(aggr_addr))
+}
+
+//Frames of the serialized row at arg:row_phys_addr in the default geometry,
+//its own frame first: in a geometry with smaller rows (see
+//`RowAndInitValue::set_geometry`), a row can start at any page of a default
+//row
+fn parse_row(row_phys_addr: u64) -> Vec<u64> {
+  let frame = row_phys_addr & *PAGE_ALIGN_MASK;
+  std::iter::once(frame).chain(utils::get_frames_in_row(frame).into_iter()
+    .filter(|f| *f != frame)).collect()
+}
+
+impl RowAndInitValue {
+  //Derives the frames of the row from its first frame and arg:geometry, the
+  //parsed rows have the frames of the default geometry
+  pub fn set_geometry(&mut self, geometry: &Geometry) {
+    self.frames = geometry.frames_in_row(self.frames[0]);
+  }
+
+  pub fn rate(&self) -> AccessRate {
+    AccessRate {weight: self.weight, interval: self.interval}
+  }
+}
+
+impl AttackConfig {
+  //Derives the frames of the aggressor rows from arg:geometry (see
+  //`RowAndInitValue::set_geometry`), before `validate`
+  pub fn with_geometry(mut self, geometry: &Geometry) -> AttackConfig {
+    self.aggressor_patterns.values_mut()
+      .for_each(|p| p.set_geometry(geometry));
+    self
+  }
+
+  //Resolves the victim bits given by their symbol (see `VictimBit::symbol`) in
+  //the binary at arg:program_path and panics if that fails
+  #[cfg(feature = "system")]
+  pub fn resolve(mut self, program_path: &str) -> AttackConfig {
+    let errors = self.resolve_symbols(program_path);
+    assert!(errors.is_empty(), "Resolving the victim symbols failed: {}",
+      errors.join("; "));
+    self
+  }
+
+  //Sets `offset` of the victim bits given by their symbol and the
+  //`page_file_offset` of their victim frame (if missing), returns a
+  //description of every bit that could not be resolved
+  //All symbol bits of a frame have to be in the same page of the binary
+  #[cfg(feature = "system")]
+  pub fn resolve_symbols(&mut self, program_path: &str) -> Vec<String> {
+    let has_symbols = self.victim_frames.iter()
+      .any(|v| v.victim_bits.iter().any(|b| b.symbol.is_some()));
+    if !has_symbols {
+      return Vec::new();
+    }
+    let symbols = match SymbolTable::open(program_path) {
+      Ok(symbols) => symbols,
+      Err(e) => return vec![e]
+    };
+
+    let mut errors = Vec::new();
+    for frame in &mut self.victim_frames {
+      for bit in &mut frame.victim_bits {
+        let symbol = match &bit.symbol {
+          Some(symbol) => symbol,
+          None => continue
+        };
+        let file_offset = match symbols.file_offset(symbol) {
+          Some(offset) => offset + bit.insn_offset,
+          None => {
+            errors.push(format!("Symbol {} not found in {}", symbol,
+              program_path));
+            continue;
+          }
+        };
+        let page = *frame.page_file_offset
+          .get_or_insert(file_offset & *PAGE_ALIGN_MASK) & *PAGE_ALIGN_MASK;
+        if file_offset & *PAGE_ALIGN_MASK != page {
+          errors.push(format!("Victim bit {}+0x{:x} (file offset 0x{:x}) is \
+            not in the page at file offset 0x{:x} of victim frame 0x{:x}",
+            symbol, bit.insn_offset, file_offset, page, frame.frame_addr));
+          continue;
+        }
+        bit.offset = file_offset & *PAGE_OFFSET_MASK;
+        bit.has_offset = true;
+      }
+    }
+    errors
+  }
+
+  //Normalizes the config (see `normalize`) and panics if any of the checks of
+  //`check` fails
+  pub fn validate(self) -> AttackConfig {
+    let config = self.normalize();
+    let errors = config.check();
+    assert!(errors.is_empty(), "Invalid attack config: {}", errors.join("; "));
+    config
+  }
+
+  //Merges the victim frames that map the same file page to the same frame into
+  //one frame with the union of their victim bits, so configs generated by
+  //multiple tools can be concatenated
+  //Frames that map the same file page to different frames are kept (and
+  //rejected by `check`)
+  //The bits of multi-byte victim values are moved to their byte (see
+  //`VictimBit::size`), after the symbols are resolved, the bits of the victim
+  //values (see `VictimValue`) are added
+  pub fn normalize(mut self) -> AttackConfig {
+    self.victim_frames.iter_mut().flat_map(|v| v.victim_bits.iter_mut())
+      .chain(self.pte_victim_frames.iter_mut()
+      .flat_map(|v| v.victim_bits.iter_mut()))
+      .for_each(|b| b.move_to_byte());
+    for frame in &mut self.victim_frames {
+      let bits: Vec<VictimBit> = frame.victim_values.iter()
+        .flat_map(|v| v.victim_bits()).collect();
+      for mut bit in bits {
+        bit.move_to_byte();
+        if !frame.victim_bits.contains(&bit) {
+          frame.victim_bits.push(bit);
+        }
+      }
+    }
+    let mut merged: Vec<VictimFrame> = Vec::new();
+    for frame in self.victim_frames.drain(..) {
+      let page = frame.page_file_offset.map(|x| x & *PAGE_ALIGN_MASK);
+      match merged.iter_mut().find(|m| m.frame_addr == frame.frame_addr
+        && m.page_file_offset.map(|x| x & *PAGE_ALIGN_MASK) == page) {
+        Some(m) => {
+          for bit in frame.victim_bits {
+            if !m.victim_bits.contains(&bit) {
+              m.victim_bits.push(bit);
+            }
+          }
+          for value in frame.victim_values {
+            if !m.victim_values.contains(&value) {
+              m.victim_values.push(value);
+            }
+          }
+        },
+        None => merged.push(frame)
+      }
+    }
+    self.victim_frames = merged;
+    self
+  }
+
+  //Consistency checks of the attack config, returns a description of every
+  //failed check
+  pub fn check(&self) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    //1. in an attack scenario, there should be no overlap between victim and
+    //aggressor rows, because the victims will contain actual code/data and can
+    //therefore not be initialized to the aggressor init value
+    //the aggressor rows have all their frames (see `with_geometry`), so a
+    //victim frame in one of them is an overlap, even if the victim bit is in
+    //another page of the row than the aggressor address
+    let aggrs: HashSet<u64> = self.iter_aggr_frames().collect();
+    let victims: HashSet<u64> = self.iter_victim_frame_addrs().collect();
+    let t = aggrs.intersection(&victims)
+      .fold(String::new(), |acc, x| acc + &format!("0x{:x} ", x));
+    if t != "" {
+      errors.push(format!(
+        "There is overlap between victim and aggressor rows: {}", t));
+    }
+
+    //2. check that mappings at the same file page offset are the same frame
+    //the same mapping (file offset and victim frame) multiple times with a
+    //different collection of victim bits is merged by `normalize`
+    //=> only check on the uniqueness of the file offsets
+    let mut tmp = HashSet::new();
+    for x in &self.victim_frames {
+      match x.page_file_offset {
+        Some(offset) => if !tmp.insert(offset & *PAGE_ALIGN_MASK) {
+          errors.push(format!("The same file page is mapped to different \
+            physical page frames (file offset 0x{:x})", offset));
+        },
+        None if x.victim_bits.iter().any(|b| b.symbol.is_some()) =>
+          errors.push(format!("Victim frame 0x{:x} has no page_file_offset, \
+            the symbols of its victim bits are not resolved", x.frame_addr)),
+        None => errors.push(format!("Victim frame 0x{:x} has no \
+          page_file_offset", x.frame_addr))
+      }
+    }
+
+    //every victim bit needs its position in the page
+    let frames = self.victim_frames.iter()
+      .map(|v| (v.frame_addr, &v.victim_bits))
+      .chain(self.pte_victim_frames.iter()
+      .map(|v| (v.frame_addr, &v.victim_bits)));
+    for (frame_addr, bits) in frames {
+      for bit in bits.iter().filter(|b| !b.has_offset && b.symbol.is_none()) {
+        errors.push(format!("Victim bit {} at frame 0x{:x} has neither an \
+          offset nor a symbol", bit.bitflip, frame_addr));
+      }
+    }
+
+    //3. check there are no duplicate aggressor patterns
+    let mut tmp = HashSet::new();
+    for (_,v) in &self.aggressor_patterns {
+      if !tmp.insert(v) {
+        errors.push(format!("Duplicate aggressor pattern detected: {}",
+          v.fmt_phys()));
+      }
+    }
+
+    //4. the same aggressor row in different patterns with different init
+    //values is no error: the patterns are initialized and hammered in
+    //separate groups (see rowhammer::victim_conflicts::init_groups)
+
+    //5. page-table victims can only flip the frame number of a PTE, and their
+    //frames can not hold binary content at the same time
+    for x in &self.pte_victim_frames {
+      if self.victim_frames.iter().any(|v| v.frame_addr == x.frame_addr) {
+        errors.push(format!("Victim frame 0x{:x} is used for binary content \
+          and for a page table", x.frame_addr));
+      }
+      for bit in x.victim_bits.iter().filter(|b| !in_pte_frame(b)) {
+        errors.push(format!("Page-table victim bit {} at 0x{:x} is not in \
+          the frame number of its PTE", bit.bitflip,
+          x.frame_addr + bit.offset));
+      }
+      if x.victim_bits.iter().any(|b| b.symbol.is_some()) {
+        errors.push(format!("Page-table victim frame 0x{:x} has victim bits \
+          given by a symbol", x.frame_addr));
+      }
+    }
+
+    //6. the bits of multi-byte victim values are in their value, the values
+    //are in their page, the expected content of the victim words fits in
+    //their size and flips
+    let bits = self.victim_frames.iter().map(|v| (v.frame_addr, &v.victim_bits))
+      .chain(self.pte_victim_frames.iter()
+      .map(|v| (v.frame_addr, &v.victim_bits)))
+      .flat_map(|(frame_addr, bits)| bits.iter().map(move |b| (frame_addr, b)));
+    for (frame_addr, bit) in bits {
+      let (value_offset, size) = bit.value();
+      if size == 0 || value_offset + size > *PAGE_SIZE as u64 {
+        errors.push(format!("The {}-byte victim value at 0x{:x} is not in \
+          its page", size, frame_addr + value_offset));
+      } else if bit.offset >= value_offset + size {
+        errors.push(format!("Victim bit {} at 0x{:x} is outside of the \
+          {}-byte victim value at 0x{:x}", bit.bitflip, frame_addr
+          + bit.offset, size, frame_addr + value_offset));
+      }
+    }
+    for frame in &self.victim_frames {
+      for value in &frame.victim_values {
+        let phys_addr = frame.frame_addr + value.offset;
+        if value.size == 0 || value.size > 8 {
+          errors.push(format!("The victim value at 0x{:x} has {} bytes, \
+            expected 1 to 8", phys_addr, value.size));
+        } else if !value.fits(value.before) || !value.fits(value.after) {
+          errors.push(format!("The expected content 0x{:x} -> 0x{:x} of the \
+            victim value at 0x{:x} does not fit in {} bytes", value.before,
+            value.after, phys_addr, value.size));
+        } else if value.before == value.after {
+          errors.push(format!("The victim value at 0x{:x} has no flips \
+            (0x{:x} before and after)", phys_addr, value.before));
+        }
+      }
+    }
+
+    //7. decoy rows only pollute the TRR sampler, a pattern needs a real
+    //aggressor
+    for (key, pattern) in &self.aggressor_patterns {
+      if pattern.real_aggressors().next().is_none() {
+        errors.push(format!("Aggressor pattern {} only has decoy rows", key));
+      }
+    }
+
+    errors
+  }
+
+  //frames of all victims (binary content and page tables)
+  pub fn iter_victim_frame_addrs(&self) -> impl Iterator<Item=u64> + '_ {
+    self.victim_frames.iter().map(|x| x.frame_addr)
+      .chain(self.pte_victim_frames.iter().map(|x| x.frame_addr))
+  }
+
+  pub fn aggr_pattern(&self, key: &str) -> &AggressorPattern {
+    self.aggressor_patterns.get(key)
+      .expect(&format!("Unknown aggressor pattern key {}", key))
+  }
+
+  //iter over (key, pattern) of all aggressor patterns
+  pub fn iter_aggr_patterns(
+    &self
+  ) -> impl Iterator<Item=(&String, &AggressorPattern)> {
+    self.aggressor_patterns.iter()
+  }
+
+  //Replaces the aggressor row starting at frame arg:row in all patterns by a
+  //row with arg:frames (keeps the init value)
+  pub fn substitute_aggr_row(&mut self, row: u64, frames: Vec<u64>) {
+    assert!(!frames.is_empty(), "Substitute aggressor row has no frames");
+    self.aggressor_patterns.values_mut()
+      .flat_map(|p| p.pattern.iter_mut())
+      .filter(|r| r.frames[0] == row)
+      .for_each(|r| r.frames = frames.clone());
+  }
+
+  //number of times the loader may hammer for arg:victim_bit
+  pub fn hammer_attempts(&self, victim_bit: &VictimBit) -> u32 {
+    victim_bit.attempts.unwrap_or(1 + self.rehammer_attempts)
+  }
+
+  //can contain duplicates
+  pub fn iter_aggr_patterns_per_victim(
+    &self
+  ) -> impl Iterator<Item=&AggressorPattern> {
+    self.victim_frames.iter()
+      .map(|a| &a.victim_bits).flatten()
+      .map(move |b| self.aggressor_patterns.get(&b.aggr_pattern_key).unwrap())
+  }
+
+  //Groups arg:patterns into the patterns that are hammered together: all
+  //(distinct) patterns at once if `interleave` is set (see
+  //`hammer::hammer_jit_interleaved`), otherwise one pattern per round
+  pub fn hammer_rounds<'a>(
+    &self, patterns: impl Iterator<Item=&'a AggressorPattern>
+  ) -> Vec<Vec<&'a AggressorPattern>> {
+    if self.interleave {
+      let mut round: Vec<&AggressorPattern> = Vec::new();
+      for p in patterns {
+        if !round.contains(&p) {
+          round.push(p);
+        }
+      }
+      if round.is_empty() {Vec::new()} else {vec![round]}
+    } else {
+      patterns.map(|p| vec![p]).collect()
+    }
+  }
+
+  //iter all rows used as aggressor
+  //can contain duplicates (potentially with different init value!!) if same
+  //row is used in multiple patterns! (see victim_conflicts::init_groups)
+  pub fn iter_aggr_rows(&self) -> impl Iterator<Item=&RowAndInitValue> + '_ {
+    self.aggressor_patterns.values().flatten()
+  }
+
+  //iterator over all frames that are used as an aggressor (all frames in all
+  //aggr rows)
+  pub fn iter_aggr_frames(&self) -> impl Iterator<Item=u64> + '_ {
+    self.iter_aggr_rows().map(|x| &x.frames).flatten().cloned()
+  }
+}
+
+
+// --- TEMPLATER ---
+//main structure for templater_config.toml
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TemplaterConfig {
+  pub row_start: u64,
+  pub row_end: u64,
+  pub bank_idxs: Vec<u64>,
+  pub hammer_count: u64,
+  pub garbage_count_start: u32,
+  pub garbage_count_end: u32,
+  pub drop_frac: f64,
+  pub init_values: Vec<VicAggrInit>,
+  pub repetition: usize,
+  //the rows of a setup, one character per row (see `parse_hammer_pattern` in
+  //the templater), or the name of a classic mode (see `expand_pattern`)
+  #[serde(deserialize_with = "deserialize_pattern")]
+  pub pattern: String,
+  //access weight of every aggressor row of `pattern` (the `A` rows, the `B`
+  //rows, the `D` rows, then the `F` rows), empty = 1 each, only the
+  //`double_sided` kernel of the `jit` backend honors them (see
+  //`RowAndInitValue::weight`), e.g. to access the decoy rows more often than
+  //the aggressors
+  #[serde(default)]
+  pub aggr_weights: Vec<u32>,
+  //the `B` ("dribble") rows of `pattern` are only accessed in every
+  //dribble_interval-th hammer iteration (see `RowAndInitValue::interval`)
+  #[serde(default = "default_dribble_interval")]
+  pub dribble_interval: u32,
+  //the `D` rows of `pattern` are decoys (see `RowAndInitValue::decoy`), only
+  //accessed in every decoy_interval-th hammer iteration
+  #[serde(default = "default_weight")]
+  pub decoy_interval: u32,
+  //the `F` ("far") rows of `pattern` are this many rows after the first row
+  //of the pattern, in the bank of the setup or in `far_row_bank`
+  #[serde(default = "default_far_row_distance")]
+  pub far_row_distance: u64,
+  #[serde(default)]
+  pub far_row_bank: Option<u64>,
+  //number of extra virtual mappings per aggressor frame to rotate between
+  //while hammering (0 = only use the allocated page)
+  #[serde(default)]
+  pub aggr_aliases: usize,
+  //byte ranges within each victim row that are inspected for flips (empty =
+  //the whole row)
+  #[serde(default)]
+  pub victim_regions: Vec<RowRegion>,
+  //target activation rates (activations per tREFI), if set the garbage counts
+  //to sweep are calibrated in [garbage_count_start, garbage_count_end[ to hit
+  //these rates instead of sweeping the whole range
+  #[serde(default)]
+  pub activation_rates: Vec<f64>,
+  //the garbage counts to sweep, overrides the range, `garbage_delays_ns` and
+  //`activation_rates` (e.g., the garbage counts of a replayed template)
+  #[serde(default)]
+  pub garbage_counts: Vec<u32>,
+  //garbage delays per hammer iteration (ns) to sweep, converted to garbage
+  //counts with the garbage duration measured at startup (see
+  //calibration::measure_garbage_ns), overrides the range and
+  //`activation_rates`
+  #[serde(default)]
+  pub garbage_delays_ns: Vec<f64>,
+  //hammer implementation (see hammer::HammerBackend), `hammer_kernel`,
+  //`hammer_schedule`, `bursts` and `aggr_aliases` only apply to `jit`
+  #[serde(default)]
+  pub hammer_backend: HammerBackend,
+  //name of the hammer kernel (see hammer::HammerKernel), defaults to
+  //`double_sided`, or `aliases` when `aggr_aliases` > 0
+  #[serde(default)]
+  pub hammer_kernel: Option<String>,
+  //hammer schedule (see rowhammer::schedule), replaces `hammer_kernel`
+  #[serde(default)]
+  pub hammer_schedule: Option<String>,
+  //setups with a row that received more activations than this over all
+  //previous experiments are skipped (see row_wear), None = no limit
+  #[serde(default)]
+  pub max_row_wear: Option<u64>,
+  //path of a row remap table (see `RowRemap`), the rows of the pattern are
+  //physically adjacent rows instead of logically adjacent rows then
+  #[serde(default)]
+  pub row_remap: Option<String>,
+  //find the minimal aggressor subset of every discovered victim after
+  //templating (see `MemoryTemplate::minimal_patterns`)
+  #[serde(default)]
+  pub minimize_aggressors: bool,
+  //only victims that flipped in at least this many experiment rounds (over
+  //all garbage counts) are written to the template (see
+  //`TemplateVictim::rounds`)
+  #[serde(default = "default_min_rounds")]
+  pub min_rounds: usize,
+  //idle times in ns between the hammer bursts, swept like the garbage counts
+  //(see `MemoryTemplate::idle_time_distribution`), empty = no idle time
+  #[serde(default)]
+  pub idle_times_ns: Vec<u64>,
+  //TSC cycles every aggressor row is kept open per activation (RowPress, see
+  //`hammer::RowPressKernel`), swept like the garbage counts (see
+  //`MemoryTemplate::row_open_distribution`), replaces `hammer_kernel`, empty
+  //= no RowPress
+  #[serde(default)]
+  pub row_open_cycles: Vec<u64>,
+  //number of bursts the hammer_count is split into (see
+  //`hammer::hammer_kernel_bursts`)
+  #[serde(default = "default_bursts")]
+  pub bursts: u64,
+  //number of setups in different banks that are hammered together in one
+  //hammer code (0 or 1 = one setup at a time, see `interleave_banks` in the
+  //templater)
+  #[serde(default)]
+  pub interleave_banks: usize,
+  //with `interleave_banks`: interleave the accesses of the setups of a group
+  //round-robin instead of hammering their aggressors one setup after
+  //another (see `hammer::interleave_accesses`)
+  #[serde(default)]
+  pub bank_parallel: bool,
+  //order in which the setups are hammered in the repetitions
+  #[serde(default)]
+  pub repetition_order: RepetitionOrder,
+  //seed of the `randomized_blocks` order (drawn and recorded in the template
+  //if not set)
+  #[serde(default)]
+  pub order_seed: Option<u64>,
+  //template the row range in windows of this many rows, only the frames of
+  //one window are allocated at a time (0 = the whole range at once, see
+  //`run_windowed` in the templater)
+  #[serde(default)]
+  pub window_rows: u64,
+  //fraction of the victim rows (in all banks) the `triage` mode of the
+  //templater samples
+  #[serde(default = "default_triage_coverage")]
+  pub triage_coverage: f64,
+  //confidence level of the flips per GB interval of the `triage` mode (see
+  //analysis::triage_estimate)
+  #[serde(default = "default_triage_confidence")]
+  pub triage_confidence: f64,
+  //non-uniform access pattern of the aggressors (see rowhammer::fuzz),
+  //replaces `hammer_kernel` and `hammer_schedule`, set by the `fuzz` mode of
+  //the templater for every sampled pattern
+  #[serde(default)]
+  pub fuzz_pattern: Option<FuzzedPattern>,
+  //number of patterns the `fuzz` mode samples
+  #[serde(default = "default_fuzz_patterns")]
+  pub fuzz_patterns: usize,
+  //rows of the random region of every cycle of the `soak` mode of the
+  //templater
+  #[serde(default = "default_soak_rows")]
+  pub soak_rows: u64,
+  //the `soak` mode gives up after this many cycles failed in a row
+  #[serde(default = "default_soak_max_failures")]
+  pub soak_max_failures: u32,
+  //workloads on other cores while hammering (see utils::workload::CoRunners),
+  //to measure how much system activity reduces the flips
+  #[serde(default)]
+  pub co_runners: Vec<CoRunner>
+}
+
+//The workload of a co-runner (see utils::workload::CoRunners)
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CoRunnerKind {
+  //streams through a large buffer, competes for the memory bandwidth
+  MemoryBandwidth,
+  //touches the lines of an LLC-sized buffer in a random order, evicts the
+  //hammer code, the page tables, ... from the caches
+  CacheThrash
+}
+
+impl CoRunnerKind {
+  pub fn default_buffer_kb(&self) -> usize {
+    match self {
+      CoRunnerKind::MemoryBandwidth => 256 << 10,
+      CoRunnerKind::CacheThrash => 32 << 10
+    }
+  }
+}
+
+//A co-runner: a thread pinned to `core` that runs the workload `kind` on a
+//buffer of `buffer_kb` KB (default: see `CoRunnerKind::default_buffer_kb`)
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CoRunner {
+  pub kind: CoRunnerKind,
+  pub core: u32,
+  #[serde(default)]
+  pub buffer_kb: Option<usize>
+}
+
+impl CoRunner {
+  pub fn buffer_size(&self) -> usize {
+    self.buffer_kb.unwrap_or_else(|| self.kind.default_buffer_kb()) << 10
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RepetitionOrder {
+  //all setups in repetition r before repetition r + 1
+  #[default]
+  RepetitionMajor,
+  //all repetitions of a setup before the next setup
+  SetupMajor,
+  //as `repetition_major`, but every repetition hammers the setups in another
+  //random order (drift, e.g. of the temperature, does not bias later setups)
+  RandomizedBlocks
+}
+
+fn default_min_rounds() -> usize {
+  1
+}
+
+fn default_bursts() -> u64 {
+  1
+}
+
+fn default_triage_coverage() -> f64 {
+  0.05
+}
+
+fn default_triage_confidence() -> f64 {
+  0.95
+}
+
+fn default_fuzz_patterns() -> usize {
+  20
+}
+
+fn default_soak_rows() -> u64 {
+  32
+}
+
+fn default_soak_max_failures() -> u32 {
+  5
+}
+
+fn default_dribble_interval() -> u32 {
+  64
+}
+
+fn default_far_row_distance() -> u64 {
+  512
+}
+
+//The pattern of the classic hammering modes:
+//  - `double_sided`: the victim between two aggressors
+//  - `single_sided`: one aggressor next to the victims and a far row that
+//    closes the aggressor row (row buffer conflict)
+//  - `one_location`: only one aggressor, the memory controller closes its
+//    row (closed-page policy)
+//Other patterns are returned as they are
+pub fn expand_pattern(pattern: &str) -> String {
+  match pattern {
+    "double_sided" => "AVA",
+    "single_sided" => "VAVF",
+    "one_location" => "VAV",
+    pattern => pattern
+  }.to_owned()
+}
+
+fn deserialize_pattern<'de, D>(d: D) -> Result<String, D::Error>
+where D: Deserializer<'de> {
+  Ok(expand_pattern(&String::deserialize(d)?))
+}
+
+//byte offsets [start, end[ relative to the start of a row
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RowRegion {
+  pub start: u64,
+  pub end: u64
+}
+
+impl TemplaterConfig {
+  //rows of the region a setup occupies (all rows of `pattern` except the `F`
+  //rows)
+  pub fn pattern_rows(&self) -> u64 {
+    self.pattern.chars().filter(|c| *c != 'F').count() as u64
+  }
+
+  //the row offset ranges to inspect for flips in rows of arg:row_size bytes
+  pub fn victim_ranges(&self, row_size: u64) -> Vec<std::ops::Range<u64>> {
+    if self.victim_regions.is_empty() {
+      return vec![0..row_size];
+    }
+    self.victim_regions.iter().map(|r| {
+      assert!(r.start < r.end && r.end <= row_size,
+        "Invalid victim region 0x{:x}-0x{:x} (row size 0x{:x})",
+        r.start, r.end, row_size);
+      r.start..r.end
+    }).collect()
+  }
+}
+
+//Logical -> physical index of the rows in a range, probed by
+//`templater probe-adjacency` (see rowhammer::adjacency)
+#[derive(Serialize, Deserialize)]
+pub struct RowRemap {
+  pub rows: BTreeMap<u64, u64>,
+  //the probed neighbors (row, number of flips) of every row, for inspection
+  #[serde(default)]
+  pub neighbors: BTreeMap<u64, Vec<(u64, u32)>>
+}
+
+impl RowRemap {
+  //rows outside the probed range are not remapped
+  pub fn to_physical(&self, row: u64) -> u64 {
+    self.rows.get(&row).cloned().unwrap_or(row)
+  }
+
+  pub fn to_logical(&self, row: u64) -> u64 {
+    self.rows.iter().find(|(_, p)| **p == row).map_or(row, |(l, _)| *l)
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VicAggrInit {
+  pub victim_init: u8,
+  pub aggr_init: u8
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MemoryTemplate {
+  pub templater_config: TemplaterConfig,
+  pub dram_config: DRAMConfig,
+  pub host_config: HostConfig,
+  pub timestamp: String,
+  pub comment: String,
+  #[serde(deserialize_with = "deserialize_victims")]
+  #[serde(serialize_with = "serialize_victims")]
+  pub victims: Vec<TemplateVictim>,
+  pub aggr_patterns: PatternStore,
+  pub distribution: HashMap<u32, Vec<Vec<usize>>>,
+  //garbage_count -> measured activations per tREFI (average over all setups)
+  #[serde(default)]
+  pub activation_rates: HashMap<u32, f64>,
+  //duration of one garbage instruction on the templating machine (ns), the
+  //garbage delay of a garbage count is garbage_count * garbage_ns
+  #[serde(default)]
+  pub garbage_ns: Option<f64>,
+  //garbage_count -> the sum of the hammer stats of all setups
+  #[serde(default)]
+  pub hammer_stats: HashMap<u32, HammerStats>,
+  //system state at the end of each experiment round
+  #[serde(default)]
+  pub system_states: Vec<SystemState>,
+  //DRAM address of each victim (same order as `victims`)
+  #[serde(default)]
+  pub victims_dram: Vec<DRAMAddr>,
+  //DRAM address of each aggressor row (same order as `aggr_patterns`)
+  #[serde(default)]
+  pub aggr_patterns_dram: Vec<Vec<DRAMAddr>>,
+  //id of the minimal aggressor pattern of each victim (same order as
+  //`victims`, only with `minimize_aggressors`, None = the victim did not flip
+  //again)
+  #[serde(default)]
+  pub minimal_patterns: Vec<Option<usize>>,
+  //idle time in ns -> the discovered victims like in `distribution` (only
+  //with `idle_times_ns`, `distribution` holds the victims of all idle times)
+  #[serde(default)]
+  pub idle_time_distribution: BTreeMap<u64, HashMap<u32, Vec<Vec<usize>>>>,
+  //row open cycles -> the discovered victims like in `distribution` (only
+  //with `row_open_cycles`, `distribution` holds the victims of all of them)
+  #[serde(default)]
+  pub row_open_distribution: BTreeMap<u64, HashMap<u32, Vec<Vec<usize>>>>,
+  //resource usage of the templater (see utils::resources)
+  #[serde(default)]
+  pub resources: ResourceSummary,
+  //what the co-runners of the templater config achieved
+  #[serde(default)]
+  pub co_runner_stats: Vec<CoRunnerStats>
+}
+
+impl MemoryTemplate {
+  //Derives the frames of the aggressor rows from arg:geometry (see
+  //`RowAndInitValue::set_geometry`)
+  pub fn with_geometry(mut self, geometry: &Geometry) -> MemoryTemplate {
+    self.aggr_patterns.set_geometry(geometry);
+    self
+  }
+
+  //DRAM addresses of arg:victims and the aggressors in arg:aggr_patterns
+  pub fn annotate_dram(
+    victims: &[TemplateVictim],
+    aggr_patterns: &PatternStore,
+    dram_config: &DRAMConfig
+  ) -> (Vec<DRAMAddr>, Vec<Vec<DRAMAddr>>) {
+    let victims_dram = victims.iter()
+      .map(|v| dram::phys_to_dram(v.phys_addr, dram_config)).collect();
+    let aggr_patterns_dram = aggr_patterns.iter().map(|p| p.into_iter()
+      .map(|r| dram::phys_to_dram(r.frames[0], dram_config)).collect())
+      .collect();
+    (victims_dram, aggr_patterns_dram)
+  }
+}
+
+//A victim discovered by the templater
+//(De)serialized as
+//"<phys_addr> <flip> <aggr_pattern_id> <rounds> <garbage_count>:<rounds>,..."
+//(templates of older versions only have the first 3 fields)
+#[derive(Clone, Default)]
+pub struct TemplateVictim {
+  pub phys_addr: u64,
+  pub bitflip: BitFlip,
+  pub aggr_pattern_id: usize,
+  //number of experiment rounds (over all garbage counts) in which the victim
+  //flipped, 0 = unknown
+  pub rounds: usize,
+  //garbage_count -> number of experiment rounds in which the victim flipped
+  pub rounds_per_garbage_count: BTreeMap<u32, usize>
+}
+
+fn deserialize_victims<'de, D>(d: D)
+  -> Result<Vec<TemplateVictim>, D::Error>
+where D: Deserializer<'de> {
+  let tmp: Vec<String> = Vec::deserialize(d)?;
+  let mut res = Vec::new();
+  for t in tmp {
+    let r: Vec<&str> = t.split(" ").collect();
+    let flip = BitFlip::from_str(r[1]).unwrap();
+    let rounds_per_garbage_count = r.get(4).map_or(BTreeMap::new(), |x|
+      x.split(',').map(|g| {
+        let (garbage_count, rounds) = g.split_once(':').unwrap();
+        (garbage_count.parse().unwrap(), rounds.parse().unwrap())
+      }).collect());
+    res.push(TemplateVictim {
+      phys_addr: u64::from_str_radix(&r[0][2..], 16).unwrap(),
+      bitflip: flip,
+      aggr_pattern_id: usize::from_str(r[2]).unwrap(),
+      rounds: r.get(3).map_or(0, |x| x.parse().unwrap()),
+      rounds_per_garbage_count
+    });
+  }
+  Ok(res)
+}
+
+fn serialize_victims<S>(v: &Vec<TemplateVictim>, s: S)
+  -> Result<S::Ok , S::Error>
+where S: Serializer {
+  let mut ser = s.serialize_seq(Some(v.len()))?;
+  for victim in v {
+    let tmp = format!("0x{:x} {} {} {} {}", victim.phys_addr, victim.bitflip,
+      victim.aggr_pattern_id, victim.rounds, victim.rounds_per_garbage_count
+      .iter().map(|(g, r)| format!("{}:{}", g, r))
+      .collect::<Vec<_>>().join(","));
+    ser.serialize_element(&tmp)?;
+  };
+
+  ser.end()
+}
+
+
+// --- DRAM CONFIG ---
+//main structure for dram_config.toml with DRAM to/from physical address
+//translation functions
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DRAMConfig {
+  pub dram_id: String,
+  pub row_fn: u64,
+  pub column_fn: u64,
+  pub bank_fns: Vec<u64>,
+  //detected memory type (configs without it are DDR3)
+  #[serde(default)]
+  pub dram_type: DRAMType,
+  //number of DDR4 bank groups, the lowest bits of the bank number select the
+  //bank group (None for DDR3)
+  #[serde(default)]
+  pub bank_groups: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DRAMType {
+  #[serde(rename = "DDR3")]
+  #[default]
+  Ddr3,
+  #[serde(rename = "DDR4")]
+  Ddr4
+}
+
+impl fmt::Display for DRAMType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DRAMType::Ddr3 => write!(f, "DDR3"),
+      DRAMType::Ddr4 => write!(f, "DDR4")
+    }
+  }
+}
+
+impl FromStr for DRAMType {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "DDR3" => Ok(DRAMType::Ddr3),
+      "DDR4" => Ok(DRAMType::Ddr4),
+      _ => Err(format!("Unsupported memory type {}", s))
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DRAMInfo {
+  pub dram_ids: HashMap<String, String>,
+  pub mapping_functions: HashMap<String, MappingFunctions>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MappingFunctions {
+  pub row_fn: u64,
+  pub column_fn: u64,
+  pub bank_fns: Vec<u64>
+}
+
+//Mapping functions derived by reverse engineering (see mapping_re), keyed by
+//the DIMM serial number
+#[derive(Serialize, Deserialize, Default)]
+pub struct MappingCache {
+  pub dimms: HashMap<String, MappingFunctions>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HostConfig {
+  pub hostname: String,
+  pub motherboard_id: String,
+  pub cpu_model: String,
+  pub smt_disabled: bool,
+  pub turbo_disabled: bool,
+  //L2 prefetchers disabled during hammering (`RH_DISABLE_PREFETCH`)
+  #[serde(default)]
+  pub prefetchers_disabled: bool,
+  //SCHED_FIFO priority of the hammering thread (`RH_SCHED_FIFO`)
+  #[serde(default)]
+  pub sched_fifo_priority: Option<i32>,
+  //timer slack in ns of the hammering thread (`RH_TIMERSLACK`)
+  #[serde(default)]
+  pub timerslack_ns: Option<u64>,
+  //victim-side workload during hammering, e.g. "Uncached every 100ns"
+  //(`RH_VICTIM_WORKLOAD`)
+  #[serde(default)]
+  pub victim_workload: Option<String>,
+  //type of the cores the hammering ran on, None without a hybrid core
+  //topology or if the assigned cores have different types
+  //(`RH_CORE_TYPE=any`)
+  #[serde(default)]
+  pub core_type: Option<CoreType>,
+  //[(cpuid, info)] info = is_isolated,is_nohz_full,scaling_governor,
+  //min&max_freq,prefetchers,core_type
+  pub cpu_ids: Vec<(u32, String)>,
+  pub git_hash: String, //not really "host" config
+  pub ram_swap_id: u32,
+  pub os_release: String,
+  pub kernel: String
+}
+
+//Core type of a CPU with a hybrid core topology (e.g., Alder Lake), the
+//aggressor access rate of an E-core is far below the rate of a P-core
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CoreType {
+  Performance,
+  Efficiency
+}
+
+impl CoreType {
+  pub fn name(&self) -> &'static str {
+    match self {
+      CoreType::Performance => "P-core",
+      CoreType::Efficiency => "E-core"
+    }
+  }
+}
+
+//system load and kernel events during one experiment round
+#[derive(Serialize, Deserialize)]
+pub struct SystemState {
+  pub repetition: usize,
+  pub loadavg: String,
+  pub memory_pressure: String,
+  //kernel log messages about machine checks, EDAC errors, OOM kills, ...
+  pub kernel_messages: Vec<String>
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn templater_config(pattern: &str) -> TemplaterConfig {
+    toml::from_str(&format!("row_start = 0\nrow_end = 8\nbank_idxs = [0]\n\
+      hammer_count = 1\ngarbage_count_start = 0\ngarbage_count_end = 1\n\
+      drop_frac = 0.0\ninit_values = []\nrepetition = 1\n\
+      pattern = \"{}\"", pattern)).unwrap()
+  }
+
+  #[test]
+  fn classic_patterns() {
+    assert_eq!(expand_pattern("double_sided"), "AVA");
+    assert_eq!(expand_pattern("single_sided"), "VAVF");
+    assert_eq!(expand_pattern("one_location"), "VAV");
+    assert_eq!(expand_pattern("AVAVA"), "AVAVA");
+    //the far rows are not in the region of a setup
+    for (pattern, expanded, rows) in [("double_sided", "AVA", 3),
+      ("single_sided", "VAVF", 3), ("one_location", "VAV", 3),
+      ("AVFAVF", "AVFAVF", 4)]
+    {
+      let config = templater_config(pattern);
+      assert_eq!(config.pattern, expanded);
+      assert_eq!(config.pattern_rows(), rows);
+      assert_eq!(config.far_row_distance, 512);
+    }
+  }
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationNanoSeconds};
+
+use crate::config::*;
+use crate::core::serialize::CoRunnerKind;
+
+//The statistics the tools record in their results: the hammer stats of
+//every hammer invocation, the refresh timing and the resource usage of a
+//session (see utils::resources) and the throughput of the co-runners (see
+//utils::workload::CoRunners)
+
+//What every hammer function returns, the templater records the sum per
+//garbage count in the template (`hammer_stats`)
+//Every aggressor access is counted as one activation
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct HammerStats {
+  //hammer time, without the JIT compilation and the idle times between
+  //bursts
+  #[serde(rename = "duration_ns")]
+  #[serde_as(as = "DurationNanoSeconds<u64>")]
+  pub duration: Duration,
+  //hammer iterations, more than the hammer_count with a `hammer_duration`
+  //(see hammer::HammerDuration)
+  #[serde(default)]
+  pub hammer_count: u64,
+  //aggressor accesses
+  pub accesses: u64,
+  pub accesses_per_s: f64,
+  //estimated from the accesses (see `activation_rate`)
+  pub activations_per_trefi: f64,
+  //emitting and assembling the JIT code, 0 for the other backends
+  #[serde(rename = "compile_time_ns")]
+  #[serde_as(as = "DurationNanoSeconds<u64>")]
+  pub compile_time: Duration,
+  //only with refresh synchronization, None if no refreshes were detected
+  pub refresh: Option<RefreshTiming>,
+  //only with latency sampling (see hammer::JitProbes)
+  #[serde(default)]
+  pub latency: Option<LatencyHistogram>
+}
+
+impl HammerStats {
+  pub fn new(
+    hammer_count: u64, accesses: u64, duration: Duration,
+    compile_time: Duration
+  ) -> HammerStats {
+    HammerStats {
+      duration,
+      hammer_count,
+      accesses,
+      accesses_per_s: accesses as f64 / duration.as_secs_f64().max(1e-9),
+      activations_per_trefi:
+        activation_rate(1, accesses, duration),
+      compile_time,
+      refresh: None,
+      latency: None
+    }
+  }
+
+  //Sums the accesses and times of arg:other into these stats
+  pub fn add(&mut self, other: &HammerStats) {
+    *self = HammerStats {
+      refresh: self.refresh.or(other.refresh),
+      latency: match (self.latency, other.latency) {
+        (Some(mut latency), Some(other)) => {
+          latency.add(&other);
+          Some(latency)
+        },
+        (latency, other) => latency.or(other)
+      },
+      ..HammerStats::new(self.hammer_count + other.hammer_count,
+        self.accesses + other.accesses, self.duration + other.duration,
+        self.compile_time + other.compile_time)
+    };
+  }
+}
+
+pub const LATENCY_BUCKETS: usize = 32;
+
+//Latencies of the sampled aggressor accesses in TSC cycles, in buckets of
+//`bucket_cycles` cycles, the last bucket counts all longer accesses
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyHistogram {
+  pub bucket_cycles: u64,
+  pub counts: [u64; LATENCY_BUCKETS]
+}
+
+impl LatencyHistogram {
+  pub fn samples(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  pub fn add(&mut self, other: &LatencyHistogram) {
+    assert_eq!(self.bucket_cycles, other.bucket_cycles,
+      "Adding latency histograms with different buckets");
+    self.counts.iter_mut().zip(other.counts.iter()).for_each(|(c, o)| *c += o);
+  }
+
+  //The lower bound (cycles) of the bucket of the arg:fraction quantile
+  pub fn quantile(&self, fraction: f64) -> u64 {
+    let rank = (self.samples() as f64 * fraction) as u64;
+    let mut seen = 0;
+    let bucket = self.counts.iter().position(|c| {
+      seen += c;
+      seen > rank
+    }).unwrap_or(LATENCY_BUCKETS - 1);
+    bucket as u64 * self.bucket_cycles
+  }
+
+  //The fraction of the samples that took at least arg:cycles
+  pub fn fraction_above(&self, cycles: u64) -> f64 {
+    let first = ((cycles / self.bucket_cycles.max(1)) as usize)
+      .min(LATENCY_BUCKETS - 1);
+    self.counts[first..].iter().sum::<u64>() as f64
+      / self.samples().max(1) as f64
+  }
+}
+
+//The refreshes detected by `detect_refresh`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RefreshTiming {
+  //latency in TSC ticks above which an access was blocked by a REF
+  pub threshold_tsc: u64,
+  pub trefi_tsc: u64,
+  pub trefi_ns: f64
+}
+
+//Activations per tREFI when arg:aggr_count aggressors are each accessed
+//arg:hammer_count times in arg:duration
+pub fn activation_rate(aggr_count: usize, hammer_count: u64, duration: Duration)
+  -> f64
+{
+  let activations = aggr_count as f64 * hammer_count as f64;
+  activations * TREFI_NS / duration.as_nanos().max(1) as f64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PhaseUsage {
+  pub phase: String,
+  pub wall_us: u64,
+  pub user_us: u64,
+  pub system_us: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ResourceSummary {
+  //maximum resident set size of the process so far
+  pub peak_rss_kb: u64,
+  pub pages_allocated: u64,
+  pub page_cache_drops: u64,
+  //phases with the same name are summed up, in the order of their first
+  //start, running phases with their usage so far
+  pub phases: Vec<PhaseUsage>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CoRunnerStats {
+  pub kind: CoRunnerKind,
+  pub core: u32,
+  //cache lines the co-runner touched (read and written)
+  pub accesses: u64,
+  //touched bytes per second while hammering
+  pub bytes_per_s: f64
+}
@@ -1,8 +1,13 @@
-use std::collections::{HashMap, BTreeMap};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Seek, Read};
+use std::ops::Range;
 use colored::Colorize;
 use log::*;
 
 use crate::utils::serialize::*;
+use crate::context::RhContext;
+use crate::mem_init::{ContentCache, VictimLocation};
 use crate::config::*;
 
 
@@ -12,7 +17,7 @@ use crate::config::*;
 pub fn check_victim_flips<'a>(
     victim_frames: impl Iterator<Item = &'a VictimFrame>,
     frame2map: &Frame2Map,
-    content_cache: &HashMap<u64, u8>
+    content_cache: &ContentCache
 ) -> bool {
   info!("Checking victims for flipped bits");
   let mut expected = true;
@@ -34,7 +39,8 @@ pub fn check_victim_flips<'a>(
       let virt_addr = (frame2map.get(&frame_addr)
         .unwrap().data() as u64 + page_offset) as *const u8;
       let content_after_rh = unsafe {std::ptr::read_volatile(virt_addr)};
-      let content_before_rh = *content_cache.get(&phys_addr).unwrap();
+      let content_before_rh = content_cache.get(
+        VictimLocation::new(frame_addr, page_offset));
 
       trace!("  - Content byte before RH: 0x{:x}, after: 0x{:x} (P0x{:x}, V0x{:x})",
         content_before_rh, content_after_rh, phys_addr, virt_addr as u64);
@@ -51,46 +57,185 @@ pub fn check_victim_flips<'a>(
 
         if exp_flip != 0 { //we expected a flip
           if flipped == exp_flip { //there was a flip with the same direction
-            warn!("{}", format!("Expected bit flip at 0x{:x}: {}{}",
+            warn!("{}", format!("Expected bit flip at P0x{:x}: {}{}",
               phys_addr, flip_idx, exp_sign).magenta());
           } else if flipped != 0 && flipped != exp_flip { //flip with diff sign
             warn!("{}", format!("Expected bit flip index, but WRONG sign at \
-              0x{:x}: expected {}{}, but found {}{}", phys_addr, flip_idx,
+              P0x{:x}: expected {}{}, but found {}{}", phys_addr, flip_idx,
               exp_sign, flip_idx, sign).red());
             expected = false;
           } else { //there was no flip
-            error!("{}", format!("Expected bit did NOT flip at 0x{:x}: \
+            error!("{}", format!("Expected bit did NOT flip at P0x{:x}: \
               expected {}{}", phys_addr, flip_idx, exp_sign).red());
             expected = false;
           }
         } else if flipped != 0 { //there was a flip while not expected
-          error!("{}", format!("UNexpected bit flip at 0x{:x}: {}{})",
+          error!("{}", format!("UNexpected bit flip at P0x{:x}: {}{})",
             phys_addr, flip_idx, sign).red());
           expected = false;
         }
       }
     }
+
+    //the whole multi-byte victim values (see `VictimBit::size`)
+    let values: BTreeMap<_, _> = victim_frame.victim_bits.iter()
+      .map(|b| VictimLocation::of_value(frame_addr, b))
+      .filter(|(_, size)| *size > 1).collect();
+    for (location, size) in values {
+      let virt_addr = frame2map.get(&frame_addr).unwrap().data() as u64
+        + location.offset;
+      let content_after_rh: Vec<u8> = (0..size).map(|i| unsafe {
+        std::ptr::read_volatile((virt_addr + i) as *const u8)}).collect();
+      debug!("  - Content of the {}-byte value before RH: {:x?}, after: {:x?} \
+        (P0x{:x}, V0x{:x})", size, content_cache.get_value(location, size),
+        content_after_rh, location.phys_addr(), virt_addr);
+    }
+
+    //the whole transition of the victim words
+    for value in &victim_frame.victim_values {
+      let location = VictimLocation::new(frame_addr, value.offset);
+      let virt_addr = frame2map.get(&frame_addr).unwrap().data() as u64
+        + value.offset;
+      let content_before_rh =
+        le_value(content_cache.get_value(location, value.size));
+      let content_after_rh = le_value(&(0..value.size).map(|i| unsafe {
+        std::ptr::read_volatile((virt_addr + i) as *const u8)})
+        .collect::<Vec<u8>>());
+      if content_before_rh != value.before {
+        error!("{}", format!("Victim value at P0x{:x} was 0x{:x} before \
+          hammering, expected 0x{:x}", location.phys_addr(),
+          content_before_rh, value.before).red());
+        expected = false;
+      } else if content_after_rh == value.after {
+        warn!("{}", format!("Expected victim value transition at P0x{:x}: \
+          0x{:x} -> 0x{:x}", location.phys_addr(), value.before, value.after)
+          .magenta());
+      } else {
+        error!("{}", format!("Victim value at P0x{:x} is 0x{:x} after \
+          hammering, expected 0x{:x} -> 0x{:x}", location.phys_addr(),
+          content_after_rh, value.before, value.after).red());
+        expected = false;
+      }
+    }
   }
 
   expected
 }
 
+//The little-endian value of arg:bytes (at most 8)
+fn le_value(bytes: &[u8]) -> u64 {
+  bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as u64)
+}
+
+//Returns whether the bit of arg:victim_bit in the frame at arg:frame_addr
+//flipped in the expected direction compared to the value in arg:content_cache
+pub fn check_victim_bit(
+  frame_addr: u64,
+  victim_bit: &VictimBit,
+  frame2map: &Frame2Map,
+  content_cache: &ContentCache
+) -> bool {
+  let virt_addr = (frame2map.get(&frame_addr)
+    .unwrap().data() as u64 + victim_bit.offset) as *const u8;
+  let content_after_rh = unsafe {std::ptr::read_volatile(virt_addr)};
+  let content_before_rh = content_cache.get(
+    VictimLocation::of_bit(frame_addr, victim_bit));
+  let mask = 1u8 << victim_bit.bitflip.flip_index;
+  let expected = if victim_bit.bitflip.flip_direction {mask} else {0};
+
+  content_before_rh & mask != expected && content_after_rh & mask == expected
+}
+
+//Checks, before hammering, that every victim byte in the mapped pages equals
+//the byte at the corresponding file offset in the binary at arg:program_path
+//(i.e., the memcpy placed the right content and nothing altered it since)
+//Panics with a diagnostic of all mismatches otherwise
+pub fn check_victim_content(
+  program_path: &str,
+  attack_config: &AttackConfig,
+  frame2map: &Frame2Map
+) {
+  info!("Checking victim bytes against the binary file");
+  let mut file = File::open(program_path).expect("Could not open file");
+  let mut mismatches = 0;
+
+  for victim_frame in &attack_config.victim_frames {
+    let frame_addr = victim_frame.frame_addr;
+    let page_file_offset = victim_frame.page_file_offset
+      .expect("Victim frame without page_file_offset");
+    for victim_bit in &victim_frame.victim_bits {
+      let file_offset = page_file_offset + victim_bit.offset;
+      let mut expected = [0u8; 1];
+      file.seek(std::io::SeekFrom::Start(file_offset))
+        .expect("Failed to seek in binary");
+      file.read_exact(&mut expected).expect("Failed to read binary");
+
+      let virt_addr = (frame2map.get(&frame_addr)
+        .unwrap().data() as u64 + victim_bit.offset) as *const u8;
+      let content = unsafe {std::ptr::read_volatile(virt_addr)};
+
+      if content != expected[0] {
+        error!("{}", format!("Victim byte at P0x{:x} (file offset 0x{:x}) \
+          is 0x{:x}, but the binary contains 0x{:x}", frame_addr
+          + victim_bit.offset, file_offset, content, expected[0]).red());
+        mismatches += 1;
+      }
+    }
+    for value in &victim_frame.victim_values {
+      let file_offset = page_file_offset + value.offset;
+      let mut content = vec![0u8; value.size as usize];
+      file.seek(std::io::SeekFrom::Start(file_offset))
+        .expect("Failed to seek in binary");
+      file.read_exact(&mut content).expect("Failed to read binary");
+      if le_value(&content) != value.before {
+        error!("{}", format!("The binary contains 0x{:x} at file offset \
+          0x{:x}, but the victim value at P0x{:x} expects 0x{:x}",
+          le_value(&content), file_offset, frame_addr + value.offset,
+          value.before).red());
+        mismatches += 1;
+      }
+    }
+  }
+
+  assert!(mismatches == 0, "{} victim bytes do not match the binary, was \
+    the page memcpyed to the wrong frame or patched after loading?",
+    mismatches);
+}
+
 //Check the row for changes compared to arg:init_value
 //Return the victim info for these flips (phys_addr, bit_idx, flip_sign)
 //Flips in the same byte get a separate item in the returned vector
 pub fn check_row_for_flips(
+  ctx: &RhContext,
   frame2map: &Frame2Map,
   phys_addr: u64,
   init_value: u8
 ) -> Vec<(u64, BitFlip)> {
-  debug!("Checking row P0x{:x} for flips", phys_addr);
+  check_row_region_for_flips(ctx, frame2map, phys_addr, init_value,
+    0..ctx.geometry.row_size())
+}
+
+//Same as `check_row_for_flips`, but only inspects the bytes at row offsets in
+//arg:region (e.g., a single page or some cachelines of the row)
+pub fn check_row_region_for_flips(
+  ctx: &RhContext,
+  frame2map: &Frame2Map,
+  phys_addr: u64,
+  init_value: u8,
+  region: Range<u64>
+) -> Vec<(u64, BitFlip)> {
+  debug!("Checking row P0x{:x} (offsets 0x{:x}-0x{:x}) for flips", phys_addr,
+    region.start, region.end);
+  let geometry = &ctx.geometry;
+  assert!(region.start <= region.end && region.end <= geometry.row_size(),
+    "Invalid row region 0x{:x}-0x{:x}", region.start, region.end);
 
   let mut discovered_victims = Vec::new();
-  let row_addr = phys_addr % ROW_ALIGN_MASK;
+  let row_addr = phys_addr & geometry.row_align_mask();
 
-  for phys_addr in row_addr..(row_addr + ROW_SIZE) {
-    let victim_frame_offset = phys_addr & PAGE_OFFSET_MASK;
-    let virt_addr = (frame2map.get(&(phys_addr & PAGE_ALIGN_MASK))
+  for phys_addr in (row_addr + region.start)..(row_addr + region.end) {
+    let victim_frame_offset = phys_addr & geometry.page_offset_mask();
+    let virt_addr = (frame2map.get(&(phys_addr & geometry.page_align_mask()))
       .unwrap().data() as u64 + victim_frame_offset) as *const u8;
     let content_after_rh = unsafe {std::ptr::read_volatile(virt_addr)};
 
@@ -104,7 +249,7 @@ pub fn check_row_for_flips(
           flip_index: flip_idx as u8,
           flip_direction: (content_after_rh & mask) != 0
         });
-        warn!("{}", format!("Bit flip at 0x{:x}: {}{}", phys_addr, flip_idx,
+        warn!("{}", format!("Bit flip at P0x{:x}: {}{}", phys_addr, flip_idx,
           if discovered_victim.1.flip_direction {"+"} else {"-"}).red());
         discovered_victims.push(discovered_victim);
       }
@@ -113,3 +258,71 @@ pub fn check_row_for_flips(
 
   discovered_victims
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mmap::{MapOption, MemoryMap};
+  use crate::context::{Geometry, Settings};
+  use crate::mem_init;
+
+  //Initializes all pages of a row (at the made-up physical address 0), flips
+  //a bit in its last page and checks the row
+  #[test]
+  fn row_init_and_flip_check() {
+    for pages_per_row in [1, 2, 4] {
+      let geometry = Geometry {pages_per_row, ..Default::default()};
+      let ctx = RhContext::new(geometry, Settings::default());
+      let row = RowAndInitValue {frames: geometry.frames_in_row(0),
+        init: 0xff, weight: 1, interval: 1, decoy: false};
+      let frame2map: Frame2Map = row.frames.iter().map(|f| (*f,
+        MemoryMap::new(geometry.page_size(),
+        &[MapOption::MapReadable, MapOption::MapWritable]).unwrap()))
+        .collect();
+      mem_init::initialize_rows(&ctx, std::iter::once(&row), &frame2map);
+      assert_eq!(row.frames.len() as u64, pages_per_row);
+      assert!(check_row_for_flips(&ctx, &frame2map, 0, row.init).is_empty());
+
+      let last = *row.frames.last().unwrap();
+      unsafe {*frame2map[&last].data().add(0x10) &= !(1 << 3);}
+      let flips = check_row_for_flips(&ctx, &frame2map, 0, row.init);
+      assert_eq!(flips.len(), 1);
+      assert_eq!(flips[0].0, last + 0x10);
+      assert!(flips[0].1 == BitFlip {flip_index: 3, flip_direction: false});
+    }
+  }
+
+  //A 2-byte victim value (at the made-up physical address 0x10) whose
+  //transition flips bit 8, i.e. bit 0 of its second byte
+  #[test]
+  fn victim_value_flip_check() {
+    let ctx = RhContext::new(Geometry::default(), Settings::default());
+    let attack_config: AttackConfig = serde_json::from_str(r#"{
+      "hammer_count": 1, "segment_virt_addr": 0, "aggressor_patterns": {},
+      "victim_frames": [{"page_file_offset": null, "frame_addr": 0,
+        "victim_values": [{"offset": 16, "size": 2, "before": 4660,
+        "after": 4916, "aggr_pattern_key": "0"}]}]}"#).unwrap();
+    let attack_config = attack_config.normalize();
+    let victims = &attack_config.victim_frames;
+    let frame2map: Frame2Map = std::iter::once((0, MemoryMap::new(
+      ctx.geometry.page_size(),
+      &[MapOption::MapReadable, MapOption::MapWritable]).unwrap())).collect();
+    let page = frame2map[&0].data();
+    let flip = || unsafe {*page.add(0x11) ^= 1};
+
+    //the page does not hold the `before` value
+    let content_cache = mem_init::read_victim_content(&ctx, victims.iter(),
+      &frame2map);
+    flip();
+    assert!(!check_victim_flips(victims.iter(), &frame2map, &content_cache));
+
+    mem_init::initialize_attack_victims(&attack_config, &frame2map);
+    assert_eq!(unsafe {[*page.add(0x10), *page.add(0x11)]}, [0x34, 0x12]);
+    let content_cache = mem_init::read_victim_content(&ctx, victims.iter(),
+      &frame2map);
+    assert!(!check_victim_flips(victims.iter(), &frame2map, &content_cache));
+    flip();
+    assert!(check_victim_flips(victims.iter(), &frame2map, &content_cache));
+    assert_eq!(le_value(&[0x35, 0x13]), 0x1335);
+  }
+}
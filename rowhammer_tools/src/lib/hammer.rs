@@ -1,22 +1,41 @@
 use std::env;
-use std::time::{SystemTime, Duration};
+use std::collections::HashMap;
+use std::arch::x86_64::{__cpuid_count, __rdtscp};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{SystemTime, Duration, Instant};
 use std::fs::File;
 use std::io::Write;
 use std::arch::asm;
 use dynasmrt::x64::Assembler;
 use log::*;
-use dynasmrt::{dynasm, DynasmApi, DynasmLabelApi};
+use dynasmrt::{dynasm, AssemblyOffset, DynamicLabel, DynasmApi, DynasmLabelApi,
+  ExecutableBuffer};
 use capstone::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::schedule::{Schedule, Step, MAX_REPEAT_DEPTH};
+use crate::fuzz::FuzzedPattern;
+use crate::schedule_export::{self, ExportedSchedule, Op};
 use crate::utils::garbage::*;
+use crate::utils::{host, sched};
+use crate::context::RhContext;
+use crate::self_guard;
+use crate::config::*;
+
+//re-export, so hammer kernels in other crates emit code with the same version
+pub use dynasmrt;
+pub use crate::core::serialize::{AccessRate, HammerBackend};
+pub use crate::core::stats::{HammerStats, LatencyHistogram, RefreshTiming,
+  LATENCY_BUCKETS};
 
 //Different rowhammer implementations, each hammers one aggressor pattern
 //for one victim
 
-//Hammer with Rust
+//Hammer with Rust, always flushes with clflush
 //(checked: accesses and clflushes are not removed during
 //optimization in release build)
-pub fn hammer_rust(pattern: &Vec<u64>, hammer_count: u64) -> Duration {
+pub fn hammer_rust(pattern: &Vec<u64>, hammer_count: u64) -> HammerStats {
   let start_time = SystemTime::now();
   for _ in 0..hammer_count {
     unsafe {
@@ -31,27 +50,15 @@ pub fn hammer_rust(pattern: &Vec<u64>, hammer_count: u64) -> Duration {
 
   let duration = SystemTime::now().duration_since(start_time).unwrap();
   debug!("Hammering took {}ms", duration.as_millis());
-  duration
+  HammerStats::new(hammer_count, pattern.len() as u64 * hammer_count,
+    duration, Duration::ZERO)
 }
 
-//Hammer with assembly with garbage interleaving
-pub fn hammer_asm(pattern: &Vec<u64>, gar: u32, hammer_count: u64) -> Duration {
-  //otherwise, infinite loop
-  assert!(gar != 0, "garbage count should not be 0 for asm hammering");
-
-  //if adds are dependent of load
-  /*
-  for d in virt_addrs {
-    unsafe {
-      *(*d as *mut i64) = -(gar as i64);
-    }
-  }
-  */
-
-  let start_time = SystemTime::now();
-
-  //intel syntax!
-  unsafe {asm!(
+//The hammer loop of `hammer_asm`, arg:flush is the mnemonic of the flush
+//instruction
+macro_rules! hammer_asm_loop {
+  ($flush:literal, $pattern:ident, $gar:ident, $hammer_count:ident) => {
+    asm!(
     //"lea     0xf(%rip),{ee}",
     "2:",
     //reset aggr_base and itr
@@ -81,7 +88,7 @@ pub fn hammer_asm(pattern: &Vec<u64>, gar: u32, hammer_count: u64) -> Duration {
     "mov     {i}, {itr}",
     "4:",
     "mov     {aggr}, [{b}]",
-    "clflush [{aggr}]", //flush aggrs
+    concat!($flush, " [{aggr}]"), //flush aggrs
     //increment to next aggr
     "add     {b}, 0x8",
     "inc     {i}",
@@ -91,20 +98,117 @@ pub fn hammer_asm(pattern: &Vec<u64>, gar: u32, hammer_count: u64) -> Duration {
     "jne     2b",
 
     //uses 64bit regs by default
-    cnt = inout(reg) -(hammer_count as i64) => _, //in and clobbered
-    aggr_base = in(reg) pattern.as_ptr() as *const u64,
+    cnt = inout(reg) -($hammer_count as i64) => _, //in and clobbered
+    aggr_base = in(reg) $pattern.as_ptr() as *const u64,
     b = out(reg) _,
-    itr = in(reg) -(pattern.len() as i64),
+    itr = in(reg) -($pattern.len() as i64),
     i = out(reg) _,
     garbage = out(reg) _,
     aggr = out(reg) _,
-    r = in(reg) -(gar as i64),
+    r = in(reg) -($gar as i64),
     //ee = out(reg) _
-  );}
+    )
+  }
+}
+
+//Hammer with assembly with garbage interleaving
+pub fn hammer_asm(
+  pattern: &Vec<u64>, gar: u32, hammer_count: u64, flush: FlushInstruction
+) -> HammerStats {
+  //otherwise, infinite loop
+  assert!(gar != 0, "garbage count should not be 0 for asm hammering");
+
+  //if adds are dependent of load
+  /*
+  for d in virt_addrs {
+    unsafe {
+      *(*d as *mut i64) = -(gar as i64);
+    }
+  }
+  */
+
+  let start_time = SystemTime::now();
+
+  //intel syntax!
+  unsafe {
+    match flush {
+      FlushInstruction::Clflush =>
+        hammer_asm_loop!("clflush", pattern, gar, hammer_count),
+      FlushInstruction::Clflushopt =>
+        hammer_asm_loop!("clflushopt", pattern, gar, hammer_count),
+      FlushInstruction::Clwb =>
+        hammer_asm_loop!("clwb", pattern, gar, hammer_count)
+    }
+  }
 
   let duration = SystemTime::now().duration_since(start_time).unwrap();
   debug!("Hammering took {}ms", duration.as_millis());
-  duration
+  HammerStats::new(hammer_count, pattern.len() as u64 * hammer_count,
+    duration, Duration::ZERO)
+}
+
+// --- HAMMER DURATION ---
+//Alternative stop criterion to the hammer_count: with `RH_HAMMER_DURATION`
+//(the `hammer_duration` setting of the context), the hammer functions hammer
+//for a wall-clock duration (e.g., `64ms`, `500us`, `2s`) or a multiple of
+//tREFI (e.g., `8192trefi`, with TREFI_NS), the budget of most
+//recent papers, instead of a fixed number of iterations
+//The hammer code of hammer_count iterations (the JIT code: with its bursts)
+//is repeated until the duration has passed, at least once, so the duration
+//is rounded up to whole runs: choose a small hammer_count for a fine
+//granularity (the JIT code is only compiled once)
+//The achieved iterations are in the `hammer_count` of the stats
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HammerDuration {
+  Time(Duration),
+  //multiple of tREFI
+  Trefi(u64)
+}
+
+impl HammerDuration {
+  pub fn parse(s: &str) -> HammerDuration {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse()
+      .unwrap_or_else(|_| panic!("Invalid hammer duration {}", s));
+    match unit {
+      "trefi" => HammerDuration::Trefi(value),
+      "ns" => HammerDuration::Time(Duration::from_nanos(value)),
+      "us" => HammerDuration::Time(Duration::from_micros(value)),
+      "ms" => HammerDuration::Time(Duration::from_millis(value)),
+      "s" => HammerDuration::Time(Duration::from_secs(value)),
+      _ => panic!("Invalid hammer duration {}, expected a number followed \
+        by ns, us, ms, s or trefi", s)
+    }
+  }
+
+  pub fn duration(&self) -> Duration {
+    match self {
+      HammerDuration::Time(duration) => *duration,
+      HammerDuration::Trefi(trefis) =>
+        Duration::from_nanos((*trefis as f64 * TREFI_NS) as u64)
+    }
+  }
+}
+
+//Repeats arg:hammer until the `hammer_duration` of arg:ctx has passed, at
+//least once (without a duration exactly once)
+fn repeat_for_duration(
+  ctx: &RhContext, mut hammer: impl FnMut() -> HammerStats
+) -> HammerStats {
+  let deadline = ctx.settings.hammer_duration
+    .map(|d| Instant::now() + d.duration());
+  let mut stats = hammer();
+  while deadline.is_some_and(|d| Instant::now() < d) {
+    stats.add(&hammer());
+  }
+  if let Some(duration) = ctx.settings.hammer_duration {
+    debug!("Hammered {} iterations in {:?} ({:?})", stats.hammer_count,
+      stats.duration, duration);
+  }
+  stats
 }
 
 pub extern "C" fn print(fmt: *const i8, arg: u64) {
@@ -126,42 +230,1613 @@ pub extern "C" fn sleep(millis: u64) {
     ; call rax
 */
 
+// --- FLUSH INSTRUCTION ---
+//The instruction that flushes the aggressors after their accesses
+//(`RH_FLUSH`, the `flush` setting of the context, default: clflush)
+//clflush is ordered with the other clflushes and the stores, clflushopt and
+//clwb only with fences, locked instructions and stores to the same line: the
+//flushes of an iteration overlap, which changes the reachable activation
+//rate (on Skylake and later)
+//clwb writes the line back but may keep it in the cache (Ice Lake and later
+//do), the next access is then no activation
+//An instruction the CPU does not support (CPUID) falls back to the next
+//weaker one: clwb -> clflushopt -> clflush
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FlushInstruction {
+  #[default]
+  Clflush,
+  Clflushopt,
+  Clwb
+}
+
+impl FlushInstruction {
+  pub fn parse(name: &str) -> FlushInstruction {
+    match name {
+      "clflush" => FlushInstruction::Clflush,
+      "clflushopt" => FlushInstruction::Clflushopt,
+      "clwb" => FlushInstruction::Clwb,
+      _ => panic!("Unknown flush instruction {} (clflush, clflushopt or clwb)",
+        name)
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      FlushInstruction::Clflush => "clflush",
+      FlushInstruction::Clflushopt => "clflushopt",
+      FlushInstruction::Clwb => "clwb"
+    }
+  }
+
+  //CPUID.(EAX=7,ECX=0):EBX bit 23 (clflushopt) and 24 (clwb)
+  pub fn supported(&self) -> bool {
+    #[allow(unused_unsafe)]
+    let ebx = unsafe {__cpuid_count(7, 0)}.ebx;
+    match self {
+      FlushInstruction::Clflush => true,
+      FlushInstruction::Clflushopt => ebx >> 23 & 1 == 1,
+      FlushInstruction::Clwb => ebx >> 24 & 1 == 1
+    }
+  }
+
+  //This instruction or the strongest supported fallback
+  pub fn resolve(self) -> FlushInstruction {
+    let mut flush = self;
+    while !flush.supported() {
+      flush = match flush {
+        FlushInstruction::Clwb => FlushInstruction::Clflushopt,
+        _ => FlushInstruction::Clflush
+      };
+    }
+    if flush != self {
+      warn!("{} is not supported by this CPU, flushing with {}", self.name(),
+        flush.name());
+    }
+    flush
+  }
+
+  //Emits the flush of the address in register arg:reg
+  pub fn emit(&self, ops: &mut Assembler, reg: u8) {
+    match self {
+      FlushInstruction::Clflush => dynasm!(ops; clflush [Rq(reg)]),
+      //clflushopt is clflush with a 0x66 prefix (not known to dynasm)
+      FlushInstruction::Clflushopt =>
+        dynasm!(ops; .byte 0x66; clflush [Rq(reg)]),
+      //66 0F AE /6 (not known to dynasm), encoded by hand: rsp and r12 need
+      //a SIB byte, rbp and r13 a displacement
+      FlushInstruction::Clwb => {
+        ops.push(0x66);
+        if reg >= 8 {
+          ops.push(0x41); //REX.B
+        }
+        ops.push(0x0f);
+        ops.push(0xae);
+        match reg & 7 {
+          4 => {ops.push(0x34); ops.push(0x24);},
+          5 => {ops.push(0x75); ops.push(0x00);},
+          rm => ops.push(0x30 | rm)
+        }
+      }
+    }
+  }
+}
+
+//The accesses of arg:aggressors aggressors with the access rates arg:rates
+//(empty = once per iteration each) in arg:hammer_count hammer iterations
+pub fn rate_accesses(aggressors: usize, rates: &[AccessRate],
+  hammer_count: u64) -> u64
+{
+  if rates.is_empty() {
+    return aggressors as u64 * hammer_count;
+  }
+  rates.iter().map(|r| r.accesses(hammer_count)).sum()
+}
+
+//Interleaves the aggressors of arg:patterns round-robin: the first aggressor
+//of every pattern, then the second ones, ... (sledgehammer-style), with the
+//patterns in different banks, consecutive accesses go to different banks, so
+//the memory controller overlaps their row activations instead of hammering
+//one bank after another
+pub fn interleave_accesses<T: Clone>(patterns: &[Vec<T>]) -> Vec<T> {
+  let len = patterns.iter().map(|p| p.len()).max().unwrap_or(0);
+  (0..len).flat_map(|i| patterns.iter().filter_map(move |p| p.get(i)))
+    .cloned().collect()
+}
+
+//registers for the aggressor addresses of `create_hammer_jit` (r12 and r13
+//first), the garbage and the access trace use rax, rcx and rdx, the loop
+//counter of `emit_iterations` is r8, the latency sampling uses r9
+static AGGR_REGS: [u8; 9] = [12, 13, 14, 15, 3, 6, 7, 10, 11];
+
+fn gcd(a: u64, b: u64) -> u64 {
+  if b == 0 {a} else {gcd(b, a % b)}
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+  a / gcd(a, b) * b
+}
+
+//Emits arg:hammer_count hammer iterations with arg:emit_iteration (gets the
+//index of the iteration): fully unrolled with arg:unroll 0, otherwise a
+//counted loop (counter in r8) around arg:unroll unrolled iterations, followed
+//by the remaining iterations, so the code size is bounded by the unroll
+//factor instead of the hammer_count (the garbage stays between the
+//iterations)
+//The iterations have to repeat after arg:period iterations (e.g., the
+//intervals of the access rates), the unroll factor is rounded up to a
+//multiple of it
+//The access trace samples the iterations of the loop body in every trip of
+//the loop, the samples of the last trip are recorded
+fn emit_iterations(
+  ops: &mut Assembler, hammer_count: u64, unroll: u64, period: u64,
+  mut emit_iteration: impl FnMut(&mut Assembler, u64)
+) {
+  let unroll = unroll.div_ceil(period) * period;
+  let trips = hammer_count.checked_div(unroll).unwrap_or(0);
+  let looped = if trips > 1 {
+    let start = ops.new_dynamic_label();
+    dynasm!(ops
+      ; mov r8, QWORD trips as i64
+      ; =>start
+    );
+    for itr in 0..unroll {
+      emit_iteration(ops, itr);
+    }
+    dynasm!(ops
+      ; dec r8
+      ; jnz =>start
+    );
+    trips * unroll
+  } else {
+    0
+  };
+  for itr in looped..hammer_count {
+    emit_iteration(ops, itr);
+  }
+}
 
 //This function dynamically creates the hammer code in an unrolled hammer loop
+//(in a counted loop around arg:unroll unrolled iterations, see
+//`emit_iterations`)
+//The aggressor addresses are in a table in the code, the first
+//AGGR_REGS.len() ones are loaded into registers, the others are spilled
+//(loaded into rcx for every access and flush)
+//Returns the offset of the table, the code only depends on the number of
+//aggressors, so it can be reused for other aggressors by patching the table
+//(see `JitCache`), unless the access trace is enabled
+//arg:rates: the access rate of every aggressor (empty = once per iteration
+//each), an aggressor with weight w is accessed w times in the hammer
+//iterations of its interval: an iteration is split into steps, step s
+//accesses (and flushes) the aggressors of the iteration with a weight > s
+//The accesses of the `latency_sampled` iterations of arg:probes are timed
+//(see `JitProbes::emit_access`)
 //CAREFUL not to clobber the regs used for aggr addresses!
+#[allow(clippy::too_many_arguments)]
 pub fn create_hammer_jit(
   ops: &mut Assembler,
+  probes: &JitProbes,
   pattern: &Vec<u64>,
+  rates: &[AccessRate],
   hammer_count: u64,
+  unroll: u64,
+  flush: FlushInstruction,
   #[allow(unused_variables)]
   garbage_fn: Box<dyn Fn(&mut Assembler)>
-) {
-
-  dynasm!(ops
-    ; mov r12, QWORD pattern[0] as i64
-    ; mov r13, QWORD pattern[1] as i64
-  );
+) -> AssemblyOffset {
+  assert!(rates.is_empty() || rates.len() == pattern.len(),
+    "{} access rates for {} aggressors", rates.len(), pattern.len());
+  let rates: Vec<AccessRate> = (0..pattern.len())
+    .map(|i| rates.get(i).cloned().unwrap_or_default()).collect();
+  let (in_regs, spilled) = pattern.split_at(pattern.len().min(AGGR_REGS.len()));
+  let labels: Vec<_> = pattern.iter().map(|_| ops.new_dynamic_label())
+    .collect();
+  let table_end = ops.new_dynamic_label();
+  dynasm!(ops; jmp =>table_end);
+  let table_offset = ops.offset();
+  for (label, aggr) in labels.iter().zip(pattern) {
+    dynasm!(ops; =>*label; .qword *aggr as i64);
+  }
+  dynasm!(ops; =>table_end);
+  for (reg, label) in AGGR_REGS.iter().zip(&labels) {
+    dynasm!(ops; mov Rq(*reg), QWORD [=>*label]);
+  }
+  let table = &labels[in_regs.len()..];
 
   //2
-  for _ in 0..hammer_count {
-    dynasm!(ops
-      ; mov rdx, [r12]
-      ; mov rdx, [r13]
-      ; clflush [r12]
-      ; clflush [r13]
-    );
+  let period = rates.iter().map(|r| r.interval as u64).fold(1, lcm);
+  emit_iterations(ops, hammer_count, unroll, period, |ops, itr| {
+    let sampled = probes.trace_sampled(itr);
+    let timed = probes.latency_sampled(itr);
+    for step in AccessRate::steps(&rates, itr) {
+      //the aggressors of this step in registers and in the table
+      let regs: Vec<(u8, u64)> = step.iter().filter(|i| **i < in_regs.len())
+        .map(|i| (AGGR_REGS[*i], in_regs[*i])).collect();
+      let labels: Vec<(DynamicLabel, u64)> = step.iter()
+        .filter(|i| **i >= in_regs.len())
+        .map(|i| (table[*i - in_regs.len()], spilled[*i - in_regs.len()]))
+        .collect();
+      for &(reg, aggr) in &regs {
+        probes.emit_access(ops, timed, |ops|
+          dynasm!(ops; mov rdx, [Rq(reg)]));
+        if sampled {
+          probes.emit_trace_sample(ops, aggr);
+        }
+      }
+      for &(label, aggr) in &labels {
+        probes.emit_access(ops, timed, |ops| dynasm!(ops
+          ; mov rcx, QWORD [=>label]
+          ; mov rdx, [rcx]
+        ));
+        if sampled {
+          probes.emit_trace_sample(ops, aggr);
+        }
+      }
+      for &(reg, _) in &regs {
+        flush.emit(ops, reg);
+      }
+      for &(label, _) in &labels {
+        dynasm!(ops; mov rcx, QWORD [=>label]);
+        flush.emit(ops, 1);
+      }
+    }
     garbage_fn(ops);
-  }
+  });
+  table_offset
+}
+
+//Same as `create_hammer_jit` but every aggressor has multiple virtual
+//addresses (aliases of the same frame), each hammer iteration uses the next
+//alias of every aggressor (the accesses are not timed, the access rates are
+//ignored)
+//arg:aliases: [aggressor x [virtual address of each alias]]
+pub fn create_hammer_jit_aliases(
+  ops: &mut Assembler,
+  probes: &JitProbes,
+  aliases: &Vec<Vec<u64>>,
+  hammer_count: u64,
+  unroll: u64,
+  flush: FlushInstruction,
+  garbage_fn: Box<dyn Fn(&mut Assembler)>
+) {
+  let period = aliases.iter().map(|a| a.len() as u64).fold(1, lcm);
+  emit_iterations(ops, hammer_count, unroll, period, |ops, itr| {
+    let sampled = probes.trace_sampled(itr);
+    let iteration: Vec<u64> = aliases.iter()
+      .map(|a| a[itr as usize % a.len()]).collect();
+    //like `create_hammer_jit`: access all aggressors, then flush them
+    for alias in &iteration {
+      dynasm!(ops
+        ; mov r12, QWORD *alias as i64
+        ; mov rdx, [r12]
+      );
+      if sampled {
+        probes.emit_trace_sample(ops, *alias);
+      }
+    }
+    for alias in &iteration {
+      dynasm!(ops; mov r12, QWORD *alias as i64);
+      flush.emit(ops, 12);
+    }
+    garbage_fn(ops);
+  });
+}
+
+//Same as `create_hammer_jit` but hammers multiple patterns concurrently: every
+//hammer iteration accesses the aggressors of all patterns round-robin, so all
+//patterns put pressure on their victims within the same refresh windows
+//Every aggressor has an access rate (see `AccessRate`): an aggressor with
+//weight w is accessed w times in the hammer iterations of its interval, the
+//accesses are spread over the iteration, the accesses of the
+//`latency_sampled` iterations of arg:probes are timed
+//arg:patterns: [pattern x [(virtual address, access rate) of each aggressor]]
+pub fn create_hammer_jit_interleaved(
+  ops: &mut Assembler,
+  probes: &JitProbes,
+  patterns: &[Vec<(u64, AccessRate)>],
+  hammer_count: u64,
+  unroll: u64,
+  flush: FlushInstruction,
+  garbage_fn: Box<dyn Fn(&mut Assembler)>
+) {
+  let period = patterns.iter().flatten().map(|a| a.1.interval as u64)
+    .fold(1, lcm);
+  emit_iterations(ops, hammer_count, unroll, period, |ops, itr| {
+    let sampled = probes.trace_sampled(itr);
+    let timed = probes.latency_sampled(itr);
+    for pattern in patterns {
+      let rates: Vec<AccessRate> = pattern.iter().map(|a| a.1).collect();
+      for step in AccessRate::steps(&rates, itr) {
+        let aggrs = step.iter().map(|i| pattern[*i].0);
+        //like `create_hammer_jit`: access all aggressors, then flush them
+        for aggr in aggrs.clone() {
+          dynasm!(ops; mov r12, QWORD aggr as i64);
+          probes.emit_access(ops, timed, |ops| dynasm!(ops; mov rdx, [r12]));
+          if sampled {
+            probes.emit_trace_sample(ops, aggr);
+          }
+        }
+        for aggr in aggrs {
+          dynasm!(ops; mov r12, QWORD aggr as i64);
+          flush.emit(ops, 12);
+        }
+      }
+      garbage_fn(ops);
+    }
+  });
 }
 
 //Hammer with dynamically generated hammer code in an unrolled hammer loop,
 //with garbage interleaving
 pub fn hammer_jit(
+  ctx: &RhContext,
+  pattern: &Vec<u64>,
+  garbage_count: u32,
+  hammer_count: u64
+) -> HammerStats {
+  hammer_jit_weighted(ctx, pattern, &[], garbage_count, hammer_count)
+}
+
+//Same as `hammer_jit` but accesses every aggressor with its access rate in
+//arg:rates (see `create_hammer_jit`)
+pub fn hammer_jit_weighted(
+  ctx: &RhContext,
+  pattern: &Vec<u64>,
+  rates: &[AccessRate],
+  garbage_count: u32,
+  hammer_count: u64
+) -> HammerStats {
+  let accesses = rate_accesses(pattern.len(), rates, hammer_count);
+  run_jit(ctx, hammer_count, accesses, |ops| {
+    create_hammer_jit(ops, &ctx.jit_probes, pattern, rates, hammer_count,
+      ctx.settings.jit_unroll, ctx.settings.flush,
+      Box::new(move |mut ops| {
+        garbage_add(&mut ops, 1, garbage_count);
+      }));
+  })
+}
+
+//Same as `hammer_jit` but interleaves multiple patterns and honors the access
+//rates of the aggressors, see `create_hammer_jit_interleaved`
+pub fn hammer_jit_interleaved(
+  ctx: &RhContext,
+  patterns: &[Vec<(u64, AccessRate)>],
+  garbage_count: u32,
+  hammer_count: u64
+) -> HammerStats {
+  let accesses: u64 = patterns.iter().flatten()
+    .map(|a| a.1.accesses(hammer_count)).sum();
+  run_jit(ctx, hammer_count, accesses, |ops| {
+    create_hammer_jit_interleaved(ops, &ctx.jit_probes, patterns, hammer_count,
+      ctx.settings.jit_unroll, ctx.settings.flush,
+      Box::new(move |ops| garbage_add(ops, 1, garbage_count)));
+  })
+}
+
+//Same as `hammer_jit` but rotates between the aliases of each aggressor, see
+//`create_hammer_jit_aliases`
+pub fn hammer_jit_aliases(
+  ctx: &RhContext,
+  aliases: &Vec<Vec<u64>>,
+  garbage_count: u32,
+  hammer_count: u64
+) -> HammerStats {
+  run_jit(ctx, hammer_count, aliases.len() as u64 * hammer_count, |ops| {
+    create_hammer_jit_aliases(ops, &ctx.jit_probes, aliases, hammer_count,
+      ctx.settings.jit_unroll, ctx.settings.flush,
+      Box::new(move |mut ops| {
+        garbage_add(&mut ops, 1, garbage_count);
+      }));
+  })
+}
+
+//Hammers arg:pattern with arg:backend
+pub fn hammer_with_backend(
+  ctx: &RhContext,
+  backend: HammerBackend,
+  pattern: &Vec<u64>,
+  garbage_count: u32,
+  hammer_count: u64
+) -> HammerStats {
+  schedule_export::export_if_requested(ctx, || {
+    let flush = match backend {
+      HammerBackend::Rust => FlushInstruction::Clflush,
+      _ => ctx.settings.flush
+    };
+    Some(ExportedSchedule::new(ctx, format!("{:?}", backend).to_lowercase(),
+      hammer_count,
+      schedule_export::backend_iteration(backend, pattern, garbage_count),
+      flush))
+  });
+  match backend {
+    HammerBackend::Rust =>
+      repeat_for_duration(ctx, || hammer_rust(pattern, hammer_count)),
+    HammerBackend::Asm => repeat_for_duration(ctx, || hammer_asm(pattern,
+      garbage_count, hammer_count, ctx.settings.flush)),
+    HammerBackend::Jit => hammer_jit(ctx, pattern, garbage_count,
+      hammer_count),
+    HammerBackend::Refsync => hammer_jit_refresh(ctx, pattern, garbage_count,
+      hammer_count, true),
+    HammerBackend::Smt => hammer_smt(ctx, pattern, garbage_count,
+      hammer_count),
+    HammerBackend::Fake => {
+      debug!("Fake backend, not hammering");
+      HammerStats::default()
+    }
+  }
+}
+
+// --- REFRESH SYNCHRONIZATION ---
+//TRR samples the activations between refresh commands (REF), hammering in
+//bursts that start right after a REF and end before the next one keeps the
+//aggressors out of the sampling windows the mitigation expects
+//A REF blocks the bank for tRFC, so an uncached access that coincides with
+//one takes much longer than the others: `detect_refresh` times accesses to
+//an aggressor with rdtscp and takes these latency spikes as the REFs, the
+//median interval between them is tREFI
+
+//Times REF_DETECT_SAMPLES uncached accesses to arg:addr, None if there are
+//not enough latency spikes to derive tREFI from
+pub fn detect_refresh(addr: u64) -> Option<RefreshTiming> {
+  let mut aux = 0;
+  let mut samples = Vec::with_capacity(REF_DETECT_SAMPLES);
+  for _ in 0..REF_DETECT_SAMPLES {
+    unsafe {
+      let start = __rdtscp(&mut aux);
+      std::ptr::read_volatile(addr as *const u8);
+      let end = __rdtscp(&mut aux);
+      core::arch::x86_64::_mm_clflush(addr as *const u8);
+      samples.push((start, end - start));
+    }
+  }
+  let mut latencies: Vec<u64> = samples.iter().map(|s| s.1).collect();
+  latencies.sort_unstable();
+  let threshold_tsc =
+    (latencies[latencies.len() / 2] as f64 * REF_SPIKE_FACTOR) as u64;
+
+  //consecutive spikes belong to the same REF
+  let mut spikes: Vec<u64> = Vec::new();
+  let mut previous_spike = false;
+  for (start, latency) in samples {
+    let spike = latency > threshold_tsc;
+    if spike && !previous_spike {
+      spikes.push(start);
+    }
+    previous_spike = spike;
+  }
+  let mut intervals: Vec<u64> = spikes.windows(2).map(|w| w[1] - w[0])
+    .collect();
+  if intervals.len() < 2 {
+    return None;
+  }
+  intervals.sort_unstable();
+  let trefi_tsc = intervals[intervals.len() / 2];
+  let timing = RefreshTiming {threshold_tsc, trefi_tsc,
+    trefi_ns: trefi_tsc as f64 / measure_tsc_per_ns()};
+  debug!("{} refreshes detected: {:?}", spikes.len(), timing);
+  Some(timing)
+}
+
+//Emits a loop that times accesses to arg:addr until one takes longer than
+//arg:threshold_tsc (a REF) or after REF_WAIT_MAX_ACCESSES accesses
+//Always flushes with clflush (clwb may keep the line cached)
+//Uses rax, rcx, rdx, r8 and r9
+fn emit_wait_for_refresh(ops: &mut Assembler, addr: u64, threshold_tsc: u64) {
+  let (wait, done) = (ops.new_dynamic_label(), ops.new_dynamic_label());
+  dynasm!(ops
+    ; mov r9d, REF_WAIT_MAX_ACCESSES
+    ; =>wait
+    ; rdtscp
+    ; shl rdx, 32
+    ; or rax, rdx
+    ; mov r8, rax
+    ; mov rcx, QWORD addr as i64
+    ; mov rdx, [rcx]
+    ; rdtscp
+    ; shl rdx, 32
+    ; or rax, rdx
+    ; sub rax, r8
+    ; mov rcx, QWORD addr as i64
+    ; clflush [rcx]
+    ; mov r8, QWORD threshold_tsc as i64
+    ; cmp rax, r8
+    ; ja =>done
+    ; dec r9d
+    ; jnz =>wait
+    ; =>done
+  );
+}
+
+//Same as `hammer_jit`, but with arg:sync_with_refresh, the REFs are detected
+//(see `detect_refresh`) and the hammer_count is split into bursts of the
+//hammer iterations that fit into one tREFI, each burst starts right after
+//the JIT code observed a REF
+//Hammers without synchronization if no refreshes are detected
+pub fn hammer_jit_refresh(
+  ctx: &RhContext,
   pattern: &Vec<u64>,
   garbage_count: u32,
+  hammer_count: u64,
+  sync_with_refresh: bool
+) -> HammerStats {
+  let refresh = if sync_with_refresh {detect_refresh(pattern[0])} else {None};
+  let refresh = match refresh {
+    Some(refresh) => refresh,
+    None => {
+      if sync_with_refresh {
+        warn!("No refreshes detected, hammering without synchronization");
+      }
+      return hammer_jit(ctx, pattern, garbage_count, hammer_count);
+    }
+  };
+
+  //hammer iterations per tREFI, measured with an unsynchronized run
+  let probe_count = hammer_count.clamp(1, 1000);
+  let probe = hammer_jit(ctx, pattern, garbage_count, probe_count);
+  let ns_per_iteration =
+    probe.duration.as_nanos() as f64 / probe_count as f64;
+  let burst = ((refresh.trefi_ns / ns_per_iteration.max(1.0)) as u64)
+    .clamp(1, hammer_count.max(1));
+  info!("Detected tREFI {:.0}ns, hammering in bursts of {} iterations",
+    refresh.trefi_ns, burst);
+
+  let accesses = pattern.len() as u64 * hammer_count;
+  let stats = run_jit(ctx, hammer_count, accesses, |ops| {
+    let mut remaining = hammer_count;
+    while remaining > 0 {
+      let count = remaining.min(burst);
+      emit_wait_for_refresh(ops, pattern[0], refresh.threshold_tsc);
+      create_hammer_jit(ops, &ctx.jit_probes, pattern, &[], count,
+        ctx.settings.jit_unroll, ctx.settings.flush,
+        Box::new(move |ops| garbage_add(ops, 1, garbage_count)));
+      remaining -= count;
+    }
+  });
+  HammerStats {refresh: Some(refresh), ..stats}
+}
+
+// --- SMT CO-HAMMERING ---
+//The two hyperthreads (SMT siblings) of a core share its load and flush
+//ports but issue their accesses independently: `hammer_smt` splits the
+//aggressors of a pattern into two disjoint halves and hammers one half from
+//each sibling at the same time, e.g. to see whether two interleaved access
+//streams reach a higher activation rate than one thread, or change what the
+//mitigation samples
+//Both codes are compiled first, the threads start hammering together after a
+//spin barrier, the access trace and the latency histogram hold the accesses
+//of both siblings
+//Needs SMT enabled (the calling thread's core needs a sibling)
+
+//Spins until arg:threads threads arrived at arg:arrived, false if a thread
+//set arg:failed instead of arriving (it will never arrive)
+fn spin_barrier(arrived: &AtomicUsize, threads: usize, failed: &AtomicBool)
+  -> bool
+{
+  arrived.fetch_add(1, Ordering::AcqRel);
+  while arrived.load(Ordering::Acquire) < threads {
+    if failed.load(Ordering::Acquire) {
+      return false;
+    }
+    std::hint::spin_loop();
+  }
+  true
+}
+
+//Same as `hammer_jit`, but the first half of the aggressors of arg:pattern
+//is hammered by the calling thread (pinned to its current core during the
+//run) and the second half by a thread on the SMT sibling of that core, each
+//half arg:hammer_count times
+//The duration is the time until both siblings are done
+pub fn hammer_smt(
+  ctx: &RhContext,
+  pattern: &[u64],
+  garbage_count: u32,
   hammer_count: u64
-) -> Duration {
+) -> HammerStats {
+  assert!(pattern.len() >= 2, "SMT co-hammering needs at least 2 aggressors");
+  let core = sched::current_core();
+  let sibling = host::get_smt_sibling(core).unwrap_or_else(||
+    panic!("Core {} has no SMT sibling, SMT co-hammering needs SMT", core));
+  let (first, second) = pattern.split_at(pattern.len() / 2);
+  debug!("SMT co-hammering: {} aggressors on core {}, {} on core {}",
+    first.len(), core, second.len(), sibling);
+
+  ctx.jit_probes.start_trace(ctx.settings.access_trace);
+  ctx.jit_probes.start_latency_sampling(ctx.settings.latency_samples);
+  let codes: Vec<JitCode> = [first, second].iter().map(|half| {
+    assemble_jit(ctx, hammer_count, |ops| {
+      create_hammer_jit(ops, &ctx.jit_probes, &half.to_vec(), &[],
+        hammer_count,
+        ctx.settings.jit_unroll, ctx.settings.flush,
+        Box::new(move |ops| garbage_add(ops, 1, garbage_count)));
+      None
+    })
+  }).collect();
+  let compile_time = codes.iter().map(|c| c.compile_time).sum();
+
+  let affinity = sched::get_affinity();
+  sched::pin_to_core(core);
+  let stats = repeat_for_duration(ctx, || {
+    let arrived = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let duration = std::thread::scope(|s| {
+      let sibling_thread = s.spawn(|| {
+        //e.g. the sibling is not in the cpuset of the tool, the calling
+        //thread would wait at the barrier forever
+        if std::panic::catch_unwind(|| sched::pin_to_core(sibling)).is_err() {
+          failed.store(true, Ordering::Release);
+          return;
+        }
+        spin_barrier(&arrived, 2, &failed);
+        codes[1].run();
+      });
+      assert!(spin_barrier(&arrived, 2, &failed),
+        "Pinning the SMT co-hammering thread to core {} failed", sibling);
+      let start = Instant::now();
+      codes[0].run();
+      sibling_thread.join().expect("The SMT sibling thread panicked");
+      start.elapsed()
+    });
+    HammerStats::new(hammer_count, pattern.len() as u64 * hammer_count,
+      duration, compile_time)
+  });
+  sched::pin_to_cores(&affinity);
+  ctx.jit_probes.dump_trace();
+  HammerStats {latency: ctx.jit_probes.take_latency_histogram(), compile_time,
+    ..stats}
+}
+
+// --- HAMMER KERNELS ---
+//A hammer kernel emits the hammer code for one aggressor pattern, research
+//variants can implement this trait instead of changing this file
+//Kernels are selected by name, other crates can add kernels to a context
+//with `KernelRegistry::register` or in a plugin library (see
+//`KernelRegistry::load_plugin`)
+
+//Input of a hammer kernel
+pub struct KernelParams<'a> {
+  //[aggressor x [virtual address of each alias]], the first address is the
+  //mapping in Frame2Map (without aliases, each aggressor has one address)
+  pub aggrs: &'a Vec<Vec<u64>>,
+  //access rate of every aggressor (empty = once per iteration each, see
+  //`create_hammer_jit`), only the `double_sided` kernel honors them
+  pub rates: &'a [AccessRate],
+  pub hammer_count: u64,
+  pub garbage_count: u32,
+  //the flush instruction after the accesses (resolved, see
+  //`FlushInstruction::resolve`)
+  pub flush: FlushInstruction,
+  //hammer iterations in the loop body of the JIT code, 0 = fully unrolled
+  //(the `jit_unroll` setting, see `emit_iterations`)
+  pub unroll: u64,
+  //the instrumentation of the code (the `jit_probes` of the context)
+  pub probes: &'a JitProbes
+}
+
+pub trait HammerKernel: Send + Sync {
+  fn name(&self) -> &str;
+  //Emits the hammer code into arg:ops, all general purpose registers are
+  //saved and restored around it, the code must not touch the stack pointer
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams);
+  //Same as `emit`, for kernels whose code only depends on the number of
+  //aggressors (not on their addresses): the addresses (the first address of
+  //every aggressor) are in a table in the code, whose offset is returned, so
+  //the finalized code can be reused for other aggressors with the same
+  //params by patching the table (see `JitCache`)
+  //The default emits the code with `emit` and returns None (not cached)
+  fn emit_patchable(&self, ops: &mut Assembler, params: &KernelParams)
+    -> Option<AssemblyOffset>
+  {
+    self.emit(ops, params);
+    None
+  }
+  //The operations of one hammer iteration for the schedule export (see
+  //schedule_export), None if the iterations differ
+  fn access_schedule(&self, _params: &KernelParams) -> Option<Vec<Op>> {
+    None
+  }
+}
+
+//`create_hammer_jit` (only uses the first address of each aggressor)
+struct DoubleSidedKernel;
+
+impl HammerKernel for DoubleSidedKernel {
+  fn name(&self) -> &str {
+    "double_sided"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams) {
+    self.emit_patchable(ops, params);
+  }
+
+  fn emit_patchable(&self, ops: &mut Assembler, params: &KernelParams)
+    -> Option<AssemblyOffset>
+  {
+    let pattern = params.aggrs.iter().map(|a| a[0]).collect();
+    let garbage_count = params.garbage_count;
+    Some(create_hammer_jit(ops, params.probes, &pattern, params.rates,
+      params.hammer_count, params.unroll, params.flush,
+      Box::new(move |mut ops| garbage_add(&mut ops, 1, garbage_count))))
+  }
+
+  fn access_schedule(&self, params: &KernelParams) -> Option<Vec<Op>> {
+    //the iterations differ with intervals
+    if params.rates.iter().any(|r| r.interval != 1) {
+      return None;
+    }
+    let pattern: Vec<u64> = params.aggrs.iter().map(|a| a[0]).collect();
+    Some(schedule_export::jit_iteration(&pattern, params.rates,
+      params.garbage_count))
+  }
+}
+
+//`create_hammer_jit_aliases`
+struct AliasesKernel;
+
+impl HammerKernel for AliasesKernel {
+  fn name(&self) -> &str {
+    "aliases"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams) {
+    let garbage_count = params.garbage_count;
+    create_hammer_jit_aliases(ops, params.probes, params.aggrs,
+      params.hammer_count, params.unroll, params.flush,
+      Box::new(move |mut ops| garbage_add(&mut ops, 1, garbage_count)));
+  }
+}
+
+//Runs a hammer schedule (see schedule), built from the `hammer_schedule` of
+//the config instead of by name
+//Every access is followed by its flush, the garbage follows every
+//iteration of the schedule, the iterations are unrolled like the other
+//kernels (see `emit_iterations`), the repetitions are loops (counters in
+//SCHEDULE_LOOP_REGS) and the pauses busy wait on the TSC
+pub struct ScheduleKernel {
+  schedule: Schedule,
+  tsc_per_ns: f64
+}
+
+//one loop counter per nesting level of the repetitions (not r8, the loop
+//counter of `emit_iterations`)
+static SCHEDULE_LOOP_REGS: [u8; MAX_REPEAT_DEPTH] = [9, 10, 11, 14, 15, 3];
+
+impl ScheduleKernel {
+  pub fn new(schedule: Schedule) -> ScheduleKernel {
+    ScheduleKernel {schedule, tsc_per_ns: measure_tsc_per_ns()}
+  }
+
+  fn emit_steps(
+    &self, ops: &mut Assembler, steps: &[Step], rows: &[u64], depth: usize,
+    flush: FlushInstruction
+  ) {
+    for step in steps {
+      match step {
+        Step::Access {dummy, idx} => {
+          let addr = rows[self.schedule.row_index(*dummy, *idx)];
+          dynasm!(ops
+            ; mov r12, QWORD addr as i64
+            ; mov rdx, [r12]
+          );
+          flush.emit(ops, 12);
+        },
+        Step::Pause(duration) => {
+          let cycles = (duration.as_nanos() as f64 * self.tsc_per_ns) as i64;
+          let wait = ops.new_dynamic_label();
+          dynasm!(ops
+            ; rdtsc
+            ; shl rdx, 32
+            ; or rax, rdx
+            ; mov r13, QWORD cycles
+            ; add r13, rax
+            ; =>wait
+            ; rdtsc
+            ; shl rdx, 32
+            ; or rax, rdx
+            ; cmp rax, r13
+            ; jb =>wait
+          );
+        },
+        Step::Repeat(steps, 1) =>
+          self.emit_steps(ops, steps, rows, depth, flush),
+        Step::Repeat(steps, n) => {
+          let counter = SCHEDULE_LOOP_REGS[depth];
+          let body = ops.new_dynamic_label();
+          dynasm!(ops
+            ; mov Rq(counter), QWORD *n as i64
+            ; =>body
+          );
+          self.emit_steps(ops, steps, rows, depth + 1, flush);
+          dynasm!(ops
+            ; dec Rq(counter)
+            ; jnz =>body
+          );
+        }
+      }
+    }
+  }
+
+  //The operations of arg:steps with the repetitions unrolled
+  fn steps_schedule(&self, steps: &[Step], rows: &[u64], out: &mut Vec<Op>) {
+    for step in steps {
+      match step {
+        Step::Access {dummy, idx} => {
+          let addr = rows[self.schedule.row_index(*dummy, *idx)];
+          out.push(Op::access(addr));
+          out.push(Op::flush(addr));
+        },
+        Step::Pause(duration) =>
+          out.push(Op::Pause {ns: duration.as_nanos() as u64}),
+        Step::Repeat(steps, n) => for _ in 0..*n {
+          self.steps_schedule(steps, rows, out);
+        }
+      }
+    }
+  }
+}
+
+impl HammerKernel for ScheduleKernel {
+  fn name(&self) -> &str {
+    "schedule"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams) {
+    let rows: Vec<u64> = params.aggrs.iter().map(|a| a[0]).collect();
+    assert!(rows.len() >= self.schedule.rows(), "The hammer schedule needs {} \
+      aggressors and {} dummy rows, the pattern has {} rows",
+      self.schedule.aggressors, self.schedule.dummies, rows.len());
+    emit_iterations(ops, params.hammer_count, params.unroll, 1, |ops, _| {
+      self.emit_steps(ops, &self.schedule.steps, &rows, 0, params.flush);
+      garbage_add(ops, 1, params.garbage_count);
+    });
+  }
+
+  fn access_schedule(&self, params: &KernelParams) -> Option<Vec<Op>> {
+    let rows: Vec<u64> = params.aggrs.iter().map(|a| a[0]).collect();
+    let mut schedule = Vec::new();
+    self.steps_schedule(&self.schedule.steps, &rows, &mut schedule);
+    schedule.push(Op::Garbage {count: params.garbage_count});
+    Some(schedule)
+  }
+}
+
+//Runs a fuzzed non-uniform pattern (see fuzz), built from the `fuzz_pattern`
+//of the config instead of by name
+//Every access is followed by its flush, one hammer iteration is one base
+//period (a loop in the hammer code, the counter is in r8), followed by the
+//garbage
+pub struct FuzzKernel {
+  pattern: FuzzedPattern
+}
+
+impl FuzzKernel {
+  pub fn new(pattern: FuzzedPattern) -> FuzzKernel {
+    FuzzKernel {pattern}
+  }
+}
+
+impl HammerKernel for FuzzKernel {
+  fn name(&self) -> &str {
+    "fuzz"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams) {
+    let rows: Vec<u64> = params.aggrs.iter().map(|a| a[0]).collect();
+    assert!(rows.len() == self.pattern.aggressors.len(), "The fuzzed pattern \
+      has {} aggressors, the aggressor pattern has {} rows",
+      self.pattern.aggressors.len(), rows.len());
+    if params.hammer_count == 0 {
+      return;
+    }
+    let period = ops.new_dynamic_label();
+    dynasm!(ops
+      ; mov r8, QWORD params.hammer_count as i64
+      ; =>period
+    );
+    for i in self.pattern.access_order() {
+      dynasm!(ops
+        ; mov r12, QWORD rows[i] as i64
+        ; mov rdx, [r12]
+      );
+      params.flush.emit(ops, 12);
+    }
+    garbage_add(ops, 1, params.garbage_count);
+    dynasm!(ops
+      ; dec r8
+      ; jnz =>period
+    );
+  }
+
+  fn access_schedule(&self, params: &KernelParams) -> Option<Vec<Op>> {
+    let mut schedule: Vec<Op> = self.pattern.access_order().into_iter()
+      .flat_map(|i| vec![Op::access(params.aggrs[i][0]),
+        Op::flush(params.aggrs[i][0])])
+      .collect();
+    schedule.push(Op::Garbage {count: params.garbage_count});
+    Some(schedule)
+  }
+}
+
+//RowPress: keeps every aggressor row open for `row_open_cycles` TSC cycles
+//per activation instead of closing it right after the access, the
+//disturbance grows with the time a row is open (tAggON), so bits flip with
+//far fewer activations than with rowhammer
+//After the activating access, the aggressor line is flushed and read again in
+//a chain of dependent loads (the address of every load depends on the value
+//of the previous one) until the cycles passed, the reads are row buffer hits
+//as long as the memory controller keeps the row open (open page policy)
+//Built from the `row_open_cycles` of the config instead of by name, one
+//hammer iteration is a loop in the hammer code (the counter is in r8, the
+//deadline of the open row in r9), followed by the garbage
+pub struct RowPressKernel {
+  row_open_cycles: u64
+}
+
+impl RowPressKernel {
+  pub fn new(row_open_cycles: u64) -> RowPressKernel {
+    assert!(row_open_cycles <= i32::MAX as u64,
+      "row_open_cycles {} is too large", row_open_cycles);
+    RowPressKernel {row_open_cycles}
+  }
+}
+
+impl HammerKernel for RowPressKernel {
+  fn name(&self) -> &str {
+    "row_press"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams) {
+    if params.hammer_count == 0 {
+      return;
+    }
+    let iteration = ops.new_dynamic_label();
+    dynasm!(ops
+      ; mov r8, QWORD params.hammer_count as i64
+      ; =>iteration
+    );
+    for aggr in params.aggrs.iter().map(|a| a[0]) {
+      let open = ops.new_dynamic_label();
+      dynasm!(ops
+        ; mov r12, QWORD aggr as i64
+        ; rdtsc
+        ; shl rdx, 32
+        ; or rax, rdx
+        ; mov r9, QWORD self.row_open_cycles as i64
+        ; add r9, rax
+        ; =>open
+        ; mov rdx, [r12]
+      );
+      params.flush.emit(ops, 12);
+      dynasm!(ops
+        ; mfence
+        //the next load depends on this one (`and` keeps the dependency)
+        ; and rdx, 0
+        ; add r12, rdx
+        ; rdtscp
+        ; shl rdx, 32
+        ; or rax, rdx
+        ; cmp rax, r9
+        ; jb =>open
+      );
+    }
+    garbage_add(ops, 1, params.garbage_count);
+    dynasm!(ops
+      ; dec r8
+      ; jnz =>iteration
+    );
+  }
+}
+
+//Replays the iteration of an exported hammer schedule (see schedule_export)
+//operation by operation, ignores the aggressors of the params (the addresses
+//are in the operations), one hammer iteration is a loop in the hammer code
+//(the counter is in r8), the pauses busy wait on the TSC
+//The flushes use the flush instruction of their operation (see
+//`FlushInstruction::resolve`), not the one of the params
+pub struct ReplayKernel {
+  iteration: Vec<Op>,
+  tsc_per_ns: f64
+}
+
+impl ReplayKernel {
+  pub fn new(iteration: Vec<Op>) -> ReplayKernel {
+    //resolved once per instruction (`resolve` warns about a fallback)
+    let mut resolved = HashMap::new();
+    let iteration = iteration.into_iter().map(|op| match op {
+      Op::Flush {virt, phys, instruction} => Op::Flush {virt, phys,
+        instruction: *resolved.entry(instruction)
+          .or_insert_with(|| instruction.resolve())},
+      op => op
+    }).collect();
+    ReplayKernel {iteration, tsc_per_ns: measure_tsc_per_ns()}
+  }
+}
+
+impl HammerKernel for ReplayKernel {
+  fn name(&self) -> &str {
+    "replay"
+  }
+
+  fn emit(&self, ops: &mut Assembler, params: &KernelParams) {
+    if params.hammer_count == 0 {
+      return;
+    }
+    let iteration = ops.new_dynamic_label();
+    dynasm!(ops
+      ; mov r8, QWORD params.hammer_count as i64
+      ; =>iteration
+    );
+    for op in &self.iteration {
+      match op {
+        Op::Access {virt, ..} => dynasm!(ops
+          ; mov r12, QWORD *virt as i64
+          ; mov rdx, [r12]
+        ),
+        Op::Flush {virt, instruction, ..} => {
+          dynasm!(ops; mov r12, QWORD *virt as i64);
+          instruction.emit(ops, 12);
+        },
+        Op::Fence => dynasm!(ops; mfence),
+        Op::Garbage {count} => garbage_add(ops, 1, *count),
+        Op::Pause {ns} => {
+          //pauses of more than 2^31 cycles do not fit in a displacement
+          let cycles = (*ns as f64 * self.tsc_per_ns) as i64;
+          let wait = ops.new_dynamic_label();
+          dynasm!(ops
+            ; rdtsc
+            ; shl rdx, 32
+            ; or rax, rdx
+            ; mov r13, QWORD cycles
+            ; add r13, rax
+            ; =>wait
+            ; rdtsc
+            ; shl rdx, 32
+            ; or rax, rdx
+            ; cmp rax, r13
+            ; jb =>wait
+          );
+        }
+      }
+    }
+    dynasm!(ops
+      ; dec r8
+      ; jnz =>iteration
+    );
+  }
+
+  fn access_schedule(&self, _params: &KernelParams) -> Option<Vec<Op>> {
+    Some(self.iteration.clone())
+  }
+}
+
+//TSC ticks per ns, measured over TSC_MEASURE_TIME
+fn measure_tsc_per_ns() -> f64 {
+  let mut aux = 0;
+  let start_time = Instant::now();
+  let start_tsc = unsafe {__rdtscp(&mut aux)};
+  std::thread::sleep(TSC_MEASURE_TIME);
+  let cycles = unsafe {__rdtscp(&mut aux)} - start_tsc;
+  cycles as f64 / start_time.elapsed().as_nanos() as f64
+}
+
+static TSC_MEASURE_TIME: Duration = Duration::from_millis(50);
+
+//symbol a plugin library exports to register its kernels, with type
+//`PluginRegisterFn`
+pub static PLUGIN_REGISTER_SYMBOL: &[u8] = b"rowhammer_register_kernels";
+pub type PluginRegisterFn = fn(&mut dyn FnMut(Arc<dyn HammerKernel>));
+
+//The kernels added to a context (`RhContext::kernels`) with `register` or
+//from plugins
+#[derive(Default)]
+pub struct KernelRegistry(Mutex<Vec<Arc<dyn HammerKernel>>>);
+
+impl KernelRegistry {
+  //Adds arg:kernel, it replaces a kernel with the same name
+  pub fn register(&self, kernel: Arc<dyn HammerKernel>) {
+    info!("Registering hammer kernel {}", kernel.name());
+    let mut kernels = self.0.lock().unwrap();
+    kernels.retain(|k| k.name() != kernel.name());
+    kernels.push(kernel);
+  }
+
+  //Returns the kernel with arg:name (registered kernels before built-in
+  //ones)
+  pub fn get(&self, name: &str) -> Arc<dyn HammerKernel> {
+    if let Some(k) = self.0.lock().unwrap().iter().find(|k| k.name() == name) {
+      return k.clone();
+    }
+    match name {
+      "double_sided" => Arc::new(DoubleSidedKernel),
+      "aliases" => Arc::new(AliasesKernel),
+      _ => panic!("Unknown hammer kernel {} (registered: {})", name,
+        self.0.lock().unwrap().iter().map(|k| k.name().to_owned())
+          .collect::<Vec<_>>().join(", "))
+    }
+  }
+
+  //Names of the built-in and the registered kernels
+  pub fn names(&self) -> Vec<String> {
+    let mut names = vec!["double_sided".to_owned(), "aliases".to_owned()];
+    for kernel in self.0.lock().unwrap().iter() {
+      if !names.iter().any(|n| n == kernel.name()) {
+        names.push(kernel.name().to_owned());
+      }
+    }
+    names
+  }
+
+  //Loads the plugin library at arg:path and registers its kernels
+  //The plugin has to be built with the same compiler and version of this
+  //crate (the trait objects cross the library boundary with the Rust ABI)
+  pub fn load_plugin(&self, path: &str) {
+    info!("Loading hammer kernel plugin {}", path);
+    unsafe {
+      let lib = libloading::Library::new(path)
+        .unwrap_or_else(|e| panic!("Loading plugin {} failed: {}", path, e));
+      let register: libloading::Symbol<PluginRegisterFn> =
+        lib.get(PLUGIN_REGISTER_SYMBOL)
+          .unwrap_or_else(|e| panic!("Plugin {} has no register function: \
+            {}", path, e));
+      register(&mut |kernel| self.register(kernel));
+      //the kernels' code lives in the library, so it must stay loaded
+      std::mem::forget(lib);
+    }
+  }
+
+  //Loads the plugins in the colon separated `RH_HAMMER_PLUGINS` environment
+  //variable
+  pub fn load_plugins_from_env(&self) {
+    if let Ok(paths) = env::var("RH_HAMMER_PLUGINS") {
+      paths.split(':').filter(|p| !p.is_empty())
+        .for_each(|p| self.load_plugin(p));
+    }
+  }
+}
+
+//Hammer with the code emitted by arg:kernel
+//The code of kernels that support `HammerKernel::emit_patchable` is cached
+//in the context (see `JitCache`)
+pub fn hammer_kernel(
+  ctx: &RhContext, kernel: &dyn HammerKernel, params: &KernelParams
+) -> HammerStats {
+  hammer_kernel_bursts(ctx, kernel, params, 1, Duration::ZERO)
+}
+
+//Same as `hammer_kernel`, but the hammer code (arg:params.hammer_count
+//iterations) runs arg:bursts times with arg:idle in between (busy waiting,
+//so sub-tREFI idle times are possible), e.g. to study whether refreshes
+//between the bursts reset the disturbance of the victims
+//The stats do not include the idle times
+pub fn hammer_kernel_bursts(
+  ctx: &RhContext, kernel: &dyn HammerKernel, params: &KernelParams,
+  bursts: u64, idle: Duration
+) -> HammerStats {
+  export_kernel_schedule(ctx, kernel, params, bursts, idle);
+  ctx.jit_cache.hammer(ctx, kernel, params, bursts, idle)
+}
+
+//The aggressor accesses of arg:params.hammer_count iterations of
+//arg:kernel, one per aggressor and iteration if the kernel has no access
+//schedule
+fn kernel_accesses(kernel: &dyn HammerKernel, params: &KernelParams) -> u64 {
+  match kernel.access_schedule(params) {
+    Some(ops) => ops.iter().filter(|op| matches!(op, Op::Access {..})).count()
+      as u64 * params.hammer_count,
+    None => rate_accesses(params.aggrs.len(), params.rates,
+      params.hammer_count)
+  }
+}
+
+fn export_kernel_schedule(
+  ctx: &RhContext, kernel: &dyn HammerKernel, params: &KernelParams,
+  bursts: u64, idle: Duration
+) {
+  schedule_export::export_if_requested(ctx, || kernel.access_schedule(params)
+    .map(|iteration| ExportedSchedule {bursts, idle_ns: idle.as_nanos() as u64,
+      ..ExportedSchedule::new(ctx, format!("jit:{}", kernel.name()),
+        params.hammer_count, iteration, params.flush)}));
+}
+
+// --- ACCESS TRACE ---
+//Debug mode to inspect the realized access schedule (e.g., when a pattern
+//stopped flipping): with `RH_ACCESS_TRACE=<n>` (the `access_trace` setting
+//of the context), the JIT code records the TSC
+//right after every aggressor access of every n-th hammer iteration in a
+//preallocated buffer, which is dumped to ACCESS_TRACE_PATH after hammering
+//(one `<virtual address> <tsc>` line per access)
+//The recording (rdtscp and a store) slows down the sampled iterations, keep
+//n large
+
+//maximal number of recorded accesses per hammer run, later ones are dropped
+static ACCESS_TRACE_CAPACITY: usize = 1 << 20;
+static ACCESS_TRACE_PATH: &str = "access_trace.txt";
+
+struct AccessTrace {
+  addrs: Vec<u64>, //virtual address of each recorded access
+  tscs: Box<[u64]> //written by the JIT code
+}
+
+// --- LATENCY SAMPLING ---
+//To verify that the aggressor accesses reach DRAM and to see the structure of
+//the refreshes: with `RH_LATENCY_SAMPLES=<n>` (the `latency_samples` setting
+//of the context), the JIT code times every aggressor access of every n-th
+//hammer iteration with rdtscp and counts its latency in a histogram
+//(LATENCY_BUCKETS buckets of LATENCY_BUCKET_CYCLES cycles) in a preallocated
+//buffer, so the samples of all trips of a loop (see `emit_iterations`) are
+//counted
+//The histogram is in the hammer stats (`latency`): cache hits are in the
+//first buckets, DRAM accesses a few hundred cycles later and the accesses
+//blocked by a refresh in the tail (see `detect_refresh`)
+//The timing serializes the sampled accesses, keep n large
+
+//The instrumentation of the JIT code of a context (`RhContext::jit_probes`),
+//the access trace and the latency histogram, the code writes into their
+//buffers while it runs
+#[derive(Default)]
+pub struct JitProbes {
+  //sample intervals of the current JIT run, 0 = disabled
+  trace_interval: AtomicU64,
+  latency_interval: AtomicU64,
+  access_trace: Mutex<Option<AccessTrace>>,
+  latency_histogram: Mutex<Option<Box<[u64; LATENCY_BUCKETS]>>>
+}
+
+impl JitProbes {
+  //whether the accesses of hammer iteration arg:itr are recorded
+  pub fn trace_sampled(&self, itr: u64) -> bool {
+    let interval = self.trace_interval.load(Ordering::Relaxed);
+    interval != 0 && itr.is_multiple_of(interval)
+  }
+
+  //Emits the recording of an access to arg:addr (if the buffer is not full),
+  //clobbers rax, rcx and rdx
+  //Call it right after the access in the iterations that are `trace_sampled`
+  pub fn emit_trace_sample(&self, ops: &mut Assembler, addr: u64) {
+    let mut trace = self.access_trace.lock().unwrap();
+    let trace = match trace.as_mut() {
+      Some(trace) if trace.addrs.len() < trace.tscs.len() => trace,
+      _ => return
+    };
+    let slot = &trace.tscs[trace.addrs.len()] as *const u64 as i64;
+    trace.addrs.push(addr);
+    dynasm!(ops
+      ; rdtscp
+      ; shl rdx, 32
+      ; or rax, rdx
+      ; mov rcx, QWORD slot
+      ; mov [rcx], rax
+    );
+  }
+
+  fn start_trace(&self, interval: u64) {
+    if interval > 0 {
+      *self.access_trace.lock().unwrap() = Some(AccessTrace {
+        addrs: Vec::new(),
+        tscs: vec![0; ACCESS_TRACE_CAPACITY].into_boxed_slice()
+      });
+    }
+    self.trace_interval.store(interval, Ordering::Relaxed);
+  }
+
+  fn dump_trace(&self) {
+    self.trace_interval.store(0, Ordering::Relaxed);
+    if let Some(trace) = self.access_trace.lock().unwrap().take() {
+      info!("Dumping {} traced accesses to {}", trace.addrs.len(),
+        ACCESS_TRACE_PATH);
+      let mut file = File::create(ACCESS_TRACE_PATH)
+        .expect("Creating the access trace failed");
+      for (addr, tsc) in trace.addrs.iter().zip(trace.tscs.iter()) {
+        writeln!(file, "0x{:x} {}", addr, tsc).unwrap();
+      }
+    }
+  }
+
+  //whether the accesses of hammer iteration arg:itr are timed
+  pub fn latency_sampled(&self, itr: u64) -> bool {
+    let interval = self.latency_interval.load(Ordering::Relaxed);
+    interval != 0 && itr.is_multiple_of(interval)
+  }
+
+  //Emits the access of arg:access, timed if arg:timed (see
+  //`latency_sampled`), clobbers rax, rcx, rdx and r9 then
+  pub fn emit_access(
+    &self, ops: &mut Assembler, timed: bool,
+    access: impl FnOnce(&mut Assembler)
+  ) {
+    let histogram = match self.latency_histogram.lock().unwrap().as_ref() {
+      Some(histogram) if timed => histogram.as_ptr() as i64,
+      _ => return access(ops)
+    };
+    dynasm!(ops
+      ; lfence
+      ; rdtscp
+      ; shl rdx, 32
+      ; or rax, rdx
+      ; mov r9, rax
+    );
+    access(ops);
+    //rdtscp waits until the load is done
+    dynasm!(ops
+      ; rdtscp
+      ; lfence
+      ; shl rdx, 32
+      ; or rax, rdx
+      ; sub rax, r9
+      ; shr rax, LATENCY_BUCKET_CYCLES.trailing_zeros() as i8
+      ; mov rcx, QWORD LATENCY_BUCKETS as i64 - 1
+      ; cmp rax, rcx
+      ; cmova rax, rcx
+      ; mov rcx, QWORD histogram
+      //the SMT siblings count into the same histogram (see `hammer_smt`)
+      ; lock inc QWORD [rcx + rax * 8]
+    );
+  }
+
+  fn start_latency_sampling(&self, interval: u64) {
+    assert!(LATENCY_BUCKET_CYCLES.is_power_of_two());
+    *self.latency_histogram.lock().unwrap() =
+      (interval > 0).then(|| Box::new([0; LATENCY_BUCKETS]));
+    self.latency_interval.store(interval, Ordering::Relaxed);
+  }
+
+  //The histogram of the last JIT run (None without latency sampling)
+  fn take_latency_histogram(&self) -> Option<LatencyHistogram> {
+    self.latency_interval.store(0, Ordering::Relaxed);
+    let histogram = LatencyHistogram {bucket_cycles: LATENCY_BUCKET_CYCLES,
+      counts: *self.latency_histogram.lock().unwrap().take()?};
+    let median = histogram.quantile(0.5);
+    let blocked = (median as f64 * REF_SPIKE_FACTOR) as u64;
+    info!("Latency of {} sampled accesses: median {} cycles, {:.3}% >= {} \
+      cycles (refreshes)", histogram.samples(), median,
+      100.0 * histogram.fraction_above(blocked), blocked);
+    if histogram.samples() > 0 && median < LATENCY_CACHE_HIT_CYCLES {
+      warn!("The median access latency is {} cycles, the aggressor accesses \
+        may be served from the caches", median);
+    }
+    Some(histogram)
+  }
+}
+
+// --- JIT CACHE ---
+//The templater hammers every setup, bank and repetition with the same hammer
+//code, only the aggressor addresses differ: the finalized code of the
+//kernels that support `HammerKernel::emit_patchable` is cached in the
+//context (`RhContext::jit_cache`), keyed by the kernel and the params
+//without the aggressor addresses (number of aggressors, access rates,
+//garbage_count, hammer_count, unroll factor and flush instruction), and only
+//its aggressor table is patched for the next aggressors
+//The patch time is reported as the compile time of cached code
+//The code is not cached with the access trace (the trace samples contain the
+//aggressor addresses), latency sampling (the code contains the address of
+//the histogram) and `JIT_DUMP`, the oldest code is evicted when the cached
+//code exceeds JIT_CACHE_SIZE
+
+static JIT_CACHE_SIZE: usize = 1 << 30;
+
+#[derive(PartialEq, Eq, Hash, Debug)]
+struct JitKey {
+  kernel: String,
+  aggressors: usize,
+  rates: Vec<AccessRate>,
+  garbage_count: u32,
+  hammer_count: u64,
+  unroll: u64,
+  flush: FlushInstruction
+}
+
+impl JitKey {
+  fn new(kernel: &dyn HammerKernel, params: &KernelParams) -> JitKey {
+    JitKey {
+      kernel: kernel.name().to_owned(),
+      aggressors: params.aggrs.len(),
+      rates: params.rates.to_vec(),
+      garbage_count: params.garbage_count,
+      hammer_count: params.hammer_count,
+      unroll: params.unroll,
+      flush: params.flush
+    }
+  }
+}
+
+impl JitCode {
+  //Runs the code once
+  fn run(&self) {
+    let hammer: extern fn() = unsafe {
+      std::mem::transmute(self.buf.ptr(self.entry))
+    };
+    hammer();
+  }
+
+  //Writes arg:aggrs to the aggressor table of the code
+  fn patched(self, aggrs: &[u64]) -> JitCode {
+    let start = Instant::now();
+    let table = self.table.expect("The JIT code has no aggressor table").0;
+    let mut buf = self.buf.make_mut()
+      .expect("Making the JIT code writable failed");
+    for (i, aggr) in aggrs.iter().enumerate() {
+      buf[table + 8 * i..table + 8 * (i + 1)]
+        .copy_from_slice(&aggr.to_le_bytes());
+    }
+    let buf = buf.make_exec().expect("Making the JIT code executable failed");
+    JitCode {buf, compile_time: start.elapsed(), ..self}
+  }
+}
+
+//The cached code, the most recently used last
+#[derive(Default)]
+pub struct JitCache(Mutex<Vec<(JitKey, JitCode)>>);
+
+impl JitCache {
+  //Hammers with the cached code of arg:kernel for arg:params (patched with
+  //its aggressors), compiles (and caches) the code on a miss
+  fn hammer(
+    &self, ctx: &RhContext, kernel: &dyn HammerKernel, params: &KernelParams,
+    bursts: u64, idle: Duration
+  ) -> HammerStats {
+    let accesses = kernel_accesses(kernel, params);
+    let cacheable = ctx.settings.access_trace == 0
+      && ctx.settings.latency_samples == 0 && !ctx.settings.jit_dump;
+    let key = JitKey::new(kernel, params);
+    let mut entries = self.0.lock().unwrap();
+    let cached = entries.iter().position(|(k, _)| *k == key)
+      .filter(|_| cacheable);
+    let code = match cached {
+      Some(i) => {
+        debug!("Reusing the cached JIT code of {}", kernel.name());
+        let (key, code) = entries.remove(i);
+        let aggrs: Vec<u64> = params.aggrs.iter().map(|a| a[0]).collect();
+        entries.push((key, code.patched(&aggrs)));
+        &entries.last().unwrap().1
+      },
+      None => {
+        let code = compile_jit(ctx, params.hammer_count,
+          |ops| kernel.emit_patchable(ops, params));
+        if !cacheable || code.table.is_none() {
+          return execute_jit(ctx, &code, params.hammer_count, accesses,
+            bursts, idle);
+        }
+        entries.push((key, code));
+        let mut size: usize = entries.iter().map(|(_, c)| c.buf.len()).sum();
+        while size > JIT_CACHE_SIZE && entries.len() > 1 {
+          size -= entries.remove(0).1.buf.len();
+        }
+        &entries.last().unwrap().1
+      }
+    };
+    execute_jit(ctx, code, params.hammer_count, accesses, bursts, idle)
+  }
+}
+
+// --- JIT SUMMARY ---
+//With `JIT_SUMMARY` set (the `jit_summary` setting), a summary of the JIT
+//code (code size, instruction mix, accesses per hammer iteration) is logged
+//before it runs and the estimated IPC after it ran, to compare garbage
+//configurations without dumping the whole code with `JIT_DUMP`
+//The IPC assumes that the code runs straight through (no loops, like the
+//unrolled hammer loops) and uses TSC cycles, it is only an estimate
+//With `RH_JIT_UNROLL` the loop body is only counted once, the loads per
+//iteration and the IPC are too low then
+
+//JIT code larger than this is reported even without `JIT_SUMMARY`
+static JIT_SIZE_WARNING: usize = 1 << 30;
+//bytes disassembled at once, bounds the memory of the disassembly
+static DISASM_CHUNK_SIZE: usize = 1_000_000;
+
+struct JitSummary {
+  size: usize,
+  insns: u64,
+  mnemonics: HashMap<String, u64>,
+  loads: u64 //instructions that read memory with mov (= aggressor accesses)
+}
+
+//Disassembles the code in chunks, each chunk starts after the last complete
+//instruction of the previous one
+fn summarize_jit(code: &[u8]) -> JitSummary {
+  let cs = Capstone::new().x86().mode(arch::x86::ArchMode::Mode64)
+    .build().expect("Failed to create Capstone object");
+  let mut summary = JitSummary {
+    size: code.len(),
+    insns: 0,
+    mnemonics: HashMap::new(),
+    loads: 0
+  };
+  let mut offset = 0;
+  while offset < code.len() {
+    let end = code.len().min(offset + DISASM_CHUNK_SIZE);
+    let insns = cs.disasm_all(&code[offset..end], offset as u64)
+      .expect("Failed to disassemble");
+    let last = match insns.iter().next_back() {
+      Some(insn) => insn.address() as usize + insn.bytes().len(),
+      //undecodable bytes (e.g., the data in front of the code), skip one
+      None => offset + 1
+    };
+    for insn in insns.iter() {
+      let mnemonic = insn.mnemonic().unwrap_or("?");
+      let operands = insn.op_str().unwrap_or("");
+      if mnemonic.starts_with("mov") && operands.split(", ").nth(1)
+        .is_some_and(|src| src.contains('['))
+      {
+        summary.loads += 1;
+      }
+      *summary.mnemonics.entry(mnemonic.to_owned()).or_insert(0) += 1;
+      summary.insns += 1;
+    }
+    offset = last;
+  }
+  summary
+}
+
+fn log_jit_summary(summary: &JitSummary, hammer_count: u64) {
+  info!("JIT code: {}kB, {} instructions, {} loads ({:.1} per hammer \
+    iteration)", summary.size >> 10, summary.insns, summary.loads,
+    summary.loads as f64 / hammer_count.max(1) as f64);
+  let mut mnemonics: Vec<_> = summary.mnemonics.iter().collect();
+  mnemonics.sort_by(|a, b| b.1.cmp(a.1));
+  info!("Instruction mix: {}", mnemonics.iter().map(|(m, count)|
+    format!("{} {:.1}%", m, **count as f64 * 100.0 / summary.insns as f64))
+    .collect::<Vec<_>>().join(", "));
+}
+
+//JITs the hammer code emitted by arg:create_hammer and runs it
+//arg:hammer_count: hammer iterations of the emitted code (for the summary)
+//arg:accesses: aggressor accesses of the emitted code (for the stats)
+fn run_jit(
+  ctx: &RhContext,
+  hammer_count: u64,
+  accesses: u64,
+  create_hammer: impl FnOnce(&mut Assembler)
+) -> HammerStats {
+  run_jit_bursts(ctx, hammer_count, accesses, 1, Duration::ZERO,
+    create_hammer)
+}
+
+//Runs the JITed code arg:bursts times with arg:idle between the runs, the
+//stats do not include the idle times
+//With a `hammer_duration`, the bursts are repeated until it has passed
+//(see `HammerDuration`)
+fn run_jit_bursts(
+  ctx: &RhContext, hammer_count: u64, accesses: u64, bursts: u64,
+  idle: Duration, create_hammer: impl FnOnce(&mut Assembler)
+) -> HammerStats {
+  let code = compile_jit(ctx, hammer_count, |ops| {
+    create_hammer(ops);
+    None
+  });
+  execute_jit(ctx, &code, hammer_count, accesses, bursts, idle)
+}
+
+//Finalized JIT hammer code
+struct JitCode {
+  buf: ExecutableBuffer,
+  entry: AssemblyOffset,
+  //the patchable aggressor table (see `JitCache`)
+  table: Option<AssemblyOffset>,
+  summary: Option<JitSummary>,
+  //compile time of the code, or patch time of cached code
+  compile_time: Duration
+}
+
+//JITs the hammer code emitted by arg:create_hammer, which returns the offset
+//of its aggressor table if the code can be patched (see `JitCache`)
+fn compile_jit(
+  ctx: &RhContext, hammer_count: u64,
+  create_hammer: impl FnOnce(&mut Assembler) -> Option<AssemblyOffset>
+) -> JitCode {
+  ctx.jit_probes.start_trace(ctx.settings.access_trace);
+  ctx.jit_probes.start_latency_sampling(ctx.settings.latency_samples);
+  assemble_jit(ctx, hammer_count, create_hammer)
+}
+
+//Same as `compile_jit`, without restarting the access trace and the latency
+//sampling, so several codes can record into the same buffers
+fn assemble_jit(
+  ctx: &RhContext, hammer_count: u64,
+  create_hammer: impl FnOnce(&mut Assembler) -> Option<AssemblyOffset>
+) -> JitCode {
   debug!("JITing the rowhammer code");
+  let compile_start = Instant::now();
 
   let mut ops = dynasmrt::x64::Assembler::new().unwrap();
   let code = ops.offset();
@@ -172,25 +1847,33 @@ pub fn hammer_jit(
   );
 
   push_all_gp_regs(&mut ops);
-  create_hammer_jit(&mut ops, pattern, hammer_count, Box::new(move |mut ops| {
-    garbage_add(&mut ops, 1, garbage_count);
-  }));
+  let table = create_hammer(&mut ops);
   pop_all_gp_regs(&mut ops);
   dynasm!(ops; ret);
 
   let buf = ops.finalize().unwrap();
-  let hammer: extern fn() = unsafe {
-    std::mem::transmute(buf.ptr(code))
-  };
+  let compile_time = compile_start.elapsed();
+  //the code is mapped after the self guard checked the aggressors
+  self_guard::check_code(ctx, &buf[..]);
+
+  if buf.len() > JIT_SIZE_WARNING {
+    warn!("The JIT code is {}MB large", buf.len() >> 20);
+  }
+  let summary = ctx.settings.jit_summary.then(|| {
+    let summary = summarize_jit(&buf[..]);
+    log_jit_summary(&summary, hammer_count);
+    summary
+  });
 
-  //if the env variable `JIT_DUMP` is set, dump the jitted code to a file
+  //with the `jit_dump` setting (env variable `JIT_DUMP`), dump the jitted
+  //code to a file
   //iterate in chunks to prevent out-of-memory error for very big code regions
   //TODO the chunks could split instructions apart! => wrong disassembly and
   //restart at address 0x0
   //TODO change so it only dumps one iteration, create file overwrites the
   //previous iteration
   //for now, use kill to stop the process
-  if env::var("JIT_DUMP").is_ok() {
+  if ctx.settings.jit_dump {
     let chunk_size = 1_000_000;
     warn!("Dumping JIT code to file, expecting about {} iterations",
       buf.len() / chunk_size);
@@ -206,14 +1889,48 @@ pub fn hammer_jit(
     }
     warn!("Dumping JIT code to file finished");
   }
+  JitCode {buf, entry: code, table, summary, compile_time}
+}
 
+//Runs arg:code arg:bursts times with arg:idle between the runs (see
+//`run_jit_bursts`)
+fn execute_jit(
+  ctx: &RhContext, code: &JitCode, hammer_count: u64, accesses: u64,
+  bursts: u64, idle: Duration
+) -> HammerStats {
   //run the jitted code
   debug!("Executing JITed rowhammer code");
-  let start_time = SystemTime::now();
-  hammer();
-  let duration = SystemTime::now().duration_since(start_time).unwrap();
-  debug!("Hammering took {}ms", duration.as_millis());
+  let mut aux = 0;
+  let mut cycles = 0;
+  let mut duration = Duration::ZERO;
+  let deadline = ctx.settings.hammer_duration
+    .map(|d| Instant::now() + d.duration());
+  let mut burst = 0;
+  while burst < bursts || deadline.is_some_and(|d| Instant::now() < d) {
+    if burst > 0 {
+      let idle_end = Instant::now() + idle;
+      while Instant::now() < idle_end {
+        std::hint::spin_loop();
+      }
+    }
+    let start_time = SystemTime::now();
+    let start_tsc = unsafe {__rdtscp(&mut aux)};
+    code.run();
+    cycles += unsafe {__rdtscp(&mut aux)} - start_tsc;
+    duration += SystemTime::now().duration_since(start_time).unwrap();
+    burst += 1;
+  }
+  debug!("Hammering took {}ms ({} bursts)", duration.as_millis(), burst);
+  ctx.jit_probes.dump_trace();
+  if let Some(summary) = &code.summary {
+    info!("Estimated IPC: {:.2} ({} cycles)",
+      (summary.insns * burst) as f64 / cycles.max(1) as f64, cycles);
+  }
 
-  duration
+  HammerStats {
+    latency: ctx.jit_probes.take_latency_histogram(),
+    ..HammerStats::new(hammer_count * burst, accesses * burst, duration,
+      code.compile_time)
+  }
 }
 
@@ -0,0 +1,180 @@
+use log::*;
+use serde::Serialize;
+
+use crate::context::RhContext;
+use crate::utils::{dram, dram::DRAMAddr, files, serialize::*};
+
+//Sanity check of the aggressor address translation: with `RH_CHECK_GEOMETRY`
+//the tools translate the aggressors and the victim of every pattern back to
+//DRAM addresses (phys_to_dram) before hammering and verify that they are in
+//one bank at the row distances of the intended pattern (the `pattern` of the
+//templater config, e.g. "AVA": the victim is 1 row after the first aggressor
+//and 1 row before the second one), a wrong DRAM config or row remap shows up
+//as a structured warning instead of silently hammering the wrong rows
+//With a `row_remap`, the distances are checked in physical rows
+//Patterns with another number of aggressors than the intended pattern (e.g.
+//minimized patterns) are only checked for the bank, the far (`F`) rows are
+//not checked
+//The patterns of an attack config have no intended pattern, the prehammer and
+//the loader only check that the aggressors of every victim bit are in its
+//bank
+
+//The intended row offsets of the aggressors and victims of a pattern
+pub struct PatternLayout {
+  pattern: String,
+  //offset of every aggressor row in the pattern (the `A` rows, the `B` rows,
+  //then the `D` rows, the order of the aggressor patterns)
+  aggressors: Vec<i64>,
+  //offset of every victim row in the pattern
+  victims: Vec<i64>,
+  //number of `F` rows, the last rows of the aggressor patterns, they are far
+  //from the victims and not checked
+  far_rows: usize,
+  row_remap: Option<RowRemap>
+}
+
+#[derive(Serialize)]
+pub struct LayoutMismatch {
+  pub pattern: String,
+  pub victim_phys_addr: u64,
+  pub victim: DRAMAddr,
+  pub aggressors: Vec<DRAMAddr>,
+  //intended row distance (victim row - aggressor row) to every aggressor,
+  //empty if the number of aggressors does not match the pattern
+  pub expected_distances: Vec<i64>,
+  //actual row distance to every aggressor, None if it is in another bank
+  pub distances: Vec<Option<i64>>
+}
+
+impl PatternLayout {
+  //The layout of the pattern of arg:templater_config (None without
+  //`RH_CHECK_GEOMETRY`)
+  pub fn if_requested(ctx: &RhContext, templater_config: &TemplaterConfig)
+    -> Option<PatternLayout>
+  {
+    if !ctx.settings.check_geometry {
+      return None;
+    }
+    let mut layout = PatternLayout::parse(&templater_config.pattern);
+    layout.row_remap = templater_config.row_remap.as_ref()
+      .map(|path| files::parse_json(path));
+    Some(layout)
+  }
+
+  //The layout of the aggressor patterns of an attack config, only the banks
+  //are checked (None without `RH_CHECK_GEOMETRY`)
+  pub fn for_attack_if_requested(ctx: &RhContext) -> Option<PatternLayout> {
+    ctx.settings.check_geometry.then(|| PatternLayout::parse(""))
+  }
+
+  pub fn parse(pattern: &str) -> PatternLayout {
+    let offsets = |kind| pattern.chars().filter(|c| *c != 'F').enumerate()
+      .filter(move |(_, c)| *c == kind).map(|(i, _)| i as i64);
+    PatternLayout {
+      pattern: pattern.to_owned(),
+      aggressors: offsets('A').chain(offsets('B')).chain(offsets('D'))
+        .collect(),
+      victims: offsets('V').collect(),
+      far_rows: pattern.chars().filter(|c| *c == 'F').count(),
+      row_remap: None
+    }
+  }
+
+  fn row(&self, dram_addr: &DRAMAddr) -> u64 {
+    self.row_remap.as_ref()
+      .map_or(dram_addr.row, |r| r.to_physical(dram_addr.row))
+  }
+
+  //Compares the DRAM addresses of arg:aggr_pattern and the victim at
+  //arg:victim_phys_addr to the layout
+  pub fn check(
+    &self, ctx: &RhContext, aggr_pattern: &AggressorPattern,
+    victim_phys_addr: u64
+  ) -> Option<LayoutMismatch> {
+    let dram_config = ctx.dram_config();
+    let victim = dram::phys_to_dram(victim_phys_addr, dram_config);
+    let rows = aggr_pattern.pattern.len().saturating_sub(self.far_rows);
+    let aggressors: Vec<DRAMAddr> = aggr_pattern.into_iter().take(rows)
+      .map(|r| dram::phys_to_dram(r.frames[0], dram_config)).collect();
+    let distances: Vec<Option<i64>> = aggressors.iter()
+      .map(|a| (a.bank == victim.bank)
+      .then(|| self.row(&victim) as i64 - self.row(a) as i64))
+      .collect();
+
+    let expected_distances = if aggressors.len() == self.aggressors.len() {
+      //the victim can be any `V` row, compare to the best matching one
+      self.victims.iter().map(|v| self.aggressors.iter().map(|a| v - a)
+        .collect::<Vec<_>>())
+        .max_by_key(|expected| expected.iter().zip(&distances)
+        .filter(|(e, d)| Some(**e) == **d).count())
+        .unwrap_or_default()
+    } else {
+      Vec::new()
+    };
+    let matches = distances.iter().all(|d| d.is_some())
+      && (expected_distances.is_empty() || expected_distances.iter()
+      .zip(&distances).all(|(e, d)| Some(*e) == *d));
+    if matches {
+      return None;
+    }
+    Some(LayoutMismatch {
+      pattern: self.pattern.clone(),
+      victim_phys_addr,
+      victim,
+      aggressors,
+      expected_distances,
+      distances
+    })
+  }
+
+  //Logs a warning if arg:aggr_pattern does not match the layout, returns
+  //whether it matches
+  pub fn warn_on_mismatch(
+    &self, ctx: &RhContext, aggr_pattern: &AggressorPattern,
+    victim_phys_addr: u64
+  ) -> bool {
+    match self.check(ctx, aggr_pattern, victim_phys_addr) {
+      Some(mismatch) => {
+        warn!("Aggressor geometry mismatch: {}",
+          serde_json::to_string(&mismatch).unwrap());
+        false
+      },
+      None => true
+    }
+  }
+
+  //Checks the aggressor pattern of every victim bit of arg:attack_config (see
+  //`warn_on_mismatch`), returns the number of mismatches
+  pub fn warn_on_attack_mismatches(
+    &self, ctx: &RhContext, attack_config: &AttackConfig
+  ) -> usize {
+    let bits = attack_config.victim_frames.iter()
+      .map(|v| (v.frame_addr, &v.victim_bits))
+      .chain(attack_config.pte_victim_frames.iter()
+      .map(|v| (v.frame_addr, &v.victim_bits)))
+      .flat_map(|(frame_addr, bits)| bits.iter().map(move |b| (frame_addr, b)));
+    bits.filter(|(frame_addr, bit)| !self.warn_on_mismatch(ctx,
+      attack_config.aggr_pattern(&bit.aggr_pattern_key),
+      frame_addr + bit.offset)).count()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn far_rows_have_no_offset() {
+    let layout = PatternLayout::parse("VAVF");
+    assert_eq!(layout.aggressors, [1]);
+    assert_eq!(layout.victims, [0, 2]);
+    assert_eq!(layout.far_rows, 1);
+    //the rows after an `F` row are not shifted by it
+    let layout = PatternLayout::parse("AVFAVF");
+    assert_eq!(layout.aggressors, [0, 2]);
+    assert_eq!(layout.victims, [1, 3]);
+    assert_eq!(layout.far_rows, 2);
+    let layout = PatternLayout::parse("AVA");
+    assert_eq!((layout.aggressors, layout.far_rows), (vec![0, 2], 0));
+  }
+}
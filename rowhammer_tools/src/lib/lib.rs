@@ -1,22 +1,49 @@
+#[cfg(feature = "system")]
+use std::env;
+#[cfg(feature = "system")]
 use std::ffi::OsStr;
+#[cfg(feature = "system")]
+use std::panic;
+#[cfg(feature = "system")]
 use env_logger::Env;
+#[cfg(feature = "system")]
+use log::*;
+#[cfg(feature = "system")]
 use nix::unistd::getuid;
-use nix::sys::mman;
 
 
 //This library contains all reusable functions to build rowhammer tools with
 //Some may seem a bit arbitrary, that's because they are
+//The pure logic is in `core` (see core/mod.rs), everything that touches the
+//system needs the `system` feature (default)
 pub mod config;
+pub mod core;
 pub mod utils;
-pub mod allocation;
-pub mod flip_checks;
-pub mod mem_init;
-pub mod hammer;
+//the core modules at their old paths
+pub use crate::core::{analysis, fuzz, schedule};
+
+#[cfg(feature = "system")] pub mod allocation;
+#[cfg(feature = "system")] pub mod flip_checks;
+#[cfg(feature = "system")] pub mod mem_init;
+#[cfg(feature = "system")] pub mod hammer;
+#[cfg(feature = "system")] pub mod calibration;
+#[cfg(feature = "system")] pub mod lint;
+#[cfg(feature = "system")] pub mod mapping_re;
+#[cfg(feature = "system")] pub mod pte;
+#[cfg(feature = "system")] pub mod adjacency;
+#[cfg(feature = "system")] pub mod schedule_export;
+#[cfg(feature = "system")] pub mod victim_conflicts;
+#[cfg(feature = "system")] pub mod context;
+#[cfg(feature = "system")] pub mod layout;
+#[cfg(feature = "system")] pub mod self_guard;
+#[cfg(feature = "system")] pub mod preflight;
+#[cfg(feature = "system")]
 use config::*;
 
 //Some basic checks every tool should perform
 //Returns an env_logger::Builder instance for further tool-specific logger
 //configurations, call `init()` on it to finish the creation of the instance
+#[cfg(feature = "system")]
 pub fn configure() -> env_logger::Builder {
   let hostname = hostname::get().expect("Could not get hostname");
   for name in FORBIDDEN_HOSTNAMES {
@@ -27,15 +54,40 @@ pub fn configure() -> env_logger::Builder {
   assert!(getuid().is_root(), "Program should run with root privileges");
 
   //lock all pages of this process in physical memory (= prevent swapping)
-  mman::mlockall(mman::MlockAllFlags::all()).expect("mlockall failed");
+  //falls back to locking only the allocated frames (see utils::memlock)
+  utils::memlock::lock_all();
+
+  install_panic_hook();
 
   let mut logger_builder =
     env_logger::Builder::from_env(Env::default().default_filter_or("info"));
   logger_builder.format_timestamp(None);
   //logger_builder.format(|buf, record| writeln!(buf, "{}", record.args()));
+  //optionally redact the physical addresses (`RH_REDACT_PHYS`)
+  utils::redact::configure_logger(&mut logger_builder);
   logger_builder
 }
 
-
-
-
+//Logs panics with the logger (e.g., to the log files of the soak mode of the
+//templater) instead of only printing them to stderr, the default hook still
+//prints them before the logger is initialized and with `RUST_BACKTRACE`
+//The system state the tools change is restored by guards that are dropped
+//while the panic unwinds: the prefetchers (utils::msr::PrefetcherGuard), the
+//scheduling settings (utils::sched::SchedGuard), the tool lock, the sprayed
+//page tables (pte::PteSpray) and the background threads (ECC guard, victim
+//workload), the timeline writes the events recorded so far and the templater
+//reports its partial template
+#[cfg(feature = "system")]
+fn install_panic_hook() {
+  let default_hook = panic::take_hook();
+  panic::set_hook(Box::new(move |info| {
+    let logged = log_enabled!(Level::Error);
+    if logged {
+      error!("{}", info);
+      logger().flush();
+    }
+    if !logged || env::var("RUST_BACKTRACE").is_ok() {
+      default_hook(info);
+    }
+  }));
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+use crate::config::*;
+use crate::utils::{host, serialize::*};
+use crate::utils::victim_ledger::{self, VictimLedger};
+
+//Static checks of an attack config, nothing is allocated or hammered
+//Runs without root privileges, but the reserved memory check is skipped then
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+  Warning,
+  Error
+}
+
+pub struct Lint {
+  pub severity: Severity,
+  pub message: String
+}
+
+impl Lint {
+  fn warning(message: String) -> Lint {
+    Lint {severity: Severity::Warning, message}
+  }
+
+  fn error(message: String) -> Lint {
+    Lint {severity: Severity::Error, message}
+  }
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.severity {
+      Severity::Warning => write!(f, "warning: {}", self.message),
+      Severity::Error => write!(f, "error: {}", self.message)
+    }
+  }
+}
+
+//Runs the checks of `AttackConfig::check` and the lints below
+//The lints on the PT_NULL segment and `segment_virt_addr` need the attacked
+//binary at arg:program_path
+//The victim bits are checked against the victim ledger (see victim_ledger)
+pub fn lint_attack_config(
+  attack_config: &AttackConfig, program_path: Option<&str>
+) -> Vec<Lint> {
+  let mut lints: Vec<Lint> =
+    attack_config.check().into_iter().map(Lint::error).collect();
+  lint_hammer_count(attack_config, &mut lints);
+  if let Some(program_path) = program_path {
+    lint_binary(attack_config, program_path, &mut lints);
+  }
+  lint_reserved_memory(attack_config, &mut lints);
+  let ledger = VictimLedger::load(&victim_ledger::ledger_path());
+  lints.extend(lint_victims(&ledger, program_path,
+    attack_config.victim_frames.iter().flat_map(|v| v.victim_bits.iter()
+    .map(move |b| (v.frame_addr + b.offset, &b.bitflip)))));
+  lints
+}
+
+//Warns about worn victim bits in arg:victims (byte address, bitflip) and
+//about victim bits that are already used for binaries other than
+//arg:program_path (all other binaries if None)
+pub fn lint_victims<'a>(
+  ledger: &VictimLedger, program_path: Option<&str>,
+  victims: impl Iterator<Item = (u64, &'a BitFlip)>
+) -> Vec<Lint> {
+  let binary = program_path.map(victim_ledger::binary_name);
+  let mut lints = Vec::new();
+  for (phys_addr, bitflip) in victims {
+    if ledger.is_worn(phys_addr, bitflip) {
+      lints.push(Lint::warning(format!("Victim bit {} at P0x{:x} is worn (it \
+        did not flip in its last {} uses)", bitflip, phys_addr,
+        WORN_VICTIM_FAILURES)));
+    }
+    for (other, count) in ledger.binaries(phys_addr, bitflip) {
+      if Some(other) != binary.as_deref() {
+        lints.push(Lint::warning(format!("Victim bit {} at P0x{:x} is used \
+          by {} ({} times)", bitflip, phys_addr, other, count)));
+      }
+    }
+  }
+  lints
+}
+
+fn lint_hammer_count(attack_config: &AttackConfig, lints: &mut Vec<Lint>) {
+  if attack_config.hammer_count == 0 {
+    lints.push(Lint::error("hammer_count is 0".to_owned()));
+  } else if attack_config.hammer_count < MIN_PLAUSIBLE_HAMMER_COUNT {
+    lints.push(Lint::warning(format!("hammer_count {} is implausibly low \
+      (< {}), is it a leftover of a test?", attack_config.hammer_count,
+      MIN_PLAUSIBLE_HAMMER_COUNT)));
+  }
+}
+
+//Mirrors the assumptions of `allocation::map_binary`
+fn lint_binary(
+  attack_config: &AttackConfig, program_path: &str, lints: &mut Vec<Lint>
+) {
+  let elf_file = match elf::File::open_path(program_path) {
+    Ok(elf_file) => elf_file,
+    Err(e) => {
+      lints.push(Lint::error(format!("Could not open ELF file {}: {:?}",
+        program_path, e)));
+      return;
+    }
+  };
+  let segment = match elf_file.phdrs.iter()
+    .find(|&&i| i.progtype == elf::types::PT_NULL) {
+    Some(segment) => segment,
+    None => {
+      lints.push(Lint::error(format!("{} has no PT_NULL segment",
+        program_path)));
+      return;
+    }
+  };
+
+  let segment_page_count = (segment.filesz >> *PAGE_SIZE_BITS) + 1;
+  if segment_page_count != (segment.memsz >> *PAGE_SIZE_BITS) + 1 {
+    lints.push(Lint::error("memsz != filesz of the PT_NULL segment, does \
+      your section contain only code?".to_owned()));
+  }
+  let segment_size = segment_page_count * *PAGE_SIZE as u64;
+
+  //1. victims must be pages of the PT_NULL segment
+  let file_range = segment.offset..segment.offset + segment_size;
+  for victim in &attack_config.victim_frames {
+    let offset = match victim.page_file_offset {
+      Some(offset) => offset & *PAGE_ALIGN_MASK,
+      None => continue //already reported by `check`
+    };
+    if !file_range.contains(&offset) {
+      lints.push(Lint::error(format!("Victim frame P0x{:x} has file offset \
+        0x{:x} outside of the PT_NULL segment (0x{:x}-0x{:x})",
+        victim.frame_addr, offset, file_range.start, file_range.end)));
+      continue;
+    }
+    for bit in &victim.victim_bits {
+      if offset + bit.offset >= segment.offset + segment.filesz {
+        lints.push(Lint::warning(format!("Victim bit at file offset 0x{:x} \
+          is in the padding after the PT_NULL segment", offset + bit.offset)));
+      }
+    }
+  }
+
+  //2. the remapped segment must not overlap with the loaded binary
+  let virt_addr = attack_config.segment_virt_addr;
+  if virt_addr & *PAGE_OFFSET_MASK != 0 {
+    lints.push(Lint::error(format!("segment_virt_addr 0x{:x} is not page \
+      aligned", virt_addr)));
+  }
+  if elf_file.ehdr.elftype == elf::types::ET_DYN {
+    lints.push(Lint::warning(format!("{} is position independent, overlaps \
+      of segment_virt_addr with its segments can not be checked",
+      program_path)));
+    return;
+  }
+  let virt_range = virt_addr..virt_addr + segment_size;
+  for phdr in elf_file.phdrs.iter()
+    .filter(|i| i.progtype == elf::types::PT_LOAD) {
+    let load_range = phdr.vaddr & *PAGE_ALIGN_MASK..phdr.vaddr + phdr.memsz;
+    if overlaps(&virt_range, &load_range) {
+      lints.push(Lint::error(format!("segment_virt_addr range 0x{:x}-0x{:x} \
+        overlaps with the LOAD segment at 0x{:x}-0x{:x}", virt_range.start,
+        virt_range.end, load_range.start, load_range.end)));
+    }
+  }
+}
+
+//Frames outside "System RAM" are reserved (firmware, MMIO, ...) and will
+//never be handed out by the buddy allocator
+fn lint_reserved_memory(attack_config: &AttackConfig, lints: &mut Vec<Lint>) {
+  let ram = host::get_system_ram_ranges();
+  if ram.iter().all(|r| r.end <= 1) {
+    lints.push(Lint::warning("Physical addresses in /proc/iomem are hidden, \
+      run as root to check for aggressors in reserved memory".to_owned()));
+    return;
+  }
+  let in_ram = |frame: u64| ram.iter().any(|r| r.contains(&frame)
+    && r.contains(&(frame + *PAGE_OFFSET_MASK)));
+
+  let aggrs: BTreeSet<u64> = attack_config.iter_aggr_frames().collect();
+  for frame in aggrs.into_iter().filter(|f| !in_ram(*f)) {
+    lints.push(Lint::error(format!("Aggressor frame P0x{:x} is in reserved \
+      memory", frame)));
+  }
+  for victim in attack_config.victim_frames.iter()
+    .filter(|v| !in_ram(v.frame_addr)) {
+    lints.push(Lint::error(format!("Victim frame P0x{:x} is in reserved \
+      memory", victim.frame_addr)));
+  }
+}
+
+fn overlaps(a: &Range<u64>, b: &Range<u64>) -> bool {
+  a.start < b.end && b.start < a.end
+}
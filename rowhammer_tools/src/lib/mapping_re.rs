@@ -0,0 +1,240 @@
+use std::arch::x86_64::{__rdtscp, _mm_clflush, _mm_mfence};
+use std::collections::HashMap;
+use mmap::{MapOption, MemoryMap};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use log::*;
+
+use crate::config::*;
+use crate::utils::{host, oracle::PhysAddrOracle, serialize::*};
+
+//Timing based reverse engineering of the DRAM mapping functions (like DRAMA,
+//Pessl et al., USENIX Security 2016)
+//Two addresses in the same bank but in a different row cause a row buffer
+//conflict, which makes accessing them together measurably slower
+//Only mapping functions within the restrictions described in `dram` are
+//found: bank fns of 2 bits and contiguous row and column bits
+//The results are only as good as the timing measurements, run it on an idle
+//system
+
+//size of the analyzed memory pool
+static POOL_SIZE: usize = 1 << 30;
+//measurements per address pair (the median is used)
+static PAIR_MEASUREMENTS: usize = 32;
+//random address pairs to determine the conflict threshold
+static THRESHOLD_PAIRS: usize = 2000;
+//random addresses that are sorted into bank sets
+static SAMPLE_ADDRESSES: usize = 4096;
+//address pairs per bit to classify the bit as row or column bit
+static BIT_SAMPLES: usize = 16;
+//minimal fraction of every bank set that has to agree on a bank fn
+static BANK_FN_AGREEMENT: f64 = 0.9;
+
+//Pages of the pool by physical frame address
+struct Pool {
+  _map: MemoryMap,
+  frame2virt: HashMap<u64, u64>
+}
+
+impl Pool {
+  fn allocate(oracle: &impl PhysAddrOracle) -> Pool {
+    info!("Allocating a pool of {}MB for reverse engineering",
+      POOL_SIZE >> 20);
+    let map = MemoryMap::new(POOL_SIZE,
+      &[MapOption::MapReadable, MapOption::MapWritable])
+      .expect("Pool allocation failed");
+    let frame2virt = (0..POOL_SIZE).step_by(*PAGE_SIZE).map(|offset| {
+      let virt = map.data() as u64 + offset as u64;
+      unsafe {std::ptr::write_volatile(virt as *mut u8, 1);}
+      (oracle.virt_to_phys(virt), virt)
+    }).collect();
+    Pool {_map: map, frame2virt}
+  }
+
+  fn virt(&self, phys_addr: u64) -> Option<u64> {
+    self.frame2virt.get(&(phys_addr & *PAGE_ALIGN_MASK))
+      .map(|v| v + (phys_addr & *PAGE_OFFSET_MASK))
+  }
+
+  //random physical addresses (cache line aligned) in the pool
+  fn sample(&self, count: usize) -> Vec<u64> {
+    let mut rng = rand::thread_rng();
+    let frames: Vec<&u64> = self.frame2virt.keys().collect();
+    (0..count).map(|_| {
+      let cache_line = rng.gen_range(0..*PAGE_SIZE as u64 / 64);
+      **frames.choose(&mut rng).unwrap() + cache_line * 64
+    }).collect()
+  }
+}
+
+//cycles to access both virtual addresses from DRAM (median)
+fn pair_latency(a: u64, b: u64) -> u64 {
+  let mut aux = 0;
+  let mut cycles: Vec<u64> = (0..PAIR_MEASUREMENTS).map(|_| unsafe {
+    _mm_clflush(a as *const u8);
+    _mm_clflush(b as *const u8);
+    _mm_mfence();
+    let start = __rdtscp(&mut aux);
+    std::ptr::read_volatile(a as *const u8);
+    std::ptr::read_volatile(b as *const u8);
+    let end = __rdtscp(&mut aux);
+    end - start
+  }).collect();
+  cycles.sort_unstable();
+  cycles[cycles.len() / 2]
+}
+
+//About 1/arg:banks of random address pairs are in the same bank (and most of
+//those in a different row), the threshold lies between the typical latency
+//and the latency of these conflicts
+fn conflict_threshold(pool: &Pool, banks: u32) -> u64 {
+  let addrs = pool.sample(2 * THRESHOLD_PAIRS);
+  let mut latencies: Vec<u64> = addrs.chunks_exact(2).map(|p|
+    pair_latency(pool.virt(p[0]).unwrap(), pool.virt(p[1]).unwrap()))
+    .collect();
+  latencies.sort_unstable();
+  let typical = latencies[latencies.len() / 2];
+  let conflict =
+    latencies[latencies.len() - latencies.len() / (4 * banks as usize)];
+  info!("Typical access: {} cycles, row conflict: {} cycles", typical,
+    conflict);
+  assert!(conflict > typical, "No row buffer conflicts measured");
+  (typical + conflict) / 2
+}
+
+fn conflicts(pool: &Pool, a: u64, b: u64, threshold: u64) -> bool {
+  pair_latency(pool.virt(a).unwrap(), pool.virt(b).unwrap()) > threshold
+}
+
+//Sorts random addresses into sets of the same bank
+fn bank_sets(pool: &Pool, threshold: u64, banks: u32) -> Vec<Vec<u64>> {
+  let mut remaining = pool.sample(SAMPLE_ADDRESSES);
+  let min_set_size = SAMPLE_ADDRESSES / banks as usize / 4;
+  let mut sets = Vec::new();
+  while sets.len() < banks as usize {
+    let base = match remaining.pop() {
+      Some(base) => base,
+      None => break
+    };
+    let (mut set, rest): (Vec<u64>, Vec<u64>) = remaining.into_iter()
+      .partition(|x| conflicts(pool, base, *x, threshold));
+    remaining = rest;
+    if set.len() >= min_set_size {
+      debug!("Bank set {} with {} addresses", sets.len(), set.len() + 1);
+      set.push(base);
+      sets.push(set);
+    }
+  }
+  assert!(sets.len() == banks as usize,
+    "Found only {} of the {} banks", sets.len(), banks);
+  sets
+}
+
+fn parity(addr: u64, mask: u64) -> u64 {
+  (addr & mask).count_ones() as u64 % 2
+}
+
+//The 2-bit functions that are (nearly) constant within every bank set, but
+//not over all sets, reduced to a linearly independent set
+fn find_bank_fns(sets: &[Vec<u64>], max_bit: u32, banks: u32) -> Vec<u64> {
+  let mut basis: Vec<u64> = Vec::new();
+  let mut bank_fns = Vec::new();
+  for high in 7..max_bit {
+    for low in 6..high {
+      let mask = (1 << high) | (1 << low);
+      let mut values = Vec::new();
+      let consistent = sets.iter().all(|set| {
+        let ones = set.iter().filter(|a| parity(**a, mask) == 1).count();
+        let agreement = ones.max(set.len() - ones) as f64 / set.len() as f64;
+        values.push(ones * 2 > set.len());
+        agreement >= BANK_FN_AGREEMENT
+      });
+      if !consistent || values.iter().all(|v| *v == values[0]) {
+        continue;
+      }
+      //skip combinations of the functions found so far (the basis is sorted
+      //descending and has a unique highest bit per element)
+      let reduced = basis.iter().fold(mask, |m, b| m.min(m ^ b));
+      if reduced != 0 {
+        debug!("Bank fn 0x{:x}", mask);
+        basis.push(reduced);
+        basis.sort_unstable_by(|a, b| b.cmp(a));
+        bank_fns.push(mask);
+      }
+    }
+  }
+  assert!(1 << bank_fns.len() == banks,
+    "Found {} bank fns for {} banks", bank_fns.len(), banks);
+  bank_fns
+}
+
+//Classifies the bits above the cache line offset: flipping a row bit keeps
+//the bank but changes the row (conflict), flipping a column bit does not
+//Bank fn bits are flipped together with their partner bit to keep the bank,
+//the higher bit of a bank fn is assumed to be the row bit and the lower one
+//the column bit
+fn find_row_column_fns(
+  pool: &Pool, bank_fns: &[u64], threshold: u64, max_bit: u32
+) -> (u64, u64) {
+  let mut row_fn = 0;
+  let mut column_fn = 0x3f;
+  let mut addrs = pool.sample(SAMPLE_ADDRESSES);
+  addrs.shuffle(&mut rand::thread_rng());
+  for bit in 6..max_bit {
+    let bank_fn = bank_fns.iter().find(|f| *f & (1 << bit) != 0);
+    let flip = *bank_fn.unwrap_or(&(1 << bit));
+    //for bank fns, only classify the pair once (at its higher bit)
+    if bank_fn.is_some() && flip >> (bit + 1) != 0 {
+      continue;
+    }
+    let results: Vec<bool> = addrs.iter()
+      .filter(|a| pool.virt(*a ^ flip).is_some())
+      .take(BIT_SAMPLES)
+      .map(|a| conflicts(pool, *a, a ^ flip, threshold))
+      .collect();
+    if results.is_empty() {
+      warn!("No address pairs in the pool to classify bit {}", bit);
+      continue;
+    }
+    let is_row = results.iter().filter(|c| **c).count() * 2 > results.len();
+    match (bank_fn, is_row) {
+      (None, true) => row_fn |= 1 << bit,
+      (None, false) => column_fn |= 1 << bit,
+      (Some(f), true) => row_fn |= 1 << (63 - f.leading_zeros()),
+      (Some(f), false) => column_fn |= 1 << f.trailing_zeros()
+    }
+  }
+  (row_fn, column_fn)
+}
+
+//Reverse engineers the mapping functions of a system with arg:banks banks
+//(banks of all ranks)
+pub fn reverse_engineer(oracle: &impl PhysAddrOracle, banks: u32)
+  -> MappingFunctions
+{
+  assert!(banks.is_power_of_two());
+  //the highest physical address bit of the installed memory
+  let max_bit = host::get_system_ram_ranges().iter().map(|r| r.end)
+    .max().map_or(32, |end| 64 - (end - 1).leading_zeros());
+  info!("Reverse engineering the mapping functions for {} banks and {} \
+    address bits", banks, max_bit);
+
+  let pool = Pool::allocate(oracle);
+  let threshold = conflict_threshold(&pool, banks);
+  let sets = bank_sets(&pool, threshold, banks);
+  let bank_fns = find_bank_fns(&sets, max_bit, banks);
+  let (row_fn, column_fn) =
+    find_row_column_fns(&pool, &bank_fns, threshold, max_bit);
+
+  for (name, f) in [("row", row_fn), ("column", column_fn)] {
+    let shifted = f >> f.trailing_zeros();
+    if shifted & (shifted + 1) != 0 {
+      warn!("The {} bits 0x{:x} are not contiguous, the DRAM address \
+        translation will be wrong", name, f);
+    }
+  }
+  info!("Mapping functions: row 0x{:x}, column 0x{:x}, banks {}", row_fn,
+    column_fn, bank_fns.iter().map(|f| format!("0x{:x}", f))
+    .collect::<Vec<_>>().join(","));
+  MappingFunctions {row_fn, column_fn, bank_fns}
+}
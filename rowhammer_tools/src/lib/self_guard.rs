@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use log::*;
+use serde::Serialize;
+
+use crate::config::*;
+use crate::context::RhContext;
+use crate::utils::{dram, proc};
+
+//Guard against hammering the tool itself: the frames of the critical
+//mappings of this process (its code, i.e. all executable mappings, its stack
+//and its heap) are read from /proc/self/maps and the pagemap before
+//hammering, an aggressor within SELF_GUARD_ROWS rows of such a frame (in the
+//same bank) may flip bits in the running tool (crashes or silent corruption),
+//as may a victim row that contains such a frame
+//The allocated frames (see Frame2Map) are excluded, e.g., the victim pages of
+//the binary the loader maps executable on purpose
+//The JIT hammer code is mapped after the check, when it is compiled: the
+//checked aggressors and victims are kept in the context (`GuardedFrames`) and
+//the frames of every new code buffer are checked against them (see
+//`check_code`)
+//`RH_SELF_GUARD`: `warn` (default) logs the violations, `refuse` aborts
+//before hammering, `off` disables the check
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelfGuard {
+  Off,
+  #[default]
+  Warn,
+  Refuse
+}
+
+impl SelfGuard {
+  pub fn from_env() -> SelfGuard {
+    match env::var("RH_SELF_GUARD").as_deref() {
+      Err(_) | Ok("warn") => SelfGuard::Warn,
+      Ok("refuse") => SelfGuard::Refuse,
+      Ok("off") => SelfGuard::Off,
+      Ok(x) => panic!("Unknown RH_SELF_GUARD {}, expected warn, refuse or off",
+        x)
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct SelfGuardViolation {
+  //"aggressor" or "victim"
+  pub role: &'static str,
+  pub frame: u64,
+  //a frame of this process near arg:frame and its mapping
+  pub own_frame: u64,
+  pub mapping: String,
+  //row distance of the own frame to the frame (0 = same row)
+  pub distance: i64
+}
+
+//The aggressor and victim frames of the last `check` of a context
+//(`RhContext::guarded_frames`)
+#[derive(Default)]
+pub struct GuardedFrames(Mutex<Option<(Vec<u64>, Vec<u64>)>>);
+
+//Frames of the critical mappings of this process -> name of the mapping
+pub fn own_critical_frames(ctx: &RhContext) -> HashMap<u64, String> {
+  let pid = std::process::id();
+  let mut frames = HashMap::new();
+  for mapping in proc::get_maps(pid) {
+    //[vsyscall] is outside of the pagemap
+    let critical = (mapping.perms.contains('x')
+      && mapping.path != "[vsyscall]")
+      || mapping.path == "[stack]" || mapping.path == "[heap]";
+    if !critical {
+      continue;
+    }
+    let name = if mapping.path.is_empty() {"[anon exec]".to_owned()}
+      else {mapping.path.clone()};
+    for page in (mapping.start..mapping.end).step_by(ctx.geometry.page_size())
+    {
+      //pages that are not present cannot be flipped while hammering
+      if let Some(phys_addr) = proc::pid_virt_to_phys(pid, page) {
+        frames.insert(phys_addr & ctx.geometry.page_align_mask(),
+          name.clone());
+      }
+    }
+  }
+  frames
+}
+
+//Own frames (arg:own_frames, see `own_critical_frames`) within
+//SELF_GUARD_ROWS rows of arg:aggressors or in the rows of arg:victims,
+//without the frames in arg:frame2map
+pub fn violations(
+  ctx: &RhContext, own_frames: &HashMap<u64, String>,
+  aggressors: impl Iterator<Item = u64>, victims: impl Iterator<Item = u64>,
+  frame2map: &Frame2Map
+) -> Vec<SelfGuardViolation> {
+  let dram_config = ctx.dram_config();
+  //(bank, row) -> own frames
+  let mut own_rows: HashMap<(u64, u64), Vec<u64>> = HashMap::new();
+  for frame in own_frames.keys().filter(|f| !frame2map.contains_key(f)) {
+    let dram_addr = dram::phys_to_dram(*frame, dram_config);
+    own_rows.entry((dram_addr.bank, dram_addr.row)).or_default().push(*frame);
+  }
+
+  let mut violations = Vec::new();
+  let mut check = |role, frame, distances: &[i64]| {
+    let dram_addr = dram::phys_to_dram(frame, dram_config);
+    for distance in distances {
+      let row = dram_addr.row as i64 + distance;
+      //one violation per row suffices
+      if let Some(own) = own_rows.get(&(dram_addr.bank, row as u64))
+        .and_then(|frames| frames.iter().min())
+      {
+        violations.push(SelfGuardViolation {
+          role,
+          frame,
+          own_frame: *own,
+          mapping: own_frames[own].clone(),
+          distance: *distance
+        });
+      }
+    }
+  };
+  let aggr_distances: Vec<i64> = (-SELF_GUARD_ROWS..=SELF_GUARD_ROWS)
+    .filter(|d| *d != 0).collect();
+  let mut aggressors: Vec<u64> = aggressors.collect();
+  aggressors.sort_unstable();
+  aggressors.dedup();
+  for frame in aggressors {
+    check("aggressor", frame, &aggr_distances);
+  }
+  let mut victims: Vec<u64> = victims.collect();
+  victims.sort_unstable();
+  victims.dedup();
+  for frame in victims {
+    check("victim", frame, &[0]);
+  }
+  violations
+}
+
+//Checks arg:aggressors and arg:victims against the critical frames of this
+//process according to `RH_SELF_GUARD` (see above), panics on a violation
+//with `refuse`
+pub fn check(
+  ctx: &RhContext, aggressors: impl Iterator<Item = u64>,
+  victims: impl Iterator<Item = u64>, frame2map: &Frame2Map
+) {
+  let guard = ctx.settings.self_guard;
+  if guard == SelfGuard::Off {
+    return;
+  }
+  let aggressors: Vec<u64> = aggressors.collect();
+  let victims: Vec<u64> = victims.collect();
+  let own_frames = own_critical_frames(ctx);
+  let violations = violations(ctx, &own_frames, aggressors.iter().cloned(),
+    victims.iter().cloned(), frame2map);
+  *ctx.guarded_frames.0.lock().unwrap() = Some((aggressors, victims));
+  report(guard, &violations, own_frames.len());
+}
+
+//Checks the frames of the JIT code in arg:code against the aggressors and
+//victims of the last `check` of arg:ctx (if any), like `check`
+pub fn check_code(ctx: &RhContext, code: &[u8]) {
+  let guard = ctx.settings.self_guard;
+  if guard == SelfGuard::Off {
+    return;
+  }
+  let guarded = ctx.guarded_frames.0.lock().unwrap();
+  let Some((aggressors, victims)) = guarded.as_ref() else {
+    return;
+  };
+  let pid = std::process::id();
+  let start = code.as_ptr() as u64 & ctx.geometry.page_align_mask();
+  let end = code.as_ptr() as u64 + code.len() as u64;
+  let own_frames: HashMap<u64, String> = (start..end)
+    .step_by(ctx.geometry.page_size())
+    .filter_map(|page| proc::pid_virt_to_phys(pid, page))
+    .map(|phys_addr| (phys_addr & ctx.geometry.page_align_mask(),
+      "[jit code]".to_owned()))
+    .collect();
+  let violations = violations(ctx, &own_frames, aggressors.iter().cloned(),
+    victims.iter().cloned(), &Frame2Map::new());
+  report(guard, &violations, own_frames.len());
+}
+
+//Logs arg:violations, panics with `refuse`
+fn report(
+  guard: SelfGuard, violations: &[SelfGuardViolation], own_frames: usize
+) {
+  for v in violations {
+    warn!("Hammering near a frame of this process: {}",
+      serde_json::to_string(v).unwrap());
+  }
+  if violations.is_empty() {
+    debug!("No aggressor is near one of the {} critical frames of this \
+      process", own_frames);
+  } else {
+    assert!(guard != SelfGuard::Refuse, "Refusing to hammer: {} aggressor or \
+      victim frames are near a frame of this process (RH_SELF_GUARD=refuse)",
+      violations.len());
+  }
+}
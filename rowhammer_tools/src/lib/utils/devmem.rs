@@ -1,4 +1,5 @@
-use std::fs::OpenOptions;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
 use std::io::{Seek, Write, Read};
 use log::info;
 
@@ -12,14 +13,18 @@ use crate::config::*;
 //    (https://github.com/misc0110/PTEditor)
 
 
+pub fn open() -> File {
+  OpenOptions::new()
+    .read(true).write(true).open("/dev/mem").expect("Could not open /dev/mem")
+}
+
 //Fakes the rowhammer effect by flipping bits using /dev/mem
 //This function does flip all victims at once
 pub fn hammer_fake(
   attack_config: &AttackConfig, frame2map: &Frame2Map
 ) {
   info!("Performing fake RowHammer with /dev/mem");
-  let mut devmem = OpenOptions::new()
-    .read(true).write(true).open("/dev/mem").expect("Could not open /dev/mem");
+  let mut devmem = open();
   //TODO add O_SYNC
   for victim_frame in &attack_config.victim_frames {
     let victim_addr = victim_frame.frame_addr;
@@ -32,25 +37,59 @@ pub fn hammer_fake(
       //flush the victim so the next access is done from memory
       unsafe {core::arch::x86_64::_mm_clflush(virt_victim as *mut u8);}
 
-      //read the original value
-      let mut value = [0; 1];
-      devmem.seek(std::io::SeekFrom::Start(phys_victim))
-        .expect("Failed to seek in pagemap");
-      devmem.read_exact(&mut value).unwrap();
-
-      //apply the bitflip
-      let mask: u8 = 1u8 << expected_flip.flip_index;
-      value[0] = if expected_flip.flip_direction == true {
-        value[0] | mask
-      } else {
-        value[0] & !mask
-      };
-
-      //write the change to memory
-      devmem.seek(std::io::SeekFrom::Start(phys_victim))
-        .expect("Failed to seek in pagemap");
-      devmem.write_all(&value).unwrap();
+      apply_bitflip(&mut devmem, phys_victim, expected_flip);
     }
   }
   // /dev/mem map drops
 }
+
+//Applies arg:bitflip to the byte at physical address arg:phys_addr through
+//arg:devmem (/dev/mem), returns the (original, new) value of the byte
+pub fn apply_bitflip(devmem: &mut File, phys_addr: u64, bitflip: &BitFlip)
+  -> (u8, u8)
+{
+  //read the original value
+  let mut value = [0; 1];
+  devmem.seek(std::io::SeekFrom::Start(phys_addr))
+    .expect("Failed to seek in /dev/mem");
+  devmem.read_exact(&mut value).unwrap();
+  let original = value[0];
+
+  //apply the bitflip
+  let mask: u8 = 1u8 << bitflip.flip_index;
+  value[0] = if bitflip.flip_direction {
+    value[0] | mask
+  } else {
+    value[0] & !mask
+  };
+
+  //write the change to memory
+  devmem.seek(std::io::SeekFrom::Start(phys_addr))
+    .expect("Failed to seek in /dev/mem");
+  devmem.write_all(&value).unwrap();
+  (original, value[0])
+}
+
+//Writes the bytes in arg:content (physical address -> value) with arg:devmem
+//(/dev/mem) and reads them back, returns the physical addresses that still
+//differ
+pub fn restore_bytes(mut devmem: &File, content: &HashMap<u64, u8>)
+  -> Vec<u64>
+{
+  info!("Restoring victim bytes with /dev/mem");
+  let mut failed = Vec::new();
+  for (&phys_addr, &value) in content {
+    devmem.seek(std::io::SeekFrom::Start(phys_addr))
+      .expect("Failed to seek in /dev/mem");
+    devmem.write_all(&[value]).unwrap();
+
+    let mut restored = [0; 1];
+    devmem.seek(std::io::SeekFrom::Start(phys_addr))
+      .expect("Failed to seek in /dev/mem");
+    devmem.read_exact(&mut restored).unwrap();
+    if restored[0] != value {
+      failed.push(phys_addr);
+    }
+  }
+  failed
+}
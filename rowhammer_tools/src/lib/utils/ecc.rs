@@ -0,0 +1,173 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use byteorder::{ByteOrder, LittleEndian};
+use log::*;
+
+//Memory error monitoring: hammering can cause uncorrectable ECC errors, which
+//can crash the machine when they hit kernel memory
+//The guard aborts the run as soon as new uncorrectable errors are reported by
+//EDAC (/sys/devices/system/edac) or the machine check log (/dev/mcelog)
+
+pub(crate) static EDAC_MC_PATH: &str = "/sys/devices/system/edac/mc";
+//ioctls of /dev/mcelog: _IOR('M', 1, int) and _IOR('M', 2, int)
+static MCE_GET_RECORD_LEN: u64 = 0x80044d01;
+static MCE_GET_LOG_LEN: u64 = 0x80044d02;
+//uncorrected error bit in the status (first field) of a machine check record
+static MCI_STATUS_UC: u64 = 1 << 61;
+//how often the guard polls for new errors
+static GUARD_INTERVAL: Duration = Duration::from_millis(100);
+
+//Sum of arg:counter (ue_count or ce_count) over all EDAC memory controllers
+//Returns None if EDAC is not available
+fn read_edac_count(counter: &str) -> Option<u64> {
+  let mut total = None;
+  for entry in fs::read_dir(EDAC_MC_PATH).ok()?.flatten() {
+    if !entry.file_name().to_string_lossy().starts_with("mc") {
+      continue;
+    }
+    if let Ok(count) = fs::read_to_string(entry.path().join(counter)) {
+      *total.get_or_insert(0) += count.trim().parse::<u64>().unwrap_or(0);
+    }
+  }
+  total
+}
+
+//uncorrectable errors reported by EDAC so far
+pub fn read_edac_ue_count() -> Option<u64> {
+  read_edac_count("ue_count")
+}
+
+//corrected errors reported by EDAC so far
+pub fn read_edac_ce_count() -> Option<u64> {
+  read_edac_count("ce_count")
+}
+
+//Reads (and consumes!) the machine check records of /dev/mcelog
+//Do not run this next to the mcelog daemon, it would miss the records
+pub struct McelogReader {
+  file: File,
+  record_len: usize,
+  log_len: usize
+}
+
+impl McelogReader {
+  pub fn open() -> io::Result<McelogReader> {
+    let file = OpenOptions::new().read(true)
+      .custom_flags(libc::O_NONBLOCK).open("/dev/mcelog")?;
+    let mut record_len: libc::c_int = 0;
+    let mut log_len: libc::c_int = 0;
+    unsafe {
+      if libc::ioctl(file.as_raw_fd(), MCE_GET_RECORD_LEN as _,
+          &mut record_len) < 0
+        || libc::ioctl(file.as_raw_fd(), MCE_GET_LOG_LEN as _, &mut log_len) < 0
+      {
+        return Err(io::Error::last_os_error());
+      }
+    }
+    Ok(McelogReader {
+      file, record_len: record_len as usize, log_len: log_len as usize
+    })
+  }
+
+  //Returns (corrected, uncorrected) number of new machine check records
+  pub fn read_new(&mut self) -> (usize, usize) {
+    //the whole log has to be read at once
+    let mut buf = vec![0u8; self.record_len * self.log_len];
+    let n = match self.file.read(&mut buf) {
+      Ok(n) => n,
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+      Err(e) => {
+        error!("Reading /dev/mcelog failed: {}", e);
+        0
+      }
+    };
+    let uncorrected = buf[..n].chunks_exact(self.record_len)
+      .filter(|r| LittleEndian::read_u64(&r[0..8]) & MCI_STATUS_UC != 0)
+      .count();
+    (n / self.record_len - uncorrected, uncorrected)
+  }
+}
+
+//Watchdog thread that sets an abort flag on new uncorrectable memory errors
+//The thread stops when the guard is dropped without `stop` (e.g., when a
+//panic unwinds)
+pub struct EccGuard {
+  stop: Arc<AtomicBool>,
+  triggered: Arc<AtomicBool>,
+  handle: Option<JoinHandle<()>>
+}
+
+impl EccGuard {
+  //Stops the watchdog, returns whether it aborted the run
+  pub fn stop(mut self) -> bool {
+    self.stop.store(true, Ordering::SeqCst);
+    self.handle.take().unwrap().join().expect("ECC guard thread panicked");
+    self.triggered.load(Ordering::SeqCst)
+  }
+}
+
+impl Drop for EccGuard {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::SeqCst);
+  }
+}
+
+//Starts a watchdog that sets arg:abort when new uncorrectable errors appear
+//Returns None if neither EDAC nor /dev/mcelog is available
+pub fn spawn_guard(abort: Arc<AtomicBool>) -> Option<EccGuard> {
+  let ue_start = read_edac_ue_count();
+  let mut ce_last = read_edac_ce_count().unwrap_or(0);
+  let mut mcelog = McelogReader::open().ok();
+  if ue_start.is_none() && mcelog.is_none() {
+    warn!("Neither EDAC nor /dev/mcelog is available, memory errors are NOT \
+      monitored");
+    return None;
+  }
+  info!("Monitoring memory errors (EDAC: {}, mcelog: {})",
+    ue_start.is_some(), mcelog.is_some());
+  //discard old machine check records
+  if let Some(m) = mcelog.as_mut() {
+    m.read_new();
+  }
+
+  let stop = Arc::new(AtomicBool::new(false));
+  let triggered = Arc::new(AtomicBool::new(false));
+  let (s, t) = (stop.clone(), triggered.clone());
+  let handle = std::thread::spawn(move || {
+    while !s.load(Ordering::SeqCst) {
+      let mut uncorrected = 0;
+      if let (Some(start), Some(now)) = (ue_start, read_edac_ue_count()) {
+        uncorrected += now.saturating_sub(start) as usize;
+      }
+      if let Some(ce) = read_edac_ce_count() {
+        if ce > ce_last {
+          warn!("{} new corrected memory errors (EDAC)", ce - ce_last);
+          ce_last = ce;
+        }
+      }
+      if let Some(m) = mcelog.as_mut() {
+        let (c, u) = m.read_new();
+        if c > 0 {
+          warn!("{} new corrected machine check records", c);
+        }
+        uncorrected += u;
+      }
+
+      if uncorrected > 0 && !t.load(Ordering::SeqCst) {
+        error!("{} uncorrectable memory errors detected, aborting",
+          uncorrected);
+        t.store(true, Ordering::SeqCst);
+        abort.store(true, Ordering::SeqCst);
+      }
+      std::thread::sleep(GUARD_INTERVAL);
+    }
+  });
+
+  Some(EccGuard {stop, triggered, handle: Some(handle)})
+}
@@ -1,10 +1,11 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::str::FromStr;
-use std::fs;
+use std::{env, fs};
 use log::*;
 
 use crate::config::*;
-use crate::utils::{self, serialize::*};
+use crate::utils::{self, msr, ram_swap, sched, serialize::*, workload};
 
 
 pub fn get_motherboard_id() -> String {
@@ -29,9 +30,9 @@ pub fn get_cpu_model() -> String {
 }
 
 pub fn check_turbo_boost_disabled() -> bool {
-  let stdout = utils::run(&["rdmsr", "0x1a0", "-f", "38:38"]);
-  let t = u32::from_str(stdout.trim()).expect("rdmsr turbo boost value NaN");
-  assert!(t == 0 || t == 1);
+  //turbo disable bit in IA32_MISC_ENABLE (same as `rdmsr 0x1a0 -f 38:38`)
+  let t = msr::read_msr_field(0, msr::IA32_MISC_ENABLE, 38, 38)
+    .unwrap_or_else(|e| panic!("Reading turbo boost state failed: {}", e));
   let turbo_disabled = t == 1;
   if !turbo_disabled {
     warn!("Turbo boost is NOT disabled! Enter anything to continue");
@@ -73,6 +74,15 @@ pub fn get_isolated_cpu_cores() -> HashSet<u32> {
   parse_sys_cpu_format(&isolated)
 }
 
+pub fn get_nohz_full_cpu_cores() -> HashSet<u32> {
+  //get all adaptive-tick cores from /sys/devices/system/cpu/nohz_full
+  //(missing if the kernel is built without CONFIG_NO_HZ_FULL)
+  match fs::read_to_string("/sys/devices/system/cpu/nohz_full") {
+    Ok(nohz_full) => parse_sys_cpu_format(&nohz_full),
+    Err(_) => HashSet::new()
+  }
+}
+
 pub fn get_all_cpu_cores() -> HashSet<u32> {
   //get all isolated cores from /sys/devices/system/cpu/isolated
   let online = fs::read_to_string("/sys/devices/system/cpu/online").unwrap();
@@ -81,22 +91,34 @@ pub fn get_all_cpu_cores() -> HashSet<u32> {
 
 fn parse_sys_cpu_format(sys_cpu: &str) -> HashSet<u32> {
   //format example: 1,5-8
+  //"(null)" is printed for an empty nohz_full mask
+  if sys_cpu.trim() == "(null)" {
+    return HashSet::new();
+  }
   sys_cpu.trim().split(",").map(|x| {
     match &x.split("-").collect::<Vec<_>>()[..] {
       [c] if c.is_empty() =>
         Box::new(std::iter::empty()) as Box<dyn Iterator<Item = u32>>,
       [c] => Box::new(std::iter::once(u32::from_str(c)
-        .expect("isolated core id NaN"))) as Box<dyn Iterator<Item = u32>>,
+        .expect("core id NaN"))) as Box<dyn Iterator<Item = u32>>,
       [c1, c2] => {
-        let i1 = u32::from_str(c1).expect("isolated core id NaN");
-        let i2 = u32::from_str(c2).expect("isolated core id NaN");
-        Box::new(i1..i2) as Box<dyn Iterator<Item = u32>>
+        let i1 = u32::from_str(c1).expect("core id NaN");
+        let i2 = u32::from_str(c2).expect("core id NaN");
+        //ranges are inclusive
+        Box::new(i1..=i2) as Box<dyn Iterator<Item = u32>>
       },
-      _ => panic!("Cannot parse contents of /sys/devices/system/cpu/isolated")
+      _ => panic!("Cannot parse cpu list `{}`", sys_cpu)
     }
   }).flatten().collect()
 }
 
+//The other hyperthread of arg:core, None without SMT
+pub fn get_smt_sibling(core: u32) -> Option<u32> {
+  let siblings = fs::read_to_string(format!(
+    "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list", core)).ok()?;
+  parse_sys_cpu_format(&siblings).into_iter().filter(|c| *c != core).min()
+}
+
 pub fn check_cpu_cores_isolated(cores_info: &mut Vec<(u32, Vec<String>)>) {
   let isolated_cores = get_isolated_cpu_cores();
   cores_info.iter_mut().for_each(|(core, info)| {
@@ -111,6 +133,20 @@ pub fn check_cpu_cores_isolated(cores_info: &mut Vec<(u32, Vec<String>)>) {
   });
 }
 
+pub fn check_cpu_cores_nohz_full(cores_info: &mut Vec<(u32, Vec<String>)>) {
+  let nohz_full_cores = get_nohz_full_cpu_cores();
+  cores_info.iter_mut().for_each(|(core, info)| {
+    //the scheduler tick is only stopped on nohz_full cores (if a single task
+    //runs), otherwise timer interrupts periodically preempt the hammering
+    let is_nohz_full = nohz_full_cores.contains(core);
+    if !is_nohz_full {
+      warn!("Core {} is NOT nohz_full, expect timer interrupts while \
+        hammering", core);
+    }
+    info.push(format!("is_nohz_full: {}", is_nohz_full));
+  });
+}
+
 pub fn check_cpu_scaling_governer(cores_info: &mut Vec<(u32, Vec<String>)>) {
   cores_info.iter_mut().for_each(|(core, info)| {
     //check scaling governor of assigned cores
@@ -147,6 +183,160 @@ pub fn check_cpu_freq_maxed(cores_info: &mut Vec<(u32, Vec<String>)>) {
   });
 }
 
+pub fn check_prefetchers(cores_info: &mut Vec<(u32, Vec<String>)>) {
+  cores_info.iter_mut().for_each(|(core, info)| {
+    //report the hardware prefetcher state of assigned cores, prefetching
+    //perturbs the aggressor access stream
+    match msr::read_prefetcher_state(*core) {
+      Ok(enabled) => {
+        let state = msr::PREFETCHER_NAMES.iter().zip(enabled.iter())
+          .map(|(n, e)| format!("{}: {}", n, if *e {"on"} else {"off"}))
+          .collect::<Vec<_>>().join(", ");
+        info!("Prefetchers of core {}: {}", core, state);
+        info.push(format!("prefetchers: {}", state));
+      },
+      Err(e) => {
+        warn!("Could not read prefetcher state of core {}: {}", core, e);
+        info.push("prefetchers: unknown".to_owned());
+      }
+    }
+  });
+}
+
+//Core types of all cores of a CPU with a hybrid core topology (e.g., Alder
+//Lake), None if all cores have the same type
+//The kernel registers a separate PMU for every core type, cpu_core for the
+//P-cores and cpu_atom for the E-cores, with the cores of that type
+pub fn get_core_types() -> Option<HashMap<u32, CoreType>> {
+  let cores = |pmu: &str| fs::read_to_string(
+    format!("/sys/devices/{}/cpus", pmu)).ok()
+    .map(|cpus| parse_sys_cpu_format(&cpus));
+  let (p_cores, e_cores) = (cores("cpu_core")?, cores("cpu_atom")?);
+  Some(p_cores.into_iter().map(|c| (c, CoreType::Performance))
+    .chain(e_cores.into_iter().map(|c| (c, CoreType::Efficiency)))
+    .collect())
+}
+
+//Core type the hammering has to run on with a hybrid core topology, set with
+//the `RH_CORE_TYPE=<p|e|any>` environment variable (default: p), None for any
+pub fn requested_core_type() -> Option<CoreType> {
+  match env::var("RH_CORE_TYPE").as_deref() {
+    Err(_) | Ok("p") => Some(CoreType::Performance),
+    Ok("e") => Some(CoreType::Efficiency),
+    Ok("any") => None,
+    Ok(t) => panic!("Unknown RH_CORE_TYPE {}, expected p, e or any", t)
+  }
+}
+
+//Restricts this process to the assigned cores of the requested core type
+//(see `requested_core_type`), call it before any thread is created, it is a
+//no-op without a hybrid core topology
+//Fails if none of the assigned cores has that type: the hammering never
+//silently moves to the other type
+pub fn restrict_to_core_type() {
+  let (Some(types), Some(core_type)) = (get_core_types(),
+    requested_core_type()) else {
+    return;
+  };
+  let assigned = get_assigned_cpu_cores();
+  let mut cores: Vec<u32> = assigned.iter().cloned()
+    .filter(|c| types.get(c) == Some(&core_type)).collect();
+  cores.sort();
+  assert!(!cores.is_empty(), "None of the assigned cores {:?} is a {}, assign \
+    one with taskset or set RH_CORE_TYPE", assigned, core_type.name());
+  if cores.len() < assigned.len() {
+    sched::pin_to_cores(&cores);
+  }
+  info!("Hybrid core topology: hammering on the {}s {:?}", core_type.name(),
+    cores);
+}
+
+//Type of the assigned cores, None without a hybrid core topology or if they
+//have different types
+pub fn get_assigned_core_type() -> Option<CoreType> {
+  let types = get_core_types()?;
+  let assigned: HashSet<Option<&CoreType>> = get_assigned_cpu_cores().iter()
+    .map(|c| types.get(c)).collect();
+  match assigned.into_iter().collect::<Vec<_>>()[..] {
+    [Some(core_type)] => Some(*core_type),
+    _ => None
+  }
+}
+
+pub fn check_core_types(cores_info: &mut [(u32, Vec<String>)]) {
+  let Some(types) = get_core_types() else {
+    return;
+  };
+  cores_info.iter_mut().for_each(|(core, info)| {
+    let name = types.get(core).map_or("unknown", |t| t.name());
+    info.push(format!("core type: {}", name));
+  });
+}
+
+//Whether the L2 prefetchers should be disabled during hammering, set with the
+//`RH_DISABLE_PREFETCH` environment variable
+pub fn prefetch_disable_requested() -> bool {
+  env::var("RH_DISABLE_PREFETCH").is_ok()
+}
+
+//Disables the L2 prefetchers on the assigned cores if requested, until the
+//returned guard is dropped (it restores nothing if nothing was changed)
+pub fn disable_prefetchers_if_requested() -> msr::PrefetcherGuard {
+  if !prefetch_disable_requested() {
+    return msr::PrefetcherGuard::default();
+  }
+  msr::disable_l2_prefetchers(&get_assigned_cpu_cores())
+}
+
+//SCHED_FIFO priority (1-99) of the hammering thread, set with the
+//`RH_SCHED_FIFO` environment variable
+pub fn requested_sched_fifo_priority() -> Option<i32> {
+  env::var("RH_SCHED_FIFO").ok().map(|p| {
+    let priority = p.parse().expect("RH_SCHED_FIFO is NaN");
+    assert!((1..=99).contains(&priority),
+      "RH_SCHED_FIFO must be in 1..=99");
+    priority
+  })
+}
+
+//Timer slack in ns of the hammering thread, set with the `RH_TIMERSLACK`
+//environment variable
+pub fn requested_timerslack() -> Option<u64> {
+  env::var("RH_TIMERSLACK").ok()
+    .map(|t| t.parse().expect("RH_TIMERSLACK is NaN"))
+}
+
+//Applies the requested scheduling settings to the calling thread until the
+//returned guard is dropped (None if nothing was changed)
+pub fn shield_if_requested() -> Option<sched::SchedGuard> {
+  let priority = requested_sched_fifo_priority();
+  let timerslack = requested_timerslack();
+  if priority.is_none() && timerslack.is_none() {
+    return None;
+  }
+  let saved = sched::SchedGuard::save();
+  if let Some(priority) = priority {
+    sched::set_fifo(priority);
+  }
+  if let Some(timerslack) = timerslack {
+    sched::set_timerslack(timerslack);
+  }
+  Some(saved)
+}
+
+//Physical address ranges [start, end) of the top level "System RAM" entries
+//in /proc/iomem, the addresses are all zero without root privileges
+pub fn get_system_ram_ranges() -> Vec<Range<u64>> {
+  let iomem = fs::read_to_string("/proc/iomem").expect("Reading iomem failed");
+  utils::regex(&iomem,
+    r"(?m)^(?P<start>[0-9a-f]+)-(?P<end>[0-9a-f]+) : System RAM$")
+    .iter().map(|c| {
+      let start = u64::from_str_radix(&c["start"], 16).unwrap();
+      let end = u64::from_str_radix(&c["end"], 16).unwrap();
+      start..end + 1
+    }).collect()
+}
+
 pub fn get_os_info() -> (String, String) {
   //read os-release
   let os = fs::read_to_string("/etc/os-release").unwrap();
@@ -162,10 +352,9 @@ pub fn get_os_info() -> (String, String) {
   (os_release, kernel)
 }
 
+//the id set before by the tool (see ram_swap::ensure_or_ask)
 pub fn get_ram_swap_id() -> u32 {
-  fs::read_to_string(RAM_SWAP_ID_FILE)
-    .expect(&format!("RAM_SWAP_ID_FILE: {} not found", RAM_SWAP_ID_FILE))
-    .trim().parse().unwrap()
+  ram_swap::ensure()
 }
 
 pub fn read_config() -> HostConfig {
@@ -178,8 +367,11 @@ pub fn read_config() -> HostConfig {
   let mut cores_info =
     get_assigned_cpu_cores().iter().map(|x| (*x, Vec::new())).collect();
   check_cpu_cores_isolated(&mut cores_info);
+  check_cpu_cores_nohz_full(&mut cores_info);
   check_cpu_scaling_governer(&mut cores_info);
   check_cpu_freq_maxed(&mut cores_info);
+  check_prefetchers(&mut cores_info);
+  check_core_types(&mut cores_info);
   let (os_release, kernel) = get_os_info();
   let ram_swap_id = get_ram_swap_id();
 
@@ -189,6 +381,12 @@ pub fn read_config() -> HostConfig {
     cpu_model,
     smt_disabled,
     turbo_disabled,
+    prefetchers_disabled: prefetch_disable_requested(),
+    sched_fifo_priority: requested_sched_fifo_priority(),
+    timerslack_ns: requested_timerslack(),
+    victim_workload: workload::requested()
+      .map(|(access, interval)| format!("{:?} every {:?}", access, interval)),
+    core_type: get_assigned_core_type(),
     cpu_ids: cores_info.iter().map(|(c, i)| (*c, i.join(" ; "))).collect(),
     git_hash: GIT_HASH.to_owned(),
     ram_swap_id,
@@ -0,0 +1,109 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use log::*;
+
+use crate::utils;
+
+//Kernel log and system load monitoring, kernel-level events (machine checks,
+//EDAC reports, the OOM killer, ...) during an experiment make its results
+//hard to interpret and otherwise go unnoticed
+
+//kernel messages matching this regex are reported (case insensitive)
+pub static INTERESTING_KMSG: &str = concat!(r"(?i)(mce|machine check|edac|",
+  r"hardware error|oom|out of memory|killed process)");
+
+//Reads the records that are appended to the kernel log (/dev/kmsg)
+pub struct KmsgReader {
+  file: File
+}
+
+impl KmsgReader {
+  //Only messages that are logged after opening are returned
+  pub fn open() -> io::Result<KmsgReader> {
+    let mut file = OpenOptions::new().read(true)
+      .custom_flags(libc::O_NONBLOCK).open("/dev/kmsg")?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(KmsgReader {file})
+  }
+
+  //Returns the text of all new kernel log records
+  pub fn read_new(&mut self) -> Vec<String> {
+    let mut messages = Vec::new();
+    //each read returns exactly one record: "prio,seq,time,flags;text\n..."
+    let mut buf = [0u8; 8192];
+    loop {
+      match self.file.read(&mut buf) {
+        Ok(0) => break,
+        Ok(n) => {
+          let record = String::from_utf8_lossy(&buf[..n]);
+          let text = record.split_once(';').map_or(&*record, |(_, t)| t);
+          messages.push(text.lines().next().unwrap_or("").to_owned());
+        },
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        //the reader was overrun, records were lost, continue with the next
+        Err(e) if e.raw_os_error() == Some(libc::EPIPE) => {
+          warn!("Kernel log records were lost");
+        },
+        Err(e) => {
+          error!("Reading /dev/kmsg failed: {}", e);
+          break;
+        }
+      }
+    }
+    messages
+  }
+
+  //Returns the new kernel log records that match INTERESTING_KMSG
+  pub fn read_new_interesting(&mut self) -> Vec<String> {
+    self.read_new().into_iter()
+      .filter(|m| !utils::regex(m, INTERESTING_KMSG).is_empty())
+      .collect()
+  }
+}
+
+//Contents of /proc/loadavg, e.g. "0.12 0.08 0.05 1/123 4567"
+pub fn read_loadavg() -> String {
+  fs::read_to_string("/proc/loadavg")
+    .map(|x| x.trim().to_owned())
+    .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+//Memory pressure stall information from /proc/pressure/memory (requires a
+//kernel with CONFIG_PSI), lines are joined with " ; "
+pub fn read_memory_pressure() -> String {
+  fs::read_to_string("/proc/pressure/memory")
+    .map(|x| x.lines().collect::<Vec<_>>().join(" ; "))
+    .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+//All temperature sensors in /sys/class/hwmon as ("<chip> <label>", degrees
+//Celsius), e.g. ("coretemp Package id 0", 45.0), empty without hwmon
+pub fn read_temperatures() -> Vec<(String, f64)> {
+  let mut temperatures = Vec::new();
+  let chips = match fs::read_dir("/sys/class/hwmon") {
+    Ok(chips) => chips,
+    Err(_) => return temperatures
+  };
+  for chip in chips.flatten().map(|c| c.path()) {
+    let name = fs::read_to_string(chip.join("name"))
+      .map_or("unknown".to_owned(), |x| x.trim().to_owned());
+    for input in fs::read_dir(&chip).into_iter().flatten().flatten() {
+      let file_name = input.file_name().to_string_lossy().into_owned();
+      let sensor = match file_name.strip_suffix("_input") {
+        Some(sensor) if sensor.starts_with("temp") => sensor.to_owned(),
+        _ => continue
+      };
+      let millidegrees: Option<f64> = fs::read_to_string(input.path()).ok()
+        .and_then(|x| x.trim().parse().ok());
+      let label = fs::read_to_string(chip.join(format!("{}_label", sensor)))
+        .map_or(sensor, |x| x.trim().to_owned());
+      if let Some(millidegrees) = millidegrees {
+        temperatures.push((format!("{} {}", name, label),
+          millidegrees / 1000.0));
+      }
+    }
+  }
+  temperatures.sort_by(|a, b| a.0.cmp(&b.0));
+  temperatures
+}
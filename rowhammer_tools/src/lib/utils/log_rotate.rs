@@ -0,0 +1,70 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use chrono::prelude::*;
+
+//Log target of long-running modes (e.g., the soak mode of the templater): the
+//log lines go to stderr and to <dir>/<prefix>_<date>.log, a new file is
+//started every day (local time) and only the newest `keep` files are kept, so
+//week-long runs do not fill the disk
+//Pass it to the logger with `env_logger::Target::Pipe` before `init()`
+
+pub struct RotatingLog {
+  dir: PathBuf,
+  prefix: String,
+  keep: usize,
+  //date of the open file
+  date: String,
+  file: Option<File>
+}
+
+impl RotatingLog {
+  pub fn new(dir: &str, prefix: &str, keep: usize) -> RotatingLog {
+    fs::create_dir_all(dir)
+      .unwrap_or_else(|_| panic!("Creating the log directory {} failed", dir));
+    RotatingLog {dir: dir.into(), prefix: prefix.to_owned(),
+      keep: keep.max(1), date: String::new(), file: None}
+  }
+
+  //whether arg:name is one of the log files
+  fn is_log(&self, name: &str) -> bool {
+    name.starts_with(&format!("{}_", self.prefix)) && name.ends_with(".log")
+  }
+
+  //Opens the file of today if the date changed and removes the oldest files
+  fn rotate(&mut self) -> io::Result<()> {
+    let date = Local::now().format("%Y-%m-%d").to_string();
+    if self.file.is_some() && date == self.date {
+      return Ok(());
+    }
+    let path = self.dir.join(format!("{}_{}.log", self.prefix, date));
+    self.file = Some(OpenOptions::new().create(true).append(true)
+      .open(path)?);
+    self.date = date;
+    //the dates sort chronologically
+    let mut logs: Vec<PathBuf> = fs::read_dir(&self.dir)?
+      .filter_map(|e| e.ok()).map(|e| e.path())
+      .filter(|p| p.file_name().and_then(|n| n.to_str())
+      .is_some_and(|n| self.is_log(n)))
+      .collect();
+    logs.sort();
+    for old in &logs[..logs.len().saturating_sub(self.keep)] {
+      fs::remove_file(old)?;
+    }
+    Ok(())
+  }
+}
+
+impl Write for RotatingLog {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    io::stderr().write_all(buf)?;
+    self.rotate()?;
+    self.file.as_mut().unwrap().write_all(buf)?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    io::stderr().flush()?;
+    self.file.as_mut().map_or(Ok(()), |f| f.flush())
+  }
+}
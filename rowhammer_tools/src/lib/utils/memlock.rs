@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use nix::sys::mman;
+use colored::Colorize;
+use log::*;
+
+use crate::config::*;
+
+//Locks the pages of the process in physical memory (= prevent swapping, the
+//frames of the allocated pages must not change)
+//mlockall fails when RLIMIT_MEMLOCK is too low, even for root in containers
+//(without CAP_IPC_LOCK), then the limit is raised if possible, otherwise only
+//the pages we care about (the pages in Frame2Map) are locked one by one with
+//`lock_page`
+
+//`lock_all` runs in `configure`, before the logger is initialized, so it
+//reports on stderr
+
+//whether mlockall failed and the pages have to be locked with `lock_page`
+static PER_PAGE: AtomicBool = AtomicBool::new(false);
+
+fn get_memlock_limit() -> libc::rlimit {
+  let mut limit = libc::rlimit {rlim_cur: 0, rlim_max: 0};
+  assert!(unsafe {libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit)} == 0,
+    "Reading RLIMIT_MEMLOCK failed");
+  limit
+}
+
+fn set_memlock_limit(cur: libc::rlim_t, max: libc::rlim_t) -> bool {
+  let limit = libc::rlimit {rlim_cur: cur, rlim_max: max};
+  unsafe {libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) == 0}
+}
+
+fn format_limit(limit: libc::rlim_t) -> String {
+  if limit == libc::RLIM_INFINITY {
+    "unlimited".to_owned()
+  } else {
+    format!("{}kB", limit >> 10)
+  }
+}
+
+fn report(message: String) {
+  if super::stealth::enabled() {
+    return;
+  }
+  eprintln!("{}", format!("WARNING memlock: {}", message).yellow());
+}
+
+pub fn lock_all() {
+  let flags = mman::MlockAllFlags::all();
+  if mman::mlockall(flags).is_ok() {
+    return;
+  }
+  let limit = get_memlock_limit();
+  report(format!("mlockall failed with RLIMIT_MEMLOCK {} (max {}), raising \
+    the limit", format_limit(limit.rlim_cur), format_limit(limit.rlim_max)));
+
+  //needs CAP_SYS_RESOURCE
+  if set_memlock_limit(libc::RLIM_INFINITY, libc::RLIM_INFINITY)
+    && mman::mlockall(flags).is_ok()
+  {
+    report("Raised RLIMIT_MEMLOCK to unlimited".to_owned());
+    return;
+  }
+  //the soft limit can always be raised to the hard limit
+  if limit.rlim_cur < limit.rlim_max
+    && set_memlock_limit(limit.rlim_max, limit.rlim_max)
+    && mman::mlockall(flags).is_ok()
+  {
+    report(format!("Raised RLIMIT_MEMLOCK to {}",
+      format_limit(limit.rlim_max)));
+    return;
+  }
+
+  report(format!("mlockall failed, only the victim and aggressor pages are \
+    locked (RLIMIT_MEMLOCK {}), the other pages of this process can be \
+    swapped", format_limit(get_memlock_limit().rlim_cur)));
+  PER_PAGE.store(true, Ordering::Relaxed);
+}
+
+//Locks the page at arg:page_addr if mlockall failed (see `lock_all`)
+pub fn lock_page(page_addr: *const u8) {
+  if !PER_PAGE.load(Ordering::Relaxed) {
+    return;
+  }
+  if let Err(e) = unsafe {
+    mman::mlock(page_addr as *const libc::c_void, *PAGE_SIZE)
+  } {
+    warn!("Locking page V0x{:x} failed: {}", page_addr as u64, e);
+  }
+}
@@ -1,10 +1,31 @@
-pub mod proc;
-pub mod serialize;
-pub mod dram;
-pub mod files;
-pub mod host;
-pub mod garbage;
-pub mod devmem;
+//the core modules at their old paths (see core/mod.rs)
+pub use crate::core::{dram, files, serialize};
+
+//the system layer, only with the `system` feature
+#[cfg(feature = "system")] pub mod proc;
+#[cfg(feature = "system")] pub mod host;
+#[cfg(feature = "system")] pub mod garbage;
+#[cfg(feature = "system")] pub mod devmem;
+#[cfg(feature = "system")] pub mod msr;
+#[cfg(feature = "system")] pub mod oracle;
+#[cfg(feature = "system")] pub mod sched;
+#[cfg(feature = "system")] pub mod kmsg;
+#[cfg(feature = "system")] pub mod ecc;
+#[cfg(feature = "system")] pub mod pressure;
+#[cfg(feature = "system")] pub mod victim_ledger;
+#[cfg(feature = "system")] pub mod row_wear;
+#[cfg(feature = "system")] pub mod workload;
+#[cfg(feature = "system")] pub mod symbols;
+#[cfg(feature = "system")] pub mod timeline;
+#[cfg(feature = "system")] pub mod resources;
+#[cfg(feature = "system")] pub mod tool_lock;
+#[cfg(feature = "system")] pub mod ram_swap;
+#[cfg(feature = "system")] pub mod memlock;
+#[cfg(feature = "system")] pub mod log_rotate;
+#[cfg(feature = "system")] pub mod redact;
+#[cfg(feature = "system")] pub mod report;
+#[cfg(feature = "system")] pub mod stealth;
+#[cfg(feature = "system")] pub mod perf;
 
 use std::process::Command;
 use regex::{Regex, Captures};
@@ -19,20 +40,26 @@ use crate::config::*;
 //programs
 //We do not consider this situation in this proof-of-concept implementation
 
-//Clear page cache
-pub fn clear_page_cache() {
+//Clear page cache (skipped in stealth mode, it runs `su`)
+//Returns whether the page cache was cleared
+#[cfg(feature = "system")]
+pub fn clear_page_cache() -> bool {
+  if stealth::enabled() {
+    return false;
+  }
   info!("Clearing the page cache");
   let mut cmd = Command::new("su");
   cmd.args(&["-c", "sync && echo 1 > /proc/sys/vm/drop_caches"]);
   let status = cmd.status()
                   .expect("Clearing the page cache failed with IO error");
   assert!(status.success(), "Clearing the page cache failed");
+  true
 }
 
 pub fn get_frames_in_row(row_phys_addr: u64) -> Vec<u64> {
-  let row_start = row_phys_addr & ROW_ALIGN_MASK;
-  let row_end = row_start + ROW_SIZE;
-  (row_start..row_end).step_by(PAGE_SIZE).collect()
+  let row_start = row_phys_addr & *ROW_ALIGN_MASK;
+  let row_end = row_start + *ROW_SIZE;
+  (row_start..row_end).step_by(*PAGE_SIZE).collect()
 }
 
 //arg::command = [command, arg1, arg2, ...]
@@ -56,4 +83,3 @@ pub fn regex<'t>(text: &'t str, regex: &str) -> Vec<Captures<'t>> {
   let reg = Regex::new(regex).expect(&format!("Regex `{}` invalid", regex));
   reg.captures_iter(text).collect()
 }
-
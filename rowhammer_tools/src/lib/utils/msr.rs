@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, Read, Write};
+use std::path::Path;
+use byteorder::{ByteOrder, LittleEndian};
+use log::*;
+
+//Access to model specific registers via the /dev/cpu/<core>/msr interface
+//This requires the `msr` kernel module to be loaded (`modprobe msr`)
+
+//contains the turbo boost disable bit (bit 38)
+pub static IA32_MISC_ENABLE: u64 = 0x1a0;
+//hardware prefetcher control (Intel), a set bit disables the prefetcher:
+//  bit 0: L2 hardware prefetcher
+//  bit 1: L2 adjacent cache line prefetcher
+//  bit 2: DCU (L1 data) prefetcher
+//  bit 3: DCU IP prefetcher
+pub static MSR_MISC_FEATURE_CONTROL: u64 = 0x1a4;
+pub static PREFETCHER_NAMES: [&str; 4] =
+  ["L2 hw", "L2 adjacent line", "DCU", "DCU IP"];
+//L2 hardware and L2 adjacent cache line prefetcher bits
+pub static L2_PREFETCHERS_MASK: u64 = 0b11;
+
+fn msr_path(core: u32) -> String {
+  format!("/dev/cpu/{}/msr", core)
+}
+
+fn open_msr(core: u32, write: bool) -> io::Result<File> {
+  let path = msr_path(core);
+  if !Path::new(&path).exists() {
+    return Err(io::Error::new(io::ErrorKind::NotFound,
+      format!("{} not found, is the msr kernel module loaded? \
+        (`modprobe msr`)", path)));
+  }
+  OpenOptions::new().read(true).write(write).open(&path)
+    .map_err(|e| io::Error::new(e.kind(),
+      format!("Could not open {}: {}", path, e)))
+}
+
+//Read the 64 bit value of arg:msr on arg:core
+pub fn read_msr(core: u32, msr: u64) -> io::Result<u64> {
+  let mut file = open_msr(core, false)?;
+  //the offset in the file selects the msr
+  file.seek(std::io::SeekFrom::Start(msr))?;
+  let mut buf: [u8; 8] = [0; 8];
+  file.read_exact(&mut buf).map_err(|e| io::Error::new(e.kind(),
+    format!("Could not read msr 0x{:x} on core {}: {}", msr, core, e)))?;
+  Ok(LittleEndian::read_u64(&buf))
+}
+
+//Write arg:value to arg:msr on arg:core
+pub fn write_msr(core: u32, msr: u64, value: u64) -> io::Result<()> {
+  let mut file = open_msr(core, true)?;
+  file.seek(std::io::SeekFrom::Start(msr))?;
+  let mut buf: [u8; 8] = [0; 8];
+  LittleEndian::write_u64(&mut buf, value);
+  file.write_all(&buf).map_err(|e| io::Error::new(e.kind(),
+    format!("Could not write msr 0x{:x} on core {}: {}", msr, core, e)))
+}
+
+//Read bits arg:high..=arg:low of arg:msr on arg:core (like `rdmsr -f`)
+pub fn read_msr_field(core: u32, msr: u64, high: u32, low: u32)
+  -> io::Result<u64>
+{
+  assert!(high >= low && high < 64, "Invalid msr field {}:{}", high, low);
+  let value = read_msr(core, msr)?;
+  let width = high - low + 1;
+  let mask = if width == 64 {u64::MAX} else {(1 << width) - 1};
+  Ok((value >> low) & mask)
+}
+
+//Returns for each prefetcher in PREFETCHER_NAMES whether it is enabled
+pub fn read_prefetcher_state(core: u32) -> io::Result<[bool; 4]> {
+  let value = read_msr(core, MSR_MISC_FEATURE_CONTROL)?;
+  let mut enabled = [false; 4];
+  for (i, e) in enabled.iter_mut().enumerate() {
+    *e = value & (1 << i) == 0;
+  }
+  Ok(enabled)
+}
+
+//Restores the original msr values of the cores whose prefetchers were
+//disabled when it is dropped, also when the tool panics while hammering
+#[derive(Default)]
+pub struct PrefetcherGuard {
+  //(core, original msr value)
+  saved: Vec<(u32, u64)>
+}
+
+impl Drop for PrefetcherGuard {
+  fn drop(&mut self) {
+    restore_prefetchers(&self.saved);
+  }
+}
+
+//Disables the L2 hardware and adjacent line prefetchers on arg:cores until
+//the returned guard is dropped
+pub fn disable_l2_prefetchers(cores: &HashSet<u32>) -> PrefetcherGuard {
+  //the cores that were changed before a failure are restored as well
+  let mut guard = PrefetcherGuard::default();
+  for core in cores {
+    let value = read_msr(*core, MSR_MISC_FEATURE_CONTROL)
+      .unwrap_or_else(|e| panic!("Disabling prefetchers failed: {}", e));
+    write_msr(*core, MSR_MISC_FEATURE_CONTROL, value | L2_PREFETCHERS_MASK)
+      .unwrap_or_else(|e| panic!("Disabling prefetchers failed: {}", e));
+    info!("Disabled L2 prefetchers on core {}", core);
+    guard.saved.push((*core, value));
+  }
+
+  guard
+}
+
+//Restores the saved msr values of a `PrefetcherGuard`
+fn restore_prefetchers(saved: &[(u32, u64)]) {
+  for (core, value) in saved {
+    match write_msr(*core, MSR_MISC_FEATURE_CONTROL, *value) {
+      Ok(()) => info!("Restored prefetchers on core {}", core),
+      Err(e) => error!("Restoring prefetchers on core {} failed: {}", core, e)
+    }
+  }
+}
@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::utils::proc;
+use crate::config::*;
+
+//Physical address oracles translate virtual addresses of this process to
+//physical addresses
+//The allocation code is generic over the oracle so it can be reused in
+//environments where /proc/self/pagemap is unavailable
+pub trait PhysAddrOracle {
+  //Returns the physical address of arg:virt_addr (the page must be present)
+  fn virt_to_phys(&self, virt_addr: u64) -> u64;
+  //Called after the page at arg:old_virt was mremapped to arg:new_virt (see
+  //allocation::map_binary), the page keeps its frame
+  //The oracles that translate through the page tables ignore it
+  fn remapped(&self, _old_virt: u64, _new_virt: u64) {}
+}
+
+// --- PAGEMAP ---
+//Translation with /proc/self/pagemap (requires root to see the frame numbers)
+pub struct PagemapOracle;
+
+impl PhysAddrOracle for PagemapOracle {
+  fn virt_to_phys(&self, virt_addr: u64) -> u64 {
+    proc::virt_to_phys(virt_addr)
+  }
+}
+
+// --- PTEDITOR ---
+//Translation by walking the page tables with the PTEditor kernel module
+//(https://github.com/misc0110/PTEditor)
+//Layout and ioctl number as in ptedit_header.h
+#[repr(C)]
+#[derive(Default)]
+struct PteditEntry {
+  pid: usize,
+  vaddr: usize,
+  pgd: usize,
+  p4d: usize,
+  pud: usize,
+  pmd: usize,
+  pte: usize,
+  valid: usize
+}
+
+//_IOR(0x3d17, 1, size_t)
+static PTEDITOR_IOCTL_CMD_VM_RESOLVE: u64 = 0x803d1701;
+static PTEDIT_VALID_MASK_PMD: usize = 1 << 3;
+static PTEDIT_VALID_MASK_PTE: usize = 1 << 4;
+//page size bit in a page directory entry (2MB page)
+static PMD_PS_BITMASK: usize = 1 << 7;
+//pfn bits 12..51 of a page table entry (x86-64 page tables, 4KB pages)
+static PFN_MASK: usize = ((1 << 52) - 1) & !((1 << 12) - 1);
+
+pub struct PTEditorOracle {
+  device: File
+}
+
+impl PTEditorOracle {
+  pub fn new() -> PTEditorOracle {
+    assert!(*PAGE_SIZE == 1 << 12, "PTEditor needs 4KB pages, the page size \
+      is {}KB", *PAGE_SIZE >> 10);
+    let device = OpenOptions::new().read(true).write(true)
+      .open("/dev/pteditor")
+      .expect("Could not open /dev/pteditor, is the PTEditor module loaded?");
+    PTEditorOracle {device}
+  }
+}
+
+impl PhysAddrOracle for PTEditorOracle {
+  fn virt_to_phys(&self, virt_addr: u64) -> u64 {
+    //pid 0 = this process
+    let mut entry =
+      PteditEntry {vaddr: virt_addr as usize, ..Default::default()};
+    let ret = unsafe {libc::ioctl(self.device.as_raw_fd(),
+      PTEDITOR_IOCTL_CMD_VM_RESOLVE as _, &mut entry as *mut PteditEntry)};
+    assert!(ret == 0, "PTEditor could not resolve V0x{:x}", virt_addr);
+
+    if entry.valid & PTEDIT_VALID_MASK_PTE != 0 {
+      (entry.pte & PFN_MASK) as u64 | (virt_addr & *PAGE_OFFSET_MASK)
+    } else if entry.valid & PTEDIT_VALID_MASK_PMD != 0
+      && entry.pmd & PMD_PS_BITMASK != 0
+    {
+      let huge_offset_mask = (1 << 21) - 1;
+      (entry.pmd & PFN_MASK & !huge_offset_mask) as u64
+        | (virt_addr & huge_offset_mask as u64)
+    } else {
+      panic!("Virtual to physical address translation failed: page not \
+        present (V0x{:x})", virt_addr);
+    }
+  }
+}
+
+// --- HUGEPAGE ---
+//Translation by offset arithmetic inside one physically contiguous huge page
+//Only the physical address of the start of the huge page has to be known
+//(e.g., from another oracle or from the kernel log)
+pub struct HugepageOracle {
+  pub virt_base: u64,
+  pub phys_base: u64,
+  pub size: u64
+}
+
+impl PhysAddrOracle for HugepageOracle {
+  fn virt_to_phys(&self, virt_addr: u64) -> u64 {
+    assert!(virt_addr >= self.virt_base
+      && virt_addr < self.virt_base + self.size,
+      "V0x{:x} is outside of the huge page", virt_addr);
+    self.phys_base + (virt_addr - self.virt_base)
+  }
+}
+
+// --- SIMULATION ---
+//Assigns every new virtual page a random frame in the first arg:frame_count
+//frames, e.g., to exercise the allocation logic without root privileges
+//Remapped pages keep their frame (see `remapped`), but two pages can get the
+//same frame
+pub struct SimulatedOracle {
+  frame_count: u64,
+  rng: RefCell<StdRng>,
+  page2frame: RefCell<HashMap<u64, u64>>
+}
+
+impl SimulatedOracle {
+  pub fn new(frame_count: u64, seed: u64) -> SimulatedOracle {
+    SimulatedOracle {
+      frame_count,
+      rng: RefCell::new(StdRng::seed_from_u64(seed)),
+      page2frame: RefCell::new(HashMap::new())
+    }
+  }
+}
+
+impl PhysAddrOracle for SimulatedOracle {
+  fn virt_to_phys(&self, virt_addr: u64) -> u64 {
+    let page = virt_addr & *PAGE_ALIGN_MASK;
+    let frame = *self.page2frame.borrow_mut().entry(page).or_insert_with(|| {
+      self.rng.borrow_mut().gen_range(0..self.frame_count) << *PAGE_SIZE_BITS
+    });
+    frame | (virt_addr & *PAGE_OFFSET_MASK)
+  }
+
+  fn remapped(&self, old_virt: u64, new_virt: u64) {
+    let mut page2frame = self.page2frame.borrow_mut();
+    if let Some(frame) = page2frame.remove(&(old_virt & *PAGE_ALIGN_MASK)) {
+      page2frame.insert(new_virt & *PAGE_ALIGN_MASK, frame);
+    }
+  }
+}
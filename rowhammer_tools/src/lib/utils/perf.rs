@@ -0,0 +1,94 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+
+//Minimal perf_event_open wrapper for counting events (no sampling), e.g. the
+//LLC misses of a task or the DRAM activations counted by the uncore memory
+//controller PMUs
+
+pub static PERF_TYPE_HARDWARE: u32 = 0;
+pub static PERF_COUNT_HW_CACHE_REFERENCES: u64 = 2;
+pub static PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+
+static FLAG_EXCLUDE_HV: u64 = 1 << 6;
+static PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+//struct perf_event_attr (PERF_ATTR_SIZE_VER5)
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+  type_: u32,
+  size: u32,
+  config: u64,
+  sample_period: u64,
+  sample_type: u64,
+  read_format: u64,
+  flags: u64,
+  wakeup_events: u32,
+  bp_type: u32,
+  config1: u64,
+  config2: u64,
+  branch_sample_type: u64,
+  sample_regs_user: u64,
+  sample_stack_user: u32,
+  clockid: i32,
+  sample_regs_intr: u64,
+  aux_watermark: u32,
+  sample_max_stack: u16,
+  reserved: u16
+}
+
+//An enabled counter
+pub struct Counter {
+  file: File
+}
+
+impl Counter {
+  //Counts event arg:config of PMU arg:type_ for task (thread) arg:pid (-1 =
+  //all tasks) on arg:cpu (-1 = all CPUs), see perf_event_open(2)
+  pub fn open(type_: u32, config: u64, pid: i32, cpu: i32)
+    -> io::Result<Counter>
+  {
+    let attr = PerfEventAttr {
+      type_,
+      size: std::mem::size_of::<PerfEventAttr>() as u32,
+      config,
+      flags: if pid >= 0 {FLAG_EXCLUDE_HV} else {0},
+      ..Default::default()
+    };
+    let fd = unsafe {libc::syscall(libc::SYS_perf_event_open,
+      &attr as *const PerfEventAttr, pid, cpu, -1, PERF_FLAG_FD_CLOEXEC)};
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(Counter {file: unsafe {File::from_raw_fd(fd as i32)}})
+  }
+
+  pub fn read(&mut self) -> u64 {
+    let mut buf = [0u8; 8];
+    self.file.read_exact(&mut buf).map_or(0, |_| u64::from_ne_bytes(buf))
+  }
+}
+
+//(name, type, first CPU of the cpumask) of all PMUs whose name starts with
+//arg:prefix, e.g. "uncore_imc"
+pub fn find_pmus(prefix: &str) -> Vec<(String, u32, i32)> {
+  let devices = match fs::read_dir("/sys/bus/event_source/devices") {
+    Ok(devices) => devices,
+    Err(_) => return Vec::new()
+  };
+  let mut pmus: Vec<_> = devices.flatten().filter_map(|d| {
+    let name = d.file_name().to_string_lossy().into_owned();
+    if !name.starts_with(prefix) {
+      return None;
+    }
+    let type_ = fs::read_to_string(d.path().join("type")).ok()?
+      .trim().parse().ok()?;
+    let cpu = fs::read_to_string(d.path().join("cpumask"))
+      .ok().and_then(|m| m.trim().split([',', '-']).next()?.parse().ok())
+      .unwrap_or(0);
+    Some((name, type_, cpu))
+  }).collect();
+  pmus.sort();
+  pmus
+}
@@ -0,0 +1,115 @@
+use std::env;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::waitpid;
+use nix::unistd::{close, fork, pipe, read, write, ForkResult, Pid};
+use log::*;
+
+use crate::config::*;
+
+//Controlled memory pressure: a child process allocates and touches memory to
+//push the page cache out and to drain the free lists of the buddy allocator
+//Releasing the pressure returns all these frames to the buddy allocator at
+//once, which is a more predictable starting state for the allocation than
+//only dropping the page cache
+
+//size of the chunks the child allocates (and the granularity of the rate
+//limit)
+static CHUNK_SIZE: usize = 64 << 20;
+
+//Child process that holds the allocated memory until it is released
+pub struct MemoryPressure {
+  child: Pid,
+  done_fd: RawFd
+}
+
+impl MemoryPressure {
+  //Allocates and touches arg:bytes in a child process, with at most
+  //arg:rate bytes per second (None = as fast as possible)
+  //Returns immediately, use `wait_allocated` to wait for the allocation
+  pub fn spawn(bytes: u64, rate: Option<u64>) -> MemoryPressure {
+    info!("Creating memory pressure of {}MB", bytes >> 20);
+    let (read_fd, write_fd) = pipe().expect("Creating pipe failed");
+    match unsafe {fork()}.expect("Forking the memory pressure child failed") {
+      ForkResult::Parent {child} => {
+        close(write_fd).unwrap();
+        MemoryPressure {child, done_fd: read_fd}
+      },
+      ForkResult::Child => {
+        //only async-signal-safe calls from here (no allocations, no logging)
+        let _ = close(read_fd);
+        allocate_and_touch(bytes, rate);
+        let _ = write(write_fd, &[1]);
+        loop {
+          unsafe {libc::pause();}
+        }
+      }
+    }
+  }
+
+  //Blocks until the child allocated all memory
+  pub fn wait_allocated(&self) {
+    let mut buf = [0u8; 1];
+    match read(self.done_fd, &mut buf) {
+      Ok(1) => info!("Memory pressure is applied"),
+      //the child died (e.g. killed by the OOM killer)
+      _ => warn!("Memory pressure child exited before allocating everything")
+    }
+  }
+
+  //Kills the child, all its memory returns to the system
+  pub fn release(self) {
+    let _ = kill(self.child, Signal::SIGKILL);
+    let _ = waitpid(self.child, None);
+    let _ = close(self.done_fd);
+    info!("Memory pressure is released");
+  }
+}
+
+fn allocate_and_touch(bytes: u64, rate: Option<u64>) {
+  let start = Instant::now();
+  let mut allocated = 0u64;
+  while allocated < bytes {
+    let len = CHUNK_SIZE.min((bytes - allocated) as usize);
+    let chunk = unsafe {libc::mmap(std::ptr::null_mut(), len,
+      libc::PROT_READ | libc::PROT_WRITE,
+      libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)};
+    if chunk == libc::MAP_FAILED {
+      return;
+    }
+    for offset in (0..len).step_by(*PAGE_SIZE) {
+      unsafe {std::ptr::write_volatile((chunk as *mut u8).add(offset), 1);}
+    }
+    //keep the pages in memory instead of pushing them to swap
+    unsafe {libc::mlock(chunk, len);}
+    allocated += len as u64;
+
+    if let Some(rate) = rate {
+      let target = Duration::from_secs_f64(allocated as f64 / rate as f64);
+      if let Some(wait) = target.checked_sub(start.elapsed()) {
+        std::thread::sleep(wait);
+      }
+    }
+  }
+}
+
+//Memory pressure requested with the `RH_MEMORY_PRESSURE=<MB>` environment
+//variable, optionally rate limited with `RH_MEMORY_PRESSURE_RATE=<MB/s>`
+//Returns (bytes, bytes per second)
+pub fn requested() -> Option<(u64, Option<u64>)> {
+  let mb: u64 = env::var("RH_MEMORY_PRESSURE").ok()?.parse()
+    .expect("RH_MEMORY_PRESSURE is NaN");
+  let rate = env::var("RH_MEMORY_PRESSURE_RATE").ok()
+    .map(|r| r.parse::<u64>().expect("RH_MEMORY_PRESSURE_RATE is NaN") << 20);
+  Some((mb << 20, rate))
+}
+
+//Applies and releases the requested memory pressure (if any)
+pub fn apply_requested() {
+  if let Some((bytes, rate)) = requested() {
+    let pressure = MemoryPressure::spawn(bytes, rate);
+    pressure.wait_allocated();
+    pressure.release();
+  }
+}
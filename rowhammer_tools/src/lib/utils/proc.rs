@@ -1,23 +1,27 @@
 use std::fs::File;
-use std::io::{Seek, Read};
+use std::io::{BufRead, BufReader, Seek, Read};
+use std::os::unix::fs::FileExt;
 use std::path::Path;
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::config::*;
 
-pub fn virt_to_phys(virt_addr: u64) -> u64 {
-  let pagemap_offset: u64 = (virt_addr >> 12) * 8;
-
+pub fn open_pagemap() -> File {
   let path = Path::new("/proc/self/pagemap");
-  let mut pagemap = File::open(path)
-    .expect(&format!("Couldn't open {}", path.display()));
+  File::open(path).expect(&format!("Couldn't open {}", path.display()))
+}
 
-  pagemap.seek(std::io::SeekFrom::Start(pagemap_offset))
-    .expect("Failed to seek in pagemap");
+pub fn virt_to_phys(virt_addr: u64) -> u64 {
+  virt_to_phys_in(&open_pagemap(), virt_addr)
+}
+
+//Same as `virt_to_phys` with an open arg:pagemap (see `open_pagemap`)
+pub fn virt_to_phys_in(pagemap: &File, virt_addr: u64) -> u64 {
+  let pagemap_offset: u64 = (virt_addr >> *PAGE_SIZE_BITS) * 8;
 
   //read may fail if page is not present
   let mut buf: [u8; 8] = [0; 8];
-  pagemap.read_exact(&mut buf)
+  pagemap.read_exact_at(&mut buf, pagemap_offset)
     .expect("Failed to read physical address from pagemap");
   let ret = LittleEndian::read_u64(&buf);
 
@@ -30,8 +34,83 @@ pub fn virt_to_phys(virt_addr: u64) -> u64 {
       (pages should be locked in memory or swap disabled");
   }
 
-  let page_offset = ((1 << PAGE_SIZE_BITS) - 1) & virt_addr;
-  ((ret & ((1 << 55) - 1)) << PAGE_SIZE_BITS) + page_offset
+  let page_offset = ((1 << *PAGE_SIZE_BITS) - 1) & virt_addr;
+  ((ret & ((1 << 55) - 1)) << *PAGE_SIZE_BITS) + page_offset
+}
+
+//Flags of the frame at arg:frame_addr in arg:kpageflags (/proc/kpageflags,
+//one u64 per page frame number, see
+//Documentation/admin-guide/mm/pagemap.rst), None if it cannot be read
+pub fn read_kpageflags(kpageflags: &File, frame_addr: u64) -> Option<u64> {
+  let mut buf: [u8; 8] = [0; 8];
+  kpageflags.read_exact_at(&mut buf, (frame_addr >> *PAGE_SIZE_BITS) * 8)
+    .ok()?;
+  Some(LittleEndian::read_u64(&buf))
+}
+
+//A line of /proc/<pid>/maps
+#[derive(Debug)]
+pub struct Mapping {
+  pub start: u64,
+  pub end: u64,
+  pub perms: String, //e.g. "r-xp"
+  pub file_offset: u64,
+  pub path: String //empty for anonymous mappings
+}
+
+//Reads the mappings of process arg:pid
+pub fn get_maps(pid: u32) -> Vec<Mapping> {
+  let path = format!("/proc/{}/maps", pid);
+  let maps = File::open(&path)
+    .unwrap_or_else(|_| panic!("Couldn't open {}", path));
+  BufReader::new(maps).lines().map(|l| {
+    let line = l.unwrap();
+    //start-end perms offset dev inode [path]
+    let fields: Vec<_> = line.splitn(6, ' ').collect();
+    let (start, end) = fields[0].split_once('-')
+      .unwrap_or_else(|| panic!("Reading {} failed", path));
+    Mapping {
+      start: u64::from_str_radix(start, 16).unwrap(),
+      end: u64::from_str_radix(end, 16).unwrap(),
+      perms: fields[1].to_owned(),
+      file_offset: u64::from_str_radix(fields[2], 16).unwrap(),
+      path: fields.get(5).map_or("", |p| p.trim()).to_owned()
+    }
+  }).collect()
+}
+
+//Same as `virt_to_phys` but for arg:virt_addr in process arg:pid (needs root)
+//Returns None if the page is not present (or swapped), `pid_read_byte` makes
+//it present
+pub fn pid_virt_to_phys(pid: u32, virt_addr: u64) -> Option<u64> {
+  let path = format!("/proc/{}/pagemap", pid);
+  let mut pagemap = File::open(&path)
+    .unwrap_or_else(|_| panic!("Couldn't open {}", path));
+  pagemap.seek(std::io::SeekFrom::Start((virt_addr >> *PAGE_SIZE_BITS) * 8))
+    .expect("Failed to seek in pagemap");
+  let mut buf: [u8; 8] = [0; 8];
+  pagemap.read_exact(&mut buf)
+    .expect("Failed to read physical address from pagemap");
+  let ret = LittleEndian::read_u64(&buf);
+  if ret & PRESENT_BITMASK == 0 || ret & SWAP_BITMASK != 0 {
+    return None;
+  }
+  Some(((ret & ((1 << 55) - 1)) << *PAGE_SIZE_BITS)
+    + (virt_addr & *PAGE_OFFSET_MASK))
+}
+
+//Reads the byte at arg:virt_addr in process arg:pid, which faults the page in
+pub fn pid_read_byte(pid: u32, virt_addr: u64) -> u8 {
+  let path = format!("/proc/{}/mem", pid);
+  let mut mem = File::open(&path)
+    .unwrap_or_else(|_| panic!("Couldn't open {}", path));
+  mem.seek(std::io::SeekFrom::Start(virt_addr))
+    .expect("Failed to seek in process memory");
+  let mut value = [0; 1];
+  mem.read_exact(&mut value)
+    .unwrap_or_else(|_| panic!("Could not read V0x{:x} of process {}",
+    virt_addr, pid));
+  value[0]
 }
 
 /*
@@ -52,7 +131,7 @@ fn put_myself_in_phys_mem() {
       let start_addr = u64::from_str_radix(&l[0..pos1], 16).unwrap();
       let end_addr = u64::from_str_radix(&l[pos1+1..pos2],16).unwrap();
 
-      for virt in (start_addr..end_addr).step_by(PAGE_SIZE as usize) {
+      for virt in (start_addr..end_addr).step_by(*PAGE_SIZE as usize) {
         let phys1 = proc::virt_to_phys(virt).unwrap_or(0);
         unsafe { std::ptr::read_volatile(virt as *const u8); }
         let phys2 = proc::virt_to_phys(virt).unwrap_or(0);
@@ -82,9 +161,10 @@ pub fn print_phys_addrs_of_maps(path: &str, message: &str) {
       let start_addr = u64::from_str_radix(&line[0..pos1], 16).unwrap();
       let end_addr = u64::from_str_radix(&line[pos1+1..pos2],16).unwrap();
 
-      for virt in (start_addr..end_addr).step_by(PAGE_SIZE as usize) {
+      for virt in (start_addr..end_addr).step_by(*PAGE_SIZE as usize) {
         let phys = virt_to_phys(virt).unwrap_or(0);
-        info!("  Virtual address: 0x{:x}, physical address: 0x{:x}", virt, phys);
+        info!("  Virtual address: V0x{:x}, physical address: P0x{:x}", virt,
+          phys);
       }
     }
   }
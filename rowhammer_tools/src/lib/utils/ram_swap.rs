@@ -0,0 +1,200 @@
+use std::io::IsTerminal;
+use std::path::Path;
+use std::{env, fs};
+use chrono::prelude::*;
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::*;
+use crate::utils::{dram, files};
+
+//RAM swap ids (see RAM_SWAP_ID_FILE): every RAM swap on this machine is
+//recorded with its id, the serials of the installed DIMMs and when it was
+//recorded in a history at RAM_SWAP_HISTORY_PATH (can be overridden with the
+//`RH_RAM_SWAP_HISTORY` environment variable), which survives reboots
+//The id of the running boot stays in RAM_SWAP_ID_FILE, which is cleared on
+//reboot: when it is missing, the tools ask for it on their terminal (the id
+//of the last swap is the default if its DIMMs are still installed, see
+//`ensure_or_ask`) instead of failing, without a terminal (e.g. under the
+//daemon) the id has to be given in `RH_RAM_SWAP_ID`
+//An id whose recorded DIMMs are not the detected ones is reported when it is
+//set (e.g. the DIMMs were swapped but RAM_SWAP_ID_FILE was not updated), the
+//DIMMs are only detected (decode-dimms) then
+//`sudo ./prehammer ram-id` manages the id explicitly
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RamSwap {
+  pub id: u32,
+  //serials of the installed DIMMs (see dram::detect_dimm_serials)
+  pub serials: Vec<String>,
+  pub since: String
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RamSwapHistory {
+  pub swaps: Vec<RamSwap>
+}
+
+//Path of the history: `RH_RAM_SWAP_HISTORY` or RAM_SWAP_HISTORY_PATH
+pub fn history_path() -> String {
+  env::var("RH_RAM_SWAP_HISTORY")
+    .unwrap_or_else(|_| RAM_SWAP_HISTORY_PATH.to_owned())
+}
+
+impl RamSwapHistory {
+  //Returns an empty history if arg:path does not exist yet
+  pub fn load(path: &str) -> RamSwapHistory {
+    if Path::new(path).exists() {
+      files::parse_json(path)
+    } else {
+      RamSwapHistory::default()
+    }
+  }
+
+  pub fn save(&self, path: &str) {
+    if let Some(dir) = Path::new(path).parent() {
+      fs::create_dir_all(dir)
+        .expect("Creating the RAM swap history directory failed");
+    }
+    fs::write(path, serde_json::to_string_pretty(self).unwrap())
+      .unwrap_or_else(|_| panic!("Writing the RAM swap history {} failed",
+      path));
+  }
+
+  pub fn last(&self) -> Option<&RamSwap> {
+    self.swaps.last()
+  }
+
+  //the latest swap with arg:id
+  pub fn get(&self, id: u32) -> Option<&RamSwap> {
+    self.swaps.iter().rev().find(|s| s.id == id)
+  }
+
+  //Records a swap to arg:id with the DIMMs arg:serials, unless it is the last
+  //swap already
+  pub fn record(&mut self, id: u32, serials: &[String]) {
+    if self.last().is_some_and(|l| l.id == id && l.serials == serials) {
+      return;
+    }
+    info!("Recording RAM swap {} with the DIMMs {:?}", id, serials);
+    self.swaps.push(RamSwap {id, serials: serials.to_vec(),
+      since: Local::now().to_rfc3339()});
+  }
+
+  //The problem with using arg:id for the DIMMs arg:serials, if any
+  pub fn check(&self, id: u32, serials: &[String]) -> Option<String> {
+    let swap = self.get(id)?;
+    if swap.serials != serials {
+      return Some(format!("RAM swap {} was recorded with the DIMMs {:?} \
+        (since {}), but {:?} are installed", id, swap.serials, swap.since,
+        serials));
+    }
+    //restoring earlier DIMMs changes the cells as well (see RAM_SWAP_ID_FILE)
+    match self.last() {
+      Some(last) if last.id != id => Some(format!("RAM swap {} is not the \
+        last swap ({}, since {}), DIMMs that were installed again need a new \
+        id", id, last.id, last.since)),
+      _ => None
+    }
+  }
+}
+
+//The id in `RH_RAM_SWAP_ID`, if set
+pub fn explicit_id() -> Option<u32> {
+  env::var("RH_RAM_SWAP_ID").ok()
+    .map(|id| id.parse().expect("RH_RAM_SWAP_ID is NaN"))
+}
+
+//The id in RAM_SWAP_ID_FILE, None if it does not exist (after a reboot)
+pub fn current_id() -> Option<u32> {
+  let id = fs::read_to_string(RAM_SWAP_ID_FILE).ok()?;
+  Some(id.trim().parse().unwrap_or_else(|_|
+    panic!("{} does not contain a RAM swap id", RAM_SWAP_ID_FILE)))
+}
+
+fn set_current_id(id: u32) {
+  fs::write(RAM_SWAP_ID_FILE, format!("{}\n", id))
+    .unwrap_or_else(|_| panic!("Writing {} failed", RAM_SWAP_ID_FILE));
+}
+
+//The id of the installed DIMMs for the host config, without asking: the id in
+//`RH_RAM_SWAP_ID` or in RAM_SWAP_ID_FILE (both were checked against the DIMMs
+//by `ensure_or_ask` or `set`)
+pub fn ensure() -> u32 {
+  explicit_id().or_else(current_id).unwrap_or_else(|| panic!("{} not found \
+    (after a reboot?), set the RAM swap id with `sudo ./prehammer ram-id` or \
+    in RH_RAM_SWAP_ID", RAM_SWAP_ID_FILE))
+}
+
+//To call by the tools before they read the host config: uses the id in
+//`RH_RAM_SWAP_ID` (see `set`), otherwise the id in RAM_SWAP_ID_FILE, or asks
+//for it with arg:ask (see `prompt`) if stdin is a terminal
+pub fn ensure_or_ask(ask: impl FnMut(&str) -> String) -> u32 {
+  if let Some(id) = explicit_id() {
+    set(&mut RamSwapHistory::load(&history_path()), id,
+      &dram::detect_dimm_serials());
+    return id;
+  }
+  if let Some(id) = current_id() {
+    return id;
+  }
+  warn!("{} not found (after a reboot?)", RAM_SWAP_ID_FILE);
+  prompt(&mut RamSwapHistory::load(&history_path()),
+    &dram::detect_dimm_serials(), ask)
+}
+
+//Asks for the id of the installed DIMMs arg:serials with arg:ask (prints the
+//question and returns the answer, the tools read it from their terminal), the
+//default is the last swap if its DIMMs are installed, a new id otherwise
+//The id is recorded in arg:history and written to RAM_SWAP_ID_FILE
+//Panics if stdin is not a terminal (an empty answer would silently take the
+//default)
+pub fn prompt(
+  history: &mut RamSwapHistory, serials: &[String],
+  mut ask: impl FnMut(&str) -> String
+) -> u32 {
+  assert!(std::io::stdin().is_terminal(), "Cannot ask for the RAM swap id \
+    without a terminal, set it in RH_RAM_SWAP_ID or with `sudo ./prehammer \
+    ram-id <id>`");
+  let default = match history.last() {
+    Some(last) if last.serials == serials => {
+      println!("The installed DIMMs {:?} are those of RAM swap {} (since {})",
+        serials, last.id, last.since);
+      last.id
+    },
+    Some(last) => {
+      println!("The installed DIMMs {:?} are not those of the last RAM swap \
+        {} ({:?})", serials, last.id, last.serials);
+      history.swaps.iter().map(|s| s.id).max().unwrap() + 1
+    },
+    None => {
+      println!("No RAM swaps recorded yet, installed DIMMs: {:?}", serials);
+      0
+    }
+  };
+  let id = loop {
+    let input = ask(&format!("RAM swap id (a new id if the DIMMs were swapped \
+      in between) [{}]:", default));
+    match input.trim() {
+      "" => break default,
+      input => match input.parse() {
+        Ok(id) => break id,
+        Err(_) => println!("{} is not a number", input)
+      }
+    }
+  };
+  set(history, id, serials);
+  id
+}
+
+//Uses arg:id for the installed DIMMs arg:serials: records it in arg:history
+//and writes RAM_SWAP_ID_FILE
+pub fn set(history: &mut RamSwapHistory, id: u32, serials: &[String]) {
+  if let Some(problem) = history.check(id, serials) {
+    warn!("{}", problem);
+  }
+  history.record(id, serials);
+  history.save(&history_path());
+  set_current_id(id);
+  info!("RAM swap id {} written to {}", id, RAM_SWAP_ID_FILE);
+}
@@ -0,0 +1,118 @@
+use std::env;
+use std::io::Write;
+use std::sync::OnceLock;
+use regex::{Captures, Regex};
+
+//Redaction of the physical addresses in the log output, so logs can be
+//shared in bug reports and artifacts without the attack coordinates of the
+//machine, set with the `RH_REDACT_PHYS` environment variable:
+//  - `redact`: every physical address is replaced by `P<redacted>`
+//  - `hash`: every physical address is replaced by a salted hash (`P#<hash>`),
+//    the same address gets the same hash, so the addresses of a log can still
+//    be correlated, the salt is `RH_REDACT_SALT` (random without it, the
+//    hashes of two runs are only comparable with the same salt)
+//  - `off` (default)
+//Only the log output is redacted, the result files (templates, attack
+//configs, reports, ...) keep the addresses
+//The logs write physical addresses with the `P0x` prefix (virtual addresses
+//with `V0x`), the JSON warnings in fields named *phys_addr* or *frame*
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+  Redact,
+  //with the salt
+  Hash(u64)
+}
+
+//FNV-1a of arg:bytes, unlike the hasher of the standard library it does not
+//change between toolchains, so the hashes of logs written by different
+//builds can be correlated
+fn hash(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b|
+    (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+//`P0x<hex>`
+fn phys_addr_regex() -> &'static Regex {
+  static REGEX: OnceLock<Regex> = OnceLock::new();
+  REGEX.get_or_init(|| Regex::new(r"\bP0x(?P<addr>[0-9a-fA-F]+)\b").unwrap())
+}
+
+//`"<key>":<decimal>` with a key containing phys_addr or frame
+fn json_field_regex() -> &'static Regex {
+  static REGEX: OnceLock<Regex> = OnceLock::new();
+  REGEX.get_or_init(|| Regex::new(
+    r#""(?P<key>\w*(phys_addr|frame)\w*)":(?P<addr>\d+)"#).unwrap())
+}
+
+impl Redaction {
+  //None without `RH_REDACT_PHYS` (or with `off`)
+  pub fn from_env() -> Option<Redaction> {
+    let mode = env::var("RH_REDACT_PHYS").ok()?;
+    match mode.as_str() {
+      "off" => None,
+      "redact" => Some(Redaction::Redact),
+      "hash" => Some(Redaction::Hash(env::var("RH_REDACT_SALT")
+        .map_or_else(|_| rand::random(), |salt| hash(salt.as_bytes())))),
+      _ => panic!("Unknown RH_REDACT_PHYS mode {}, expected redact, hash or \
+        off", mode)
+    }
+  }
+
+  //The replacement of arg:addr (without the `P` prefix)
+  pub fn replace(&self, addr: u64) -> String {
+    match self {
+      Redaction::Redact => "<redacted>".to_owned(),
+      Redaction::Hash(salt) => format!("#{:08x}",
+        hash(&[salt.to_le_bytes(), addr.to_le_bytes()].concat()) as u32)
+    }
+  }
+
+  //Redacts the physical addresses in arg:message
+  pub fn apply(&self, message: &str) -> String {
+    let message = phys_addr_regex().replace_all(message, |c: &Captures|
+      format!("P{}", self.replace(u64::from_str_radix(&c["addr"], 16)
+      .unwrap_or(u64::MAX))));
+    json_field_regex().replace_all(&message, |c: &Captures|
+      format!("\"{}\":\"P{}\"", &c["key"], self.replace(c["addr"].parse()
+      .unwrap_or(u64::MAX)))).into_owned()
+  }
+}
+
+//Redacts the log output of arg:builder if requested, the format is the
+//default format of env_logger (without timestamps, see `configure`)
+pub fn configure_logger(builder: &mut env_logger::Builder) {
+  if let Some(redaction) = Redaction::from_env() {
+    builder.format(move |buf, record| writeln!(buf, "[{:<5} {}] {}",
+      buf.default_styled_level(record.level()),
+      record.module_path().unwrap_or_default(),
+      redaction.apply(&record.args().to_string())));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn redacts_phys_addrs() {
+    let message = "Found frame P0x1a2b000 (page V0x7f0000001000)";
+    assert_eq!(Redaction::Redact.apply(message),
+      "Found frame P<redacted> (page V0x7f0000001000)");
+    let warning = r#"{"victim_phys_addr":123,"frame":456,"row":7}"#;
+    assert_eq!(Redaction::Redact.apply(warning),
+      r#"{"victim_phys_addr":"P<redacted>","frame":"P<redacted>","row":7}"#);
+  }
+
+  #[test]
+  fn hashes_phys_addrs() {
+    let hashed = Redaction::Hash(1).apply("P0x1000 P0x2000 P0x1000");
+    let tokens: Vec<&str> = hashed.split(' ').collect();
+    assert!(tokens.iter().all(|t| t.starts_with("P#") && t.len() == 10));
+    assert_eq!(tokens[0], tokens[2]);
+    assert_ne!(tokens[0], tokens[1]);
+    assert_ne!(Redaction::Hash(2).apply("P0x1000"), tokens[0]);
+    //the same hash with every toolchain
+    assert_eq!(tokens[0], "P#6b2779d4");
+  }
+}
@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::net::TcpStream;
+use serde::{Deserialize, Serialize};
+use log::*;
+
+use crate::utils::{serialize::*, stealth};
+use crate::utils::resources::ResourceSummary;
+
+//Output sinks for the results of the tools: the templater, prehammer and
+//loader report every discovered flip, every finished setup (a hammered
+//aggressor pattern) and the result of the whole experiment to a `Reporter`,
+//new output formats only need a new implementation of the trait
+//The sinks are configured with the `RH_REPORT` environment variable, a comma
+//separated list of:
+//  - `stdout`: one JSON object per event on stdout
+//  - `json:<path>`: the experiment result as a JSON document (the format of
+//    the templating<id>.json files), flips and setups are not written
+//  - `sqlite:<path>`: all events in the tables `flips`, `setups` and
+//    `experiments` of an SQLite database (needs the `sqlite` feature)
+//  - `tcp:<host>:<port>`: one JSON object per event over a TCP connection
+//The templater always writes its template to templating<id>.json as well
+//The soak mode of the templater reports every template of its cycles and a
+//summary of every day (`SoakDay`)
+
+pub trait Reporter {
+  fn flip_discovered(&mut self, flip: &Flip);
+  fn setup_finished(&mut self, setup: &SetupSummary);
+  fn experiment_finished(&mut self, result: &ExperimentResult);
+}
+
+#[derive(Serialize, Clone)]
+pub struct Flip {
+  pub phys_addr: u64,
+  pub bitflip: BitFlip,
+  //first frame of every aggressor row
+  pub aggr_rows: Vec<u64>,
+  //templater only
+  pub garbage_count: Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct SetupSummary {
+  pub setup: String,
+  //templater only
+  pub garbage_count: Option<u32>,
+  //number of flips found with the setup
+  pub flips: usize,
+  pub duration_us: u64
+}
+
+//Summary of one day (local time) of the soak mode of the templater
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SoakDay {
+  pub date: String,
+  //templating cycles (one random region each) that finished and that failed
+  //(and were restarted)
+  pub cycles: usize,
+  pub failed_cycles: usize,
+  //rows of the finished cycles
+  pub rows: u64,
+  //victims of the finished cycles (a victim found in two cycles counts
+  //twice), in total and per bank
+  pub victims: usize,
+  pub victims_per_bank: BTreeMap<u64, usize>,
+  //time spent in the cycles
+  pub duration_s: u64,
+  //cycle and panic message of every failed cycle
+  pub failures: Vec<String>
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ExperimentResult<'a> {
+  //templater
+  Template(&'a MemoryTemplate),
+  //prehammer template tester: the victims that flipped reliably
+  TemplateTest {tested: usize, reliable: Vec<Flip>,
+    resources: ResourceSummary},
+  //loader and prehammer attack tester
+  Attack {binary: String, flipped: Vec<Flip>, failed: Vec<Flip>,
+    resources: ResourceSummary},
+  //templater soak mode
+  SoakDay(&'a SoakDay)
+}
+
+impl ExperimentResult<'_> {
+  pub fn kind(&self) -> &'static str {
+    match self {
+      ExperimentResult::Template(_) => "template",
+      ExperimentResult::TemplateTest {..} => "template_test",
+      ExperimentResult::Attack {..} => "attack",
+      ExperimentResult::SoakDay(_) => "soak_day"
+    }
+  }
+
+  //The outcome of all victim bits of arg:attack_config (including the
+  //page-table victims), arg:flipped tells whether a bit (frame address, bit)
+  //flipped
+  pub fn attack(
+    binary: &str, attack_config: &AttackConfig, resources: ResourceSummary,
+    flipped: impl Fn(u64, &VictimBit) -> bool
+  ) -> ExperimentResult<'static> {
+    let bits = attack_config.victim_frames.iter()
+      .flat_map(|v| v.victim_bits.iter().map(move |b| (v.frame_addr, b)))
+      .chain(attack_config.pte_victim_frames.iter()
+      .flat_map(|v| v.victim_bits.iter().map(move |b| (v.frame_addr, b))));
+    let (flipped, failed): (Vec<_>, Vec<_>) =
+      bits.partition(|(frame_addr, bit)| flipped(*frame_addr, bit));
+    let to_flips = |bits: Vec<(u64, &VictimBit)>| bits.into_iter()
+      .map(|(frame_addr, bit)| Flip {
+        phys_addr: frame_addr + bit.offset,
+        bitflip: bit.bitflip.clone(),
+        aggr_rows: attack_config.aggr_pattern(&bit.aggr_pattern_key)
+          .into_iter().map(|r| r.frames[0]).collect(),
+        garbage_count: None
+      }).collect();
+    ExperimentResult::Attack {
+      binary: binary.to_owned(),
+      flipped: to_flips(flipped),
+      failed: to_flips(failed),
+      resources
+    }
+  }
+}
+
+//Fans the events out to all configured sinks
+#[derive(Default)]
+pub struct Reporters(Vec<Box<dyn Reporter>>);
+
+impl Reporters {
+  //The sinks in `RH_REPORT` (none without it or in stealth mode)
+  pub fn from_env() -> Reporters {
+    let mut reporters = Reporters::default();
+    if stealth::enabled() {
+      return reporters;
+    }
+    if let Ok(sinks) = env::var("RH_REPORT") {
+      for sink in sinks.split(',').filter(|s| !s.is_empty()) {
+        info!("Reporting to {}", sink);
+        reporters = reporters.with(parse_sink(sink));
+      }
+    }
+    reporters
+  }
+
+  pub fn with(mut self, reporter: Box<dyn Reporter>) -> Reporters {
+    self.0.push(reporter);
+    self
+  }
+}
+
+impl Reporter for Reporters {
+  fn flip_discovered(&mut self, flip: &Flip) {
+    self.0.iter_mut().for_each(|r| r.flip_discovered(flip));
+  }
+
+  fn setup_finished(&mut self, setup: &SetupSummary) {
+    self.0.iter_mut().for_each(|r| r.setup_finished(setup));
+  }
+
+  fn experiment_finished(&mut self, result: &ExperimentResult) {
+    self.0.iter_mut().for_each(|r| r.experiment_finished(result));
+  }
+}
+
+fn parse_sink(sink: &str) -> Box<dyn Reporter> {
+  let (kind, arg) = sink.split_once(':').unwrap_or((sink, ""));
+  match kind {
+    "stdout" => Box::new(JsonLinesReporter(std::io::stdout())),
+    "json" => Box::new(JsonFileReporter {path: arg.to_owned()}),
+    "sqlite" => sqlite_reporter(arg),
+    "tcp" => Box::new(JsonLinesReporter(TcpStream::connect(arg)
+      .unwrap_or_else(|e| panic!("Connecting to {} failed: {}", arg, e)))),
+    _ => panic!("Unknown report sink `{}` in RH_REPORT", sink)
+  }
+}
+
+// --- JSON FILE ---
+//Writes the experiment result to arg:path
+pub struct JsonFileReporter {
+  pub path: String
+}
+
+impl Reporter for JsonFileReporter {
+  fn flip_discovered(&mut self, _: &Flip) {}
+
+  fn setup_finished(&mut self, _: &SetupSummary) {}
+
+  fn experiment_finished(&mut self, result: &ExperimentResult) {
+    info!("Writing the {} result to {}", result.kind(), self.path);
+    fs::write(&self.path, serde_json::to_string(result).unwrap())
+      .unwrap_or_else(|_| panic!("Writing {} failed", self.path));
+  }
+}
+
+// --- JSON LINES (stdout, tcp) ---
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Line<'a> {
+  FlipDiscovered(&'a Flip),
+  SetupFinished(&'a SetupSummary),
+  ExperimentFinished {kind: &'a str, result: &'a ExperimentResult<'a>}
+}
+
+//Writes every event as a JSON object on its own line to the writer
+pub struct JsonLinesReporter<W: Write>(pub W);
+
+impl<W: Write> JsonLinesReporter<W> {
+  fn write(&mut self, line: &Line) {
+    //a broken sink must not abort the experiment
+    if let Err(e) = writeln!(self.0, "{}", serde_json::to_string(line).unwrap())
+      .and_then(|_| self.0.flush())
+    {
+      warn!("Reporting failed: {}", e);
+    }
+  }
+}
+
+impl<W: Write> Reporter for JsonLinesReporter<W> {
+  fn flip_discovered(&mut self, flip: &Flip) {
+    self.write(&Line::FlipDiscovered(flip));
+  }
+
+  fn setup_finished(&mut self, setup: &SetupSummary) {
+    self.write(&Line::SetupFinished(setup));
+  }
+
+  fn experiment_finished(&mut self, result: &ExperimentResult) {
+    self.write(&Line::ExperimentFinished {kind: result.kind(), result});
+  }
+}
+
+// --- SQLITE ---
+#[cfg(feature = "sqlite")]
+fn sqlite_reporter(path: &str) -> Box<dyn Reporter> {
+  Box::new(SqliteReporter::open(path))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_reporter(_: &str) -> Box<dyn Reporter> {
+  panic!("The sqlite report sink needs the `sqlite` feature");
+}
+
+//Inserts every event in the database at arg:path (the tables are created if
+//needed), every row has the time of the event
+#[cfg(feature = "sqlite")]
+pub struct SqliteReporter {
+  connection: rusqlite::Connection
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteReporter {
+  pub fn open(path: &str) -> SqliteReporter {
+    let connection = rusqlite::Connection::open(path)
+      .unwrap_or_else(|e| panic!("Opening {} failed: {}", path, e));
+    connection.execute_batch("
+      CREATE TABLE IF NOT EXISTS flips (time TEXT, phys_addr INTEGER,
+        bitflip TEXT, aggr_rows TEXT, garbage_count INTEGER);
+      CREATE TABLE IF NOT EXISTS setups (time TEXT, setup TEXT,
+        garbage_count INTEGER, flips INTEGER, duration_us INTEGER);
+      CREATE TABLE IF NOT EXISTS experiments (time TEXT, kind TEXT,
+        result TEXT);")
+      .unwrap_or_else(|e| panic!("Creating the tables in {} failed: {}", path,
+      e));
+    SqliteReporter {connection}
+  }
+
+  fn insert(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) {
+    let time = chrono::Local::now().to_rfc3339();
+    let params: Vec<&dyn rusqlite::ToSql> =
+      std::iter::once(&time as &dyn rusqlite::ToSql)
+      .chain(params.iter().cloned()).collect();
+    if let Err(e) = self.connection.execute(sql, &params[..]) {
+      warn!("Reporting failed: {}", e);
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+impl Reporter for SqliteReporter {
+  fn flip_discovered(&mut self, flip: &Flip) {
+    let aggr_rows = flip.aggr_rows.iter().map(|r| format!("0x{:x}", r))
+      .collect::<Vec<_>>().join(",");
+    self.insert("INSERT INTO flips VALUES (?1, ?2, ?3, ?4, ?5)",
+      &[&(flip.phys_addr as i64), &flip.bitflip.to_string(), &aggr_rows,
+      &flip.garbage_count]);
+  }
+
+  fn setup_finished(&mut self, setup: &SetupSummary) {
+    self.insert("INSERT INTO setups VALUES (?1, ?2, ?3, ?4, ?5)",
+      &[&setup.setup, &setup.garbage_count, &(setup.flips as i64),
+      &(setup.duration_us as i64)]);
+  }
+
+  fn experiment_finished(&mut self, result: &ExperimentResult) {
+    self.insert("INSERT INTO experiments VALUES (?1, ?2, ?3)",
+      &[&result.kind(), &serde_json::to_string(result).unwrap()]);
+  }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+pub use crate::core::stats::{PhaseUsage, ResourceSummary};
+
+//Resource usage of a session: the peak RSS of the process, the pages the
+//allocation code allocated, the page cache drops and the wall and CPU time of
+//every phase, written into the output metadata of every run (the memory
+//template and the experiment results of the reports, see utils/report.rs), so
+//resource regressions of allocation strategy changes show up in the data
+//The usage of a session is in its context (see RhContext), like the timeline
+//The CPU times are those of the whole process (getrusage), a phase that runs
+//within another one counts in both
+//A run reports its usage with `take_summary`, which restarts the counters,
+//so the runs of a session (e.g. the templates of the batch, fuzz, windowed
+//and soak modes of the templater) do not accumulate, only the peak RSS is
+//that of the whole process
+
+//wall time, user and system time at the start of a phase
+struct PhaseStart {
+  wall: Instant,
+  user_us: u64,
+  system_us: u64
+}
+
+#[derive(Default)]
+struct State {
+  pages_allocated: u64,
+  page_cache_drops: u64,
+  phases: Vec<PhaseUsage>,
+  running: HashMap<String, PhaseStart>
+}
+
+#[derive(Default)]
+pub struct ResourceUsage(Mutex<State>);
+
+fn rusage() -> libc::rusage {
+  unsafe {
+    let mut usage: libc::rusage = std::mem::zeroed();
+    assert!(libc::getrusage(libc::RUSAGE_SELF, &mut usage) == 0,
+      "getrusage failed");
+    usage
+  }
+}
+
+fn timeval_us(t: libc::timeval) -> u64 {
+  t.tv_sec as u64 * 1_000_000 + t.tv_usec as u64
+}
+
+impl PhaseStart {
+  fn now() -> PhaseStart {
+    let usage = rusage();
+    PhaseStart {
+      wall: Instant::now(),
+      user_us: timeval_us(usage.ru_utime),
+      system_us: timeval_us(usage.ru_stime)
+    }
+  }
+
+  //the usage since this start
+  fn usage(&self, phase: &str) -> PhaseUsage {
+    let end = PhaseStart::now();
+    PhaseUsage {
+      phase: phase.to_owned(),
+      wall_us: self.wall.elapsed().as_micros() as u64,
+      user_us: end.user_us - self.user_us,
+      system_us: end.system_us - self.system_us
+    }
+  }
+}
+
+//Adds arg:usage to the phase with the same name in arg:phases
+fn add_phase(phases: &mut Vec<PhaseUsage>, usage: PhaseUsage) {
+  match phases.iter_mut().find(|p| p.phase == usage.phase) {
+    Some(p) => {
+      p.wall_us += usage.wall_us;
+      p.user_us += usage.user_us;
+      p.system_us += usage.system_us;
+    },
+    None => phases.push(usage)
+  }
+}
+
+impl ResourceUsage {
+  pub fn phase_start(&self, phase: &str) {
+    self.0.lock().unwrap().running
+      .insert(phase.to_owned(), PhaseStart::now());
+  }
+
+  pub fn phase_end(&self, phase: &str) {
+    let mut state = self.0.lock().unwrap();
+    let start = state.running.remove(phase)
+      .unwrap_or_else(|| panic!("Phase {} ended, but was not started", phase));
+    add_phase(&mut state.phases, start.usage(phase));
+  }
+
+  pub fn pages_allocated(&self, pages: u64) {
+    self.0.lock().unwrap().pages_allocated += pages;
+  }
+
+  pub fn page_cache_dropped(&self) {
+    self.0.lock().unwrap().page_cache_drops += 1;
+  }
+
+  pub fn summary(&self) -> ResourceSummary {
+    let state = self.0.lock().unwrap();
+    let mut phases = state.phases.clone();
+    let mut running: Vec<(&String, &PhaseStart)> =
+      state.running.iter().collect();
+    running.sort_by_key(|(_, start)| start.wall);
+    for (phase, start) in running {
+      add_phase(&mut phases, start.usage(phase));
+    }
+    ResourceSummary {
+      //ru_maxrss is in KB on Linux
+      peak_rss_kb: rusage().ru_maxrss as u64,
+      pages_allocated: state.pages_allocated,
+      page_cache_drops: state.page_cache_drops,
+      phases
+    }
+  }
+
+  //The summary of the usage since the last `take_summary` or `reset`, the
+  //counters and the finished phases restart (the running phases continue)
+  pub fn take_summary(&self) -> ResourceSummary {
+    let summary = self.summary();
+    let mut state = self.0.lock().unwrap();
+    let running = std::mem::take(&mut state.running);
+    *state = State {running, ..State::default()};
+    summary
+  }
+
+  //Restarts the counters and drops all phases, e.g. the running phases of a
+  //failed run
+  pub fn reset(&self) {
+    *self.0.lock().unwrap() = State::default();
+  }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{env, fs};
+use serde::{Deserialize, Serialize};
+use log::*;
+
+use crate::config::*;
+use crate::utils::{self, dram, serialize::*};
+
+//Persistent wear accounting: the total number of activations each DRAM row
+//received over all experiments on this machine
+//Rows that are hammered over and over again can wear out, which biases
+//reliability studies, the accounting is kept per DRAM module (`dram_id` of
+//the DRAM config) because the physical to DRAM mapping depends on it
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RowWear {
+  //dram_id -> bank -> row -> activations
+  modules: BTreeMap<String, BTreeMap<u64, BTreeMap<u64, u64>>>
+}
+
+//Path of the accounting: `RH_ROW_WEAR` or ROW_WEAR_PATH
+pub fn wear_path() -> String {
+  env::var("RH_ROW_WEAR").unwrap_or_else(|_| ROW_WEAR_PATH.to_owned())
+}
+
+impl RowWear {
+  //Returns an empty accounting if arg:path does not exist yet
+  pub fn load(path: &str) -> RowWear {
+    if Path::new(path).exists() {
+      utils::files::parse_json(path)
+    } else {
+      RowWear::default()
+    }
+  }
+
+  pub fn save(&self, path: &str) {
+    info!("Saving row wear to {}", path);
+    if let Some(dir) = Path::new(path).parent() {
+      fs::create_dir_all(dir).expect("Creating the row wear directory failed");
+    }
+    fs::write(path, serde_json::to_string(self).unwrap())
+      .unwrap_or_else(|_| panic!("Writing the row wear {} failed", path));
+  }
+
+  //Adds arg:activations to the row at physical address arg:row_phys_addr
+  pub fn add(
+    &mut self, dram_config: &DRAMConfig, row_phys_addr: u64, activations: u64
+  ) {
+    let dram_addr = dram::phys_to_dram(row_phys_addr, dram_config);
+    *self.modules.entry(dram_config.dram_id.clone()).or_default()
+      .entry(dram_addr.bank).or_default()
+      .entry(dram_addr.row).or_insert(0) += activations;
+  }
+
+  //Accounts one hammer run of arg:pattern, every aggressor row is activated
+  //as often as its access rate gives in arg:hammer_count iterations
+  pub fn add_pattern(
+    &mut self, dram_config: &DRAMConfig, pattern: &AggressorPattern,
+    hammer_count: u64
+  ) {
+    for row in pattern {
+      self.add(dram_config, row.frames[0], row.rate().accesses(hammer_count));
+    }
+  }
+
+  //total activations of the row at physical address arg:row_phys_addr
+  pub fn activations(&self, dram_config: &DRAMConfig, row_phys_addr: u64)
+    -> u64
+  {
+    let dram_addr = dram::phys_to_dram(row_phys_addr, dram_config);
+    self.modules.get(&dram_config.dram_id)
+      .and_then(|banks| banks.get(&dram_addr.bank))
+      .and_then(|rows| rows.get(&dram_addr.row))
+      .cloned().unwrap_or(0)
+  }
+}
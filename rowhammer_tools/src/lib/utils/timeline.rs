@@ -0,0 +1,124 @@
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Instant;
+use chrono::prelude::*;
+use serde::Serialize;
+use log::*;
+
+//Structured timeline of a loader run (phases, allocation progress, hammer
+//durations, flip outcomes) to profile the attack latency on real targets
+//Recording is enabled with the `RH_TIMELINE=<path>` environment variable,
+//without it all record functions are no-ops
+//The timeline of a session is in its context (see RhContext), so the
+//allocation code records to the timeline of the session it allocates for
+//A timeline that is dropped before `finish` (e.g., when a panic unwinds)
+//writes the events recorded so far
+
+//number of allocated pages between two progress events (a power of 2)
+static PROGRESS_INTERVAL: u64 = 1 << 16;
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+  PhaseStart {phase: String},
+  PhaseEnd {phase: String},
+  //allocation progress, every PROGRESS_INTERVAL pages and at the end
+  PagesAllocated {pages: u64, frames_found: u64},
+  FrameFound {frame_addr: u64, pages: u64},
+  Hammer {aggressors: usize, hammer_count: u64, duration_us: u64},
+  Flip {phys_addr: u64, bitflip: String, flipped: bool, attempts: u32}
+}
+
+#[derive(Serialize)]
+struct Entry {
+  time_us: u64, //since the start of the recording
+  #[serde(flatten)]
+  event: Event
+}
+
+#[derive(Serialize)]
+struct Recording {
+  binary: String,
+  start: String,
+  #[serde(skip)]
+  start_instant: Instant,
+  #[serde(skip)]
+  path: String,
+  entries: Vec<Entry>
+}
+
+#[derive(Default)]
+pub struct Timeline(Mutex<Option<Recording>>);
+
+impl Timeline {
+  //Starts recording if `RH_TIMELINE` is set (never with arg:stealth)
+  pub fn start_if_requested(&self, binary: &str, stealth: bool) {
+    if stealth {
+      return;
+    }
+    if let Ok(path) = env::var("RH_TIMELINE") {
+      info!("Recording the timeline to {}", path);
+      *self.0.lock().unwrap() = Some(Recording {
+        binary: binary.to_owned(),
+        start: Local::now().to_rfc3339(),
+        start_instant: Instant::now(),
+        path,
+        entries: Vec::new()
+      });
+    }
+  }
+
+  pub fn record(&self, event: Event) {
+    if let Some(recording) = self.0.lock().unwrap().as_mut() {
+      let time_us = recording.start_instant.elapsed().as_micros() as u64;
+      recording.entries.push(Entry {time_us, event});
+    }
+  }
+
+  pub fn phase_start(&self, phase: &str) {
+    self.record(Event::PhaseStart {phase: phase.to_owned()});
+  }
+
+  pub fn phase_end(&self, phase: &str) {
+    self.record(Event::PhaseEnd {phase: phase.to_owned()});
+  }
+
+  //Allocation progress after arg:pages allocated pages (only every
+  //PROGRESS_INTERVAL pages, use arg:force for the final count)
+  pub fn pages_allocated(&self, pages: u64, frames_found: u64, force: bool) {
+    if force || pages & (PROGRESS_INTERVAL - 1) == 0 {
+      self.record(Event::PagesAllocated {pages, frames_found});
+    }
+  }
+
+  //Stops recording and writes the timeline (as JSON) to the `RH_TIMELINE`
+  //path
+  pub fn finish(&self) {
+    if let Some(recording) = self.0.lock().unwrap().take() {
+      recording.write()
+        .unwrap_or_else(|_| panic!("Writing the timeline {} failed",
+        recording.path));
+    }
+  }
+}
+
+impl Recording {
+  fn write(&self) -> std::io::Result<()> {
+    info!("Writing the timeline ({} events) to {}", self.entries.len(),
+      self.path);
+    fs::write(&self.path, serde_json::to_string_pretty(self).unwrap())
+  }
+}
+
+impl Drop for Timeline {
+  fn drop(&mut self) {
+    //the lock is poisoned if a panic unwound while recording
+    let recording = self.0.get_mut().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(recording) = recording {
+      if let Err(e) = recording.write() {
+        error!("Writing the timeline {} failed: {}", recording.path, e);
+      }
+    }
+  }
+}
@@ -0,0 +1,155 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use chrono::prelude::*;
+use log::*;
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use serde::{Deserialize, Serialize};
+
+use crate::config::*;
+
+//Machine-wide lock of the rowhammer tools: the templater, the prehammer, the
+//bench and the loader take an exclusive flock on TOOL_LOCK_PATH (can be
+//overridden with the `RH_TOOL_LOCK` environment variable) before they
+//allocate or hammer, so two tools can not run at the same time and silently
+//compete for frames, the page cache and the isolated cores
+//The holder writes its tool name, pid and start time into the lock file and
+//empties it when it releases the lock, a second tool refuses to start and
+//names the holder
+//Stale locks:
+//  - the kernel releases the flock when the holder exits, a lock file that is
+//    not locked but not empty is the leftover of a crashed tool, it is taken
+//    over with a note
+//  - a lock that is held, but whose holder pid is gone (a child process
+//    inherited the lock), is taken over with a warning
+//With `--steal-lock` (the loader: `RH_STEAL_LOCK`), a lock of a running tool
+//is taken over as well: the lock file is replaced by a new one, the old
+//holder keeps running with its lock on the removed file
+//The loader in stealth mode takes the lock anonymously (see
+//`acquire_anonymous`): it holds the flock of an empty lock file, the other
+//tools refuse to start without naming the holder
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LockHolder {
+  pub tool: String,
+  pub pid: u32,
+  pub since: String
+}
+
+impl LockHolder {
+  fn alive(&self) -> bool {
+    Path::new(&format!("/proc/{}", self.pid)).exists()
+  }
+}
+
+//Held until it is dropped
+pub struct ToolLock {
+  file: File
+}
+
+//Path of the lock: `RH_TOOL_LOCK` or TOOL_LOCK_PATH
+pub fn lock_path() -> String {
+  env::var("RH_TOOL_LOCK").unwrap_or_else(|_| TOOL_LOCK_PATH.to_owned())
+}
+
+//Removes `--steal-lock` from arg:args, returns whether it was given
+pub fn take_steal_flag(args: &mut Vec<String>) -> bool {
+  let len = args.len();
+  args.retain(|a| a != "--steal-lock");
+  args.len() != len
+}
+
+//None if the lock file is empty (or the holder is still writing it)
+fn read_holder(file: &mut File) -> Option<LockHolder> {
+  let mut content = String::new();
+  file.seek(SeekFrom::Start(0)).ok()?;
+  file.read_to_string(&mut content).ok()?;
+  serde_json::from_str(&content).ok()
+}
+
+//whether arg:path still is arg:file (and was not replaced by a steal)
+fn is_current(file: &File, path: &str) -> bool {
+  match (file.metadata(), fs::metadata(path)) {
+    (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+    _ => false
+  }
+}
+
+impl ToolLock {
+  //Takes the lock for arg:tool, panics if a running tool holds it, unless
+  //arg:steal
+  pub fn acquire(tool: &str, steal: bool) -> ToolLock {
+    ToolLock::take(Some(tool), steal)
+  }
+
+  //Same as `acquire`, without writing the holder into the lock file (it is
+  //left empty), for the stealth mode of the loader
+  pub fn acquire_anonymous(steal: bool) -> ToolLock {
+    ToolLock::take(None, steal)
+  }
+
+  fn take(tool: Option<&str>, steal: bool) -> ToolLock {
+    let path = lock_path();
+    loop {
+      let mut file = OpenOptions::new().read(true).write(true).create(true)
+        .truncate(false)
+        .open(&path)
+        .unwrap_or_else(|e| panic!("Opening the tool lock {} failed: {}",
+          path, e));
+      match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+        Ok(()) => (),
+        Err(Errno::EWOULDBLOCK) => {
+          match read_holder(&mut file) {
+            Some(holder) if !holder.alive() =>
+              warn!("Taking over the stale tool lock {} of {} (pid {} \
+                exited)", path, holder.tool, holder.pid),
+            Some(holder) if steal =>
+              warn!("Stealing the tool lock {} from {} (pid {}, since {})",
+                path, holder.tool, holder.pid, holder.since),
+            Some(holder) => panic!("{} (pid {}, since {}) holds the tool lock \
+              {}, wait for it to finish or pass --steal-lock (the loader: \
+              RH_STEAL_LOCK)", holder.tool, holder.pid, holder.since, path),
+            None if steal =>
+              warn!("Stealing the tool lock {} from an unknown holder", path),
+            None => panic!("Another tool is taking the tool lock {} (or a \
+              loader in stealth mode holds it)", path)
+          }
+          //the holder keeps the lock on the removed file
+          let _ = fs::remove_file(&path);
+          continue;
+        },
+        Err(e) => panic!("Locking {} failed: {}", path, e)
+      }
+      //stolen between opening and locking it
+      if !is_current(&file, &path) {
+        continue;
+      }
+      if let Some(holder) = read_holder(&mut file) {
+        info!("Taking over the tool lock {} of {} (pid {}), it exited without \
+          releasing it", path, holder.tool, holder.pid);
+      }
+      //an empty file (the stale holder would let the other tools take over)
+      file.set_len(0).unwrap();
+      if let Some(tool) = tool {
+        let holder = LockHolder {tool: tool.to_owned(),
+          pid: std::process::id(), since: Local::now().to_rfc3339()};
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(serde_json::to_string(&holder).unwrap().as_bytes())
+          .unwrap_or_else(|_| panic!("Writing the tool lock {} failed", path));
+      }
+      debug!("Took the tool lock {}", path);
+      return ToolLock {file};
+    }
+  }
+}
+
+impl Drop for ToolLock {
+  //empties the file, the flock is released when it is closed
+  fn drop(&mut self) {
+    let _ = self.file.set_len(0);
+  }
+}
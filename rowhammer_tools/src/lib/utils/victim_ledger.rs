@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{env, fs};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use log::*;
+
+use crate::config::*;
+use crate::utils::{self, serialize::*};
+
+//Bookkeeping of the victim bits that were used in attacks, across runs
+//Cells can degrade when they are hammered over and over again, a victim bit
+//that flipped before but keeps failing in the latest attacks is considered
+//worn and should not be selected (again) for a new binary
+
+//Use of one victim bit in one attack
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VictimUse {
+  pub phys_addr: u64, //physical address of the victim byte
+  pub bitflip: BitFlip,
+  pub binary: String,
+  pub time: String,
+  pub flipped: bool
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct VictimLedger {
+  uses: Vec<VictimUse>
+}
+
+//Path of the ledger: `RH_VICTIM_LEDGER` or VICTIM_LEDGER_PATH
+pub fn ledger_path() -> String {
+  env::var("RH_VICTIM_LEDGER")
+    .unwrap_or_else(|_| VICTIM_LEDGER_PATH.to_owned())
+}
+
+impl VictimLedger {
+  //Returns an empty ledger if arg:path does not exist yet
+  pub fn load(path: &str) -> VictimLedger {
+    if Path::new(path).exists() {
+      utils::files::parse_json(path)
+    } else {
+      VictimLedger::default()
+    }
+  }
+
+  pub fn save(&self, path: &str) {
+    if let Some(dir) = Path::new(path).parent() {
+      fs::create_dir_all(dir).expect("Creating the ledger directory failed");
+    }
+    fs::write(path, serde_json::to_string_pretty(self).unwrap())
+      .unwrap_or_else(|_| panic!("Writing the victim ledger {} failed", path));
+  }
+
+  pub fn record(
+    &mut self, binary: &str, phys_addr: u64, bitflip: &BitFlip, flipped: bool
+  ) {
+    self.uses.push(VictimUse {
+      phys_addr, bitflip: bitflip.clone(), binary: binary.to_owned(),
+      time: Local::now().to_rfc3339(), flipped
+    });
+  }
+
+  //all recorded uses of the victim bit, oldest first
+  pub fn uses<'a>(
+    &'a self, phys_addr: u64, bitflip: &'a BitFlip
+  ) -> impl Iterator<Item = &'a VictimUse> {
+    self.uses.iter()
+      .filter(move |u| u.phys_addr == phys_addr && u.bitflip == *bitflip)
+  }
+
+  //A victim bit is worn when it flipped before, but not in its last
+  //WORN_VICTIM_FAILURES uses
+  pub fn is_worn(&self, phys_addr: u64, bitflip: &BitFlip) -> bool {
+    let uses: Vec<_> = self.uses(phys_addr, bitflip).collect();
+    let recent = uses.len().saturating_sub(WORN_VICTIM_FAILURES);
+    uses.len() > WORN_VICTIM_FAILURES
+      && uses[..recent].iter().any(|u| u.flipped)
+      && uses[recent..].iter().all(|u| !u.flipped)
+  }
+
+  //binary -> number of uses of the victim bit
+  pub fn binaries<'a>(&'a self, phys_addr: u64, bitflip: &'a BitFlip)
+    -> BTreeMap<&'a str, usize>
+  {
+    let mut binaries = BTreeMap::new();
+    for u in self.uses(phys_addr, bitflip) {
+      *binaries.entry(u.binary.as_str()).or_insert(0) += 1;
+    }
+    binaries
+  }
+}
+
+//Identifies the binary at arg:path in the ledger (absolute path if possible)
+pub fn binary_name(path: &str) -> String {
+  fs::canonicalize(path).map(|p| p.to_string_lossy().into_owned())
+    .unwrap_or_else(|_| path.to_owned())
+}
+
+//Victim bits (byte address, bitflip) in a victim addresses file (see
+//docs/example_victim_addresses.txt), e.g. VICTIM_ADDRESSES_PATH
+pub fn parse_victim_addresses(path: &str) -> Vec<(u64, BitFlip)> {
+  info!("Parsing victim addresses: {}", path);
+  fs::read_to_string(path)
+    .unwrap_or_else(|_| panic!("Reading victim addresses {} failed", path))
+    .lines()
+    .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+    .map(|l| {
+      let fields: Vec<_> = l.split_whitespace().collect();
+      assert!(fields.len() >= 3, "Invalid victim address line: {}", l);
+      let phys_addr =
+        u64::from_str_radix(fields[0].trim_start_matches("0x"), 16)
+        .unwrap_or_else(|_| panic!("Invalid victim address: {}", fields[0]));
+      let bitflip: BitFlip = format!("{}{}", fields[1], fields[2]).parse()
+        .unwrap();
+      (phys_addr, bitflip)
+    }).collect()
+}
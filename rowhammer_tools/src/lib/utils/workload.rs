@@ -0,0 +1,265 @@
+use std::arch::x86_64::{_mm_clflush, _mm_mfence};
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::*;
+
+use crate::core::stats::CoRunnerStats;
+use crate::utils::{host, sched};
+use crate::utils::serialize::{CoRunner, CoRunnerKind};
+
+//Victim-side workload: a thread that accesses the victim pages while the
+//aggressors are hammered, like the target does in a real attack, to evaluate
+//whether these accesses (which recharge the cells of the victim row)
+//suppress flips
+//Without the workload the victims are completely idle during hammering
+
+//Co-runners (`co_runners` in the templater config): threads pinned to other
+//cores that generate system activity while hammering, to measure how much
+//flips a realistic, busy machine loses compared to an idle one (see
+//`CoRunnerKind`)
+//Every co-runner reads and writes cache lines of its own buffer, the
+//`memory_bandwidth` co-runner sequentially, the `cache_thrash` co-runner in
+//a random order, and only while hammering (between `resume` and `pause`),
+//it sleeps during the memory initialization and the flip checks
+
+//cache lines a co-runner touches between the checks of its flags
+static CO_RUNNER_BATCH: usize = 1 << 12;
+//sleep of a paused co-runner between the checks of its flags
+static CO_RUNNER_SLEEP: Duration = Duration::from_micros(100);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VictimAccess {
+  //plain reads, mostly served from the cache
+  Read,
+  //clflush + read, every access opens the victim row in DRAM
+  //There is no instruction fetch variant: executing the victim content is not
+  //safe (it is arbitrary), and a fetch that misses the caches has the same
+  //effect on the DRAM row as this read
+  Uncached
+}
+
+impl FromStr for VictimAccess {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "read" => Ok(VictimAccess::Read),
+      "uncached" => Ok(VictimAccess::Uncached),
+      _ => Err(format!("Unknown victim access {}", s))
+    }
+  }
+}
+
+//The thread stops when the workload is dropped without `stop` (e.g., when a
+//panic unwinds)
+pub struct VictimWorkload {
+  stop: Arc<AtomicBool>,
+  handle: Option<JoinHandle<u64>>
+}
+
+impl VictimWorkload {
+  //Accesses all virtual addresses in arg:addrs every arg:interval until
+  //stopped, from a thread pinned to core arg:core (the thread spins between
+  //the rounds to keep short intervals accurate)
+  pub fn spawn(
+    addrs: Vec<u64>, access: VictimAccess, interval: Duration, core: u32
+  ) -> VictimWorkload {
+    let stop = Arc::new(AtomicBool::new(false));
+    let s = stop.clone();
+    info!("Starting the {:?} victim workload on core {}", access, core);
+    let handle = std::thread::spawn(move || {
+      sched::pin_to_core(core);
+      let mut accesses = 0u64;
+      let mut next = Instant::now();
+      while !s.load(Ordering::Relaxed) {
+        if Instant::now() < next {
+          std::hint::spin_loop();
+          continue;
+        }
+        for &addr in &addrs {
+          unsafe {
+            if access == VictimAccess::Uncached {
+              _mm_clflush(addr as *const u8);
+              _mm_mfence();
+            }
+            std::ptr::read_volatile(addr as *const u8);
+          }
+        }
+        accesses += addrs.len() as u64;
+        next = Instant::now() + interval;
+      }
+      accesses
+    });
+    VictimWorkload {stop, handle: Some(handle)}
+  }
+
+  //Stops the workload, returns the number of victim accesses
+  pub fn stop(mut self) -> u64 {
+    self.stop.store(true, Ordering::Relaxed);
+    let accesses = self.handle.take().unwrap().join()
+      .expect("Victim workload panicked");
+    debug!("Victim workload performed {} accesses", accesses);
+    accesses
+  }
+}
+
+impl Drop for VictimWorkload {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+//Workload requested with the `RH_VICTIM_WORKLOAD=<read|uncached>`
+//environment variable, the interval between the access rounds is
+//`RH_VICTIM_WORKLOAD_INTERVAL=<ns>` (default 0 = back to back)
+pub fn requested() -> Option<(VictimAccess, Duration)> {
+  let access = env::var("RH_VICTIM_WORKLOAD").ok()?.parse()
+    .expect("Invalid RH_VICTIM_WORKLOAD");
+  let interval = env::var("RH_VICTIM_WORKLOAD_INTERVAL").ok().map_or(0, |x|
+    x.parse().expect("RH_VICTIM_WORKLOAD_INTERVAL is NaN"));
+  Some((access, Duration::from_nanos(interval)))
+}
+
+//Core of the victim workload: `RH_VICTIM_WORKLOAD_CORE=<core>`, by default
+//the first online core that is not assigned to the hammering, the workload
+//must not share (and slow down) the hammering core
+fn workload_core() -> u32 {
+  let assigned = host::get_assigned_cpu_cores();
+  if let Ok(core) = env::var("RH_VICTIM_WORKLOAD_CORE") {
+    let core = core.parse().expect("RH_VICTIM_WORKLOAD_CORE is NaN");
+    assert!(host::get_all_cpu_cores().contains(&core),
+      "Victim workload core {} does not exist", core);
+    if assigned.contains(&core) {
+      warn!("Victim workload on core {}, which is assigned to the hammering",
+        core);
+    }
+    return core;
+  }
+  let mut free: Vec<u32> = host::get_all_cpu_cores().difference(&assigned)
+    .cloned().collect();
+  free.sort();
+  *free.first().expect("All cores are assigned to the hammering, set \
+    RH_VICTIM_WORKLOAD_CORE or assign fewer cores with taskset")
+}
+
+//Starts the requested workload (if any) on the victim virtual addresses
+//arg:addrs
+pub fn spawn_if_requested(addrs: Vec<u64>) -> Option<VictimWorkload> {
+  requested().map(|(access, interval)|
+    VictimWorkload::spawn(addrs, access, interval, workload_core()))
+}
+
+//The co-runner threads stop when they are dropped without `stop`
+pub struct CoRunners {
+  active: Arc<AtomicBool>,
+  stop: Arc<AtomicBool>,
+  threads: Vec<(CoRunner, JoinHandle<u64>)>,
+  //total time the co-runners were active, start of the current activity
+  active_time: Duration,
+  resumed: Option<Instant>
+}
+
+//The workload of arg:co_runner until arg:stop, only while arg:active
+//Returns the number of touched cache lines
+fn run_co_runner(
+  co_runner: CoRunner, active: &AtomicBool, stop: &AtomicBool
+) -> u64 {
+  sched::pin_to_core(co_runner.core);
+  //allocated on the node of the co-runner's core (first touch)
+  let mut buffer = vec![1u8; co_runner.buffer_size().max(64)];
+  let lines = buffer.len() / 64;
+  let (mut line, mut state, mut accesses) = (0, 0x9e37_79b9_7f4a_7c15u64, 0);
+  while !stop.load(Ordering::Relaxed) {
+    if !active.load(Ordering::Relaxed) {
+      thread::sleep(CO_RUNNER_SLEEP);
+      continue;
+    }
+    for _ in 0..CO_RUNNER_BATCH {
+      line = match co_runner.kind {
+        CoRunnerKind::MemoryBandwidth => (line + 1) % lines,
+        CoRunnerKind::CacheThrash => {
+          //xorshift, the hardware prefetchers can not follow it
+          state ^= state << 13;
+          state ^= state >> 7;
+          state ^= state << 17;
+          (state % lines as u64) as usize
+        }
+      };
+      unsafe {
+        let addr = buffer.as_mut_ptr().add(line * 64);
+        std::ptr::write_volatile(addr,
+          std::ptr::read_volatile(addr).wrapping_add(1));
+      }
+    }
+    accesses += CO_RUNNER_BATCH as u64;
+  }
+  accesses
+}
+
+impl CoRunners {
+  //Starts the threads of arg:co_runners, paused
+  pub fn spawn(co_runners: &[CoRunner]) -> CoRunners {
+    let active = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (cores, assigned) = if co_runners.is_empty() {
+      Default::default()
+    } else {
+      (host::get_all_cpu_cores(), host::get_assigned_cpu_cores())
+    };
+    let threads = co_runners.iter().map(|c| {
+      assert!(cores.contains(&c.core), "Co-runner core {} does not exist",
+        c.core);
+      if assigned.contains(&c.core) {
+        warn!("Co-runner {:?} on core {}, which is assigned to the \
+          hammering", c.kind, c.core);
+      }
+      info!("Starting a {:?} co-runner on core {} ({}KB buffer)", c.kind,
+        c.core, c.buffer_size() >> 10);
+      let (co_runner, a, s) = (*c, active.clone(), stop.clone());
+      (*c, thread::spawn(move || run_co_runner(co_runner, &a, &s)))
+    }).collect();
+    CoRunners {active, stop, threads, active_time: Duration::ZERO,
+      resumed: None}
+  }
+
+  //Lets the co-runners run (before hammering)
+  pub fn resume(&mut self) {
+    if !self.threads.is_empty() && self.resumed.is_none() {
+      self.active.store(true, Ordering::Relaxed);
+      self.resumed = Some(Instant::now());
+    }
+  }
+
+  //Pauses the co-runners (after hammering)
+  pub fn pause(&mut self) {
+    if let Some(resumed) = self.resumed.take() {
+      self.active.store(false, Ordering::Relaxed);
+      self.active_time += resumed.elapsed();
+    }
+  }
+
+  //Stops the co-runners, returns what they achieved while active
+  pub fn stop(mut self) -> Vec<CoRunnerStats> {
+    self.pause();
+    self.stop.store(true, Ordering::Relaxed);
+    let active_s = self.active_time.as_secs_f64().max(1e-9);
+    self.threads.drain(..).map(|(co_runner, handle)| {
+      let accesses = handle.join().expect("Co-runner panicked");
+      let stats = CoRunnerStats {kind: co_runner.kind, core: co_runner.core,
+        accesses, bytes_per_s: (accesses * 64) as f64 / active_s};
+      info!("{:?} co-runner on core {}: {:.2}GB/s while hammering",
+        stats.kind, stats.core, stats.bytes_per_s / 1e9);
+      stats
+    }).collect()
+  }
+}
+
+impl Drop for CoRunners {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use log::*;
+use serde::Serialize;
+
+use crate::config::*;
+use crate::context::RhContext;
+use crate::utils::{dram, serialize::*};
+
+//Interactions between the victim bits of an attack config that are hammered
+//with different aggressor patterns:
+//  - init: the patterns of two victim bits use the same aggressor row with
+//    different init values, initializing all aggressors at once would
+//    overwrite the init value one of them was templated with
+//    => the patterns are split into init groups (see `init_groups`), the
+//    rows of a group are initialized right before the group is hammered
+//  - interference: an aggressor row of another pattern is within
+//    VICTIM_INTERFERENCE_ROWS rows (same bank) of the victim row of a bit,
+//    hammering that pattern disturbs the victim row as well (flips in other
+//    bits of the victim frame, which holds binary content)
+//    => reported, with `on_conflict = "refuse"` the attack is aborted
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictKind {
+  Init,
+  Interference
+}
+
+#[derive(Serialize)]
+pub struct VictimConflict {
+  pub kind: ConflictKind,
+  pub victim_phys_addr: u64,
+  pub bitflip: String,
+  //key of the aggressor pattern of the victim bit
+  pub pattern: String,
+  //key of the conflicting aggressor pattern
+  pub other_pattern: String,
+  //first frame of the conflicting aggressor row of the other pattern
+  pub aggressor: u64,
+  //init: init value of the row in the pattern and in the other pattern
+  pub inits: Option<(u8, u8)>,
+  //interference: row distance of the victim to the aggressor
+  pub distance: Option<i64>
+}
+
+//An aggressor row (first frame) of arg:a that arg:b uses with another init
+//value, with both init values
+pub fn init_conflict(a: &AggressorPattern, b: &AggressorPattern)
+  -> Option<(u64, u8, u8)>
+{
+  a.into_iter().find_map(|x| b.into_iter()
+    .find(|y| y.frames[0] == x.frames[0] && y.init != x.init)
+    .map(|y| (x.frames[0], x.init, y.init)))
+}
+
+//Splits arg:patterns (in order) into groups without init conflicts, each
+//pattern goes into the first group it does not conflict with
+pub fn init_groups<'a>(patterns: &[&'a AggressorPattern])
+  -> Vec<Vec<&'a AggressorPattern>>
+{
+  let mut groups: Vec<Vec<&AggressorPattern>> = Vec::new();
+  for p in patterns {
+    match groups.iter_mut()
+      .find(|g| g.iter().all(|q| init_conflict(p, q).is_none())) {
+      Some(group) => group.push(p),
+      None => groups.push(vec![p])
+    }
+  }
+  groups
+}
+
+//All conflicts of the victim bits of arg:attack_config (binary content and
+//page tables), needs the DRAM config of arg:ctx
+pub fn find(ctx: &RhContext, attack_config: &AttackConfig)
+  -> Vec<VictimConflict>
+{
+  let dram_config = ctx.dram_config();
+  //the hammered patterns
+  let mut patterns: HashMap<&str, &AggressorPattern> = HashMap::new();
+  let bits: Vec<(u64, &VictimBit)> = attack_config.victim_frames.iter()
+    .flat_map(|v| v.victim_bits.iter().map(move |b| (v.frame_addr, b)))
+    .chain(attack_config.pte_victim_frames.iter()
+    .flat_map(|v| v.victim_bits.iter().map(move |b| (v.frame_addr, b))))
+    .collect();
+  for (_, bit) in &bits {
+    patterns.insert(&bit.aggr_pattern_key,
+      attack_config.aggr_pattern(&bit.aggr_pattern_key));
+  }
+  let mut keys: Vec<&str> = patterns.keys().cloned().collect();
+  keys.sort_unstable();
+
+  let mut conflicts = Vec::new();
+  for (frame_addr, bit) in bits {
+    let phys_addr = frame_addr + bit.offset;
+    let victim = dram::phys_to_dram(phys_addr, dram_config);
+    let pattern = patterns[bit.aggr_pattern_key.as_str()];
+    let conflict = |kind, other: &str, aggressor, inits, distance|
+      VictimConflict {
+        kind,
+        victim_phys_addr: phys_addr,
+        bitflip: bit.bitflip.to_string(),
+        pattern: bit.aggr_pattern_key.clone(),
+        other_pattern: other.to_owned(),
+        aggressor,
+        inits,
+        distance
+      };
+    for other in keys.iter().filter(|k| **k != bit.aggr_pattern_key) {
+      if let Some((row, init, other_init)) =
+        init_conflict(pattern, patterns[other])
+      {
+        conflicts.push(conflict(ConflictKind::Init, other, row,
+          Some((init, other_init)), None));
+      }
+      //the nearest aggressor of the other pattern that is not an aggressor
+      //of the bit's pattern (overlaps with the victim row are rejected by
+      //`AttackConfig::check`)
+      let nearest = patterns[other].into_iter()
+        .filter(|a| pattern.into_iter().all(|x| x.frames[0] != a.frames[0]))
+        .filter_map(|a| {
+          let aggr = dram::phys_to_dram(a.frames[0], dram_config);
+          let distance = victim.row as i64 - aggr.row as i64;
+          (aggr.bank == victim.bank && distance != 0
+            && distance.abs() <= VICTIM_INTERFERENCE_ROWS)
+            .then(|| (distance, a.frames[0]))
+        })
+        .min_by_key(|(distance, _)| distance.abs());
+      if let Some((distance, aggressor)) = nearest {
+        conflicts.push(conflict(ConflictKind::Interference, other, aggressor,
+          None, Some(distance)));
+      }
+    }
+  }
+  conflicts
+}
+
+//Logs the conflicts of arg:attack_config (see `find`), panics on an
+//interference with `on_conflict = "refuse"`
+pub fn check(ctx: &RhContext, attack_config: &AttackConfig) {
+  let conflicts = find(ctx, attack_config);
+  for c in &conflicts {
+    let json = serde_json::to_string(c).unwrap();
+    match c.kind {
+      ConflictKind::Init => info!("Victim bits need different aggressor init \
+        values, their patterns are initialized and hammered separately: {}",
+        json),
+      ConflictKind::Interference => warn!("Hammering an aggressor pattern \
+        disturbs the victim row of another pattern: {}", json)
+    }
+  }
+  let interferences = conflicts.iter()
+    .filter(|c| c.kind == ConflictKind::Interference).count();
+  assert!(interferences == 0 || attack_config.on_conflict
+    != ConflictPolicy::Refuse, "Refusing to hammer: {} victim bits are \
+    disturbed by the aggressors of other patterns (on_conflict = refuse)",
+    interferences);
+}
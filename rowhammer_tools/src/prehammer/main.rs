@@ -1,9 +1,19 @@
 use log::*;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
 use colored::Colorize;
 
 use rowhammer::{*, config::*};
-use rowhammer::utils::{dram, files, serialize::*};
+use rowhammer::utils::{dram, files, host, ram_swap, row_wear};
+use rowhammer::utils::serialize::*;
+use rowhammer::utils::workload;
+use rowhammer::utils::victim_ledger::{self, VictimLedger};
+use rowhammer::utils::oracle::PagemapOracle;
+use rowhammer::context::RhContext;
+use rowhammer::layout::PatternLayout;
+use rowhammer::utils::report::{self, Reporter, Reporters};
+use rowhammer::utils::tool_lock::{self, ToolLock};
 
 pub static CONSECUTIVE_SUCCESSES: u8 = 5;
 pub static GIVE_UP_THESHOLD: u8 = 20;
@@ -15,67 +25,278 @@ pub static GIVE_UP_THESHOLD: u8 = 20;
 //  be caseful with overlappings
 //  Config in attack_config.toml, ignores the `page_file_offset` and
 //  `segment_virt_addr` fields
+//Both can access the victims while hammering with `RH_VICTIM_WORKLOAD` (see
+//utils::workload)
 //Both stop if there are either CONSECUTIVE_SUCCESSES consecutive successes or
 //after GIVE_UP_THESHOLD iterations
+//Both restore the victim bytes to their content before hammering afterwards
+//and verify it (through /dev/mem with `RH_RESTORE_DEVMEM`)
+//Both report the tested victims and the result to the sinks in `RH_REPORT`
+//(see utils/report.rs)
+//Both take the tool lock (see utils/tool_lock.rs), add `--steal-lock` to the
+//arguments to take it from a running tool
+//Both check their external dependencies first (see preflight.rs)
 //
-//USAGE: sudo ./prehammer mode attack_config/memory_template
+//"config checker" mode: validates and lints an attack config without
+//  allocating or hammering anything (does not need root), exits with an
+//  error code if any of the checks fails, the aggressor rows have the pages
+//  per row of the DRAM config at DRAM_CONFIG_PATH
+//
+//USAGE: sudo ./prehammer mode attack_config/memory_template [binary]
 //  `mode` is either `attack` for the "attack tester" mode, or anything else for
 //  the "template tester" mode
 //  `attack_config/memory_template` is the path to the attack_config toml file
 //  for the "attack tester" mode, or the memory_template json file for the
 //  "template tester" mode
+//  `binary` (attack tester only) is the attacked binary, needed to resolve
+//  victim bits given by a symbol
+//"victim checker" mode: checks the victim bits in a victim addresses file
+//  against the victim ledger (worn bits, bits used by other binaries)
+//
+//USAGE: ./prehammer check-config attack_config [binary]
+//  `binary` is the attacked binary, the checks of the PT_NULL segment and
+//  `segment_virt_addr` are skipped and victim bits given by a symbol are not
+//  resolved without it
+//USAGE: ./prehammer check-victims [victim_addresses]
+//  `victim_addresses` defaults to VICTIM_ADDRESSES_PATH
+//"preflight" mode: checks the external dependencies (commands, /proc, /sys
+//  and /dev files, config files) of a tool (default: all tools) and lists
+//  every missing one, exits with an error code if a required one is missing
+//  (does not need root, but run it as root to find the files only root sees)
+//USAGE: ./prehammer preflight [templater|prehammer|bench|loader]
+//"RAM swap id" mode: sets the RAM swap id of the installed DIMMs (see
+//  utils/ram_swap.rs), asks for it without `id`, `show` prints the recorded
+//  swaps and the current id
+//USAGE: sudo ./prehammer ram-id [id | show]
 
 pub fn main() {
+  let mut args: Vec<_> = std::env::args().collect();
+  let steal_lock = tool_lock::take_steal_flag(&mut args);
+  if args[1] == "check-config" {
+    run_config_checker(&args[2], args.get(3).map(|x| x.as_str()));
+    return;
+  } else if args[1] == "check-victims" {
+    run_victim_checker(args.get(2).map_or(VICTIM_ADDRESSES_PATH, |x| x));
+    return;
+  } else if args[1] == "preflight" {
+    run_preflight(args.get(2).map(|x| x.as_str()));
+    return;
+  }
+
   info!("Starting prehammer");
   let mut logger_builder = rowhammer::configure();
   //logger_builder.filter_module("rowhammer", LevelFilter::Info);
   logger_builder.init();
-  let args: Vec<_> = std::env::args().collect();
+  if args[1] == "ram-id" {
+    run_ram_id(args.get(2).map(|x| x.as_str()));
+    return;
+  }
+  let mut features = vec![preflight::Feature::Allocation,
+    preflight::Feature::PageCache];
+  if args[1] == "attack" {
+    features.push(preflight::Feature::DramConfig);
+  }
+  preflight::check(&features);
+  let _lock = ToolLock::acquire("prehammer", steal_lock);
+  host::restrict_to_core_type();
 
   if args[1] == "attack" {
-    run_attack_tester(&args[2]);
+    run_attack_tester(&args[2], args.get(3).map(|x| x.as_str()));
   } else {
     run_template_tester(&args[2]);
   }
 }
 
+fn run_config_checker(path: &str, program_path: Option<&str>) {
+  //the geometry of the saved DRAM config, creating it from the DIMMs needs
+  //root
+  let mut lints = Vec::new();
+  let ctx = if Path::new(DRAM_CONFIG_PATH).exists() {
+    RhContext::from_env().with_dram_config(files::parse_toml(DRAM_CONFIG_PATH))
+  } else {
+    let ctx = RhContext::from_env();
+    lints.push(lint::Lint {severity: lint::Severity::Warning,
+      message: format!("No DRAM config at {}, the rows are checked with {} \
+        pages per row", DRAM_CONFIG_PATH, ctx.geometry.pages_per_row)});
+    ctx
+  };
+  let mut attack_config = files::parse_toml::<AttackConfig>(path)
+    .with_geometry(&ctx.geometry);
+  lints.extend(program_path
+    .map_or(Vec::new(), |p| attack_config.resolve_symbols(p)).into_iter()
+    .map(|message| lint::Lint {severity: lint::Severity::Error, message}));
+  let attack_config = attack_config.normalize();
+  lints.extend(lint::lint_attack_config(&attack_config, program_path));
+  print_lints(path, &lints);
+}
+
+fn run_victim_checker(path: &str) {
+  let victims = victim_ledger::parse_victim_addresses(path);
+  let ledger = VictimLedger::load(&victim_ledger::ledger_path());
+  let lints = lint::lint_victims(&ledger, None,
+    victims.iter().map(|(phys_addr, bitflip)| (*phys_addr, bitflip)));
+  print_lints(path, &lints);
+}
+
+fn run_preflight(tool: Option<&str>) {
+  let tools = tool.map_or(vec!["templater", "prehammer", "bench", "loader"],
+    |t| vec![t]);
+  let mut failed = false;
+  for tool in tools {
+    let missing = preflight::missing(&preflight::tool_features(tool));
+    for dependency in &missing {
+      if dependency.required {
+        println!("{}", format!("{}: missing {}", tool, dependency).red());
+      } else {
+        println!("{}", format!("{}: missing optional {}", tool, dependency)
+          .yellow());
+      }
+    }
+    let required = missing.iter().filter(|d| d.required).count();
+    println!("{}: {} required, {} optional dependencies missing", tool,
+      required, missing.len() - required);
+    failed |= required > 0;
+  }
+  if failed {
+    std::process::exit(1);
+  }
+}
+
+//Prints arg:question and returns the answer from the terminal (see
+//ram_swap::prompt)
+fn ask(question: &str) -> String {
+  println!("{}", question);
+  let mut answer = String::new();
+  std::io::stdin().read_line(&mut answer).unwrap();
+  answer
+}
+
+fn run_ram_id(arg: Option<&str>) {
+  let mut history = ram_swap::RamSwapHistory::load(&ram_swap::history_path());
+  if arg == Some("show") {
+    for swap in &history.swaps {
+      println!("{}: {:?} (since {})", swap.id, swap.serials, swap.since);
+    }
+    println!("current: {}", ram_swap::current_id()
+      .map_or("not set".to_owned(), |id| id.to_string()));
+    return;
+  }
+  let serials = dram::detect_dimm_serials();
+  match arg {
+    Some(id) => ram_swap::set(&mut history,
+      id.parse().expect("The RAM swap id is NaN"), &serials),
+    None => {
+      ram_swap::prompt(&mut history, &serials, ask);
+    }
+  }
+}
+
+//prints a report of arg:lints, exits with an error code if there are errors
+fn print_lints(path: &str, lints: &[lint::Lint]) {
+  for l in lints {
+    match l.severity {
+      lint::Severity::Error => println!("{}", l.to_string().red()),
+      lint::Severity::Warning => println!("{}", l.to_string().yellow())
+    }
+  }
+  let errors = lints.iter()
+    .filter(|l| l.severity == lint::Severity::Error).count();
+  println!("{}: {} errors, {} warnings", path, errors, lints.len() - errors);
+  if errors > 0 {
+    std::process::exit(1);
+  }
+}
+
 fn run_template_tester(path: &str) {
   info!("Prehammer using rowhammer template");
   let memory_template = files::parse_json::<MemoryTemplate>(path);
   let hammer_count = memory_template.templater_config.hammer_count;
+  let hammer_backend = memory_template.templater_config.hammer_backend;
+  let ctx = RhContext::from_env()
+    .with_dram_config(memory_template.dram_config.clone());
+  let memory_template = memory_template.with_geometry(&ctx.geometry);
+  let dram_config = ctx.dram_config();
+  let page_align_mask = ctx.geometry.page_align_mask();
 
-  //allocate all required frames
+  //allocate all required frames (the whole victim rows)
   let frames_to_allocate = memory_template.victims.iter()
-    .map(|(t,_,_)| t & PAGE_ALIGN_MASK)
+    .flat_map(|v| ctx.geometry.frames_in_row(v.phys_addr))
     .chain(memory_template.aggr_patterns.iter()
     .map(|x| &x.pattern).flatten().map(|x| &x.frames).flatten().cloned())
     .collect();
-  let frame2map = allocation::allocate_pages(frames_to_allocate, 0f64);
+  ctx.phase_start("allocation");
+  let frame2map = allocation::allocate_pages(
+    &ctx, &PagemapOracle, frames_to_allocate, 0f64);
+  ctx.phase_end("allocation");
+  //do not hammer next to the code, stack or heap of the prehammer
+  self_guard::check(&ctx, memory_template.aggr_patterns.iter()
+    .flat_map(|x| x.into_iter().flat_map(|r| r.frames.iter().cloned())),
+    memory_template.victims.iter().map(|v| v.phys_addr & page_align_mask),
+    &frame2map);
+  //optionally verify the aggressors of every victim (see layout)
+  let layout =
+    PatternLayout::if_requested(&ctx, &memory_template.templater_config);
+  let aggr_aliases = memory_template.templater_config.aggr_aliases;
+  let frame2aliases = if aggr_aliases > 0 {
+    allocation::map_aliases(&ctx, memory_template.aggr_patterns.iter()
+      .flat_map(|x| x.into_iter().map(|r| r.frames[0])), aggr_aliases)
+  } else {
+    HashMap::new()
+  };
+
+  //optionally disable the L2 prefetchers for the duration of the hammering
+  let prefetchers = host::disable_prefetchers_if_requested();
+  //optionally switch to SCHED_FIFO and reduce the timer slack
+  let shield = host::shield_if_requested();
+
+  //the activations of the hammered rows (see row_wear)
+  let wear_path = row_wear::wear_path();
+  let mut row_wear = row_wear::RowWear::load(&wear_path);
+
+  //the victim pages before hammering, shared by all victims: a page is only
+  //snapshotted again when a victim initializes it with another value
+  let mut content_cache = mem_init::ContentCache::new(&ctx);
+  let mut page_inits: HashMap<u64, u8> = HashMap::new();
 
   //do everything separately for each victim
+  let mut reporters = Reporters::from_env();
+  let tested = memory_template.victims.len();
   let mut successes = Vec::new();
+  let mut reliable = Vec::new();
   for victim in memory_template.victims {
-    let aggrs = &memory_template.aggr_patterns[victim.2];
-    let victim_frame = VictimFrame {
-      page_file_offset: None,
-      frame_addr: victim.0 & PAGE_ALIGN_MASK,
-      victim_bits: vec![VictimBit {
-        offset: victim.0 % PAGE_SIZE as u64,
-        bitflip: victim.1.clone(),
-        aggr_pattern_key: victim.2.to_string()}]
-    };
+    let start_time = Instant::now();
+    let aggrs = memory_template.aggr_patterns.get(victim.aggr_pattern_id);
+    let victim_frame = VictimFrame::single(victim.phys_addr, &victim.bitflip,
+      victim.aggr_pattern_id);
+    info!("Testing victim P0x{:x} {} at {} with aggressors {}",
+      victim.phys_addr, victim.bitflip,
+      dram::phys_to_dram(victim.phys_addr, dram_config),
+      dram_rows(aggrs, dram_config));
+    if let Some(layout) = &layout {
+      layout.warn_on_mismatch(&ctx, aggrs, victim.phys_addr);
+    }
 
     //init aggr and victim rows
-    //notice: we init the whole victim PAGE instead of only the victim bit
-    mem_init::initialize_rows(aggrs.pattern.iter(), &frame2map);
-    mem_init::initialize_rows(std::iter::once(&RowAndInitValue{
-      //only one page!! (bcs other not allocated)
-      frames: vec![victim.0 & PAGE_ALIGN_MASK],
-      init: if victim.1.flip_direction {0x0} else {0xff}
-    }), &frame2map);
+    //notice: we init the whole victim ROW instead of only the victim bit
+    let victim_row = RowAndInitValue {
+      frames: ctx.geometry.frames_in_row(victim.phys_addr),
+      init: if victim.bitflip.flip_direction {0x0} else {0xff},
+      weight: 1,
+      interval: 1,
+      decoy: false
+    };
+    mem_init::initialize_rows(&ctx, aggrs.pattern.iter(), &frame2map);
+    aggrs.pattern.iter().flat_map(|r| r.frames.iter())
+      .for_each(|f| {page_inits.remove(f);});
+    mem_init::initialize_rows(&ctx, std::iter::once(&victim_row),
+      &frame2map);
 
-    let content_cache =
-      mem_init::read_victim_content(std::iter::once(&victim_frame), &frame2map);
+    for page in &victim_row.frames {
+      if page_inits.insert(*page, victim_row.init) != Some(victim_row.init) {
+        content_cache.refresh(*page, &frame2map);
+      }
+    }
+    content_cache.track(std::iter::once(&victim_frame), &frame2map);
 
     //the hammer loop
     let mut consecutive_successes = 1;
@@ -86,17 +307,35 @@ fn run_template_tester(path: &str) {
     {
       info!("#### STARTING ITERATION {} ####", iteration);
       //reinitialize victims
-      mem_init::initialize_rows(std::iter::once(&RowAndInitValue{
-        frames: vec![victim.0 & PAGE_ALIGN_MASK],
-        init: if victim.1.flip_direction {0x0} else {0xff}
-      }), &frame2map);
+      mem_init::initialize_rows(&ctx, std::iter::once(&victim_row),
+        &frame2map);
 
-      //hammer
-      let aggrs = aggrs.aggr_rows_to_virt(&frame2map);
+      //hammer (optionally with accesses to the victim)
       std::thread::sleep(Duration::new(2, 0));
-      //hammer::hammer_rust(&aggrs, hammer_count);
-      //hammer::hammer_asm(&aggrs, 40, hammer_count);
-      hammer::hammer_jit(&aggrs, 40, hammer_count);
+      let victim_workload = workload::spawn_if_requested(vec![
+        frame2map[&(victim.phys_addr & page_align_mask)].data() as u64
+          + (victim.phys_addr & ctx.geometry.page_offset_mask())]);
+      if aggrs.is_weighted() {
+        assert!(hammer_backend == hammer::HammerBackend::Jit
+          && frame2aliases.is_empty(), "Weighted aggressors need the jit \
+          hammer backend without aggressor aliases");
+        hammer::hammer_jit_weighted(&ctx, &aggrs.aggr_rows_to_virt(&frame2map),
+          &aggrs.rates(), 40, hammer_count);
+      } else if frame2aliases.is_empty() {
+        let aggrs = aggrs.aggr_rows_to_virt(&frame2map);
+        hammer::hammer_with_backend(&ctx, hammer_backend, &aggrs, 40,
+          hammer_count);
+      } else {
+        assert_eq!(hammer_backend, hammer::HammerBackend::Jit,
+          "Aggressor aliases need the jit hammer backend");
+        let aliases =
+          aggrs.aggr_rows_to_virt_aliases(&frame2map, &frame2aliases);
+        hammer::hammer_jit_aliases(&ctx, &aliases, 40, hammer_count);
+      }
+      if let Some(w) = victim_workload {
+        w.stop();
+      }
+      row_wear.add_pattern(dram_config, aggrs, hammer_count);
 
       //check for flips
       if flip_checks::check_victim_flips(
@@ -113,23 +352,89 @@ fn run_template_tester(path: &str) {
       iteration += 1;
     }
 
-    if iteration == GIVE_UP_THESHOLD {
+    //the next victim (or experiment) starts from a known state
+    mem_init::restore_and_verify(&ctx,
+      &content_cache.victim_bytes(std::iter::once(&victim_frame)),
+      &frame2map);
+
+    let success = iteration != GIVE_UP_THESHOLD;
+    reporters.setup_finished(&report::SetupSummary {
+      setup: format!("victim 0x{:x} {} with aggressors {}", victim.phys_addr,
+        victim.bitflip, dram_rows(aggrs, dram_config)),
+      garbage_count: None,
+      flips: success as usize,
+      duration_us: start_time.elapsed().as_micros() as u64
+    });
+    if !success {
       info!("GAVE UP");
     } else {
         info!("SUCCESS");
+        let flip = report::Flip {
+          phys_addr: victim.phys_addr,
+          bitflip: victim.bitflip.clone(),
+          aggr_rows: aggrs.into_iter().map(|r| r.frames[0]).collect(),
+          garbage_count: None
+        };
+        reporters.flip_discovered(&flip);
+        reliable.push(flip);
         successes.push(victim);
     }
   }
 
+  //restores the prefetchers and the scheduling settings (also dropped when
+  //a panic unwinds)
+  drop(shield);
+  drop(prefetchers);
+
+  row_wear.save(&wear_path);
+
   info!("Success list: (length: {})", successes.len());
-  successes.iter().for_each(|x| info!("0x{:x} {} {}", x.0, x.1, x.2));
+  successes.iter().for_each(|x| info!("P0x{:x} {} {} {}", x.phys_addr,
+    x.bitflip, x.aggr_pattern_id,
+    dram::phys_to_dram(x.phys_addr, dram_config)));
+  reporters.experiment_finished(
+    &report::ExperimentResult::TemplateTest {tested, reliable,
+      resources: ctx.resources.summary()});
 }
 
-fn run_attack_tester(path: &str) {
+//DRAM addresses of the rows in arg:pattern, e.g. for logging
+fn dram_rows(pattern: &AggressorPattern, dram_config: &DRAMConfig) -> String {
+  pattern.into_iter()
+    .map(|r| dram::phys_to_dram(r.frames[0], dram_config).to_string())
+    .collect::<Vec<_>>().join(",")
+}
+
+fn run_attack_tester(path: &str, program_path: Option<&str>) {
   info!("Prehammer using attack config");
-  let dram_config: DRAMConfig = dram::create_config();
-  let attack_config = files::parse_toml::<AttackConfig>(path).validate();
-  let frame2map = allocation::allocate_attack(&dram_config, &attack_config);
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
+  let dram_config = ctx.dram_config();
+  let mut attack_config = files::parse_toml::<AttackConfig>(path)
+    .with_geometry(&ctx.geometry);
+  if let Some(program_path) = program_path {
+    attack_config = attack_config.resolve(program_path);
+  }
+  let mut attack_config = attack_config.validate();
+  ctx.phase_start("allocation");
+  let frame2map = allocation::allocate_attack(
+    &ctx, &PagemapOracle, &mut attack_config);
+  ctx.phase_end("allocation");
+  self_guard::check(&ctx, attack_config.iter_aggr_frames(),
+    attack_config.iter_victim_frame_addrs(), &frame2map);
+  victim_conflicts::check(&ctx, &attack_config);
+  //optionally verify the banks of the aggressors of every victim (see layout)
+  if let Some(layout) = PatternLayout::for_attack_if_requested(&ctx) {
+    let mismatches = layout.warn_on_attack_mismatches(&ctx, &attack_config);
+    info!("{} victim bits have aggressors in another bank", mismatches);
+  }
+  for frame in &attack_config.victim_frames {
+    for bit in &frame.victim_bits {
+      let phys_addr = frame.frame_addr + bit.offset;
+      info!("Victim P0x{:x} {} at {} with aggressors {}", phys_addr,
+        bit.bitflip, dram::phys_to_dram(phys_addr, dram_config),
+        dram_rows(attack_config.aggr_pattern(&bit.aggr_pattern_key),
+          dram_config));
+    }
+  }
 
   //initialize victims
   //cannot use initialize_rows bcs it inits the whole row to the same value
@@ -137,11 +442,29 @@ fn run_attack_tester(path: &str) {
   //=> init from attack_config (alternative: load pages from binary)
   mem_init::initialize_attack_victims(&attack_config, &frame2map);
   //bcs the attack config is validated, victims and aggressors do not overlap,
-  //thus all aggressors can be initialized at once
-  mem_init::initialize_rows(attack_config.iter_aggr_rows(), &frame2map);
-  let content_cache = mem_init::read_victim_content(
+  //thus all aggressors of an init group can be initialized at once
+  //patterns that use an aggressor row with different init values are in
+  //different groups, the rows of a group are initialized before it is
+  //hammered (see victim_conflicts)
+  let patterns: Vec<_> = attack_config.iter_aggr_patterns_per_victim()
+    .collect();
+  let init_groups = victim_conflicts::init_groups(&patterns);
+  if init_groups.len() == 1 {
+    mem_init::initialize_rows(&ctx, attack_config.iter_aggr_rows(),
+      &frame2map);
+  }
+  let content_cache = mem_init::read_victim_content(&ctx,
     attack_config.victim_frames.iter(), &frame2map);
 
+  //optionally disable the L2 prefetchers for the duration of the hammering
+  let prefetchers = host::disable_prefetchers_if_requested();
+  //optionally switch to SCHED_FIFO and reduce the timer slack
+  let shield = host::shield_if_requested();
+
+  //the activations of the hammered rows (see row_wear)
+  let wear_path = row_wear::wear_path();
+  let mut row_wear = row_wear::RowWear::load(&wear_path);
+
   //the hammer loop
   let mut consecutive_successes = 1;
   let mut iteration = 0;
@@ -153,12 +476,43 @@ fn run_attack_tester(path: &str) {
     mem_init::initialize_attack_victims(&attack_config, &frame2map);
 
     //hammer
-    for v in attack_config.iter_aggr_patterns_per_victim() {
-      let aggrs = v.aggr_rows_to_virt(&frame2map);
-      std::thread::sleep(Duration::new(2, 0));
-      //hammer::hammer_rust(&aggrs, attack_config.hammer_count);
-      //hammer::hammer_asm(&aggrs, 40, attack_config.hammer_count);
-      hammer::hammer_jit(&aggrs, 40, attack_config.hammer_count);
+    for group in &init_groups {
+      if init_groups.len() > 1 {
+        mem_init::initialize_rows(&ctx,
+          group.iter().flat_map(|p| p.into_iter()), &frame2map);
+      }
+      for round in attack_config.hammer_rounds(group.iter().cloned()) {
+        std::thread::sleep(Duration::new(2, 0));
+        //optionally access all victims while hammering, like the target
+        //would
+        let victim_workload = workload::spawn_if_requested(
+          attack_config.victim_frames.iter().flat_map(|f| {
+            let page = frame2map[&f.frame_addr].data() as u64;
+            f.victim_bits.iter().map(move |b| page + b.offset)
+          }).collect());
+        match &round[..] {
+          [pattern] if !pattern.is_weighted() => {
+            let aggrs = pattern.aggr_rows_to_virt(&frame2map);
+            hammer::hammer_with_backend(&ctx, attack_config.hammer_backend,
+              &aggrs, 40, attack_config.hammer_count);
+          },
+          _ => {
+            assert_eq!(attack_config.hammer_backend,
+              hammer::HammerBackend::Jit,
+              "Concurrently hammered patterns need the jit hammer backend");
+            let aggrs: Vec<_> = round.iter()
+              .map(|p| p.aggr_rows_to_virt_weighted(&frame2map)).collect();
+            hammer::hammer_jit_interleaved(&ctx, &aggrs, 40,
+              attack_config.hammer_count);
+          }
+        }
+        if let Some(w) = victim_workload {
+          w.stop();
+        }
+        for v in round {
+          row_wear.add_pattern(dram_config, v, attack_config.hammer_count);
+        }
+      }
     }
 
     //check for flips
@@ -177,6 +531,25 @@ fn run_attack_tester(path: &str) {
     iteration += 1;
   }
 
+  //restores the prefetchers and the scheduling settings (also dropped when
+  //a panic unwinds)
+  drop(shield);
+  drop(prefetchers);
+
+  row_wear.save(&wear_path);
+  //the outcome of the last iteration
+  let result = report::ExperimentResult::attack(program_path.unwrap_or(""),
+    &attack_config, ctx.resources.summary(),
+    |frame_addr, bit| flip_checks::check_victim_bit(frame_addr, bit,
+    &frame2map, &content_cache));
+  mem_init::restore_and_verify(&ctx, &content_cache.all_victim_bytes(),
+    &frame2map);
+
   info!("{}", if iteration == GIVE_UP_THESHOLD {"GAVE UP"} else {"SUCCESS"});
+  let mut reporters = Reporters::from_env();
+  if let report::ExperimentResult::Attack {flipped, ..} = &result {
+    flipped.iter().for_each(|f| reporters.flip_discovered(f));
+  }
+  reporters.experiment_finished(&result);
 }
 
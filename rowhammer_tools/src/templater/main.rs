@@ -1,14 +1,33 @@
 use log::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
+use std::path::Path;
 use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use chrono::prelude::*;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::Serialize;
 
 use rowhammer::{*, config::*};
 use rowhammer::utils::{*, dram::*, serialize::*};
+use rowhammer::utils::oracle::PagemapOracle;
+use rowhammer::utils::tool_lock::{self, ToolLock};
+use rowhammer::context::RhContext;
+use rowhammer::layout::PatternLayout;
+use rowhammer::utils::report::{self, Reporter, Reporters, SoakDay};
+use rowhammer::utils::log_rotate::RotatingLog;
+
+//hammer runs per aggressor subset when minimizing the aggressor patterns
+static MINIMIZE_ATTEMPTS: usize = 3;
+//rotated log files the soak mode keeps (one per day)
+static SOAK_KEEP_LOGS: usize = 14;
+//wait before the next cycle after a failed cycle of the soak mode, multiplied
+//by the failures in a row
+static SOAK_RESTART_DELAY: Duration = Duration::from_secs(60);
 
 
 //Simple rowhammer templating tool using double-sided rowhammer
@@ -19,10 +38,129 @@ use rowhammer::utils::{*, dram::*, serialize::*};
 //garbage code
 //To template without interleaved garbage code, set `garbage_count_start` 
 //to 0, and `garbage_count_end` to 1 in the config file
-//On ctrl-c, the templating stops and the results so far are exported
+//Alternatively, set `activation_rates` to sweep the garbage counts that are
+//calibrated to hit these activation rates (activations per tREFI), or
+//`garbage_delays_ns` to sweep the garbage counts of these delays (the
+//garbage duration is measured at startup, see calibration.rs)
+//Set `hammer_schedule` to hammer with a schedule of the aggressor and dummy
+//(`D` in `pattern`) rows instead of a kernel (see schedule)
+//The dummy rows are decoys: the kernels hammer them like the aggressors to
+//pollute the sampler of the TRR mitigation (in every `decoy_interval`-th
+//hammer iteration), but they are no real aggressors of the victims (see
+//`RowAndInitValue::decoy`)
+//To study the effect of refreshes between hammer bursts, set `idle_times_ns`
+//and `bursts`: every garbage count is swept with every idle time between the
+//bursts, the victims per idle time are in `idle_time_distribution`
+//Set `row_open_cycles` to hammer RowPress-style: every aggressor row is kept
+//open for that many TSC cycles per activation (see hammer::RowPressKernel),
+//every garbage count is swept with every row open time, the victims per row
+//open time are in `row_open_distribution`
+//`repetition_order` sets the order of the setups and repetitions: all setups
+//per repetition (default), all repetitions per setup, or all setups per
+//repetition in a random order per repetition (`order_seed`, recorded in the
+//template)
+//Set `interleave_banks` to hammer up to that many setups in different banks
+//together (see `interleave_banks`), which multiplies the throughput of
+//single-core runs, and `bank_parallel` to interleave their accesses in the
+//hammer code (see hammer::interleave_accesses), which raises the activation
+//rate of every bank (compare `activation_rates` of the templates)
+//The wanted frames that the allocation captured and missed are written to
+//frame_map<id>.json (render it with `analyzer frame-map`)
+//On machines with little memory, set `window_rows` to template the row range
+//in windows that are allocated, hammered and released one after another (see
+//`run_windowed`)
+//On ctrl-c or new uncorrectable memory errors (EDAC, mcelog), the templating
+//stops and the results so far are exported
+//Every flip, finished setup and the final template are also reported to the
+//sinks in `RH_REPORT` (see utils/report.rs)
+//Set `RH_VICTIM_WORKLOAD` to access the victim rows while hammering (see
+//workload), and `co_runners` to load other cores while hammering (memory
+//bandwidth or cache thrashing, see workload::CoRunners), their throughput is
+//recorded in `co_runner_stats`
+//The activations of every hammered row are added to the row wear accounting
+//(see row_wear), set `max_row_wear` to skip setups with heavily used rows
+//With `minimize_aggressors`, every discovered victim is hammered again with
+//subsets of its aggressor pattern to find the aggressors it needs (see
+//`minimize_aggressors`)
+//At the end, all flipped victim bytes are restored to their init value and
+//verified (through /dev/mem with `RH_RESTORE_DEVMEM`)
+//With `RH_CHECK_GEOMETRY`, the DRAM addresses of the aggressors of every
+//setup are verified against `pattern` before hammering (see layout)
+//Every victim in the results records the number of experiment rounds it
+//flipped in (in total and per garbage count), victims with less than
+//`min_rounds` rounds are filtered out of the results
 //USAGE: sudo ./templater <arbitrary_id> [threshold]
-//If a threshold is given, the bitflips that flipped in less experiment rounds
-//are filtered out of the final results (defaults to 1)
+//If a threshold is given, it overrides `min_rounds` of the config
+//
+//"adjacency probing" mode: probes which rows in the configured row range are
+//physically adjacent (see adjacency) and writes the logical -> physical row
+//remap table to row_remap<id>.json, set `row_remap` to that path to generate
+//the patterns of the next templating runs with physically adjacent rows
+//USAGE: sudo ./templater probe-adjacency <arbitrary_id>
+//
+//"drop_frac suggestion" mode: allocates pages until all frames of the
+//configured rows and banks are found or none was found for PROBE_GRACE_PAGES
+//pages (see allocation::probe_allocation), and suggests the fraction of
+//unobtainable frames (plus DROP_FRAC_MARGIN) as `drop_frac`, the probe is
+//written to drop_frac<id>.json
+//USAGE: sudo ./templater suggest-drop-frac <arbitrary_id>
+//
+//"triage" mode: quick estimate of the flips per GB of a new DIMM, templates
+//a random sample of `triage_coverage` of the victim rows of the configured
+//rows and banks (all init values) and extrapolates their flips to flips per
+//GB with a `triage_confidence` interval (see analysis::triage_estimate), the
+//template is written to templating<id>_triage.json and the estimate (and the
+//seed of the sample) to triage<id>.json
+//USAGE: sudo ./templater triage <arbitrary_id> [seed]
+//
+//"fuzz" mode: templates the configured rows with `fuzz_patterns` sampled
+//non-uniform access patterns (frequency, phase and amplitude per aggressor,
+//see fuzz) of the aggressor rows of `pattern` (e.g. "AVAVAVA"), one after
+//another on the same allocation; the template of every sampled pattern
+//records it as `fuzz_pattern` in its templater config (so it can be replayed)
+//and is written to templating<id>_fuzz<i>.json, the patterns and their
+//victims are summarized in fuzz<id>_summary.json after every pattern
+//USAGE: sudo ./templater fuzz <arbitrary_id> [seed]
+//
+//"batch" mode: runs the templating for every given templater config (or every
+//*.toml file in the given directory, in alphabetical order) back-to-back, for
+//overnight parameter sweeps; configs whose frames overlap share one
+//allocation (see `group_configs`), so the configs of a group run together.
+//Every config writes its template to templating<id>_<config name>.json, the
+//summary of all configs is written to batch<id>_summary.json after every
+//config (see `BatchEntry`), ctrl-c stops the running config and skips the
+//remaining ones
+//USAGE: sudo ./templater batch <arbitrary_id> <config dir | configs...>
+//
+//"replay" mode: templates again with the templater and DRAM config recorded
+//in a template and the garbage counts it swept (see analysis::replay_config),
+//so the setups run in the recorded order with the recorded init values and
+//hammer parameters; the replayed template is written to
+//templating<id>_replay.json and its divergences from the recorded one (host
+//config, activation rates, missing/new victims, rounds) are logged and
+//written to replay<id>_divergences.json
+//Prehammer reports do not record the hammer parameters, replay the template
+//that was given to the prehammer instead
+//USAGE: sudo ./templater replay <arbitrary_id> <template.json>
+//
+//"soak" mode: week-long DIMM qualification, templates a random region
+//(`soak_rows` rows of the configured row range in one random bank of
+//`bank_idxs`) after another until ctrl-c or an uncorrectable memory error;
+//everything is written to soak<id>/: the template of every cycle to
+//templating<i>.json, the log to soak_<date>.log (rotated daily, the newest
+//SOAK_KEEP_LOGS are kept, see utils::log_rotate) and a summary of every day to
+//days.json (see report::SoakDay), which is also reported to the sinks in
+//`RH_REPORT` (e.g., an SQLite database) when the day is over or the mode
+//stops (a resumed day is reported again)
+//A failed (panicking) cycle is logged and restarted after SOAK_RESTART_DELAY
+//times the failures in a row, the mode gives up after `soak_max_failures`
+//failures in a row; started again with the same id, it resumes the summary
+//and the cycle numbering of soak<id>/days.json
+//USAGE: sudo ./templater soak <arbitrary_id> [seed]
+//
+//All modes take the tool lock (see utils/tool_lock.rs), add `--steal-lock` to
+//the arguments to take it from a running tool
+//All modes check their external dependencies first (see preflight.rs)
 
 #[derive(PartialEq, Eq, Hash)]
 pub struct Setup {
@@ -35,129 +173,657 @@ impl Setup {
     self.victim_rows.iter().chain(
       self.aggr_pattern.into_iter()).map(|x| x.frames.clone()).flatten()
   }
+
+  //first frame of all victim and aggressor rows
+  pub fn iter_all_rows(&self) -> impl Iterator<Item = u64> + '_ {
+    self.victim_rows.iter().chain(
+      self.aggr_pattern.into_iter()).map(|x| x.frames[0])
+  }
 }
 
 impl fmt::Display for Setup {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "victim rows: {}; aggressor rows: {}",
       self.victim_rows.iter().map(|x| {
-        format!("0x{:x}", x.frames[0])
+        format!("P0x{:x}", x.frames[0])
       }).collect::<Vec<_>>().join(","),
       self.aggr_pattern.into_iter().map(|x| {
-        format!("0x{:x}", x.frames[0])
+        format!("P0x{:x}", x.frames[0])
       }).collect::<Vec<_>>().join(","))
   }
 }
 
+//Prints arg:question and returns the answer from the terminal (see
+//ram_swap::prompt)
+fn ask(question: &str) -> String {
+  println!("{}", question);
+  let mut answer = String::new();
+  std::io::stdin().read_line(&mut answer).unwrap();
+  answer
+}
+
 pub fn main() {
   info!("Starting templater");
-  let start_time = Local::now();
 
   //init
-  assert!(std::env::args().len() >= 2,
+  let mut args: Vec<String> = std::env::args().skip(1).collect();
+  let steal_lock = tool_lock::take_steal_flag(&mut args);
+  assert!(!args.is_empty(),
     "Provide an experiment ID, and optionally a threshold");
-  let mut args = std::env::args().skip(1);
-  let id = args.next().unwrap();
-  let threshold = args.next().map_or(1, |x| x.parse().unwrap());
+  let mut args = args.into_iter();
+  let mut id = args.next().unwrap();
+  let probe_adjacency = id == "probe-adjacency";
+  let batch = id == "batch";
+  let replay = id == "replay";
+  let suggest_drop_frac = id == "suggest-drop-frac";
+  let triage = id == "triage";
+  let fuzz = id == "fuzz";
+  let soak = id == "soak";
+  if probe_adjacency || batch || replay || suggest_drop_frac || triage || fuzz
+    || soak
+  {
+    id = args.next().expect("Provide an experiment ID");
+  }
   let mut logger_builder = rowhammer::configure();
   //RUST_LOG env var does not work anymore when using `filter_module`
   //logger_builder.filter_module("rowhammer", LevelFilter::Info);
   //logger_builder.filter_module("rowhammer::utils::hammer",LevelFilter::Debug);
+  if soak {
+    logger_builder.target(env_logger::Target::Pipe(Box::new(
+      RotatingLog::new(&soak_dir(&id), "soak", SOAK_KEEP_LOGS))));
+  }
   logger_builder.init();
+  let mut features = vec![preflight::Feature::Allocation,
+    preflight::Feature::PageCache, preflight::Feature::HostConfig,
+    preflight::Feature::Monitoring];
+  if !replay {
+    features.push(preflight::Feature::DramConfig);
+  }
+  if !batch && !replay {
+    features.push(preflight::Feature::TemplaterConfig);
+  }
+  preflight::check(&features);
+  let _lock = ToolLock::acquire("templater", steal_lock);
+  host::restrict_to_core_type();
+  //the host configs of all modes record it
+  ram_swap::ensure_or_ask(ask);
 
-  let templater_config: TemplaterConfig =
+  if batch {
+    run_batch(&id, &batch_config_paths(args.collect()));
+    return;
+  }
+  if replay {
+    run_replay(&id, &args.next().expect("Provide the template to replay"));
+    return;
+  }
+  if triage {
+    run_triage(&id, args.next().map(|x| x.parse().expect("The seed is NaN")));
+    return;
+  }
+  if fuzz {
+    run_fuzz(&id, args.next().map(|x| x.parse().expect("The seed is NaN")));
+    return;
+  }
+  if soak {
+    run_soak(&id, args.next().map(|x| x.parse().expect("The seed is NaN")));
+    return;
+  }
+  let threshold: Option<usize> = args.next().map(|x| x.parse().unwrap());
+
+  let mut templater_config: TemplaterConfig =
     files::parse_toml(TEMPLATER_CONFIG_PATH);
-  let dram_config: DRAMConfig = dram::create_config();
+  if let Some(threshold) = threshold {
+    templater_config.min_rounds = threshold;
+  }
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
   let host_config: HostConfig = host::read_config();
-  let hammer_count = templater_config.hammer_count;
+
+  if probe_adjacency {
+    run_adjacency_probe(&ctx, &id, &templater_config);
+    return;
+  }
+  if suggest_drop_frac {
+    run_drop_frac_probe(&ctx, &id, &templater_config);
+    return;
+  }
+
+  if templater_config.window_rows > 0 {
+    run_windowed(&ctx, &id, templater_config, host_config);
+    return;
+  }
 
   let (frames_to_allocate, setups) =
-    parse_hammer_pattern(&templater_config, &dram_config);
+    parse_hammer_pattern(&ctx, &templater_config);
 
   //allocate all required frames
+  ctx.phase_start("allocation");
   let frame2map =
-    allocation::allocate_pages(frames_to_allocate, templater_config.drop_frac);
+    allocation::allocate_pages(&ctx, &PagemapOracle,
+      frames_to_allocate.clone(), templater_config.drop_frac);
+  ctx.phase_end("allocation");
+  write_frame_map(&ctx, &format!("frame_map{}.json", id), &frames_to_allocate,
+    &frame2map);
 
-  //remove the row setups for which a page frame is missing
-  let len_before = setups.len();
-  let (setups, discards) = filter_whole_setups(setups, &frame2map);
-  let comment = format!("Testing {} row setups, dropped {} row setups: \n{}",
-    setups.len(), len_before - setups.len(),
-    discards.iter().map(|f| format!("  - {}\n", f)).collect::<String>());
-  info!("{}", comment);
+  let interupted = set_ctrlc_handler();
+  //the template is written to templating<id>.json
+  let mut reporters = Reporters::from_env().with(Box::new(
+    report::JsonFileReporter {path: format!("templating{}.json", id)}));
+  run_templating(&ctx, templater_config, setups, &frame2map, host_config,
+    &interupted, &mut reporters);
+}
 
-  //collection of all unique aggressor patterns: pattern(small vector) -> idx
-  let mut discovered_aggr_patterns = HashMap::new();
-  //collection of all unique disovered victims:
-  //(frame_addr, BitFlip, discovered_aggr_patterns_idx) -> idx
-  let mut discovered_victims = HashMap::new();
-  //the discovered victims for the full experiment
-  //garbage_count -> [experiment_round x [discovered_victims_idx of the victim]]
-  let mut distribution: HashMap<u32, Vec<Vec<usize>>> = HashMap::new();
+//Writes the frames of arg:wanted that the allocation captured and missed to
+//arg:path (see analysis::frame_map)
+fn write_frame_map(
+  ctx: &RhContext, path: &str, wanted: &HashSet<u64>, frame2map: &Frame2Map
+) {
+  let map = analysis::frame_map(ctx.dram_config(), wanted, frame2map);
+  info!("Captured {} of {} frames, writing the frame map to {}",
+    wanted.len() - map.missed_frames.len(), wanted.len(), path);
+  fs::write(path, serde_json::to_string(&map).unwrap())
+    .unwrap_or_else(|_| panic!("Writing {} failed", path));
+}
+
+//[row_start, row_end[ of the windows of `window_rows` rows of
+//arg:templater_config, consecutive windows overlap by the pattern length - 1
+//rows, so every setup is in exactly one window
+fn row_windows(templater_config: &TemplaterConfig) -> Vec<(u64, u64)> {
+  let overlap = templater_config.pattern_rows() - 1;
+  let last_setup_start = templater_config.row_end.saturating_sub(overlap);
+  assert!(last_setup_start > templater_config.row_start,
+    "The rows {} - {} do not fit a setup of the pattern ({} rows)",
+    templater_config.row_start, templater_config.row_end,
+    templater_config.pattern_rows());
+  (templater_config.row_start..last_setup_start)
+    .step_by(templater_config.window_rows as usize)
+    .map(|start| (start, (start + templater_config.window_rows + overlap)
+    .min(templater_config.row_end)))
+    .collect()
+}
+
+//windows<id>_summary.json
+#[derive(Serialize)]
+struct WindowEntry {
+  row_start: u64,
+  row_end: u64,
+  template: String,
+  //wanted and captured frames of the window
+  frames: usize,
+  captured: usize,
+  victims: usize,
+  interrupted: bool
+}
+
+//"Low-memory" templating: templates the windows of the row range (see
+//`row_windows`) one after another, every window is allocated, templated and
+//released before the next one, so the peak memory is bounded by the frames
+//of one window instead of the whole range
+//Every window writes its template (with the rows of the window in its config,
+//so it can be replayed on its own) to templating<id>_window<i>.json and its
+//frame map to frame_map<id>_window<i>.json, the summary of all windows is
+//written to windows<id>_summary.json after every window
+fn run_windowed(
+  ctx: &RhContext, id: &str, templater_config: TemplaterConfig,
+  host_config: HostConfig
+) {
+  let windows = row_windows(&templater_config);
+  info!("Templating rows {} - {} in {} windows of {} rows",
+    templater_config.row_start, templater_config.row_end, windows.len(),
+    templater_config.window_rows);
+  let interupted = set_ctrlc_handler();
+  let summary_path = format!("windows{}_summary.json", id);
+  let mut summary = Vec::new();
+  for (i, (row_start, row_end)) in windows.into_iter().enumerate() {
+    if interupted.load(Ordering::SeqCst) {
+      warn!("Skipping rows {} - {}", row_start, row_end);
+      continue;
+    }
+    info!("Templating window {} (rows {} - {})", i, row_start, row_end);
+    let mut window_config = templater_config.clone();
+    window_config.row_start = row_start;
+    window_config.row_end = row_end;
+    window_config.window_rows = 0;
+    let (frames_to_allocate, setups) =
+      parse_hammer_pattern(ctx, &window_config);
+    ctx.phase_start("allocation");
+    let frame2map = allocation::allocate_pages(ctx, &PagemapOracle,
+      frames_to_allocate.clone(), window_config.drop_frac);
+    ctx.phase_end("allocation");
+    write_frame_map(ctx, &format!("frame_map{}_window{}.json", id, i),
+      &frames_to_allocate, &frame2map);
 
-  //ctrl-c handler: stop templating and write the results so far
+    let template_path = format!("templating{}_window{}.json", id, i);
+    let mut reporters = Reporters::from_env().with(Box::new(
+      report::JsonFileReporter {path: template_path.clone()}));
+    let template = run_templating(ctx, window_config, setups, &frame2map,
+      host_config.clone(), &interupted, &mut reporters);
+    summary.push(WindowEntry {
+      row_start,
+      row_end,
+      template: template_path,
+      frames: frames_to_allocate.len(),
+      captured: frame2map.len(),
+      victims: template.victims.len(),
+      interrupted: interupted.load(Ordering::SeqCst)
+    });
+    fs::write(&summary_path, serde_json::to_string_pretty(&summary)
+      .unwrap()).unwrap_or_else(|_| panic!("Writing {} failed",
+      summary_path));
+    //release the frames of this window before the next one is allocated
+    std::mem::drop(frame2map);
+  }
+  info!("Window summary written to {}", summary_path);
+}
+
+//ctrl-c handler: stop templating and write the results so far
+fn set_ctrlc_handler() -> Arc<AtomicBool> {
   let interupted = Arc::new(AtomicBool::new(false));
   let inter = interupted.clone();
   ctrlc::set_handler(move || {
     inter.store(true, Ordering::SeqCst);
     warn!("Exiting prematurely");
   }).expect("Error setting Ctrl-C handler");
+  interupted
+}
+
+//Templates arg:setups (the frames are in arg:frame2map, setups with missing
+//frames are dropped) and reports the template to arg:reporters
+fn run_templating(
+  ctx: &RhContext, templater_config: TemplaterConfig, setups: Vec<Setup>,
+  frame2map: &Frame2Map, host_config: HostConfig,
+  interupted: &Arc<AtomicBool>, reporters: &mut Reporters
+) -> MemoryTemplate {
+  let start_time = Local::now();
+  ctx.phase_start("templating");
+  //the seed of a random order is recorded in the template for replays
+  let mut templater_config = templater_config;
+  if templater_config.repetition_order == RepetitionOrder::RandomizedBlocks {
+    let seed = *templater_config.order_seed.get_or_insert_with(rand::random);
+    info!("Randomizing the setup order with seed {}", seed);
+  }
+  let dram_config = ctx.dram_config();
+  let hammer_count = templater_config.hammer_count;
+  let victim_ranges = templater_config.victim_ranges(ctx.geometry.row_size());
+
+  //remove the row setups for which a page frame is missing
+  let len_before = setups.len();
+  let (setups, discards) = filter_whole_setups(setups, frame2map);
+  let comment = format!("Testing {} row setups, dropped {} row setups: \n{}",
+    setups.len(), len_before - setups.len(),
+    discards.iter().map(|f| format!("  - {}\n", f)).collect::<String>());
+  info!("{}", comment);
+
+  //optionally remove the row setups with worn rows
+  let wear_path = row_wear::wear_path();
+  let mut row_wear = row_wear::RowWear::load(&wear_path);
+  let (setups, comment) = match templater_config.max_row_wear {
+    Some(max_row_wear) => {
+      let (setups, worn): (Vec<_>, Vec<_>) = setups.into_iter()
+        .partition(|s| s.iter_all_rows().all(|r|
+          row_wear.activations(dram_config, r) <= max_row_wear));
+      let worn_comment = format!("Dropped {} row setups with more than {} \
+        activations in a row: \n{}", worn.len(), max_row_wear,
+        worn.iter().map(|f| format!("  - {}\n", f)).collect::<String>());
+      info!("{}", worn_comment);
+      (setups, comment + &worn_comment)
+    },
+    None => (setups, comment)
+  };
+
+  //optionally map the aggressor rows at extra virtual addresses to rotate
+  //between while hammering
+  let frame2aliases = if templater_config.aggr_aliases > 0 {
+    allocation::map_aliases(ctx, setups.iter()
+      .flat_map(|s| s.aggr_pattern.into_iter().map(|x| x.frames[0])),
+      templater_config.aggr_aliases)
+  } else {
+    HashMap::new()
+  };
+
+  //optionally verify that the aggressors of every setup are where the
+  //pattern intends them to be (see layout)
+  if let Some(layout) = PatternLayout::if_requested(ctx, &templater_config) {
+    let mismatches = setups.iter().filter(|s| s.victim_rows.first()
+      .map_or(false, |v| !layout.warn_on_mismatch(ctx, &s.aggr_pattern,
+      v.frames[0]))).count();
+    info!("{} of {} setups do not match the pattern {}", mismatches,
+      setups.len(), templater_config.pattern);
+  }
+  //do not hammer next to the code, stack or heap of the templater
+  self_guard::check(ctx, setups.iter().flat_map(|s| s.aggr_pattern
+    .into_iter().flat_map(|r| r.frames.iter().cloned())),
+    setups.iter().flat_map(|s| s.victim_rows.iter())
+    .flat_map(|r| r.frames.iter().cloned()), frame2map);
 
+  //the hammer code
+  ctx.kernels.load_plugins_from_env();
+  let kernel: Arc<dyn hammer::HammerKernel> =
+    match (&templater_config.fuzz_pattern, &templater_config.hammer_schedule) {
+      (Some(pattern), _) => {
+        info!("Fuzzed pattern: {:?}", pattern);
+        Arc::new(hammer::FuzzKernel::new(pattern.clone()))
+      },
+      (None, Some(schedule)) => {
+        info!("Hammer schedule: {}", schedule);
+        Arc::new(hammer::ScheduleKernel::new(
+          schedule::Schedule::parse(schedule)))
+      },
+      (None, None) => ctx.kernels.get(templater_config.hammer_kernel
+        .as_deref().unwrap_or(if frame2aliases.is_empty() {"double_sided"}
+        else {"aliases"}))
+    };
+  //the other backends hammer the first alias of every aggressor in one burst
+  let backend = templater_config.hammer_backend;
+  //the other backends hammer the aggressors of the pattern instead
+  assert!(backend == hammer::HammerBackend::Jit
+    || (templater_config.fuzz_pattern.is_none()
+    && templater_config.hammer_schedule.is_none()),
+    "`hammer_schedule` and fuzzed patterns need the jit backend");
+  let row_press = !templater_config.row_open_cycles.is_empty();
+  assert!(!row_press || (backend == hammer::HammerBackend::Jit
+    && templater_config.fuzz_pattern.is_none()
+    && templater_config.hammer_schedule.is_none()),
+    "`row_open_cycles` needs the jit backend, without `hammer_schedule` and \
+    fuzzing");
+  if row_press {
+    info!("Using hammer kernel row_press with row open times {:?} cycles",
+      templater_config.row_open_cycles);
+  } else if backend == hammer::HammerBackend::Jit {
+    info!("Using hammer kernel {}", kernel.name());
+  } else {
+    info!("Using hammer backend {:?}", backend);
+  }
+  assert!((templater_config.aggr_weights.is_empty()
+    && !templater_config.pattern.contains('B')
+    && (templater_config.decoy_interval == 1
+    || !templater_config.pattern.contains('D')))
+    || (backend == hammer::HammerBackend::Jit && !row_press
+    && kernel.name() == "double_sided"),
+    "`aggr_weights`, `B` rows and `decoy_interval` need the double_sided \
+    kernel of the jit backend");
+
+  //physical address -> init value of every flipped victim byte, restored at
+  //the end
+  let mut flipped_bytes: HashMap<u64, u8> = HashMap::new();
+
+  //same for uncorrectable memory errors, to not crash the machine
+  let ecc_guard = ecc::spawn_guard(interupted.clone());
+
+  //optionally disable the L2 prefetchers for the duration of the hammering
+  let prefetchers = host::disable_prefetchers_if_requested();
+  //optionally switch to SCHED_FIFO and reduce the timer slack
+  let shield = host::shield_if_requested();
+  //optionally load other cores while hammering
+  let mut co_runners = workload::CoRunners::spawn(&templater_config.co_runners);
+
+  //the garbage counts to sweep
+  let garbage_ns = calibration::measure_garbage_ns();
+  info!("One garbage instruction takes {:.3}ns", garbage_ns);
+  let garbage_counts = calibrate_garbage_counts(ctx, &templater_config,
+    &setups, frame2map, garbage_ns);
+  //the idle times between the hammer bursts to sweep (None = no idle time)
+  let idle_times: Vec<Option<u64>> = if templater_config.idle_times_ns
+    .is_empty()
+  {
+    vec![None]
+  } else {
+    templater_config.idle_times_ns.iter().map(|i| Some(*i)).collect()
+  };
+  //the row open times to sweep (None = no RowPress)
+  let row_opens: Vec<Option<u64>> = if row_press {
+    templater_config.row_open_cycles.iter().map(|c| Some(*c)).collect()
+  } else {
+    vec![None]
+  };
+  //every idle time with every row open time
+  let sweeps: Vec<(Option<u64>, Option<u64>)> = idle_times.iter()
+    .flat_map(|i| row_opens.iter().map(move |r| (*i, *r))).collect();
+  //the setups that are hammered together
+  let groups = interleave_banks(&setups, templater_config.interleave_banks,
+    dram_config);
+  if groups.len() < setups.len() {
+    assert!(templater_config.hammer_schedule.is_none()
+      && templater_config.fuzz_pattern.is_none(),
+      "`interleave_banks` does not support `hammer_schedule` and fuzzing");
+    info!("Hammering {} setups in {} interleaved groups{}", setups.len(),
+      groups.len(), if templater_config.bank_parallel {
+      " with bank-parallel accesses"} else {""});
+  }
+  assert!(!templater_config.bank_parallel
+    || templater_config.interleave_banks > 1,
+    "`bank_parallel` needs `interleave_banks` > 1");
+  let bursts = templater_config.bursts;
+  assert!(bursts > 0 && hammer_count % bursts == 0,
+    "The hammer_count has to be a multiple of bursts");
+  //with a single burst there is no idle time to sweep
+  assert!(templater_config.idle_times_ns.is_empty() || bursts > 1,
+    "`idle_times_ns` needs `bursts` > 1");
+  //the other backends hammer in a single burst
+  assert!(bursts == 1 || backend == hammer::HammerBackend::Jit,
+    "`bursts` and `idle_times_ns` need the jit backend");
+  //hammer iterations per burst
+  let burst_count = hammer_count / bursts;
+  //kernel events and load per experiment round
+  let mut kmsg_reader = kmsg::KmsgReader::open().map_err(|e|
+    warn!("Could not open /dev/kmsg, kernel events are not recorded: {}", e))
+    .ok();
+  //the results of the hammer loop, reported as a partial template if a panic
+  //unwinds through it
+  let mut partial = PartialTemplate {
+    template: Some(MemoryTemplate {
+      templater_config: templater_config.clone(),
+      dram_config: dram_config.clone(), host_config,
+      timestamp: format!("{} -", start_time.format("%d/%m/%Y %H:%M")),
+      comment: comment.clone(), victims: Vec::new(),
+      aggr_patterns: PatternStore::new(), distribution: HashMap::new(),
+      activation_rates: HashMap::new(), garbage_ns: Some(garbage_ns),
+      hammer_stats: HashMap::new(), system_states: Vec::new(),
+      victims_dram: Vec::new(), aggr_patterns_dram: Vec::new(),
+      minimal_patterns: Vec::new(), idle_time_distribution: BTreeMap::new(),
+      row_open_distribution: BTreeMap::new(),
+      resources: Default::default(), co_runner_stats: Vec::new()
+    }),
+    discovered_victims: HashMap::new(),
+    reporters: Some(reporters)
+  };
+  let PartialTemplate {template, discovered_victims, reporters} = &mut partial;
+  let reporters = reporters.as_deref_mut().unwrap();
+  let MemoryTemplate {aggr_patterns, distribution, idle_time_distribution,
+    row_open_distribution, hammer_stats, system_states, ..} =
+    template.as_mut().unwrap();
+  //the repetitions and groups in the configured order
+  let passes = schedule_passes(&templater_config, groups.len());
+  //repetition -> index of its last pass
+  let last_passes: HashMap<usize, usize> = passes.iter().enumerate()
+    .map(|(i, (rep, _))| (*rep, i)).collect();
   //hammer loop
-  for rep in 0..templater_config.repetition {
+  for (pass, (rep, pass_groups)) in passes.iter().enumerate() {
+    let rep = *rep;
     if interupted.load(Ordering::SeqCst) {break;}
-    for garbage_count in templater_config.garbage_count_start
-      ..templater_config.garbage_count_end
-    {
-      if interupted.load(Ordering::SeqCst) {break;}
-      info!("Starting experiment {} with garbage count {}", rep, garbage_count);
-      let mut experiment_duration = 0u128;
-
-      for setup in &setups {
+    let pass_setups: usize = pass_groups.iter().map(|g| groups[*g].len())
+      .sum();
+    for &(idle_ns, row_open) in &sweeps {
+      let kernel: Arc<dyn hammer::HammerKernel> = match row_open {
+        Some(cycles) => Arc::new(hammer::RowPressKernel::new(cycles)),
+        None => kernel.clone()
+      };
+      for &garbage_count in &garbage_counts {
         if interupted.load(Ordering::SeqCst) {break;}
-        let aggr_pattern = &setup.aggr_pattern;
-        let victim_rows = &setup.victim_rows;
-        //init victim row and their aggressor row
-        mem_init::initialize_rows(victim_rows.iter(), &frame2map);
-        mem_init::initialize_rows(aggr_pattern.into_iter(), &frame2map);
-
-        //hammer
-        let aggrs = aggr_pattern.aggr_rows_to_virt(&frame2map);
-        let duration =
-          //hammer::hammer_rust(&aggrs, hammer_count);
-          //hammer::hammer_asm(&aggrs, garbage_count, hammer_count);
-          hammer::hammer_jit(&aggrs, garbage_count, hammer_count);
-        experiment_duration += duration.as_micros();
-
-        //check for bitflips in all victim rows
-        let found_victims: Vec<(u64, BitFlip)> =
-          victim_rows.iter().map(|victim_row| {
-            flip_checks::check_row_for_flips(
-              &frame2map, victim_row.frames[0], victim_row.init)
-        }).flatten().collect();
-
-        for victim in found_victims {
-          //add discovered aggr pattern
-          let l = discovered_aggr_patterns.len();
-          let aggr_pattern_idx = *discovered_aggr_patterns
-            .entry(aggr_pattern).or_insert_with(|| l);
-
-          //add discovered victim
-          let l = discovered_victims.len();
-          let victim_setup = (victim.0, victim.1, aggr_pattern_idx);
-          let idx = discovered_victims.entry(victim_setup).or_insert_with(|| l);
-
-          distribution.entry(garbage_count)
-            .or_insert(vec!(Vec::new(); templater_config.repetition))[rep]
-            .push(*idx);
+        info!("Starting experiment {} with garbage count {}{}{}", rep,
+          garbage_count, idle_ns.map_or(String::new(),
+          |i| format!(" and idle time {}ns", i)), row_open
+          .map_or(String::new(), |c| format!(" and row open time {} cycles",
+          c)));
+        let mut experiment_duration = 0u128;
+
+        for group in pass_groups.iter().map(|g| &groups[*g]) {
+          if interupted.load(Ordering::SeqCst) {break;}
+          //init victim row and their aggressor row
+          mem_init::initialize_rows_batched(ctx, group.iter()
+            .flat_map(|s| s.victim_rows.iter().chain(s.aggr_pattern
+            .into_iter())), frame2map);
+
+          //hammer (the aggressors of all setups of the group in one code,
+          //optionally interleaved across the banks)
+          let aggrs: Vec<Vec<Vec<u64>>> = group.iter().map(|s| s.aggr_pattern
+            .aggr_rows_to_virt_aliases(frame2map, &frame2aliases)).collect();
+          let rates: Vec<Vec<hammer::AccessRate>> = group.iter()
+            .map(|s| s.aggr_pattern.rates()).collect();
+          let (aggrs, rates) = if templater_config.bank_parallel {
+            (hammer::interleave_accesses(&aggrs),
+              hammer::interleave_accesses(&rates))
+          } else {
+            (aggrs.concat(), rates.concat())
+          };
+          //optionally access the victim rows while hammering
+          let victim_workload = workload::spawn_if_requested(group.iter()
+            .flat_map(|s| s.victim_rows.iter()).flat_map(|r| r.frames.iter()
+            .map(|f| frame2map[f].data() as u64)).collect());
+          co_runners.resume();
+          let stats = if backend == hammer::HammerBackend::Jit {
+            hammer::hammer_kernel_bursts(ctx, &*kernel,
+              &hammer::KernelParams {aggrs: &aggrs, rates: &rates,
+              hammer_count: burst_count, garbage_count,
+              flush: ctx.settings.flush, unroll: ctx.settings.jit_unroll,
+              probes: &ctx.jit_probes},
+              bursts,
+              Duration::from_nanos(idle_ns.unwrap_or(0)))
+          } else {
+            hammer::hammer_with_backend(ctx, backend,
+              &aggrs.iter().map(|a| a[0]).collect(), garbage_count,
+              hammer_count)
+          };
+          co_runners.pause();
+          if let Some(w) = victim_workload {
+            w.stop();
+          }
+          let duration = stats.duration;
+          experiment_duration += duration.as_micros();
+          hammer_stats.entry(garbage_count).or_default().add(&stats);
+
+          //the flips in the victim rows of a setup are attributed to the
+          //aggressors of that setup (the other setups are in other banks)
+          for setup in group {
+            let aggr_pattern = &setup.aggr_pattern;
+            let victim_rows = &setup.victim_rows;
+            //the achieved iterations (more with `RH_HAMMER_DURATION`)
+            row_wear.add_pattern(dram_config, aggr_pattern,
+              stats.hammer_count);
+
+            //check for bitflips in all victim rows
+            //(only the configured regions of each row)
+            let mut found_victims: Vec<(u64, BitFlip)> = Vec::new();
+            for victim_row in victim_rows {
+              for range in &victim_ranges {
+                let flips = flip_checks::check_row_region_for_flips(ctx,
+                  frame2map, victim_row.frames[0], victim_row.init,
+                  range.clone());
+                flipped_bytes.extend(flips.iter()
+                  .map(|v| (v.0, victim_row.init)));
+                found_victims.extend(flips);
+              }
+            }
+
+            reporters.setup_finished(&report::SetupSummary {
+              setup: setup.to_string(),
+              garbage_count: Some(garbage_count),
+              flips: found_victims.len(),
+              duration_us: duration.as_micros() as u64
+            });
+            for victim in found_victims {
+              reporters.flip_discovered(&report::Flip {
+                phys_addr: victim.0,
+                bitflip: victim.1.clone(),
+                aggr_rows: aggr_pattern.into_iter().map(|r| r.frames[0])
+                  .collect(),
+                garbage_count: Some(garbage_count)
+              });
+              //add discovered aggr pattern
+              let aggr_pattern_id = aggr_patterns.intern(aggr_pattern);
+
+              //add discovered victim
+              let l = discovered_victims.len();
+              let victim_setup = (victim.0, victim.1, aggr_pattern_id);
+              let idx =
+                discovered_victims.entry(victim_setup).or_insert_with(|| l);
+
+              //the same victim can flip with several idle times in a round
+              let round = &mut distribution.entry(garbage_count)
+                .or_insert(vec!(Vec::new(); templater_config.repetition))[rep];
+              if !round.contains(idx) {
+                round.push(*idx);
+              }
+              //the same victim can flip with several row open times per idle
+              //time
+              if let Some(idle_ns) = idle_ns {
+                let rounds = idle_time_distribution.entry(idle_ns)
+                  .or_default().entry(garbage_count)
+                  .or_insert(vec!(Vec::new(); templater_config.repetition));
+                if !rounds[rep].contains(idx) {
+                  rounds[rep].push(*idx);
+                }
+              }
+              if let Some(cycles) = row_open {
+                let rounds = row_open_distribution.entry(cycles)
+                  .or_default().entry(garbage_count)
+                  .or_insert(vec!(Vec::new(); templater_config.repetition));
+                if !rounds[rep].contains(idx) {
+                  rounds[rep].push(*idx);
+                }
+              }
+            }
+          }
         }
+
+        info!("Average hammer time over all row setups in this experiment: \
+          {}us", experiment_duration / pass_setups as u128);
       }
+    }
 
-      info!("Average hammer time over all row setups in this experiment: {}us",
-            experiment_duration / setups.len() as u128);
+    //the system state at the end of every repetition
+    if last_passes[&rep] != pass {
+      continue;
     }
+    let kernel_messages = kmsg_reader.as_mut()
+      .map_or(Vec::new(), |r| r.read_new_interesting());
+    kernel_messages.iter()
+      .for_each(|m| warn!("Kernel event during experiment {}: {}", rep, m));
+    system_states.push(SystemState {
+      repetition: rep,
+      loadavg: kmsg::read_loadavg(),
+      memory_pressure: kmsg::read_memory_pressure(),
+      kernel_messages
+    });
   }
 
+  //finalize list of unique victims for exportation
+  let (template, discovered_victims, reporters) = partial.finish();
+  let MemoryTemplate {host_config, mut aggr_patterns, mut distribution,
+    hammer_stats, system_states, mut idle_time_distribution,
+    mut row_open_distribution, ..} = template;
+  let victims = unique_victims(discovered_victims, &distribution);
+  let min_rounds = templater_config.min_rounds;
+  let victims = filter_victims(victims, &mut distribution,
+    &mut idle_time_distribution, &mut row_open_distribution, min_rounds);
+
+  //optionally find the minimal aggressor subset of every victim
+  let minimal_patterns = if templater_config.minimize_aggressors {
+    minimize_aggressors(ctx, &victims, &flipped_bytes,
+      &mut aggr_patterns, &templater_config, frame2map, interupted)
+  } else {
+    Vec::new()
+  };
+
+  let ecc_aborted = ecc_guard.map_or(false, |g| g.stop());
+  let co_runner_stats = co_runners.stop();
+  //restores the prefetchers and the scheduling settings (also dropped when
+  //a panic unwinds)
+  drop(shield);
+  drop(prefetchers);
+  row_wear.save(&wear_path);
+  //repeated experiments on the same frames start from a known state
+  mem_init::restore_and_verify(ctx, &flipped_bytes, frame2map);
+
   info!("Finalizing data structures and emitting results to file");
 
   let end_time = Local::now();
@@ -165,59 +831,855 @@ pub fn main() {
   let timestamp = format!("{} - {}",
     start_time.format(time_format), end_time.format(time_format));
 
-  //finalize list of unique victims and aggressor patterns for exportation
-  let mut victims = vec!((0, Default::default(), 0); discovered_victims.len());
-  discovered_victims.into_iter().for_each(|(k,v)| victims[v] = k);
-  let mut aggr_patterns =
-    vec!(Default::default(); discovered_aggr_patterns.len());
-  discovered_aggr_patterns.into_iter()
-    .for_each(|(k,v)| aggr_patterns[v] = k.clone());
-
-  //print number of bits that flipped in all experiment rounds
-  info!("Found {} unique flips", victims.len());
-  for (k,v) in &distribution {
-    //you know what, let's use another hashmap, i don't care anymore
-    //counts the occurances of a specific bitflip accross the different 
-    //experiment rounds
-    let mut histogram = HashMap::new();
-    //iter over all experiment rounds
-    //flips within a round are unique, so we can use flatten here
-    v.iter().flatten().for_each(|i|
-      *histogram.entry(i).or_insert_with(|| 0) += 1);
-    //plot your beautiful histogram here
-
-    //filter out the flips that didn't flip in enough experiment rounds
-    let t = histogram.iter().fold(0, |mut acc, (_,v)| {
-      if *v >= threshold {acc += 1};
-      acc
+  let comment = if ecc_aborted {
+    comment + "Aborted because of uncorrectable memory errors\n"
+  } else {
+    comment
+  };
+
+  //average activation rate per garbage count
+  let activation_rates: HashMap<u32, f64> = hammer_stats.iter()
+    .map(|(g, stats)| {
+      info!("  garbage_count {} ({:.0}ns): {:.2} activations/tREFI, {:.0} \
+        accesses/s, {} hammer iterations, {}ms JIT compile time", g,
+        *g as f64 * garbage_ns, stats.activations_per_trefi,
+        stats.accesses_per_s, stats.hammer_count,
+        stats.compile_time.as_millis());
+      (*g, stats.activations_per_trefi)
+    }).collect();
+
+  //print number of bits that flipped in enough experiment rounds
+  info!("Found {} unique flips in >= {} experiment rounds", victims.len(),
+    min_rounds);
+  for k in distribution.keys() {
+    let t = victims.iter()
+      .filter(|v| v.rounds_per_garbage_count.contains_key(k)).count();
+    info!("  garbage_count {}: {} of them flipped", k, t);
+  }
+
+  //DRAM addresses, so the analysis does not have to translate them again
+  let (victims_dram, aggr_patterns_dram) =
+    MemoryTemplate::annotate_dram(&victims, &aggr_patterns, dram_config);
+
+  //write results to file (and the other sinks)
+  ctx.phase_end("templating");
+  let template = MemoryTemplate {
+    templater_config, dram_config: dram_config.clone(), host_config,
+    timestamp,
+    comment, victims, aggr_patterns, distribution, activation_rates,
+    garbage_ns: Some(garbage_ns), hammer_stats, system_states, victims_dram,
+    aggr_patterns_dram,
+    minimal_patterns, idle_time_distribution, row_open_distribution,
+    resources: ctx.resources.take_summary(), co_runner_stats
+  };
+  reporters.experiment_finished(&report::ExperimentResult::Template(&template));
+  template
+}
+
+//The results of the hammer loop of `run_templating`, collected in the fields
+//of the template before hammering, the victims found so far are reported to
+//`reporters` (e.g., the template file) as a partial template if a panic
+//unwinds through the hammer loop
+struct PartialTemplate<'a> {
+  //None once the hammer loop finished
+  template: Option<MemoryTemplate>,
+  //collection of all unique disovered victims:
+  //(frame_addr, BitFlip, aggr_pattern_id) -> idx
+  discovered_victims: HashMap<(u64, BitFlip, usize), usize>,
+  reporters: Option<&'a mut Reporters>
+}
+
+impl<'a> PartialTemplate<'a> {
+  fn finish(mut self) -> (MemoryTemplate,
+    HashMap<(u64, BitFlip, usize), usize>, &'a mut Reporters)
+  {
+    (self.template.take().unwrap(),
+      std::mem::take(&mut self.discovered_victims),
+      self.reporters.take().unwrap())
+  }
+}
+
+impl Drop for PartialTemplate<'_> {
+  fn drop(&mut self) {
+    let (Some(mut template), Some(reporters)) =
+      (self.template.take(), self.reporters.take()) else {
+      return;
+    };
+    if !std::thread::panicking() {
+      return;
+    }
+    let victims = unique_victims(std::mem::take(&mut self.discovered_victims),
+      &template.distribution);
+    template.victims = filter_victims(victims, &mut template.distribution,
+      &mut template.idle_time_distribution,
+      &mut template.row_open_distribution,
+      template.templater_config.min_rounds);
+    template.comment += "Aborted by a panic, partial results\n";
+    warn!("Reporting the {} victims found before the panic",
+      template.victims.len());
+    reporters.experiment_finished(
+      &report::ExperimentResult::Template(&template));
+  }
+}
+
+//The victims of arg:discovered_victims (in the order of their index) with
+//the number of rounds they flipped in arg:distribution
+fn unique_victims(
+  discovered_victims: HashMap<(u64, BitFlip, usize), usize>,
+  distribution: &HashMap<u32, Vec<Vec<usize>>>
+) -> Vec<TemplateVictim> {
+  let mut victims = vec!(TemplateVictim::default(); discovered_victims.len());
+  discovered_victims.into_iter().for_each(|((phys_addr, bitflip,
+    aggr_pattern_id), v)| victims[v] = TemplateVictim {phys_addr, bitflip,
+    aggr_pattern_id, ..Default::default()});
+  //flips within a round are unique, so we can use flatten here
+  for (garbage_count, rounds) in distribution {
+    for i in rounds.iter().flatten() {
+      victims[*i].rounds += 1;
+      *victims[*i].rounds_per_garbage_count.entry(*garbage_count)
+        .or_insert(0) += 1;
+    }
+  }
+  victims
+}
+
+//Drops the victims that flipped in less than arg:min_rounds experiment
+//rounds, their indices are removed from arg:distribution (and
+//arg:idle_time_distribution and arg:row_open_distribution) and the remaining
+//ones are renumbered
+fn filter_victims(
+  victims: Vec<TemplateVictim>,
+  distribution: &mut HashMap<u32, Vec<Vec<usize>>>,
+  idle_time_distribution: &mut BTreeMap<u64, HashMap<u32, Vec<Vec<usize>>>>,
+  row_open_distribution: &mut BTreeMap<u64, HashMap<u32, Vec<Vec<usize>>>>,
+  min_rounds: usize
+) -> Vec<TemplateVictim> {
+  let mut new_idx = Vec::new();
+  let mut kept = Vec::new();
+  for victim in victims {
+    if victim.rounds >= min_rounds {
+      new_idx.push(Some(kept.len()));
+      kept.push(victim);
+    } else {
+      new_idx.push(None);
+    }
+  }
+  if kept.len() < new_idx.len() {
+    info!("Dropped {} victims that flipped in less than {} experiment rounds",
+      new_idx.len() - kept.len(), min_rounds);
+  }
+  for round in distribution.values_mut().chain(idle_time_distribution
+    .values_mut().chain(row_open_distribution.values_mut())
+    .flat_map(|d| d.values_mut())).flatten()
+  {
+    *round = round.iter().filter_map(|i| new_idx[*i]).collect();
+  }
+  kept
+}
+
+//Leave-one-out minimization of the aggressor pattern of every victim: the
+//aggressors are dropped one by one as long as the victim still flips (in one
+//of MINIMIZE_ATTEMPTS hammer runs) without them, with the garbage count at
+//which the victim flipped most often
+//All aggressors of a subset are hammered (`hammer_jit_interleaved`), even if
+//the templating kernel only uses the first two, and at least two aggressors
+//are kept (a single row would only cause row buffer hits), decoy rows are
+//never dropped
+//Returns for every victim the id of its minimal pattern in arg:aggr_patterns
+//(None if the victim did not flip again with the whole pattern)
+fn minimize_aggressors(
+  ctx: &RhContext,
+  victims: &[TemplateVictim],
+  flipped_bytes: &HashMap<u64, u8>,
+  aggr_patterns: &mut PatternStore,
+  templater_config: &TemplaterConfig,
+  frame2map: &Frame2Map,
+  interupted: &AtomicBool
+) -> Vec<Option<usize>> {
+  info!("Minimizing the aggressor patterns of {} victims", victims.len());
+  let mut minimal_patterns = Vec::new();
+  for victim in victims {
+    let (phys_addr, bitflip, pattern_id) =
+      (&victim.phys_addr, &victim.bitflip, &victim.aggr_pattern_id);
+    if interupted.load(Ordering::SeqCst) {
+      minimal_patterns.push(None);
+      continue;
+    }
+    let garbage_count = victim.rounds_per_garbage_count.iter()
+      .map(|(g, rounds)| (*rounds, *g))
+      .max().map_or(templater_config.garbage_count_start, |x| x.1);
+    let victim_row = RowAndInitValue {
+      frames: ctx.geometry.frames_in_row(*phys_addr),
+      init: flipped_bytes[phys_addr],
+      weight: 1,
+      interval: 1,
+      decoy: false
+    };
+    let offset = phys_addr - victim_row.frames[0];
+    let flips = |pattern: &AggressorPattern| (0..MINIMIZE_ATTEMPTS).any(|_| {
+      mem_init::initialize_rows_batched(ctx,
+        std::iter::once(&victim_row).chain(pattern), frame2map);
+      hammer::hammer_jit_interleaved(ctx,
+        &[pattern.aggr_rows_to_virt_weighted(frame2map)], garbage_count,
+        templater_config.hammer_count);
+      flip_checks::check_row_region_for_flips(ctx, frame2map,
+        victim_row.frames[0], victim_row.init, offset..offset + 1).iter()
+        .any(|(a, f)| a == phys_addr && f == bitflip)
+    });
+
+    let mut pattern = aggr_patterns.get(*pattern_id).clone();
+    if !flips(&pattern) {
+      warn!("Victim P0x{:x} {} did not flip again", phys_addr, bitflip);
+      minimal_patterns.push(None);
+      continue;
+    }
+    //the decoy rows are kept
+    let mut i = 0;
+    while i < pattern.pattern.len() && pattern.real_aggressors().count() > 2 {
+      if pattern.pattern[i].decoy {
+        i += 1;
+        continue;
+      }
+      let mut subset = pattern.clone();
+      subset.pattern.remove(i);
+      if flips(&subset) {
+        pattern = subset;
+      } else {
+        i += 1;
+      }
+    }
+    info!("Victim P0x{:x} {}: {} of {} aggressors needed", phys_addr, bitflip,
+      pattern.real_aggressors().count(),
+      aggr_patterns.get(*pattern_id).real_aggressors().count());
+    minimal_patterns.push(Some(aggr_patterns.intern(&pattern)));
+    //leave the victim row in its init state like the other rows
+    mem_init::initialize_rows(ctx, std::iter::once(&victim_row), frame2map);
+  }
+  minimal_patterns
+}
+
+//The (repetition, indices of the groups to hammer in that order) of every
+//pass of the hammer loop in the `repetition_order` of arg:templater_config
+fn schedule_passes(templater_config: &TemplaterConfig, groups: usize)
+  -> Vec<(usize, Vec<usize>)>
+{
+  let repetition = templater_config.repetition;
+  match templater_config.repetition_order {
+    RepetitionOrder::RepetitionMajor => (0..repetition)
+      .map(|rep| (rep, (0..groups).collect())).collect(),
+    RepetitionOrder::SetupMajor => (0..groups)
+      .flat_map(|g| (0..repetition).map(move |rep| (rep, vec![g])))
+      .collect(),
+    RepetitionOrder::RandomizedBlocks => {
+      let mut rng = StdRng::seed_from_u64(templater_config.order_seed
+        .expect("No seed for the randomized order"));
+      (0..repetition).map(|rep| {
+        let mut order: Vec<usize> = (0..groups).collect();
+        order.shuffle(&mut rng);
+        (rep, order)
+      }).collect()
+    }
+  }
+}
+
+//Groups arg:setups with the same number of aggressor and victim rows in
+//different banks, at most arg:max_setups per group, to hammer them
+//together: the banks have their own row buffers, so the hammer code accesses
+//the aggressors of all setups of a group in one iteration without the setups
+//interfering, and every aggressor is still accessed hammer_count times
+//A setup joins the first group that is not full yet and fits (for DDR4, the
+//first one without a setup in its bank group if there is one), so the groups
+//keep the order of the setups as far as possible
+//Returns one group per setup for arg:max_setups <= 1
+fn interleave_banks<'a>(
+  setups: &'a [Setup], max_setups: usize, dram_config: &DRAMConfig
+) -> Vec<Vec<&'a Setup>> {
+  let dram_addr = |s: &Setup| dram::phys_to_dram(s.aggr_pattern.pattern[0]
+    .frames[0], dram_config);
+  let bank = |s: &Setup| dram_addr(s).bank;
+  let bank_group = |s: &Setup| dram::bank_group(&dram_addr(s), dram_config);
+  let mut groups: Vec<Vec<&Setup>> = Vec::new();
+  //indices of the groups that are not full
+  let mut open: Vec<usize> = Vec::new();
+  for setup in setups {
+    let fits = |g: &usize| groups[*g].iter()
+      .all(|s| bank(s) != bank(setup)
+      && s.aggr_pattern.pattern.len() == setup.aggr_pattern.pattern.len()
+      && s.victim_rows.len() == setup.victim_rows.len());
+    //accesses to another bank group follow each other sooner (tCCD_S)
+    let other_group = |g: &usize| groups[*g].iter()
+      .all(|s| bank_group(s) != bank_group(setup));
+    let fits = open.iter().position(|g| fits(g) && other_group(g))
+      .or_else(|| open.iter().position(fits));
+    let g = match fits {
+      Some(i) => open[i],
+      None => {
+        groups.push(Vec::new());
+        open.push(groups.len() - 1);
+        groups.len() - 1
+      }
+    };
+    groups[g].push(setup);
+    if groups[g].len() >= max_setups {
+      open.retain(|o| *o != g);
+    }
+  }
+  groups
+}
+
+//Returns the garbage counts to sweep: the configured `garbage_counts`, the
+//garbage count of each configured delay (with arg:garbage_ns per garbage
+//instruction), the whole configured range, or the calibrated garbage count
+//for each target activation rate (measured with the first setup)
+fn calibrate_garbage_counts(
+  ctx: &RhContext, templater_config: &TemplaterConfig, setups: &Vec<Setup>,
+  frame2map: &Frame2Map, garbage_ns: f64
+) -> Vec<u32> {
+  if !templater_config.garbage_counts.is_empty() {
+    return templater_config.garbage_counts.clone();
+  }
+  if !templater_config.garbage_delays_ns.is_empty() {
+    let mut garbage_counts: Vec<u32> = templater_config.garbage_delays_ns
+      .iter().map(|delay| {
+        let garbage_count =
+          calibration::garbage_count_for_delay(*delay, garbage_ns);
+        info!("Garbage delay {}ns: garbage count {}", delay, garbage_count);
+        garbage_count
+      }).collect();
+    //delays that are too close together give the same garbage count
+    garbage_counts.sort();
+    garbage_counts.dedup();
+    return garbage_counts;
+  }
+  let range =
+    templater_config.garbage_count_start..templater_config.garbage_count_end;
+  if templater_config.activation_rates.is_empty() || setups.is_empty() {
+    return range.collect();
+  }
+
+  let aggr_pattern = &setups[0].aggr_pattern;
+  mem_init::initialize_rows(ctx, aggr_pattern.into_iter(), frame2map);
+  let aggrs = aggr_pattern.aggr_rows_to_virt(frame2map);
+  let mut garbage_counts: Vec<u32> = templater_config.activation_rates.iter()
+    .map(|target| calibration::calibrate_garbage_count(ctx, &aggrs,
+      templater_config.hammer_count, *target, range.clone()).0)
+    .collect();
+  //targets that are too close together can give the same garbage count
+  garbage_counts.sort();
+  garbage_counts.dedup();
+  garbage_counts
+}
+
+//The templater configs of batch mode: arg:args is a directory (all *.toml
+//files in it) or a list of config files
+fn batch_config_paths(args: Vec<String>) -> Vec<String> {
+  let paths = match &args[..] {
+    [dir] if Path::new(dir).is_dir() => {
+      let mut paths: Vec<String> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Reading {} failed: {}", dir, e))
+        .flatten().map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |e| e == "toml"))
+        .map(|p| p.to_string_lossy().into_owned()).collect();
+      paths.sort();
+      paths
+    },
+    _ => args
+  };
+  assert!(!paths.is_empty(), "Provide a config directory or config files");
+  paths
+}
+
+//Groups the configs whose frames (arg:frames, one set per config) overlap,
+//transitively: the configs of a group are templated with one allocation
+//The groups are ordered by their first config, the configs within a group
+//keep their order
+fn group_configs(frames: &[HashSet<u64>]) -> Vec<Vec<usize>> {
+  //(configs, union of their frames)
+  let mut groups: Vec<(Vec<usize>, HashSet<u64>)> = Vec::new();
+  for (i, f) in frames.iter().enumerate() {
+    let (overlapping, mut rest): (Vec<_>, Vec<_>) = groups.into_iter()
+      .partition(|(_, g)| !g.is_disjoint(f));
+    let mut merged = (vec![i], f.clone());
+    for (configs, g) in overlapping {
+      merged.0.extend(configs);
+      merged.1.extend(g);
+    }
+    merged.0.sort();
+    rest.push(merged);
+    groups = rest;
+  }
+  groups.sort_by_key(|(configs, _)| configs[0]);
+  groups.into_iter().map(|(configs, _)| configs).collect()
+}
+
+//Summary of one templated config of a batch
+#[derive(Serialize)]
+struct BatchEntry {
+  config: String,
+  template: String,
+  //the configs of the same group shared the allocation
+  group: usize,
+  timestamp: String,
+  victims: usize,
+  //garbage_count -> number of victims that flipped with it
+  victims_per_garbage_count: BTreeMap<u32, usize>,
+  activation_rates: BTreeMap<u32, f64>,
+  //stopped early by ctrl-c or uncorrectable memory errors
+  interrupted: bool
+}
+
+fn run_batch(id: &str, config_paths: &[String]) {
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
+  let host_config: HostConfig = host::read_config();
+  let names: Vec<String> = config_paths.iter().map(|p| Path::new(p)
+    .file_stem().map_or(p.clone(), |s| s.to_string_lossy().into_owned()))
+    .collect();
+  assert!(names.iter().collect::<HashSet<_>>().len() == names.len(),
+    "The config file names of a batch have to be unique");
+  let mut configs: Vec<Option<(TemplaterConfig, Vec<Setup>)>> = Vec::new();
+  let mut frames = Vec::new();
+  for path in config_paths {
+    let templater_config: TemplaterConfig = files::parse_toml(path);
+    let (f, setups) = parse_hammer_pattern(&ctx, &templater_config);
+    frames.push(f);
+    configs.push(Some((templater_config, setups)));
+  }
+  let groups = group_configs(&frames);
+  info!("Templating {} configs in {} allocation groups", configs.len(),
+    groups.len());
+
+  let interupted = set_ctrlc_handler();
+  let summary_path = format!("batch{}_summary.json", id);
+  let mut summary = Vec::new();
+  for (group, members) in groups.iter().enumerate() {
+    if interupted.load(Ordering::SeqCst) {
+      warn!("Skipping {:?}", members.iter().map(|i| &names[*i])
+        .collect::<Vec<_>>());
+      continue;
+    }
+    let group_frames = members.iter().flat_map(|i| frames[*i].iter())
+      .cloned().collect();
+    //the strictest drop fraction of the group
+    let drop_frac = members.iter()
+      .map(|i| configs[*i].as_ref().unwrap().0.drop_frac)
+      .fold(f64::INFINITY, f64::min);
+    let frame2map =
+      allocation::allocate_pages(&ctx, &PagemapOracle, group_frames,
+        drop_frac);
+
+    for i in members {
+      if interupted.load(Ordering::SeqCst) {
+        warn!("Skipping {}", names[*i]);
+        continue;
+      }
+      info!("Templating config {} ({} of {})", config_paths[*i], i + 1,
+        config_paths.len());
+      let (templater_config, setups) = configs[*i].take().unwrap();
+      let template_path = format!("templating{}_{}.json", id, names[*i]);
+      let mut reporters = Reporters::from_env().with(Box::new(
+        report::JsonFileReporter {path: template_path.clone()}));
+      let template = run_templating(&ctx, templater_config, setups,
+        &frame2map, host_config.clone(), &interupted, &mut reporters);
+
+      summary.push(BatchEntry {
+        config: config_paths[*i].clone(),
+        template: template_path,
+        group,
+        timestamp: template.timestamp.clone(),
+        victims: template.victims.len(),
+        victims_per_garbage_count: template.distribution.keys()
+          .map(|g| (*g, template.victims.iter()
+          .filter(|v| v.rounds_per_garbage_count.contains_key(g)).count()))
+          .collect(),
+        activation_rates: template.activation_rates.iter()
+          .map(|(g, r)| (*g, *r)).collect(),
+        interrupted: interupted.load(Ordering::SeqCst)
+      });
+      //written after every config, so an aborted batch has a summary too
+      fs::write(&summary_path, serde_json::to_string_pretty(&summary)
+        .unwrap()).unwrap_or_else(|_| panic!("Writing {} failed",
+        summary_path));
+    }
+  }
+  info!("Batch summary written to {}", summary_path);
+}
+
+fn run_replay(id: &str, template_path: &str) {
+  let recorded: MemoryTemplate = files::parse_json(template_path);
+  let templater_config = analysis::replay_config(&recorded);
+  info!("Replaying {} ({}) with the garbage counts {:?}", template_path,
+    recorded.timestamp, templater_config.garbage_counts);
+  let ctx = RhContext::from_env()
+    .with_dram_config(recorded.dram_config.clone());
+  let host_config: HostConfig = host::read_config();
+  let (frames_to_allocate, setups) =
+    parse_hammer_pattern(&ctx, &templater_config);
+  let frame2map =
+    allocation::allocate_pages(&ctx, &PagemapOracle, frames_to_allocate,
+      templater_config.drop_frac);
+
+  let interupted = set_ctrlc_handler();
+  let mut reporters = Reporters::from_env().with(Box::new(
+    report::JsonFileReporter {path: format!("templating{}_replay.json", id)}));
+  let replayed = run_templating(&ctx, templater_config, setups, &frame2map,
+    host_config, &interupted, &mut reporters);
+
+  let divergences = analysis::compare_templates(&recorded, &replayed);
+  for divergence in &divergences {
+    warn!("Divergence: {}", serde_json::to_string(divergence).unwrap());
+  }
+  info!("{} divergences ({} victims recorded, {} replayed)",
+    divergences.len(), recorded.victims.len(), replayed.victims.len());
+  let path = format!("replay{}_divergences.json", id);
+  fs::write(&path, serde_json::to_string_pretty(&divergences).unwrap())
+    .unwrap_or_else(|_| panic!("Writing {} failed", path));
+}
+
+//triage<id>.json
+#[derive(Serialize)]
+struct Triage {
+  seed: u64,
+  coverage: f64,
+  template: String,
+  estimate: analysis::TriageEstimate
+}
+
+fn run_triage(id: &str, seed: Option<u64>) {
+  let templater_config: TemplaterConfig =
+    files::parse_toml(TEMPLATER_CONFIG_PATH);
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
+  let host_config: HostConfig = host::read_config();
+  let (_, setups) = parse_hammer_pattern(&ctx, &templater_config);
+
+  //the sampling unit are the victim rows of a setup (with all init values)
+  let mut by_victim_rows: BTreeMap<Vec<u64>, Vec<Setup>> = BTreeMap::new();
+  for setup in setups {
+    by_victim_rows.entry(setup.victim_rows.iter().map(|r| r.frames[0])
+      .collect()).or_default().push(setup);
+  }
+  let population_rows = by_victim_rows.keys().flatten()
+    .collect::<HashSet<_>>().len();
+  assert!(!by_victim_rows.is_empty(), "The config has no setups");
+  let seed = seed.unwrap_or_else(rand::random);
+  let mut keys: Vec<Vec<u64>> = by_victim_rows.keys().cloned().collect();
+  keys.shuffle(&mut StdRng::seed_from_u64(seed));
+  let samples = ((keys.len() as f64 * templater_config.triage_coverage).ceil()
+    as usize).clamp(1, keys.len());
+  info!("Sampling {} of {} victim row sets (seed {})", samples, keys.len(),
+    seed);
+  let setups: Vec<Setup> = keys[..samples].iter()
+    .flat_map(|k| by_victim_rows.remove(k).unwrap()).collect();
+  let frames_to_allocate = setups.iter().flat_map(|s| s.iter_all_frames())
+    .collect();
+  let frame2map = allocation::allocate_pages(&ctx, &PagemapOracle,
+    frames_to_allocate, templater_config.drop_frac);
+  //only the rows of the complete setups are templated
+  let (setups, _) = filter_whole_setups(setups, &frame2map);
+  let sampled_rows: HashSet<u64> = setups.iter()
+    .flat_map(|s| s.victim_rows.iter().map(|r| r.frames[0])).collect();
+
+  let interupted = set_ctrlc_handler();
+  let template_path = format!("templating{}_triage.json", id);
+  let mut reporters = Reporters::from_env().with(Box::new(
+    report::JsonFileReporter {path: template_path.clone()}));
+  let coverage = templater_config.triage_coverage;
+  let confidence = templater_config.triage_confidence;
+  let template = run_templating(&ctx, templater_config, setups, &frame2map,
+    host_config, &interupted, &mut reporters);
+
+  let estimate = analysis::triage_estimate(&template, &sampled_rows,
+    population_rows, ctx.geometry.row_size(), confidence);
+  info!("{} flips in {} of {} victim rows: {:.1} flips/GB ({:.0}% interval \
+    {:.1} - {:.1})", estimate.flips, estimate.sampled_rows,
+    estimate.population_rows, estimate.flips_per_gb, confidence * 100.0,
+    estimate.flips_per_gb_low, estimate.flips_per_gb_high);
+  let path = format!("triage{}.json", id);
+  fs::write(&path, serde_json::to_string_pretty(&Triage {seed, coverage,
+    template: template_path, estimate}).unwrap())
+    .unwrap_or_else(|_| panic!("Writing {} failed", path));
+}
+
+//fuzz<id>_summary.json
+#[derive(Serialize)]
+struct FuzzSummary {
+  seed: u64,
+  patterns: Vec<FuzzEntry>
+}
+
+#[derive(Serialize)]
+struct FuzzEntry {
+  pattern: fuzz::FuzzedPattern,
+  template: String,
+  victims: usize,
+  //garbage_count -> number of victims that flipped with it
+  victims_per_garbage_count: BTreeMap<u32, usize>
+}
+
+fn run_fuzz(id: &str, seed: Option<u64>) {
+  let templater_config: TemplaterConfig =
+    files::parse_toml(TEMPLATER_CONFIG_PATH);
+  assert!(templater_config.fuzz_pattern.is_none(),
+    "Remove `fuzz_pattern` from the config to sample patterns");
+  assert!(templater_config.hammer_backend == hammer::HammerBackend::Jit,
+    "Fuzzing needs the jit backend");
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
+  let host_config: HostConfig = host::read_config();
+  let aggressors = templater_config.pattern.chars()
+    .filter(|c| matches!(c, 'A' | 'B' | 'D' | 'F')).count();
+  let (frames_to_allocate, _) = parse_hammer_pattern(&ctx, &templater_config);
+  let frame2map = allocation::allocate_pages(&ctx, &PagemapOracle,
+    frames_to_allocate, templater_config.drop_frac);
+
+  let seed = seed.unwrap_or_else(rand::random);
+  info!("Sampling {} patterns of {} aggressors with seed {}",
+    templater_config.fuzz_patterns, aggressors, seed);
+  let mut rng = StdRng::seed_from_u64(seed);
+  let interupted = set_ctrlc_handler();
+  let summary_path = format!("fuzz{}_summary.json", id);
+  let mut summary = FuzzSummary {seed, patterns: Vec::new()};
+  for i in 0..templater_config.fuzz_patterns {
+    if interupted.load(Ordering::SeqCst) {
+      break;
+    }
+    let pattern = fuzz::FuzzedPattern::sample(&mut rng, aggressors);
+    info!("Templating fuzzed pattern {} of {}", i + 1,
+      templater_config.fuzz_patterns);
+    let mut pattern_config = templater_config.clone();
+    pattern_config.fuzz_pattern = Some(pattern.clone());
+    let (_, setups) = parse_hammer_pattern(&ctx, &pattern_config);
+    let template_path = format!("templating{}_fuzz{}.json", id, i);
+    let mut reporters = Reporters::from_env().with(Box::new(
+      report::JsonFileReporter {path: template_path.clone()}));
+    let template = run_templating(&ctx, pattern_config, setups, &frame2map,
+      host_config.clone(), &interupted, &mut reporters);
+
+    summary.patterns.push(FuzzEntry {
+      pattern,
+      template: template_path,
+      victims: template.victims.len(),
+      victims_per_garbage_count: template.distribution.keys()
+        .map(|g| (*g, template.victims.iter()
+        .filter(|v| v.rounds_per_garbage_count.contains_key(g)).count()))
+        .collect()
     });
-    info!("  garbage_count {}: {} bits flipped in >= {} experiment rounds",
-      k, t, threshold);
+    fs::write(&summary_path, serde_json::to_string_pretty(&summary)
+      .unwrap()).unwrap_or_else(|_| panic!("Writing {} failed",
+      summary_path));
   }
+  let flipping = summary.patterns.iter().filter(|p| p.victims > 0).count();
+  info!("{} of {} fuzzed patterns flipped bits, summary written to {}",
+    flipping, summary.patterns.len(), summary_path);
+}
+
+fn soak_dir(id: &str) -> String {
+  format!("soak{}", id)
+}
 
-  //write results to file
-  let mut file = File::create(format!("templating{}.json", id)).unwrap();
-  write!(file, "{}", serde_json::to_string(&MemoryTemplate {
-    templater_config, dram_config, host_config, timestamp,
-    comment, victims, aggr_patterns, distribution
-  }).unwrap()).unwrap();
+//The config of a random region of arg:templater_config: `soak_rows` rows in
+//[row_start, row_end[ in one of the banks
+fn soak_region(templater_config: &TemplaterConfig, rng: &mut StdRng)
+  -> TemplaterConfig
+{
+  let mut region = templater_config.clone();
+  let rows = templater_config.soak_rows
+    .min(templater_config.row_end - templater_config.row_start);
+  region.row_start = rng.gen_range(
+    templater_config.row_start..=templater_config.row_end - rows);
+  region.row_end = region.row_start + rows;
+  region.bank_idxs = vec![*templater_config.bank_idxs.choose(rng)
+    .expect("The config has no banks")];
+  region.window_rows = 0;
+  region
 }
 
-//create all double sided rowhammer patterns in the given DRAM region
+//The message of a panic (see `std::panic::catch_unwind`)
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+  payload.downcast_ref::<&str>().map(|s| s.to_string())
+    .or_else(|| payload.downcast_ref::<String>().cloned())
+    .unwrap_or_else(|| "unknown panic".to_owned())
+}
+
+fn run_soak(id: &str, seed: Option<u64>) {
+  let templater_config: TemplaterConfig =
+    files::parse_toml(TEMPLATER_CONFIG_PATH);
+  assert!(templater_config.soak_rows >= templater_config.pattern_rows()
+    && templater_config.row_end - templater_config.row_start
+    >= templater_config.pattern_rows(),
+    "The soak regions need at least the rows of the pattern");
+  let ctx = RhContext::from_env().with_dram_config(dram::create_config());
+  let dir = soak_dir(id);
+  let days_path = format!("{}/days.json", dir);
+  let mut days: Vec<SoakDay> = if Path::new(&days_path).exists() {
+    files::parse_json(&days_path)
+  } else {
+    Vec::new()
+  };
+  let mut cycle: usize = days.iter().map(|d| d.cycles + d.failed_cycles)
+    .sum();
+  let seed = seed.unwrap_or_else(rand::random);
+  info!("Soak test of rows {} - {} in the banks {:?}, {} rows per cycle, \
+    starting at cycle {} (seed {})", templater_config.row_start,
+    templater_config.row_end, templater_config.bank_idxs,
+    templater_config.soak_rows, cycle, seed);
+  let mut rng = StdRng::seed_from_u64(seed);
+  let interupted = set_ctrlc_handler();
+  let mut reporters = Reporters::from_env();
+  let mut failures = 0;
+  while !interupted.load(Ordering::SeqCst) {
+    //roll up the last day
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    if days.last().map_or(true, |d| d.date != today) {
+      if let Some(day) = days.last() {
+        reporters.experiment_finished(&report::ExperimentResult::SoakDay(day));
+      }
+      days.push(SoakDay {date: today, ..SoakDay::default()});
+    }
+
+    let region = soak_region(&templater_config, &mut rng);
+    let (bank, rows) = (region.bank_idxs[0], region.row_end - region.row_start);
+    info!("Soak cycle {}: rows {} - {} in bank {}", cycle, region.row_start,
+      region.row_end, bank);
+    let start = Instant::now();
+    let template_path = format!("{}/templating{}.json", dir, cycle);
+    //a failed cycle leaves its usage and running phases behind
+    ctx.resources.reset();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      let host_config: HostConfig = host::read_config();
+      let (frames_to_allocate, setups) = parse_hammer_pattern(&ctx, &region);
+      let frame2map = allocation::allocate_pages(&ctx, &PagemapOracle,
+        frames_to_allocate, region.drop_frac);
+      let mut reporters = Reporters::from_env().with(Box::new(
+        report::JsonFileReporter {path: template_path.clone()}));
+      run_templating(&ctx, region, setups, &frame2map, host_config,
+        &interupted, &mut reporters).victims.len()
+    }));
+
+    let day = days.last_mut().unwrap();
+    day.duration_s += start.elapsed().as_secs();
+    match result {
+      Ok(victims) => {
+        info!("Soak cycle {} found {} victims", cycle, victims);
+        failures = 0;
+        day.cycles += 1;
+        day.rows += rows;
+        day.victims += victims;
+        *day.victims_per_bank.entry(bank).or_default() += victims;
+      },
+      Err(payload) => {
+        failures += 1;
+        let message = panic_message(&*payload);
+        error!("Soak cycle {} failed ({} in a row): {}", cycle, failures,
+          message);
+        day.failed_cycles += 1;
+        day.failures.push(format!("cycle {}: {}", cycle, message));
+      }
+    }
+    cycle += 1;
+    fs::write(&days_path, serde_json::to_string_pretty(&days).unwrap())
+      .unwrap_or_else(|_| panic!("Writing {} failed", days_path));
+
+    if failures > 0 {
+      assert!(failures < templater_config.soak_max_failures, "{} soak cycles \
+        failed in a row, giving up", failures);
+      let restart = Instant::now() + SOAK_RESTART_DELAY * failures;
+      warn!("Restarting in {}s", (SOAK_RESTART_DELAY * failures).as_secs());
+      while Instant::now() < restart && !interupted.load(Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_secs(1));
+      }
+    }
+  }
+  if let Some(day) = days.last() {
+    reporters.experiment_finished(&report::ExperimentResult::SoakDay(day));
+  }
+  info!("Soak test stopped after {} cycles, summary in {}", cycle,
+    days_path);
+}
+
+//drop_frac<id>.json
+#[derive(Serialize)]
+struct DropFracSuggestion {
+  configured_drop_frac: f64,
+  suggested_drop_frac: f64,
+  //pages needed for the configured and the suggested drop_frac
+  pages_configured: Option<u64>,
+  pages_suggested: Option<u64>,
+  probe: allocation::AllocationProbe
+}
+
+fn run_drop_frac_probe(
+  ctx: &RhContext, id: &str, templater_config: &TemplaterConfig
+) {
+  let (frames, _) = parse_hammer_pattern(ctx, templater_config);
+  let probe = allocation::probe_allocation(ctx, &PagemapOracle, frames,
+    PROBE_GRACE_PAGES);
+  let suggestion = DropFracSuggestion {
+    configured_drop_frac: templater_config.drop_frac,
+    suggested_drop_frac: probe.suggest_drop_frac(),
+    pages_configured: probe.pages_for(templater_config.drop_frac),
+    pages_suggested: probe.pages_for(probe.suggest_drop_frac()),
+    probe
+  };
+  info!("{:.2}% of the frames are unobtainable, suggested drop_frac: {:.3} \
+    (configured: {})", suggestion.probe.missed_frac() * 100.0,
+    suggestion.suggested_drop_frac, suggestion.configured_drop_frac);
+  if suggestion.pages_configured.is_none() {
+    warn!("The configured drop_frac is too small, the allocation would not \
+      finish");
+  }
+  let path = format!("drop_frac{}.json", id);
+  fs::write(&path, serde_json::to_string_pretty(&suggestion).unwrap())
+    .unwrap_or_else(|_| panic!("Writing {} failed", path));
+}
+
+fn run_adjacency_probe(
+  ctx: &RhContext, id: &str, templater_config: &TemplaterConfig
+) {
+  let frame2map = allocation::allocate_pages(ctx, &PagemapOracle,
+    adjacency::frames_to_allocate(ctx, templater_config),
+    templater_config.drop_frac);
+  let remap = adjacency::probe(ctx, templater_config, &frame2map);
+  let path = format!("row_remap{}.json", id);
+  let mut file = File::create(&path).unwrap();
+  write!(file, "{}", serde_json::to_string_pretty(&remap).unwrap()).unwrap();
+  info!("Row remap table written to {}", path);
+}
+
+//create all rowhammer patterns of `pattern` in the given DRAM region
+//(the rows of a pattern are physically adjacent with `row_remap`)
+//`pattern` has one character per row: `A` aggressor, `V` victim, `B` dribble
+//row, `D` dummy (decoy) row and `F` far row, a decoy in the bank of the
+//setup (or in `far_row_bank`) `far_row_distance` rows after the first row of
+//the pattern, hammered in every iteration to close the aggressor rows (the
+//single-sided mode), the `F` rows do not occupy a row of the region
 fn parse_hammer_pattern(
-  templater_config: &TemplaterConfig, dram_config: &DRAMConfig
+  ctx: &RhContext, templater_config: &TemplaterConfig
 ) -> (HashSet<u64>, Vec<Setup>) {
-  info!("Generating double-sided patterns for rows: {} - {} and banks {:?}",
-    templater_config.row_start, templater_config.row_end,
-    templater_config.bank_idxs);
+  info!("Generating {} patterns for rows: {} - {} and banks {:?}",
+    templater_config.pattern, templater_config.row_start,
+    templater_config.row_end, templater_config.bank_idxs);
 
+  let row_remap = templater_config.row_remap.as_ref()
+    .map(|path| files::parse_json::<RowRemap>(path));
+  let aggr_rows = templater_config.pattern.chars()
+    .filter(|c| matches!(c, 'A' | 'B' | 'D' | 'F')).count();
+  let weights = &templater_config.aggr_weights;
+  assert!(weights.is_empty() || weights.len() == aggr_rows,
+    "{} aggr_weights for the {} aggressor rows of {}", weights.len(),
+    aggr_rows, templater_config.pattern);
+  assert!(!weights.contains(&0), "Aggressor weight 0 in aggr_weights");
+  assert!(templater_config.dribble_interval > 0, "dribble_interval 0");
+  assert!(templater_config.decoy_interval > 0, "decoy_interval 0");
+  assert!(templater_config.far_row_bank.is_some()
+    || templater_config.far_row_distance >= templater_config.pattern_rows(),
+    "far_row_distance {} is within the pattern {}",
+    templater_config.far_row_distance, templater_config.pattern);
   let mut frames_to_allocate = HashSet::new();
   //all combinations of rows and init values
   let mut setups = Vec::new();
 
   //collect all required (victim and aggressor) frames
   for row_idx in templater_config.row_start
-    ..(templater_config.row_end - templater_config.pattern.len() as u64 + 1)
+    ..(templater_config.row_end - templater_config.pattern_rows() + 1)
   {
     for bank_idx in &templater_config.bank_idxs {
       for init_value in &templater_config.init_values {
@@ -225,28 +1687,51 @@ fn parse_hammer_pattern(
           victim_rows: Vec::new(),
           aggr_pattern: AggressorPattern {pattern: Vec::new()}
         };
+        //the dribble rows, the dummy (decoy) rows and then the far rows
+        //follow the aggressors in the aggressor pattern
+        let mut dribble_rows = Vec::new();
+        let mut dummy_rows = Vec::new();
+        let mut far_rows = Vec::new();
 
-        for (i,c) in templater_config.pattern.chars().enumerate() {
-          let dram_addr = DRAMAddr {
-            bank: *bank_idx, row: row_idx + i as u64, column: 0};
-          let phys_addr = dram::dram_to_phys(&dram_addr, &dram_config);
-          let frames = utils::get_frames_in_row(phys_addr);
+        let mut i = 0;
+        for c in templater_config.pattern.chars() {
+          let (bank, row) = if c == 'F' {
+            (templater_config.far_row_bank.unwrap_or(*bank_idx),
+              row_idx + templater_config.far_row_distance)
+          } else {
+            let row = row_idx + i;
+            i += 1;
+            (*bank_idx, row_remap.as_ref().map_or(row, |r| r.to_logical(row)))
+          };
+          let dram_addr = DRAMAddr {bank, row, column: 0};
+          let phys_addr = dram::dram_to_phys(&dram_addr, ctx.dram_config());
+          let frames = ctx.geometry.frames_in_row(phys_addr);
           frames_to_allocate.extend(frames.iter());
 
           let victim_init = init_value.victim_init;
           let aggr_init = init_value.aggr_init;
+          let new_row = |init, interval| RowAndInitValue {
+            frames: frames.clone(), init, weight: 1, interval, decoy: false};
           match c {
-            'A' => {
-              setup.aggr_pattern.pattern.push(RowAndInitValue {
-                frames: frames.clone(), init: aggr_init});
-            },
-            'V' => {
-              setup.victim_rows.push(RowAndInitValue {
-                frames: frames.clone(), init: victim_init});
-            }
+            'A' => setup.aggr_pattern.pattern.push(new_row(aggr_init, 1)),
+            'V' => setup.victim_rows.push(new_row(victim_init, 1)),
+            'B' => dribble_rows.push(new_row(aggr_init,
+              templater_config.dribble_interval)),
+            'D' => dummy_rows.push(RowAndInitValue {decoy: true,
+              ..new_row(aggr_init, templater_config.decoy_interval)}),
+            'F' => far_rows.push(RowAndInitValue {decoy: true,
+              ..new_row(aggr_init, 1)}),
             _ => panic!("Unknown rowhammer pattern")
           }
         }
+        setup.aggr_pattern.pattern.extend(dribble_rows);
+        setup.aggr_pattern.pattern.extend(dummy_rows);
+        setup.aggr_pattern.pattern.extend(far_rows);
+        for (row, weight) in setup.aggr_pattern.pattern.iter_mut()
+          .zip(&templater_config.aggr_weights)
+        {
+          row.weight = *weight;
+        }
         setups.push(setup);
       }
     }
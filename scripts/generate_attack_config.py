@@ -1,9 +1,12 @@
 #!/usr/bin/python3
 
-# USAGE: ./generate_attack_config.py <path/to/target_bin> <path/to/compiler_output_<id>.txt>
+# USAGE: ./generate_attack_config.py <path/to/target_bin> <path/to/compiler_output_<id>.txt> [path/to/inventory.json]
 
 #Reads the final binary and the `compiler_output.txt` file in the same directory, and creates the `attack_config.toml` file in that directory.
 #This file is used by the `loader` component at run time.
+#With the inventory of the PT_NULL segment (see `segment_inventory.py`), victim bits in a function are given by its symbol
+#and the offset in the function instead of the file offset, so the attack config survives a recompilation of the target
+#as long as the code of the function is stable.
 
 
 """ compiler output format:
@@ -21,6 +24,7 @@ sec_name2 section_offset2 phys_victim2 aggr2.1,aggr2.2 aggr_init
 """
 
 import sys
+import json
 from tomlkit import comment, document, table, inline_table, array, dumps
 from elftools.elf.elffile import ELFFile
 
@@ -30,6 +34,7 @@ package_path = compiler_output_path.rsplit("/", 1)[0]
 id = compiler_output_path[18:-4]
 attack_config_path = package_path + "/attack_config_" + id + ".toml"
 hammer_count = 1000000
+inventory_path = sys.argv[3] if len(sys.argv) > 3 else None
 
 null_segment_offset = 0
 # sec_name -> [(offset_in_sec, phys_victim, [aggrs], aggr_init, expected_flip)]
@@ -93,6 +98,19 @@ for section_name in layout.keys():
         #frame_addr duplicates
         output[page_file_offset].append((frame_addr, (offset, aggrs, aggr_init, expected)))
 
+# Read the segment inventory
+functions = []
+if inventory_path is not None:
+    with open(inventory_path, "r") as f:
+        functions = json.load(f)["functions"]
+
+# (symbol, offset in the function) of the byte at file_offset, None if it is not in a function
+def find_symbol(file_offset):
+    for f in functions:
+        if f["file_offset"] <= file_offset < f["file_offset"] + f["size"]:
+            return (f["name"], file_offset - f["file_offset"])
+    return None
+
 # Generate attack_config.toml
 dict = {
     "hammer_count": hammer_count,
@@ -113,6 +131,7 @@ for page_file_offset in output.keys():
         "frame_addr": hex(output[page_file_offset][0][0]),
         "victim_bits": array()
     }
+    all_symbols = True
 
     for _, (offset, aggrs, aggr_init, expected) in output[page_file_offset]:
         aggr_pattern = (tuple(aggrs), aggr_init) #no support for aggressors with different init values
@@ -120,13 +139,22 @@ for page_file_offset in output.keys():
             aggressor_patterns_rev[aggr_pattern] = aggr_pattern_key
             aggr_pattern_key += 1
         t = inline_table()
+        symbol = find_symbol(page_file_offset + offset)
+        if symbol is None:
+            all_symbols = False
+            t.update({"offset": hex(offset)})
+        else:
+            t.update({"symbol": "'" + symbol[0] + "'", "insn_offset": hex(symbol[1])})
         t.update({
-            "offset": hex(offset),
             "bitflip": "'" + expected + "'", #use single quotes, see string comment below
             "aggr_pattern_key": "'" + str(aggressor_patterns_rev[aggr_pattern]) + "'"
         })
         victim_frame["victim_bits"].add_line(t);
 
+    # the loader derives the page from the symbols
+    if all_symbols:
+        del victim_frame["page_file_offset"]
+
     dict["victim_frames"].append(victim_frame)
 
 for aggr_pattern in aggressor_patterns_rev.keys():
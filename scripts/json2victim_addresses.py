@@ -10,6 +10,8 @@ from pathlib import Path
 # Only the victims that occured more than arg:X times are included in the output
 # Setting X to 1 will emit all discoved victims during the templating step
 # The number of occurences for the victim that occured the most is printed to stdout
+# Newer templates record the number of rounds in the victim records, then those are used instead of the distribution
+# If the template has minimal aggressor patterns (`minimize_aggressors` in the templater config), those are used instead of the templated patterns
 
 # IMPORTANT!!
 # This script puts all data of all garbage count together, and then it select the ones that occured >=X times!
@@ -24,6 +26,7 @@ file.close()
 victim_locations = json_data["victims"]
 aggressors = json_data["aggr_patterns"]
 distribution = json_data["distribution"]
+minimal_patterns = json_data.get("minimal_patterns", [])
 
 histogram = {}
 
@@ -32,6 +35,11 @@ for x in distribution.values():
         for i in y:
             histogram[i] = histogram.get(i, 0) + 1
 
+for k,v in enumerate(victim_locations):
+    rounds = re.search(r"^\S+ \S+ \d+ (\d+)", v)
+    if rounds is not None and int(rounds.group(1)) > 0:
+        histogram[k] = int(rounds.group(1))
+
 file = open(Path(path).parent / "victim_addresses.txt", "w")
 max = 0
 count = 0
@@ -40,6 +48,8 @@ for k,v in histogram.items():
     if v >= threshold:
         addr, bit, sign, aggr_idx = re.search(r"(0x[0-9a-f]+) (\d)([+-]) (\d+)", victim_locations[k]).groups()
         aggr_idx = int(aggr_idx)
+        if k < len(minimal_patterns) and minimal_patterns[k] is not None:
+            aggr_idx = minimal_patterns[k]
         aggr1, aggr1_init, aggr2, aggr2_init = re.search(r"(0x[0-9a-f]+)\((0x[0f]+)\),(0x[0-9a-f]+)\((0x[0f]+)\)", aggressors[aggr_idx]).groups()
         assert(aggr1_init == aggr2_init)
         file.write(addr + " " + bit + " " + sign + " " + aggr1 + "," + aggr2 + " " + aggr1_init + "\n")
@@ -0,0 +1,90 @@
+#!/usr/bin/python3
+
+# This script lists the contents of the PT_NULL segment of a target binary: its pages (file page offset, virtual address, bytes) and the functions in it
+# The output is used to find candidate victim bytes, and by `generate_attack_config.py` to specify the victim bits by symbol
+# USAGE: ./segment_inventory.py <path/to/executable> [path/to/inventory.json]
+# The inventory is printed to stdout if no output path is given
+
+""" inventory format:
+{
+  "binary": path,
+  "segment": {"file_offset": x, "vaddr": x, "filesz": x, "memsz": x},
+  "pages": [{"page_file_offset": x, "vaddr": x, "bytes": "hex string", "functions": [name, ...]}, ...],
+  "functions": [{"name": name, "file_offset": x, "vaddr": x, "size": x, "page_file_offsets": [x, ...]}, ...]
+}
+"""
+
+import sys
+import json
+from elftools.elf.elffile import ELFFile
+
+PAGE_SIZE = 0x1000
+
+filename = sys.argv[1]
+file = open(filename, "rb")
+elf = ELFFile(file)
+
+segment = None
+for s in elf.iter_segments():
+    if s["p_type"] == "PT_NULL":
+        segment = s
+        break
+assert segment is not None, filename + " has no PT_NULL segment"
+
+seg_offset = segment["p_offset"]
+seg_vaddr = segment["p_vaddr"]
+seg_filesz = segment["p_filesz"]
+
+# functions in the segment (by their file offset)
+functions = []
+symtab = elf.get_section_by_name(".symtab") or elf.get_section_by_name(".dynsym")
+assert symtab is not None, filename + " has no symbol table"
+for symbol in symtab.iter_symbols():
+    if symbol["st_info"]["type"] != "STT_FUNC" or symbol["st_size"] == 0:
+        continue
+    vaddr = symbol["st_value"]
+    if not seg_vaddr <= vaddr < seg_vaddr + seg_filesz:
+        continue
+    file_offset = vaddr - seg_vaddr + seg_offset
+    size = symbol["st_size"]
+    # the loader maps the segment page by page starting at its file offset
+    first_page = seg_offset + (file_offset - seg_offset) // PAGE_SIZE * PAGE_SIZE
+    functions.append({
+        "name": symbol.name,
+        "file_offset": file_offset,
+        "vaddr": vaddr,
+        "size": size,
+        "page_file_offsets": list(range(first_page, file_offset + size, PAGE_SIZE))
+    })
+functions.sort(key=lambda f: f["file_offset"])
+
+# pages of the segment, only the bytes in the segment are listed for the last page (the rest is padding)
+pages = []
+for page_file_offset in range(seg_offset, seg_offset + seg_filesz, PAGE_SIZE):
+    file.seek(page_file_offset)
+    pages.append({
+        "page_file_offset": page_file_offset,
+        "vaddr": page_file_offset - seg_offset + seg_vaddr,
+        "bytes": file.read(min(PAGE_SIZE, seg_offset + seg_filesz - page_file_offset)).hex(),
+        "functions": [f["name"] for f in functions if page_file_offset in f["page_file_offsets"]]
+    })
+
+file.close()
+
+inventory = {
+    "binary": filename,
+    "segment": {
+        "file_offset": seg_offset,
+        "vaddr": seg_vaddr,
+        "filesz": seg_filesz,
+        "memsz": segment["p_memsz"]
+    },
+    "pages": pages,
+    "functions": functions
+}
+
+if len(sys.argv) > 2:
+    with open(sys.argv[2], "w") as f:
+        json.dump(inventory, f, indent=2)
+else:
+    print(json.dumps(inventory, indent=2))